@@ -0,0 +1,961 @@
+use rfw::backend::RenderMode;
+use rfw::prelude::*;
+use std::error::Error;
+use std::f32::consts::PI;
+
+mod raster;
+
+use raster::{ClipVertex, Fragment, Framebuffer};
+
+/// How lighting is interpolated over triangles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Shading {
+    /// Lighting is evaluated once per triangle, at its center
+    Flat = 0,
+    /// Lighting is evaluated at the vertices and interpolated over the triangle
+    Gouraud = 1,
+}
+
+impl Default for Shading {
+    fn default() -> Self {
+        Shading::Gouraud
+    }
+}
+
+impl Shading {
+    /// Names of the shading modes, indexed by their integer value.
+    pub const NAMES: [&'static str; 2] = ["flat", "gouraud"];
+
+    fn from_i32(value: i32) -> Self {
+        match value {
+            0 => Shading::Flat,
+            _ => Shading::Gouraud,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CpuMesh {
+    vertices: Vec<Vertex3D>,
+    ranges: Vec<VertexMesh>,
+    skin_data: Vec<JointData>,
+    flags: Mesh3dFlags,
+}
+
+#[derive(Debug, Default, Clone)]
+struct CpuInstances {
+    matrices: Vec<Mat4>,
+    skin_ids: Vec<SkinID>,
+    tints: Vec<Vec4>,
+}
+
+/// First mip level of a texture, only uncompressed 8-bit formats are kept.
+#[derive(Debug, Clone)]
+struct CpuTexture {
+    width: usize,
+    height: usize,
+    format: DataFormat,
+    premultiplied: bool,
+    texels: Vec<u8>,
+}
+
+impl CpuTexture {
+    fn new(tex: &TextureData<'_>) -> Option<Self> {
+        match tex.format {
+            DataFormat::BGRA8 | DataFormat::RGBA8 => {}
+            _ => return None,
+        }
+
+        let size = tex.bytes_per_row(0) * tex.rows(0);
+        if tex.width == 0 || tex.height == 0 || tex.bytes.len() < size {
+            return None;
+        }
+
+        Some(Self {
+            width: tex.width as usize,
+            height: tex.height as usize,
+            format: tex.format,
+            premultiplied: tex.premultiplied,
+            texels: tex.bytes[..size].to_vec(),
+        })
+    }
+
+    /// Nearest sample with repeating texture coordinates, in straight alpha.
+    fn sample(&self, uv: Vec2) -> Vec4 {
+        let x = ((uv.x - uv.x.floor()) * self.width as f32) as usize;
+        let y = ((uv.y - uv.y.floor()) * self.height as f32) as usize;
+        let i = (x.min(self.width - 1) + y.min(self.height - 1) * self.width) * 4;
+        let t = &self.texels[i..(i + 4)];
+        let texel = match self.format {
+            DataFormat::BGRA8 => Vec4::new(t[2] as f32, t[1] as f32, t[0] as f32, t[3] as f32),
+            _ => Vec4::new(t[0] as f32, t[1] as f32, t[2] as f32, t[3] as f32),
+        };
+
+        let texel = texel / 255.0;
+        if self.premultiplied && texel.w > 0.0 {
+            (texel.truncate() / texel.w).extend(texel.w)
+        } else {
+            texel
+        }
+    }
+}
+
+/// A software rasterizer for machines without a usable GPU, such as CI runners. Renders the
+/// 3D scene with diffuse direct lighting into a CPU framebuffer that is read back through
+/// [Backend::capture] and [Backend::capture_hdr]. It favors simplicity over speed,
+/// 2D meshes, shadows and skyboxes are not rendered.
+pub struct CpuBackend {
+    framebuffer: Framebuffer,
+    shading: Shading,
+    grading: ColorGrading,
+    transfer: OutputTransfer,
+    material_debug: MaterialDebug,
+    clip_planes: Vec<Vec4>,
+    reveal_region: Option<RevealRegion>,
+    isolated_instance: Option<(usize, usize)>,
+    handedness: Handedness,
+
+    meshes: Vec<Option<CpuMesh>>,
+    instances: Vec<CpuInstances>,
+    /// Effects of the instances of every mesh, only [InstanceEffect::Unlit] is rendered
+    instance_effects: Vec<Vec<InstanceEffect>>,
+    materials: Vec<DeviceMaterial>,
+    textures: Vec<Option<CpuTexture>>,
+    skins: Vec<Vec<Mat4>>,
+
+    point_lights: Vec<PointLight>,
+    spot_lights: Vec<SpotLight>,
+    area_lights: Vec<AreaLight>,
+    directional_lights: Vec<DirectionalLight>,
+}
+
+impl FromWindowHandle for CpuBackend {
+    /// The window is not presented to, frames are only available through the capture API.
+    fn init<W: HasRawWindowHandle>(
+        _window: &W,
+        width: u32,
+        height: u32,
+        scale: f64,
+    ) -> Result<Box<Self>, Box<dyn Error>> {
+        let mut backend = Self::new(width, height);
+        backend.resize((width, height), scale);
+        Ok(Box::new(backend))
+    }
+}
+
+impl CpuBackend {
+    pub const SHADING: &'static str = "shading";
+
+    /// Creates a backend rendering frames of `width` by `height` pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            framebuffer: Framebuffer::new(width as usize, height as usize),
+            shading: Shading::default(),
+            grading: ColorGrading::default(),
+            transfer: OutputTransfer::default(),
+            material_debug: MaterialDebug::default(),
+            clip_planes: Vec::new(),
+            reveal_region: None,
+            isolated_instance: None,
+            handedness: Handedness::default(),
+
+            meshes: Vec::new(),
+            instances: Vec::new(),
+            instance_effects: Vec::new(),
+            materials: Vec::new(),
+            textures: Vec::new(),
+            skins: Vec::new(),
+
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+            area_lights: Vec::new(),
+            directional_lights: Vec::new(),
+        }
+    }
+
+    pub fn shading(&self) -> Shading {
+        self.shading
+    }
+
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
+
+    /// Whether `position` lies on the negative side of any clip plane.
+    fn is_clipped(&self, position: Vec3) -> bool {
+        self.clip_planes
+            .iter()
+            .any(|plane| plane.truncate().dot(position) + plane.w < 0.0)
+    }
+
+    /// Brightness of a surface at `position`, None if the reveal region cuts it away.
+    fn reveal_brightness(&self, position: Vec3) -> Option<f32> {
+        match self.reveal_region {
+            Some(region) if !region.contains(position) => region.outside,
+            _ => Some(1.0),
+        }
+    }
+
+    /// Irradiance arriving at `position` from all lights, without shadows. Negative lights can
+    /// remove light but, as in the wgpu lighting pass, never make it negative.
+    fn irradiance(&self, position: Vec3, normal: Vec3) -> Vec3 {
+        let mut irradiance = Vec3::ZERO;
+
+        for light in self.point_lights.iter() {
+            let l = light.position - position;
+            let distance2 = l.length_squared();
+            if distance2 > 0.0 {
+                let n_dot_l = normal.dot(l / distance2.sqrt()).max(0.0);
+                irradiance += light.radiance * n_dot_l / distance2;
+            }
+        }
+
+        for light in self.spot_lights.iter() {
+            let l = light.position - position;
+            let distance2 = l.length_squared();
+            if distance2 > 0.0 && light.cos_inner > light.cos_outer {
+                let l = l / distance2.sqrt();
+                let falloff = ((-l.dot(light.direction) - light.cos_outer)
+                    / (light.cos_inner - light.cos_outer))
+                    .max(0.0)
+                    .min(1.0);
+                let n_dot_l = normal.dot(l).max(0.0);
+                irradiance += light.radiance * n_dot_l * falloff / distance2;
+            }
+        }
+
+        for light in self.area_lights.iter() {
+            let l = light.position - position;
+            let distance2 = l.length_squared();
+            if distance2 > 0.0 {
+                let l = l / distance2.sqrt();
+                let n_dot_l = normal.dot(l).max(0.0);
+                let cos_light = light.normal.dot(-l).max(0.0);
+                // Solid angle of the light, bounded for points close to it
+                let solid_angle = (light.area * cos_light / distance2).min(2.0 * PI);
+                irradiance +=
+                    light.radiance * light.focus_factor(cos_light) * n_dot_l * solid_angle;
+            }
+        }
+
+        for light in self.directional_lights.iter() {
+            let n_dot_l = normal.dot(-light.direction).max(0.0);
+            irradiance += light.radiance * n_dot_l;
+        }
+
+        irradiance.max(Vec3::ZERO)
+    }
+
+    /// Radiance of a covered pixel, None for pixels cut out by the alpha of the diffuse map.
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        material: &DeviceMaterial,
+        tint: Vec4,
+        effect: InstanceEffect,
+        mode: RenderMode,
+    ) -> Option<Vec4> {
+        let mut albedo = Vec3::new(material.color[0], material.color[1], material.color[2]);
+        // Materials brighter than 1 are emitters, as in the other backends
+        if albedo.max_element() > 1.0 && mode == RenderMode::Default {
+            return Some(albedo.extend(1.0));
+        }
+
+        let texture = usize_of(material.diffuse_map)
+            .and_then(|i| self.textures.get(i))
+            .and_then(|t| t.as_ref());
+        if let Some(texture) = texture {
+            let texel = texture.sample(fragment.uv);
+            if texel.w < 0.5 {
+                return None;
+            }
+            albedo = texel.truncate();
+        }
+
+        albedo *= tint.truncate();
+        if let Some(gray) = self.material_debug.albedo {
+            albedo = Vec3::splat(gray);
+        }
+
+        let normal = fragment.normal.normalize_or_zero();
+        let color = match mode {
+            RenderMode::Normal => normal * 0.5 + Vec3::splat(0.5),
+            RenderMode::Albedo => albedo,
+            _ if effect == InstanceEffect::Unlit => albedo,
+            _ => albedo * fragment.irradiance * (1.0 / PI),
+        };
+
+        Some(color.extend(1.0))
+    }
+
+    /// Applies the color grading, matching the blit pass of the wgpu backend.
+    fn grade(&self, color: Vec3) -> Vec3 {
+        let mut color = color * self.grading.exposure.exp2();
+        color = match self.grading.tonemap {
+            Tonemap::None => color,
+            Tonemap::Reinhard => color / (Vec3::ONE + color),
+            Tonemap::Aces => {
+                let x = color;
+                ((x * (2.51 * x + Vec3::splat(0.03)))
+                    / (x * (2.43 * x + Vec3::splat(0.59)) + Vec3::splat(0.14)))
+                .max(Vec3::ZERO)
+                .min(Vec3::ONE)
+            }
+        };
+
+        let contrast = |c: f32| 0.18 * (c.max(0.0) / 0.18).powf(self.grading.contrast);
+        color = Vec3::new(contrast(color.x), contrast(color.y), contrast(color.z));
+        let luminance = color.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+        color = Vec3::splat(luminance) + (color - Vec3::splat(luminance)) * self.grading.saturation;
+        color.max(Vec3::ZERO).min(Vec3::ONE)
+    }
+
+    fn render_3d(&self, framebuffer: &mut Framebuffer, view: &CameraView3D, mode: RenderMode) {
+        let view_projection = view.get_matrix(self.handedness);
+        let default_material = DeviceMaterial {
+            color: [1.0; 4],
+            ..Default::default()
+        };
+
+        for (mesh_id, mesh) in self.meshes.iter().enumerate() {
+            let (mesh, instances) = match (mesh, self.instances.get(mesh_id)) {
+                (Some(mesh), Some(instances)) => (mesh, instances),
+                _ => continue,
+            };
+
+            let cull = self.handedness.raster_cull_mode(mesh.flags);
+            for (i, matrix) in instances.matrices.iter().enumerate() {
+                // Removed instances keep a zero matrix
+                if *matrix == Mat4::ZERO {
+                    continue;
+                }
+
+                if self
+                    .isolated_instance
+                    .map_or(false, |isolated| isolated != (mesh_id, i))
+                {
+                    continue;
+                }
+
+                let joints = instances
+                    .skin_ids
+                    .get(i)
+                    .and_then(|id| id.as_index())
+                    .and_then(|id| self.skins.get(id));
+                let skinned;
+                let vertices = match joints {
+                    Some(joints)
+                        if mesh.flags.contains(Mesh3dFlags::ALLOW_SKINNING)
+                            && mesh.skin_data.len() == mesh.vertices.len() =>
+                    {
+                        skinned = SkinnedMesh3D::apply(
+                            &mesh.vertices,
+                            &mesh.skin_data,
+                            &mesh.ranges,
+                            joints,
+                        );
+                        &skinned.vertices
+                    }
+                    _ => &mesh.vertices,
+                };
+
+                let tint = instances.tints.get(i).copied().unwrap_or(Vec4::ONE);
+                let effect = self
+                    .instance_effects
+                    .get(mesh_id)
+                    .and_then(|e| e.get(i))
+                    .copied()
+                    .unwrap_or_default();
+                let normal_matrix = matrix.inverse().transpose();
+                let shading = if mesh.flags.contains(Mesh3dFlags::FLAT_SHADING) {
+                    Shading::Flat
+                } else {
+                    self.shading
+                };
+                for triangle in vertices.chunks_exact(3) {
+                    let material = self
+                        .materials
+                        .get(triangle[0].mat_id as usize)
+                        .unwrap_or(&default_material);
+
+                    let mut clip = [ClipVertex {
+                        position: Vec4::ZERO,
+                        world: Vec3::ZERO,
+                        normal: Vec3::ZERO,
+                        uv: Vec2::ZERO,
+                        irradiance: Vec3::ZERO,
+                    }; 3];
+                    for (c, v) in clip.iter_mut().zip(triangle.iter()) {
+                        let world = *matrix * v.vertex.truncate().extend(1.0);
+                        c.position = view_projection * world;
+                        c.world = world.truncate();
+                        c.normal = (normal_matrix * v.normal.extend(0.0))
+                            .truncate()
+                            .normalize_or_zero();
+                        c.uv = v.uv;
+                    }
+
+                    match shading {
+                        Shading::Flat => {
+                            let center = (clip[0].world + clip[1].world + clip[2].world) / 3.0;
+                            let mut normal = (clip[1].world - clip[0].world)
+                                .cross(clip[2].world - clip[0].world)
+                                .normalize_or_zero();
+                            // Keep the geometric normal on the side of the vertex normals
+                            if normal.dot(clip[0].normal + clip[1].normal + clip[2].normal) < 0.0 {
+                                normal = -normal;
+                            }
+                            let irradiance = self.irradiance(center, normal);
+                            for c in clip.iter_mut() {
+                                c.normal = normal;
+                                c.irradiance = irradiance;
+                            }
+                        }
+                        Shading::Gouraud => {
+                            for c in clip.iter_mut() {
+                                c.irradiance = self.irradiance(c.world, c.normal);
+                            }
+                        }
+                    }
+
+                    framebuffer.draw_triangle(clip, cull, |fragment| {
+                        if self.is_clipped(fragment.world) {
+                            return None;
+                        }
+                        let brightness = self.reveal_brightness(fragment.world)?;
+                        self.shade(fragment, material, tint, effect, mode)
+                            .map(|c| (c.truncate() * brightness).extend(c.w))
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn usize_of(index: i32) -> Option<usize> {
+    if index >= 0 {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+impl Backend for CpuBackend {
+    /// 2D meshes are not rendered by this backend.
+    fn set_2d_mesh(&mut self, _id: usize, _data: MeshData2D<'_>) {}
+
+    fn set_2d_instances(
+        &mut self,
+        _mesh: usize,
+        _instances: InstancesData2D<'_>,
+    ) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    fn set_3d_mesh(&mut self, id: usize, data: MeshData3D<'_>) {
+        if id >= self.meshes.len() {
+            self.meshes.resize(id + 1, None);
+        }
+
+        self.meshes[id] = Some(CpuMesh {
+            vertices: data.vertices.to_vec(),
+            ranges: data.ranges.to_vec(),
+            skin_data: data.skin_data.to_vec(),
+            flags: data.flags,
+        });
+    }
+
+    fn get_3d_mesh(&self, id: usize) -> Option<MeshData3D<'_>> {
+        let mesh = self.meshes.get(id)?.as_ref()?;
+        let mut bounds = Aabb::empty();
+        mesh.vertices
+            .iter()
+            .for_each(|v| bounds.grow(v.vertex.truncate()));
+
+        Some(MeshData3D {
+            name: "",
+            bounds,
+            vertices: &mesh.vertices,
+            triangles: &[],
+            ranges: &mesh.ranges,
+            skin_data: &mesh.skin_data,
+            flags: mesh.flags,
+        })
+    }
+
+    fn unload_3d_meshes(&mut self, ids: &[usize]) {
+        for id in ids.iter().copied() {
+            if let Some(mesh) = self.meshes.get_mut(id) {
+                *mesh = None;
+            }
+            if let Some(instances) = self.instances.get_mut(id) {
+                *instances = CpuInstances::default();
+            }
+            if let Some(effects) = self.instance_effects.get_mut(id) {
+                effects.clear();
+            }
+        }
+    }
+
+    fn has_3d_mesh(&self, id: usize) -> bool {
+        matches!(self.meshes.get(id), Some(Some(_)))
+    }
+
+    fn instance_count(&self, mesh: usize) -> usize {
+        self.instances.get(mesh).map_or(0, |i| i.matrices.len())
+    }
+
+    fn set_3d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData3D<'_>,
+    ) -> Result<(), BackendError> {
+        if !self.meshes.get(mesh).map_or(false, |m| m.is_some()) {
+            return Err(BackendError::InvalidMeshId(mesh));
+        }
+
+        if mesh >= self.instances.len() {
+            self.instances.resize(mesh + 1, CpuInstances::default());
+        }
+
+        self.instances[mesh] = CpuInstances {
+            matrices: instances.matrices.to_vec(),
+            skin_ids: instances.skin_ids.to_vec(),
+            tints: instances.tints.to_vec(),
+        };
+        Ok(())
+    }
+
+    fn set_materials(&mut self, materials: &[DeviceMaterial], _changed: &BitSlice) {
+        self.materials = materials.to_vec();
+    }
+
+    fn set_material(&mut self, index: usize, material: DeviceMaterial) {
+        if let Some(m) = self.materials.get_mut(index) {
+            *m = material;
+        }
+    }
+
+    /// Only the first level of 8-bit textures is kept, other formats sample as white.
+    fn has_texture(&self, id: usize) -> bool {
+        matches!(self.textures.get(id), Some(Some(_)))
+    }
+
+    fn set_textures(
+        &mut self,
+        textures: &[TextureData<'_>],
+        _changed: &BitSlice,
+    ) -> Result<(), BackendError> {
+        self.textures = textures.iter().map(CpuTexture::new).collect();
+        Ok(())
+    }
+
+    fn synchronize(&mut self) {}
+
+    fn clear_scene(&mut self) {
+        self.meshes.clear();
+        self.instances.clear();
+        self.instance_effects.clear();
+        self.materials.clear();
+        self.textures.clear();
+        self.skins.clear();
+        self.point_lights.clear();
+        self.spot_lights.clear();
+        self.area_lights.clear();
+        self.directional_lights.clear();
+    }
+
+    fn render(
+        &mut self,
+        _view_2d: CameraView2D,
+        view_3d: CameraView3D,
+        mode: RenderMode,
+    ) -> Result<(), BackendError> {
+        let mut framebuffer = std::mem::take(&mut self.framebuffer);
+        framebuffer.clear();
+        self.render_3d(&mut framebuffer, &view_3d, mode);
+        self.framebuffer = framebuffer;
+        Ok(())
+    }
+
+    fn resize(&mut self, window_size: (u32, u32), scale_factor: f64) {
+        let width = ((window_size.0 as f64 * scale_factor) as usize).max(1);
+        let height = ((window_size.1 as f64 * scale_factor) as usize).max(1);
+        self.framebuffer = Framebuffer::new(width, height);
+    }
+
+    fn set_point_lights(&mut self, lights: &[PointLight], _changed: &BitSlice) {
+        self.point_lights = lights.to_vec();
+    }
+
+    fn set_spot_lights(&mut self, lights: &[SpotLight], _changed: &BitSlice) {
+        self.spot_lights = lights.to_vec();
+    }
+
+    fn set_area_lights(&mut self, lights: &[AreaLight], _changed: &BitSlice) {
+        self.area_lights = lights.to_vec();
+    }
+
+    fn set_directional_lights(&mut self, lights: &[DirectionalLight], _changed: &BitSlice) {
+        self.directional_lights = lights.to_vec();
+    }
+
+    /// Skyboxes are not sampled, pixels that are not covered by geometry stay black.
+    fn set_skybox(&mut self, _skybox: SkyboxData<'_>) {}
+
+    fn set_skins(&mut self, skins: &[SkinData<'_>], _changed: &BitSlice) {
+        self.skins = skins.iter().map(|s| s.joint_matrices.to_vec()).collect();
+    }
+
+    fn capture(&self) -> Option<CapturedFrame> {
+        let encode = |c: f32| (self.transfer.encode(c) * 255.0 + 0.5) as u8;
+
+        let mut pixels = Vec::with_capacity(self.framebuffer.color.len() * 4);
+        for color in self.framebuffer.color.iter() {
+            let c = self.grade(color.truncate());
+            pixels.extend_from_slice(&[encode(c.x), encode(c.y), encode(c.z), 255]);
+        }
+
+        Some(CapturedFrame {
+            width: self.framebuffer.width as u32,
+            height: self.framebuffer.height as u32,
+            pixels,
+        })
+    }
+
+    fn capture_hdr(&self) -> Option<CapturedHdrFrame> {
+        Some(CapturedHdrFrame {
+            width: self.framebuffer.width as u32,
+            height: self.framebuffer.height as u32,
+            pixels: self
+                .framebuffer
+                .color
+                .iter()
+                .flat_map(|c| [c.x, c.y, c.z, 1.0])
+                .collect(),
+        })
+    }
+
+    fn get_settings(&self) -> Vec<Setting> {
+        vec![Setting::new(
+            Self::SHADING,
+            SettingValue::Int(self.shading as i32),
+            Some(0.0..1.0),
+        )
+        .with_labels(&Shading::NAMES)]
+    }
+
+    fn set_setting(&mut self, setting: Setting) {
+        if let (Self::SHADING, Some(value)) = (setting.key(), setting.as_float()) {
+            self.shading = Shading::from_i32(value as i32);
+        }
+    }
+
+    fn set_color_grading(&mut self, grading: ColorGrading) {
+        self.grading = grading.clamped();
+    }
+
+    fn set_output_transfer(&mut self, transfer: OutputTransfer) {
+        self.transfer = transfer;
+    }
+
+    fn set_material_debug(&mut self, debug: MaterialDebug) {
+        self.material_debug = debug;
+    }
+
+    fn set_clip_planes(&mut self, planes: &[Vec4]) {
+        self.clip_planes = planes.iter().take(MAX_CLIP_PLANES).copied().collect();
+    }
+
+    fn set_reveal_region(&mut self, region: Option<RevealRegion>) {
+        self.reveal_region = region;
+    }
+
+    fn set_isolate_instance(&mut self, instance: Option<(usize, usize)>) {
+        self.isolated_instance = instance;
+    }
+
+    /// Toon and X-ray instances are shaded as standard ones.
+    fn set_3d_instance_effect(&mut self, mesh: usize, index: usize, effect: InstanceEffect) {
+        if mesh >= self.instance_effects.len() {
+            self.instance_effects.resize(mesh + 1, Vec::new());
+        }
+        let effects = &mut self.instance_effects[mesh];
+        if index >= effects.len() {
+            effects.resize(index + 1, InstanceEffect::Standard);
+        }
+        effects[index] = effect;
+    }
+
+    fn set_handedness(&mut self, handedness: Handedness) {
+        self.handedness = handedness;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets a single instance of `mesh`.
+    fn set_instance(
+        backend: &mut CpuBackend,
+        mesh: usize,
+        matrix: Mat4,
+        tint: Vec4,
+    ) -> Result<(), BackendError> {
+        backend.set_3d_instances(
+            mesh,
+            InstancesData3D {
+                matrices: &[matrix],
+                skin_ids: &[SkinID::INVALID],
+                flags: &[InstanceFlags3D::all()],
+                user_data: &[0],
+                tints: &[tint],
+                flipbook_times: &[0.0],
+                wind_weights: &[0.0],
+                local_aabb: Aabb::empty(),
+            },
+        )
+    }
+
+    fn set_material(backend: &mut CpuBackend, color: [f32; 4]) {
+        let material = DeviceMaterial {
+            color,
+            ..Default::default()
+        };
+        let changed: BitVec = BitVec::repeat(true, 1);
+        backend.set_materials(&[material], &changed);
+    }
+
+    /// Lights the scene head-on, seen from the view of [triangle_scene].
+    fn set_directional_light(backend: &mut CpuBackend, radiance: Vec3) {
+        let light = DirectionalLight::new(-Vec3::Z, radiance);
+        let changed: BitVec = BitVec::repeat(true, 1);
+        backend.set_directional_lights(&[light], &changed);
+    }
+
+    /// A backend with a front-facing triangle as mesh 0, spanning -1..1 in its XY plane, and a
+    /// view from the origin looking down -Z.
+    fn triangle_scene(size: usize) -> (CpuBackend, CameraView3D) {
+        let mut backend = CpuBackend::new(size, size);
+        let vertex = |x: f32, y: f32| Vertex3D {
+            vertex: Vec4::new(x, y, 0.0, 1.0),
+            normal: Vec3::Z,
+            ..Default::default()
+        };
+        let vertices = [vertex(-1.0, -1.0), vertex(1.0, -1.0), vertex(0.0, 1.0)];
+
+        backend.set_3d_mesh(
+            0,
+            MeshData3D {
+                name: "triangle",
+                bounds: Aabb::empty(),
+                vertices: &vertices,
+                triangles: &[],
+                ranges: &[],
+                skin_data: &[],
+                flags: Mesh3dFlags::default(),
+            },
+        );
+
+        let view = Camera3D::new()
+            .with_direction([0.0, 0.0, -1.0])
+            .get_view(size as u32, size as u32);
+        (backend, view)
+    }
+
+    #[test]
+    fn renders_lit_triangle() {
+        let (mut backend, view) = triangle_scene(32);
+        let matrix = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
+        set_instance(&mut backend, 0, matrix, Vec4::new(1.0, 0.5, 1.0, 1.0)).unwrap();
+        set_material(&mut backend, [1.0; 4]);
+        // Irradiance of PI cancels the 1/PI of the diffuse BRDF
+        set_directional_light(&mut backend, Vec3::splat(PI));
+
+        backend
+            .render(CameraView2D::default(), view, RenderMode::Default)
+            .unwrap();
+
+        let frame = backend.capture_hdr().unwrap();
+        let pixel = |x: usize, y: usize| &frame.pixels[(x + y * 32) * 4..(x + y * 32) * 4 + 3];
+        let center = pixel(16, 16);
+        assert!((center[0] - 1.0).abs() < 1e-3);
+        assert!((center[1] - 0.5).abs() < 1e-3);
+        assert_eq!(pixel(0, 0), &[0.0, 0.0, 0.0]);
+
+        // Seen from behind, the default back-face culling removes the triangle
+        assert_eq!(Mesh3dFlags::default().cull_mode(), CullMode::Back);
+        let view = Camera3D::new()
+            .with_position([0.0, 0.0, -6.0])
+            .with_direction([0.0, 0.0, 1.0])
+            .get_view(32, 32);
+        backend
+            .render(CameraView2D::default(), view, RenderMode::Default)
+            .unwrap();
+        let frame = backend.capture_hdr().unwrap();
+        assert!(frame
+            .pixels
+            .chunks_exact(4)
+            .all(|p| p[..3] == [0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn negative_lights_do_not_shade_below_black() {
+        let (mut backend, view) = triangle_scene(16);
+        let matrix = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
+        set_instance(&mut backend, 0, matrix, Vec4::ONE).unwrap();
+        set_material(&mut backend, [1.0; 4]);
+        set_directional_light(&mut backend, Vec3::splat(-PI));
+
+        backend
+            .render(CameraView2D::default(), view, RenderMode::Default)
+            .unwrap();
+        let frame = backend.capture_hdr().unwrap();
+        let i = (8 + 8 * 16) * 4;
+        assert_eq!(&frame.pixels[i..i + 3], &[0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mirrors_left_handed_scenes() {
+        // Right of the center in a right-handed scene
+        let (mut backend, view) = triangle_scene(32);
+        let matrix = Mat4::from_translation(Vec3::new(1.0, 0.0, -3.0));
+        set_instance(&mut backend, 0, matrix, Vec4::ONE).unwrap();
+        set_material(&mut backend, [1.0; 4]);
+        set_directional_light(&mut backend, Vec3::splat(PI));
+
+        let red = |backend: &CpuBackend, x: usize| {
+            backend.capture_hdr().unwrap().pixels[(x + 16 * 32) * 4]
+        };
+
+        backend
+            .render(CameraView2D::default(), view, RenderMode::Default)
+            .unwrap();
+        assert!(red(&backend, 26) > 0.5);
+        assert_eq!(red(&backend, 5), 0.0);
+
+        // Facing -Z in a left-handed scene, +X is to the left
+        backend.set_handedness(Handedness::Left);
+        backend
+            .render(CameraView2D::default(), view, RenderMode::Default)
+            .unwrap();
+        assert_eq!(red(&backend, 26), 0.0);
+        assert!(red(&backend, 5) > 0.5);
+    }
+
+    #[test]
+    fn unlit_instances_ignore_lights() {
+        let (mut backend, view) = triangle_scene(16);
+        backend.set_3d_instance_effect(0, 0, InstanceEffect::Unlit);
+        let matrix = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
+        set_instance(&mut backend, 0, matrix, Vec4::ONE).unwrap();
+        set_material(&mut backend, [0.25, 0.5, 0.75, 1.0]);
+        // Lit surfaces receive twice their albedo
+        set_directional_light(&mut backend, Vec3::splat(2.0 * PI));
+
+        let center = |backend: &mut CpuBackend| {
+            backend
+                .render(CameraView2D::default(), view, RenderMode::Default)
+                .unwrap();
+            let frame = backend.capture_hdr().unwrap();
+            let i = (8 + 8 * 16) * 4;
+            [frame.pixels[i], frame.pixels[i + 1], frame.pixels[i + 2]]
+        };
+
+        assert_eq!(center(&mut backend), [0.25, 0.5, 0.75]);
+
+        backend.set_3d_instance_effect(0, 0, InstanceEffect::Standard);
+        let lit = center(&mut backend);
+        for (lit, albedo) in lit.iter().zip([0.25, 0.5, 0.75].iter()) {
+            assert!((lit - 2.0 * albedo).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn reveal_region_masks_outside_pixels() {
+        let (mut backend, view) = triangle_scene(16);
+        let matrix = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
+        set_instance(&mut backend, 0, matrix, Vec4::ONE).unwrap();
+        set_material(&mut backend, [0.25, 0.5, 0.75, 1.0]);
+        set_directional_light(&mut backend, Vec3::splat(PI));
+
+        let assert_center = |backend: &mut CpuBackend, expected: [f32; 3]| {
+            backend
+                .render(CameraView2D::default(), view, RenderMode::Default)
+                .unwrap();
+            let frame = backend.capture_hdr().unwrap();
+            let i = (8 + 8 * 16) * 4;
+            for (c, expected) in frame.pixels[i..i + 3].iter().zip(expected.iter()) {
+                assert!((c - expected).abs() < 1e-3, "{} != {}", c, expected);
+            }
+        };
+
+        // The center pixel sees the triangle close to (0, 0, -3)
+        backend.set_reveal_region(Some(RevealRegion::sphere([2.0, 0.0, -3.0], 1.0)));
+        assert_center(&mut backend, [0.0, 0.0, 0.0]);
+        backend.set_reveal_region(Some(
+            RevealRegion::cuboid([2.0, 0.0, -3.0], [3.0, 1.0, -2.0]).with_dimming(0.5),
+        ));
+        assert_center(&mut backend, [0.125, 0.25, 0.375]);
+        backend.set_reveal_region(Some(RevealRegion::sphere([0.0, 0.0, -3.0], 1.0)));
+        assert_center(&mut backend, [0.25, 0.5, 0.75]);
+        backend.set_reveal_region(None);
+        assert_center(&mut backend, [0.25, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn rejects_instances_of_unknown_meshes() {
+        let mut backend = CpuBackend::new(8, 8);
+        let result = set_instance(&mut backend, 3, Mat4::IDENTITY, Vec4::ONE);
+        assert_eq!(result, Err(BackendError::InvalidMeshId(3)));
+        assert_eq!(backend.instance_count(3), 0);
+    }
+
+    #[test]
+    fn restores_exported_settings() {
+        let mut backend = CpuBackend::new(8, 8);
+        let mut gouraud = backend.get_settings()[0].clone();
+        gouraud.set(SettingValue::Int(Shading::Gouraud as i32));
+        backend.set_setting(gouraud);
+        let snapshot = backend.export_settings();
+        assert_eq!(
+            snapshot.get(CpuBackend::SHADING),
+            Some(&SettingValue::Int(Shading::Gouraud as i32))
+        );
+
+        let mut flat = snapshot.clone();
+        flat.set(CpuBackend::SHADING, SettingValue::Int(Shading::Flat as i32));
+        backend.apply_settings(&flat);
+        assert_eq!(backend.shading, Shading::Flat);
+
+        backend.apply_settings(&snapshot);
+        assert_eq!(backend.shading, Shading::Gouraud);
+        assert_eq!(backend.export_settings(), snapshot);
+    }
+
+    #[test]
+    fn samples_premultiplied_textures_in_straight_alpha() {
+        let bytes = [64, 32, 0, 128];
+        let texture = |premultiplied| {
+            CpuTexture::new(&TextureData {
+                width: 1,
+                height: 1,
+                mip_levels: 1,
+                bytes: &bytes,
+                format: DataFormat::RGBA8,
+                filter: TextureFilter::Linear,
+                premultiplied,
+            })
+            .unwrap()
+        };
+
+        let straight = texture(false).sample(Vec2::ZERO);
+        assert!((straight.x - 64.0 / 255.0).abs() < 1e-5);
+        let texel = texture(true).sample(Vec2::ZERO);
+        assert!((texel.x - 0.5).abs() < 1e-5);
+        assert!((texel.y - 0.25).abs() < 1e-5);
+        assert!((texel.w - 128.0 / 255.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,174 @@
+use rfw::prelude::{CullMode, Vec2, Vec3, Vec4};
+
+/// Transformed vertex of a triangle, its attributes are interpolated perspective-correctly.
+#[derive(Debug, Copy, Clone)]
+pub struct ClipVertex {
+    /// Position in clip space
+    pub position: Vec4,
+    pub world: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+    /// Irradiance at this vertex, Gouraud shading interpolates it over the triangle
+    pub irradiance: Vec3,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position + (other.position - self.position) * t,
+            world: self.world + (other.world - self.world) * t,
+            normal: self.normal + (other.normal - self.normal) * t,
+            uv: self.uv + (other.uv - self.uv) * t,
+            irradiance: self.irradiance + (other.irradiance - self.irradiance) * t,
+        }
+    }
+}
+
+/// Interpolated attributes of a pixel covered by a triangle.
+#[derive(Debug, Copy, Clone)]
+pub struct Fragment {
+    pub world: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+    pub irradiance: Vec3,
+}
+
+/// Color and depth targets of the rasterizer, rows start at the top of the image.
+#[derive(Debug, Default, Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub color: Vec<Vec4>,
+    pub depth: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            color: vec![Vec4::ZERO; width * height],
+            depth: vec![f32::INFINITY; width * height],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.color.iter_mut().for_each(|c| *c = Vec4::ZERO);
+        self.depth.iter_mut().for_each(|d| *d = f32::INFINITY);
+    }
+
+    /// Clips a triangle against the near plane and fills it, `shade` is called for every
+    /// pixel that passes the depth test. Pixels for which it returns None are discarded
+    /// and leave the depth buffer untouched.
+    pub fn draw_triangle<F>(&mut self, vertices: [ClipVertex; 3], cull: CullMode, mut shade: F)
+    where
+        F: FnMut(&Fragment) -> Option<Vec4>,
+    {
+        // Depth is in [-w, w] with OpenGL style projections, everything in front of the
+        // near plane has z + w >= 0
+        let distance = |v: &ClipVertex| v.position.z + v.position.w;
+
+        let mut polygon: Vec<ClipVertex> = Vec::with_capacity(4);
+        for (i, current) in vertices.iter().enumerate() {
+            let next = &vertices[(i + 1) % 3];
+            let (d0, d1) = (distance(current), distance(next));
+
+            if d0 >= 0.0 {
+                polygon.push(*current);
+            }
+            if (d0 >= 0.0) != (d1 >= 0.0) {
+                polygon.push(current.lerp(next, d0 / (d0 - d1)));
+            }
+        }
+
+        if polygon.len() < 3 {
+            return;
+        }
+
+        for pair in polygon[1..].windows(2) {
+            self.fill([polygon[0], pair[0], pair[1]], cull, &mut shade);
+        }
+    }
+
+    fn fill<F>(&mut self, vertices: [ClipVertex; 3], cull: CullMode, shade: &mut F)
+    where
+        F: FnMut(&Fragment) -> Option<Vec4>,
+    {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let mut screen = [Vec3::ZERO; 3];
+        for (s, v) in screen.iter_mut().zip(vertices.iter()) {
+            let ndc = v.position.truncate() / v.position.w;
+            *s = Vec3::new(
+                (ndc.x * 0.5 + 0.5) * width,
+                (0.5 - ndc.y * 0.5) * height,
+                ndc.z * 0.5 + 0.5,
+            );
+        }
+
+        let edge =
+            |a: Vec3, b: Vec3, p: Vec2| (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+        let area = edge(screen[0], screen[1], screen[2].truncate());
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+
+        // Counter-clockwise triangles in normalized device coordinates are clockwise on
+        // screen, as its y axis points down
+        let front_facing = area < 0.0;
+        match cull {
+            CullMode::Back if !front_facing => return,
+            CullMode::Front if front_facing => return,
+            _ => {}
+        }
+
+        let min = screen[0].min(screen[1]).min(screen[2]);
+        let max = screen[0].max(screen[1]).max(screen[2]);
+        let x_range = (min.x.floor().max(0.0) as usize)..(max.x.ceil().min(width) as usize);
+        let y_range = (min.y.floor().max(0.0) as usize)..(max.y.ceil().min(height) as usize);
+
+        for y in y_range {
+            for x in x_range.clone() {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let b = Vec3::new(
+                    edge(screen[1], screen[2], p),
+                    edge(screen[2], screen[0], p),
+                    edge(screen[0], screen[1], p),
+                ) / area;
+                if b.x < 0.0 || b.y < 0.0 || b.z < 0.0 {
+                    continue;
+                }
+
+                let depth = b.x * screen[0].z + b.y * screen[1].z + b.z * screen[2].z;
+                let index = x + y * self.width;
+                if !(0.0..=1.0).contains(&depth) || depth >= self.depth[index] {
+                    continue;
+                }
+
+                // Screen-space weights are divided by w to interpolate perspective-correctly
+                let w = b / Vec3::new(
+                    vertices[0].position.w,
+                    vertices[1].position.w,
+                    vertices[2].position.w,
+                );
+                let w = w / (w.x + w.y + w.z);
+                let fragment = Fragment {
+                    world: vertices[0].world * w.x
+                        + vertices[1].world * w.y
+                        + vertices[2].world * w.z,
+                    normal: vertices[0].normal * w.x
+                        + vertices[1].normal * w.y
+                        + vertices[2].normal * w.z,
+                    uv: vertices[0].uv * w.x + vertices[1].uv * w.y + vertices[2].uv * w.z,
+                    irradiance: vertices[0].irradiance * w.x
+                        + vertices[1].irradiance * w.y
+                        + vertices[2].irradiance * w.z,
+                };
+
+                if let Some(color) = shade(&fragment) {
+                    self.color[index] = color;
+                    self.depth[index] = depth;
+                }
+            }
+        }
+    }
+}
@@ -3,6 +3,9 @@ use rfw::prelude::*;
 #[allow(dead_code, non_snake_case, improper_ctypes, non_camel_case_types)]
 mod ffi;
 
+/// Substituted for textures in formats the metal renderer cannot upload.
+static PLACEHOLDER_TEXEL: [u8; 4] = [255; 4];
+
 #[derive(Default)]
 #[repr(C)]
 pub struct CameraUniform {
@@ -15,6 +18,20 @@ pub struct CameraUniform {
 
 pub struct MetalBackend {
     instance: *mut std::ffi::c_void,
+    /// Set while the window has a zero size, the drawable then keeps its last valid size
+    minimized: bool,
+    /// Meshes that are set, instances of other meshes are rejected
+    meshes_2d: BitVec,
+    meshes_3d: BitVec,
+}
+
+impl MetalBackend {
+    fn set_mesh_flag(meshes: &mut BitVec, id: usize, set: bool) {
+        if id >= meshes.len() {
+            meshes.resize(id + 1, false);
+        }
+        meshes.set(id, set);
+    }
 }
 
 impl FromWindowHandle for MetalBackend {
@@ -48,7 +65,12 @@ impl FromWindowHandle for MetalBackend {
         }
 
         if !instance.is_null() {
-            Ok(Box::new(Self { instance }))
+            Ok(Box::new(Self {
+                instance,
+                minimized: false,
+                meshes_2d: BitVec::new(),
+                meshes_3d: BitVec::new(),
+            }))
         } else {
             panic!("Could not initialize Metal renderer.");
         }
@@ -57,6 +79,7 @@ impl FromWindowHandle for MetalBackend {
 
 impl Backend for MetalBackend {
     fn set_2d_mesh(&mut self, id: usize, data: MeshData2D<'_>) {
+        Self::set_mesh_flag(&mut self.meshes_2d, id, true);
         unsafe {
             ffi::set_2d_mesh(
                 self.instance,
@@ -70,7 +93,15 @@ impl Backend for MetalBackend {
         }
     }
 
-    fn set_2d_instances(&mut self, mesh: usize, instances: InstancesData2D<'_>) {
+    fn set_2d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData2D<'_>,
+    ) -> Result<(), BackendError> {
+        if !self.meshes_2d.get(mesh).map(|b| *b).unwrap_or(false) {
+            return Err(BackendError::InvalidMeshId(mesh));
+        }
+
         unsafe {
             ffi::set_2d_instances(
                 self.instance,
@@ -81,9 +112,11 @@ impl Backend for MetalBackend {
                 },
             )
         }
+        Ok(())
     }
 
     fn set_3d_mesh(&mut self, id: usize, data: MeshData3D<'_>) {
+        Self::set_mesh_flag(&mut self.meshes_3d, id, true);
         unsafe {
             let mut bounds = ffi::Aabb::default();
             std::ptr::write(
@@ -115,13 +148,25 @@ impl Backend for MetalBackend {
     }
 
     fn unload_3d_meshes(&mut self, ids: &[usize]) {
+        for id in ids.iter().filter(|id| **id < self.meshes_3d.len()) {
+            self.meshes_3d.set(*id, false);
+        }
+
         unsafe {
             let ids = ids.iter().copied().map(|i| i as u32).collect::<Vec<_>>();
             ffi::unload_3d_meshes(self.instance, ids.as_ptr(), ids.len() as _);
         }
     }
 
-    fn set_3d_instances(&mut self, mesh: usize, instances: InstancesData3D<'_>) {
+    fn set_3d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData3D<'_>,
+    ) -> Result<(), BackendError> {
+        if !self.meshes_3d.get(mesh).map(|b| *b).unwrap_or(false) {
+            return Err(BackendError::InvalidMeshId(mesh));
+        }
+
         unsafe {
             let mut bounds = ffi::Aabb::default();
             std::ptr::write(
@@ -147,6 +192,7 @@ impl Backend for MetalBackend {
                 },
             );
         }
+        Ok(())
     }
 
     fn set_materials(&mut self, materials: &[DeviceMaterial], _changed: &BitSlice) {
@@ -159,16 +205,32 @@ impl Backend for MetalBackend {
         }
     }
 
-    fn set_textures(&mut self, textures: &[TextureData<'_>], changed: &BitSlice) {
+    fn set_textures(
+        &mut self,
+        textures: &[TextureData<'_>],
+        changed: &BitSlice,
+    ) -> Result<(), BackendError> {
         let textures = textures
             .iter()
-            .map(|t| ffi::TextureData {
-                width: t.width,
-                height: t.height,
-                mip_levels: t.mip_levels,
-                bytes: t.bytes.as_ptr(),
-                format: unsafe {
-                    std::ptr::read(&t.format as *const DataFormat as *const ffi::DataFormat)
+            .map(|t| match t.format {
+                DataFormat::BGRA8 | DataFormat::RGBA8 => ffi::TextureData {
+                    width: t.width,
+                    height: t.height,
+                    mip_levels: t.mip_levels,
+                    bytes: t.bytes.as_ptr(),
+                    format: if t.format == DataFormat::BGRA8 {
+                        ffi::DataFormat::BGRA8
+                    } else {
+                        ffi::DataFormat::RGBA8
+                    },
+                },
+                // Block compressed and HDR textures are not supported by this backend yet
+                _ => ffi::TextureData {
+                    width: 1,
+                    height: 1,
+                    mip_levels: 1,
+                    bytes: PLACEHOLDER_TEXEL.as_ptr(),
+                    format: ffi::DataFormat::BGRA8,
                 },
             })
             .collect::<Vec<ffi::TextureData>>();
@@ -186,6 +248,7 @@ impl Backend for MetalBackend {
                 changed.as_ptr(),
             );
         }
+        Ok(())
     }
 
     fn synchronize(&mut self) {
@@ -194,7 +257,16 @@ impl Backend for MetalBackend {
         }
     }
 
-    fn render(&mut self, camera_2d: CameraView2D, camera: CameraView3D, _mode: RenderMode) {
+    fn render(
+        &mut self,
+        camera_2d: CameraView2D,
+        camera: CameraView3D,
+        _mode: RenderMode,
+    ) -> Result<(), BackendError> {
+        if self.minimized {
+            return Ok(());
+        }
+
         unsafe {
             ffi::render(
                 self.instance,
@@ -239,9 +311,15 @@ impl Backend for MetalBackend {
                 },
             );
         }
+        Ok(())
     }
 
     fn resize(&mut self, window_size: (u32, u32), scale_factor: f64) {
+        self.minimized = window_size.0 == 0 || window_size.1 == 0;
+        if self.minimized {
+            return;
+        }
+
         unsafe {
             ffi::resize(self.instance, window_size.0, window_size.1, scale_factor);
         }
@@ -255,7 +333,7 @@ impl Backend for MetalBackend {
 
     fn set_directional_lights(&mut self, _lights: &[DirectionalLight], _changed: &BitSlice) {}
 
-    fn set_skybox(&mut self, _skybox: TextureData<'_>) {}
+    fn set_skybox(&mut self, _skybox: SkyboxData<'_>) {}
 
     fn set_skins(&mut self, _skins: &[SkinData<'_>], _changed: &BitSlice) {}
 }
@@ -0,0 +1,8 @@
+#version 450
+
+layout(location = 0) in vec2 UV;
+layout(location = 0) out vec4 OutColor;
+
+void main() {
+    OutColor = vec4(0.0);
+}
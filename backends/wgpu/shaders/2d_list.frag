@@ -6,18 +6,27 @@ layout(location = 2) in vec4 Color;
 
 layout(set = 0, binding = 2) uniform sampler Sampler;
 layout(set = 1, binding = 0) uniform texture2D textures[128];
+layout(set = 1, binding = 1) uniform TextureFlags {
+    // One bit per texture, set when its color is premultiplied by its alpha
+    uvec4 premultiplied;
+};
 
 layout(location = 0) out vec4 C;
 
 void main() {
-    vec4 color = Color;
+    vec4 color = vec4(Color.rgb * Color.a, Color.a);
     if (TexID > 0) {
-        color = color * texture(sampler2D(textures[TexID], Sampler), UvTex.xy).rgba;
+        vec4 texel = texture(sampler2D(textures[TexID], Sampler), UvTex.xy).rgba;
+        if ((premultiplied[TexID / 32] & (1u << (TexID % 32))) == 0) {
+            texel.rgb *= texel.a;
+        }
+        color = color * texel;
     }
 
     if (color.a <= 0.0) {
         discard;
     }
 
+    // Blended as premultiplied color
     C = color;
 }
\ No newline at end of file
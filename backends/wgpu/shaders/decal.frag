@@ -0,0 +1,66 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "material.glsl"
+
+layout(location = 0) in flat mat4 WorldToDecal;
+layout(location = 4) in flat mat3 DecalAxes;
+layout(location = 7) in flat uint MID;
+layout(location = 8) in flat float DecalOpacity;
+
+layout(std430, set = 0, binding = 1) buffer readonly Materials { Material materials[]; };
+layout(set = 0, binding = 2) uniform sampler Sampler;
+layout(set = 0, binding = 5) uniform sampler NearestSampler;
+
+layout(set = 1, binding = 0) uniform texture2D textures[128];
+
+layout(set = 2, binding = 0) uniform texture2D WorldPos;
+
+layout(location = 0) out vec4 Albedo;
+layout(location = 1) out vec4 Normal;
+
+#define SAMPLE_MAP(flags, map, uv) (HAS_NEAREST_FILTER(flags) ? texture(sampler2D(textures[map], NearestSampler), uv) : texture(sampler2D(textures[map], Sampler), uv))
+
+void main() {
+    const vec4 world_pos = texelFetch(sampler2D(WorldPos, NearestSampler), ivec2(gl_FragCoord.xy), 0);
+    // The depth in w is cleared to the far plane where no geometry was drawn
+    if (world_pos.w >= 1.0) {
+        discard;
+    }
+
+    const vec3 local = (WorldToDecal * vec4(world_pos.xyz, 1.0)).xyz;
+    if (any(greaterThan(abs(local), vec3(0.5)))) {
+        discard;
+    }
+
+    // Projected along the Z axis of the decal, v runs downwards like texture rows
+    const vec2 uv = vec2(local.x + 0.5, 0.5 - local.y);
+    const uint flags = materials[MID].flags;
+
+    vec4 color = vec4(materials[MID].color.xyz, 1.0);
+    if (HAS_DIFFUSE_MAP(flags)) {
+        vec4 texel = SAMPLE_MAP(flags, materials[MID].diffuse_map, uv);
+        // Decals are blended with straight alpha
+        if (HAS_PREMULTIPLIED_ALPHA(flags)) {
+            texel.rgb /= max(texel.a, 1e-4);
+        }
+        color *= texel;
+    }
+
+    const float alpha = color.a * DecalOpacity;
+    if (alpha <= 0.0) {
+        discard;
+    }
+
+    // Decals without a normal map keep the normals of the surface underneath
+    vec3 normal = DecalAxes[2];
+    float normal_alpha = 0.0;
+    if (HAS_NORMAL_MAP(flags)) {
+        const vec3 n = (SAMPLE_MAP(flags, materials[MID].normal_map, uv).rgb - 0.5) * 2.0;
+        normal = normalize(DecalAxes * n);
+        normal_alpha = alpha;
+    }
+
+    Albedo = vec4(color.rgb, alpha);
+    Normal = vec4(normal, normal_alpha);
+}
@@ -0,0 +1,48 @@
+#version 450
+
+layout(location = 0) in vec4 WorldToDecal0;
+layout(location = 1) in vec4 WorldToDecal1;
+layout(location = 2) in vec4 WorldToDecal2;
+layout(location = 3) in vec4 WorldToDecal3;
+layout(location = 4) in vec4 DecalToWorld0;
+layout(location = 5) in vec4 DecalToWorld1;
+layout(location = 6) in vec4 DecalToWorld2;
+layout(location = 7) in vec4 DecalToWorld3;
+layout(location = 8) in uint Material;
+layout(location = 9) in float Opacity;
+
+layout(set = 0, binding = 0) uniform Locals {
+    mat4 View;
+    mat4 Proj;
+};
+
+layout(location = 0) out flat mat4 WorldToDecal;
+layout(location = 4) out flat mat3 DecalAxes;
+layout(location = 7) out flat uint MID;
+layout(location = 8) out flat float DecalOpacity;
+
+// Unit cube centered at the origin, counter-clockwise when seen from the outside
+const vec3 CUBE[36] = vec3[](
+    vec3(-0.5, -0.5, 0.5), vec3(0.5, -0.5, 0.5), vec3(0.5, 0.5, 0.5),
+    vec3(-0.5, -0.5, 0.5), vec3(0.5, 0.5, 0.5), vec3(-0.5, 0.5, 0.5),
+    vec3(0.5, -0.5, -0.5), vec3(-0.5, -0.5, -0.5), vec3(-0.5, 0.5, -0.5),
+    vec3(0.5, -0.5, -0.5), vec3(-0.5, 0.5, -0.5), vec3(0.5, 0.5, -0.5),
+    vec3(0.5, -0.5, 0.5), vec3(0.5, -0.5, -0.5), vec3(0.5, 0.5, -0.5),
+    vec3(0.5, -0.5, 0.5), vec3(0.5, 0.5, -0.5), vec3(0.5, 0.5, 0.5),
+    vec3(-0.5, -0.5, -0.5), vec3(-0.5, -0.5, 0.5), vec3(-0.5, 0.5, 0.5),
+    vec3(-0.5, -0.5, -0.5), vec3(-0.5, 0.5, 0.5), vec3(-0.5, 0.5, -0.5),
+    vec3(-0.5, 0.5, 0.5), vec3(0.5, 0.5, 0.5), vec3(0.5, 0.5, -0.5),
+    vec3(-0.5, 0.5, 0.5), vec3(0.5, 0.5, -0.5), vec3(-0.5, 0.5, -0.5),
+    vec3(-0.5, -0.5, -0.5), vec3(0.5, -0.5, -0.5), vec3(0.5, -0.5, 0.5),
+    vec3(-0.5, -0.5, -0.5), vec3(0.5, -0.5, 0.5), vec3(-0.5, -0.5, 0.5)
+);
+
+void main() {
+    const mat4 decal_to_world = mat4(DecalToWorld0, DecalToWorld1, DecalToWorld2, DecalToWorld3);
+    WorldToDecal = mat4(WorldToDecal0, WorldToDecal1, WorldToDecal2, WorldToDecal3);
+    DecalAxes = mat3(normalize(decal_to_world[0].xyz), normalize(decal_to_world[1].xyz), normalize(decal_to_world[2].xyz));
+    MID = Material;
+    DecalOpacity = Opacity;
+
+    gl_Position = Proj * View * decal_to_world * vec4(CUBE[gl_VertexIndex], 1.0);
+}
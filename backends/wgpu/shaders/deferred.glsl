@@ -0,0 +1,139 @@
+// Geometry pass of the deferred renderer, deferred_flat.frag defines FLAT_SHADING to shade
+// with face normals
+
+#include "lights.glsl"
+#include "material.glsl"
+#include "reveal.glsl"
+
+// Matches MAX_CLIP_PLANES of rfw-backend
+#define MAX_CLIP_PLANES 4
+
+layout(location = 0) in vec4 V;
+layout(location = 1) in vec4 SSV;
+layout(location = 2) in vec3 N;
+layout(location = 3) in flat uint MID;
+layout(location = 4) in vec2 TUV;
+layout(location = 5) in vec3 T;
+layout(location = 6) in vec3 B;
+layout(location = 7) in vec2 TUV1;
+layout(location = 8) in vec4 ClipPos;
+layout(location = 9) in vec4 PrevClipPos;
+layout(location = 10) in flat uint IID;
+layout(location = 11) in flat vec4 Tint;
+layout(location = 12) in flat float FlipbookTime;
+layout(location = 13) in flat uint Effect;
+
+layout(set = 0, binding = 0) uniform Locals {
+    mat4 View;
+    mat4 Proj;
+    mat4 matrix_2d;
+    uvec4 light_count;
+    vec4 cam_pos;
+    uvec4 options;
+    mat4 inv_view_proj;
+    mat4 prev_view_proj;
+    vec4 material_debug;
+    vec4 clip_planes[MAX_CLIP_PLANES];
+    vec4 skybox_intensity;
+    vec4 time;
+    vec4 wind;
+    vec4 reveal[3];
+};
+layout(std430, set = 0, binding = 1) buffer readonly Materials { Material materials[]; };
+layout(set = 0, binding = 2) uniform sampler Sampler;
+layout(set = 0, binding = 5) uniform sampler NearestSampler;
+
+layout(set = 1, binding = 0) uniform texture2D textures[128];
+
+layout(location = 0) out vec4 Albedo;
+layout(location = 1) out vec4 Normal;
+layout(location = 2) out vec4 WorldPos;
+layout(location = 3) out vec4 SSPos;
+layout(location = 4) out vec4 Params;
+layout(location = 5) out vec4 Lightmap;
+layout(location = 6) out vec4 Motion;
+
+#define LOD_BIAS uintBitsToFloat(options.w)
+
+// Materials with a nearest filtered diffuse map sample all of their maps unfiltered
+#define SAMPLE_MAP(flags, map, uv) (HAS_NEAREST_FILTER(flags) ? texture(sampler2D(textures[map], NearestSampler), uv, LOD_BIAS) : texture(sampler2D(textures[map], Sampler), uv, LOD_BIAS))
+
+#include "parallax.glsl"
+
+void main() {
+    for (int i = 0; i < MAX_CLIP_PLANES; i++) {
+        if (dot(clip_planes[i].xyz, V.xyz) + clip_planes[i].w < 0.0) {
+            discard;
+        }
+    }
+    if (reveal_cuts(reveal, V.xyz)) {
+        discard;
+    }
+
+    vec3 color = materials[MID].color.xyz;
+#ifdef FLAT_SHADING
+    // Face normal from the screen-space derivatives of the position, on the side of the
+    // interpolated normal so it does not depend on the winding
+    vec3 normal = normalize(cross(dFdx(V.xyz), dFdy(V.xyz)));
+    normal = dot(normal, N) < 0.0 ? -normal : normal;
+#else
+    vec3 normal = N;
+#endif
+
+    const uint flags = materials[MID].flags;
+    vec4 params = vec4(0);
+
+    vec2 uv = TUV;
+    if (options.x > 0 && HAS_HEIGHT_MAP(flags)) {
+        const vec3 view = normalize(transpose(mat3(T, B, N)) * (cam_pos.xyz - V.xyz));
+        uv = parallax_occlusion(materials[MID].height_map, TUV, view, materials[MID].parallax_scale);
+    }
+
+    if (HAS_FLIPBOOK(materials[MID].flipbook)) {
+        uv = flipbook_uv(materials[MID].flipbook, uv, time.x + FlipbookTime);
+    }
+
+    if (HAS_DIFFUSE_MAP(flags)) {
+        vec4 t_color = SAMPLE_MAP(flags, materials[MID].diffuse_map, uv).rgba;
+        if (t_color.a < 0.5) {
+            discard;
+        }
+        // Surfaces are opaque, the color of premultiplied texels is restored
+        if (HAS_PREMULTIPLIED_ALPHA(flags)) {
+            t_color.rgb /= t_color.a;
+        }
+        color = t_color.xyz;
+    }
+
+    // Per-instance tint, white leaves the material unchanged
+    color *= Tint.rgb;
+
+    if (HAS_NORMAL_MAP(flags)) {
+        const vec3 n = (SAMPLE_MAP(flags, materials[MID].normal_map, uv).rgb - 0.5) * 2.0;
+        normal = normalize(mat3(T, B, normal) * n);
+    }
+
+    if (HAS_METAL_ROUGH_MAP(flags)) {
+        params.xy = SAMPLE_MAP(flags, materials[MID].metallic_roughness_map, uv).gb;
+    }
+
+    if (HAS_SHEEN_MAP(flags)) {
+        params.z = SAMPLE_MAP(flags, materials[MID].sheen_map, uv).r;
+    }
+
+    vec3 lightmap = vec3(0);
+    if (HAS_LIGHTMAP(flags)) {
+        lightmap = texture(sampler2D(textures[materials[MID].lightmap_map], Sampler), TUV1, LOD_BIAS).rgb;
+    }
+
+    Albedo = vec4(color, MID);
+    // The effect of the instance selects the shading model of the lighting pass
+    Normal = vec4(normal, float(Effect));
+    WorldPos = vec4(V.xyz, gl_FragCoord.z);
+    SSPos = SSV;
+    // The instance index is split over two half floats, which are exact up to 2048
+    Params = vec4(params.xyz, float(IID & 2047u));
+    Lightmap = vec4(lightmap, float(IID >> 11u));
+    // Screen-space velocity in UV units, from the previous to the current frame
+    Motion = vec4((ClipPos.xy / ClipPos.w - PrevClipPos.xy / PrevClipPos.w) * vec2(0.5, -0.5), 0.0, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,73 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "material.glsl"
+#include "reveal.glsl"
+
+// Matches MAX_CLIP_PLANES of rfw-backend
+#define MAX_CLIP_PLANES 4
+
+layout(location = 0) in vec4 V;
+layout(location = 2) in vec3 N;
+layout(location = 3) in flat uint MID;
+layout(location = 4) in vec2 TUV;
+layout(location = 5) in vec3 T;
+layout(location = 6) in vec3 B;
+layout(location = 12) in flat float FlipbookTime;
+
+layout(set = 0, binding = 0) uniform Locals {
+    mat4 View;
+    mat4 Proj;
+    mat4 matrix_2d;
+    uvec4 light_count;
+    vec4 cam_pos;
+    uvec4 options;
+    mat4 inv_view_proj;
+    mat4 prev_view_proj;
+    vec4 material_debug;
+    vec4 clip_planes[MAX_CLIP_PLANES];
+    vec4 skybox_intensity;
+    vec4 time;
+    vec4 wind;
+    vec4 reveal[3];
+};
+layout(std430, set = 0, binding = 1) buffer readonly Materials { Material materials[]; };
+layout(set = 0, binding = 2) uniform sampler Sampler;
+layout(set = 0, binding = 5) uniform sampler NearestSampler;
+
+layout(set = 1, binding = 0) uniform texture2D textures[128];
+
+#define LOD_BIAS uintBitsToFloat(options.w)
+
+#define SAMPLE_MAP(flags, map, uv) (HAS_NEAREST_FILTER(flags) ? texture(sampler2D(textures[map], NearestSampler), uv, LOD_BIAS) : texture(sampler2D(textures[map], Sampler), uv, LOD_BIAS))
+
+#include "parallax.glsl"
+
+// Only writes depth, but discards exactly the fragments deferred.frag discards so the geometry
+// pass can test for equal depth
+void main() {
+    for (int i = 0; i < MAX_CLIP_PLANES; i++) {
+        if (dot(clip_planes[i].xyz, V.xyz) + clip_planes[i].w < 0.0) {
+            discard;
+        }
+    }
+    if (reveal_cuts(reveal, V.xyz)) {
+        discard;
+    }
+
+    const uint flags = materials[MID].flags;
+
+    vec2 uv = TUV;
+    if (options.x > 0 && HAS_HEIGHT_MAP(flags)) {
+        const vec3 view = normalize(transpose(mat3(T, B, N)) * (cam_pos.xyz - V.xyz));
+        uv = parallax_occlusion(materials[MID].height_map, TUV, view, materials[MID].parallax_scale);
+    }
+
+    if (HAS_FLIPBOOK(materials[MID].flipbook)) {
+        uv = flipbook_uv(materials[MID].flipbook, uv, time.x + FlipbookTime);
+    }
+
+    if (HAS_DIFFUSE_MAP(flags) && SAMPLE_MAP(flags, materials[MID].diffuse_map, uv).a < 0.5) {
+        discard;
+    }
+}
@@ -44,6 +44,19 @@ float SchlickFresnel(const float u)
     return float(m * m) * (m * m) * m;
 }
 
+// Reflectance at normal incidence of the dielectric lobe, follows KHR_materials_ior and
+// KHR_materials_specular when an index of refraction is set. An ior of 1.5 with a white
+// specular color results in the same 0.04 as the default.
+vec3 dielectricSpecular(const ShadingData data, const vec3 Ctint)
+{
+	const vec3 tint = mix(vec3(1.0f), Ctint, data.specular_tint);
+	if (data.ior <= 0.0f)
+		return data.specular * .08f * tint;
+
+	float F0 = (data.ior - 1.0f) / (data.ior + 1.0f);
+	return min(F0 * F0 * data.specular * tint, vec3(1.0f));
+}
+
 vec3 BSDFEval(ShadingData data, const vec3 N, const vec3 wo, const vec3 wi)
 {
     float NDotL = dot(N, wi);
@@ -54,7 +67,7 @@ vec3 BSDFEval(ShadingData data, const vec3 N, const vec3 wo, const vec3 wi)
 	vec3 Cdlin = data.color.xyz;
 	float Cdlum = .3f * Cdlin.x + .6f * Cdlin.y + .1f * Cdlin.z; // luminance approx.
 	vec3 Ctint = Cdlum > 0.0f ? Cdlin / Cdlum : vec3(1.0f);	  // normalize lum. to isolate hue+sat
-	vec3 Cspec0 = mix(data.specular * .08f * mix(vec3(1.0f), Ctint, data.specular_tint), Cdlin, data.metallic);
+	vec3 Cspec0 = mix(dielectricSpecular(data, Ctint), Cdlin, data.metallic);
 	vec3 bsdf = vec3(0);
 	vec3 brdf = vec3(0);
 
@@ -73,12 +86,16 @@ vec3 BSDFEval(ShadingData data, const vec3 N, const vec3 wo, const vec3 wi)
 	float Fd90 = 0.5 + 2.0f * LDotH * LDotH * a;
 	float Fd = mix(1.0f, Fd90, FL) * mix(1.0f, Fd90, FV);
 
+	// sheen, a grazing retro-reflection tinted towards the base color for cloth
+	vec3 Csheen = mix(vec3(1.0f), Ctint, data.sheen_tint);
+	vec3 Fsheen = FH * data.sheen * Csheen;
+
 	// clearcoat (ior = 1.5 -> F0 = 0.04)
 	float Dr = GTR1(NDotH, mix(.1, .001, data.clearcoat_gloss));
 	float Fc = mix(.04f, 1.0f, FH);
 	float Gr = SmithGGX(NDotL, .25) * SmithGGX(NDotV, .25);
 
-	brdf = INVPI * Fd * Cdlin * (1.0f - data.metallic) * (1.0f - data.subsurface) + Gs * Fs * Ds + data.clearcoat * Gr * Fc * Dr;
+	brdf = (INVPI * Fd * Cdlin * (1.0f - data.subsurface) + Fsheen) * (1.0f - data.metallic) + Gs * Fs * Ds + data.clearcoat * Gr * Fc * Dr;
 
 	return mix(brdf, bsdf, data.transmission);
 }
@@ -0,0 +1,20 @@
+#ifndef DISPLACEMENT_H
+#define DISPLACEMENT_H
+
+// Expects the materials buffer, the textures array and Sampler to be declared before this file
+// is included
+
+// Offsets an object-space vertex along its normal by the displacement map of its material, the
+// normal is left as is so lighting detail has to come from the normal map
+vec3 displace(const uint material, const vec3 P, const vec3 normal, const vec2 uv) {
+    if (!HAS_DISPLACEMENT_MAP(materials[material].flags)) {
+        return P;
+    }
+
+    // Vertex shaders have no derivatives to select a mip level with, the full resolution
+    // level keeps the offsets of neighbouring vertices consistent
+    const float height = textureLod(sampler2D(textures[materials[material].displacement_map], Sampler), uv, 0.0).r;
+    return P + normal * (height * materials[material].displacement_scale);
+}
+
+#endif
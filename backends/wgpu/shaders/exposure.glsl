@@ -0,0 +1,48 @@
+// Bindings and helpers shared by the auto-exposure and tonemapping passes
+
+layout(set = 0, binding = 0, rgba16f) uniform readonly image2D Albedo;
+layout(set = 0, binding = 1, rgba16f) uniform readonly image2D Radiance;
+layout(set = 0, binding = 2, r16f) uniform readonly image2D SSAO;
+layout(set = 0, binding = 3) uniform Grading {
+    float exposure;
+    float contrast;
+    float saturation;
+    uint tonemap;
+    uint transfer;
+    float gamma;
+    uint auto_exposure;
+    // Rate per second at which the adapted exposure approaches its target
+    float adaptation;
+    float delta_time;
+};
+
+// Matches ToneMapPass::EXPOSURE_BINS
+#define EXPOSURE_BINS 64
+#define MIN_LOG_LUMINANCE -12.0
+#define MAX_LOG_LUMINANCE 12.0
+
+layout(std430, set = 0, binding = 4) buffer Exposure {
+    // Stops added to the manual exposure when auto-exposure is enabled
+    float adapted_exposure;
+    uint initialized;
+    uint _padding0;
+    uint _padding1;
+    uint bins[EXPOSURE_BINS];
+};
+
+layout(set = 0, binding = 5, rgba16f) uniform writeonly image2D Display;
+
+float luminance(const vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+vec3 scene_radiance(const ivec2 pixel) {
+    const vec3 albedo = imageLoad(Albedo, pixel).xyz;
+    const vec3 radiance = imageLoad(Radiance, pixel).xyz;
+    const float ssao = imageLoad(SSAO, pixel).r;
+    return radiance + albedo * 0.01 * ssao;
+}
+
+float bin_log_luminance(const uint bin) {
+    return mix(MIN_LOG_LUMINANCE, MAX_LOG_LUMINANCE, float(bin) / float(EXPOSURE_BINS - 1));
+}
@@ -0,0 +1,62 @@
+#version 450
+
+// Matches MAX_CLIP_PLANES of rfw-backend
+#define MAX_CLIP_PLANES 4
+
+layout(location = 0) in vec2 NDC;
+
+layout(set = 0, binding = 0) uniform Locals {
+    mat4 View;
+    mat4 Proj;
+    mat4 matrix_2d;
+    uvec4 light_count;
+    vec4 cam_pos;
+    uvec4 options;
+    mat4 inv_view_proj;
+    mat4 prev_view_proj;
+    vec4 material_debug;
+    vec4 clip_planes[MAX_CLIP_PLANES];
+    vec4 skybox_intensity;
+    vec4 time;
+    vec4 wind;
+};
+
+layout(set = 1, binding = 0) uniform Grid {
+    vec4 color;
+    // x: spacing, y: fade distance
+    vec4 parameters;
+};
+
+layout(location = 0) out vec4 Color;
+
+vec3 unproject(const float depth) {
+    const vec4 P = inv_view_proj * vec4(NDC, depth, 1.0);
+    return P.xyz / P.w;
+}
+
+void main() {
+    const vec3 near = unproject(0.0);
+    const vec3 direction = unproject(0.5) - near;
+    const float t = -near.y / direction.y;
+    const vec3 P = near + t * direction;
+
+    const vec4 clip = Proj * View * vec4(P, 1.0);
+    const float depth = clip.z / clip.w;
+
+    // Derivatives are taken before any pixel is discarded
+    const vec2 coord = P.xz / parameters.x;
+    const vec2 footprint = fwidth(coord);
+    const vec2 lines = abs(fract(coord - 0.5) - 0.5) / footprint;
+    const float line = 1.0 - min(min(lines.x, lines.y), 1.0);
+
+    // Rays that run parallel to the plane or point away from it, and hits beyond the far plane
+    if (!(t > 0.0) || isinf(t) || depth > 1.0) {
+        discard;
+    }
+
+    const float fade = clamp(1.0 - distance(P, cam_pos.xyz) / parameters.y, 0.0, 1.0);
+    // Far away cells shrink below a pixel and would only add moire, those fade out as well
+    const float coverage = clamp(1.0 - max(footprint.x, footprint.y), 0.0, 1.0);
+    Color = vec4(color.rgb, color.a * line * fade * coverage);
+    gl_FragDepth = depth;
+}
@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) out vec2 NDC;
+
+void main() {
+    // A single triangle covering the screen
+    NDC = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2)) * 2.0 - 1.0;
+    gl_Position = vec4(NDC, 0.0, 1.0);
+}
@@ -0,0 +1,14 @@
+// Lights of the radiance pass are culled per tile of TILE_SIZE x TILE_SIZE pixels, matches
+// RadiancePass::TILE_SIZE
+#define TILE_SIZE 16
+
+// Lights are ignored where their radiance falls below this, it bounds the otherwise infinite
+// range of the inverse square falloff
+#define LIGHT_CUTOFF 0.0005
+
+// tiles.x: tiles per row, tiles.y: rows, tiles.z: stride, tiles.w: whether culling is enabled.
+// Every tile stores its spot and area light counts followed by the spot light indices and,
+// offset by the spot light count, the area light indices.
+uint tile_offset(const uvec4 tiles, const uvec2 tile) {
+    return (tile.y * tiles.x + tile.x) * tiles.z;
+}
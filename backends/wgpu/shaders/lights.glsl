@@ -7,8 +7,8 @@ struct LightInfo {
     mat4 MP;
     
     vec4 PosRange;
-    vec4 padding0;
-    vec4 padding1;
+    vec4 Bias; // x: depth bias, y: normal bias, z: slope-scaled bias
+    vec4 Softness; // x: size of the light in shadow map space, 0 casts hard shadows
     vec4 padding2;
 
     mat4 padding3;
@@ -27,18 +27,34 @@ struct AreaLight {
     float vertex1_x;
     float vertex1_y;
     float vertex1_z;
+    int mesh_id;
     float radiance_x;
     float radiance_y;
     float radiance_z;
+    int shape;
     float vertex2_x;
     float vertex2_y;
-    float vertexw_z;
+    float vertex2_z;
+    float focus;
 };
 
+// AreaLight::shape, rectangles and disks store their center in position_energy and their edge
+// or radius vectors in vertex0 and vertex1
+#define AREA_LIGHT_TRIANGLE 0
+#define AREA_LIGHT_RECTANGLE 1
+#define AREA_LIGHT_DISK 2
+
+// Scale of the radiance leaving an area light at an angle with cosine cos_theta to its normal,
+// matches AreaLight::focus_factor
+float area_light_focus(const float focus, const float cos_theta) {
+    return focus > 0.0 ? (focus + 2.0) * 0.5 * pow(max(cos_theta, 0.0), focus) : 1.0;
+}
+
 struct SpotLight {
     vec4 position_cos_inner;
     vec4 radiance_cos_outer;
     vec4 direction_energy;
+    vec4 radius; // x: radius of the light source
 };
 
 struct DirectionalLight {
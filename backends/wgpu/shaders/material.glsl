@@ -4,7 +4,8 @@
 struct Material {
     vec4 color;
     vec4 absorption;
-    vec4 specular;
+    vec3 specular;
+    float ior;
     uvec4 parameters;
 
     uint flags;
@@ -14,14 +15,22 @@ struct Material {
 
     int emissive_map;
     int sheen_map;
-    int _dummy0;
-    int _dummy1;
+    int lightmap_map;
+    int height_map;
+
+    vec4 flipbook;
+
+    int displacement_map;
+    float displacement_scale;
+    float parallax_scale;
+    float _padding;
 };
 
 struct ShadingData {
     vec3 color;
     vec3 absorption;
     vec3 specular;
+    float ior;
     float metallic;
     float subsurface;
     float specular_f;
@@ -47,6 +56,13 @@ struct ShadingData {
 #define HAS_METAL_ROUGH_MAP(flags) ((flags & (1 << 2)) > 0)
 #define HAS_EMISSIVE_MAP(flags) ((flags & (1 << 4)) > 0)
 #define HAS_SHEEN_MAP(flags) ((flags & (1 << 5)) > 0)
+#define HAS_LIGHTMAP(flags) ((flags & (1 << 6)) > 0)
+#define HAS_HEIGHT_MAP(flags) ((flags & (1 << 7)) > 0)
+#define HAS_NEAREST_FILTER(flags) ((flags & (1 << 8)) > 0)
+#define HAS_DISPLACEMENT_MAP(flags) ((flags & (1 << 10)) > 0)
+#define HAS_PREMULTIPLIED_ALPHA(flags) ((flags & (1 << 11)) > 0)
+
+#define HAS_FLIPBOOK(flipbook) (flipbook.x > 0.0)
 
 #define IS_EMISSIVE(color) (color.x > 1.0 || color.y > 1.0 || color.z > 1.0)
 
@@ -70,11 +86,19 @@ struct ShadingData {
 #define CUSTOM2(parameters) CHAR2FLT(parameters.w, 16)
 #define CUSTOM3(parameters) CHAR2FLT(parameters.w, 24)
 
+// Maps uv into the atlas cell of the frame a flipbook shows at time t
+vec2 flipbook_uv(const vec4 flipbook, const vec2 uv, const float t) {
+    const float frame = mod(floor(t * flipbook.w), flipbook.z);
+    const vec2 cell = vec2(mod(frame, flipbook.x), floor(frame / flipbook.x));
+    return (cell + fract(uv)) / flipbook.xy;
+}
+
 ShadingData extractParameters(const vec3 color, const vec3 absorption, const vec3 specular, const uvec4 parameters) {
     ShadingData data;
     data.color = color;
     data.absorption = absorption;
     data.specular = specular;
+    data.ior = 0.0;
     data.metallic = METALLIC(parameters);
     data.subsurface = SUBSURFACE(parameters);
     data.specular_f = SPECULAR(parameters);
@@ -1,10 +1,17 @@
 #version 450
 
+#include "material.glsl"
+#include "wind.glsl"
+
+// Matches MAX_CLIP_PLANES of rfw-backend
+#define MAX_CLIP_PLANES 4
+
 layout(location = 0) in vec4 Vertex;
 layout(location = 1) in vec3 Normal;
 layout(location = 2) in uint MatID;
 layout(location = 3) in vec2 UV;
 layout(location = 4) in vec4 Tangent;
+layout(location = 5) in vec2 UV1;
 
 layout(set = 0, binding = 0) uniform Locals {
     mat4 View;
@@ -12,17 +19,35 @@ layout(set = 0, binding = 0) uniform Locals {
     mat4 matrix_2d;
     uvec4 light_count;
     vec4 cam_pos;
+    uvec4 options;
+    mat4 inv_view_proj;
+    mat4 prev_view_proj;
+    vec4 material_debug;
+    vec4 clip_planes[MAX_CLIP_PLANES];
+    vec4 skybox_intensity;
+    vec4 time;
+    vec4 wind;
 };
 
 struct Transform {
     mat4 M;
     mat4 IM;
+    mat4 PM;
+    vec4 tint;
+    vec4 animation;
 };
 
 layout(set = 0, binding = 4) buffer readonly Instances {
     Transform transforms[];
 };
 
+layout(std430, set = 0, binding = 1) buffer readonly Materials { Material materials[]; };
+layout(set = 0, binding = 2) uniform sampler Sampler;
+
+layout(set = 1, binding = 0) uniform texture2D textures[128];
+
+#include "displacement.glsl"
+
 layout(location = 0) out vec4 V;
 layout(location = 1) out vec4 SSV;
 layout(location = 2) out vec3 N;
@@ -30,9 +55,20 @@ layout(location = 3) out uint MID;
 layout(location = 4) out vec2 TUV;
 layout(location = 5) out vec3 T;
 layout(location = 6) out vec3 B;
+layout(location = 7) out vec2 TUV1;
+layout(location = 8) out vec4 ClipPos;
+layout(location = 9) out vec4 PrevClipPos;
+layout(location = 10) out uint IID;
+layout(location = 11) out vec4 Tint;
+layout(location = 12) out float FlipbookTime;
+layout(location = 13) out flat uint Effect;
 
 void main() {
-    const vec4 vertex = transforms[gl_InstanceIndex].M * Vertex;
+    // Displaced in object space, the previous position of motion vectors moves along with it
+    const vec4 displaced = vec4(displace(MatID, Vertex.xyz, Normal, UV), Vertex.w);
+    vec4 vertex = transforms[gl_InstanceIndex].M * displaced;
+    // Shadow maps and motion vectors keep the rest pose of swaying instances
+    vertex.xyz += wind_offset(wind, time.x, vertex.xyz, Vertex.y, transforms[gl_InstanceIndex].animation.y);
     const vec4 cVertex = View * vec4(vertex.xyz, 1.0);
 
     gl_Position = Proj * cVertex;
@@ -44,4 +80,12 @@ void main() {
     B = cross(N, T) * Tangent.w;
     MID = MatID;
     TUV = UV;
+    TUV1 = UV1;
+
+    ClipPos = gl_Position;
+    PrevClipPos = prev_view_proj * transforms[gl_InstanceIndex].PM * displaced;
+    IID = uint(gl_InstanceIndex);
+    Tint = transforms[gl_InstanceIndex].tint;
+    FlipbookTime = transforms[gl_InstanceIndex].animation.x;
+    Effect = uint(transforms[gl_InstanceIndex].animation.z);
 }
\ No newline at end of file
@@ -0,0 +1,12 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform Outline {
+    vec4 color;
+    vec4 offset;
+};
+
+layout(location = 0) out vec4 Color;
+
+void main() {
+    Color = color;
+}
@@ -0,0 +1,47 @@
+#version 450
+
+layout(location = 0) in vec2 UV;
+layout(location = 0) out vec4 Color;
+
+layout(set = 0, binding = 0) uniform Outline {
+    vec4 color;
+    // x: width in pixels
+    vec4 parameters;
+};
+
+layout(set = 0, binding = 1) uniform texture2D Mask;
+layout(set = 0, binding = 2) uniform sampler Sampler;
+
+const vec2 DIRECTIONS[8] = vec2[8](
+    vec2(1.0, 0.0),
+    vec2(-1.0, 0.0),
+    vec2(0.0, 1.0),
+    vec2(0.0, -1.0),
+    vec2(0.7071, 0.7071),
+    vec2(-0.7071, 0.7071),
+    vec2(0.7071, -0.7071),
+    vec2(-0.7071, -0.7071)
+);
+
+// Matches the maximum of the outline width setting
+#define MAX_WIDTH 8
+
+void main() {
+    // The stencil test only lets pixels of the selection through, a pixel lies on the outline
+    // when any pixel within the outline width is outside of the selection.
+    const ivec2 size = textureSize(sampler2D(Mask, Sampler), 0);
+    const ivec2 pixel = ivec2(gl_FragCoord.xy);
+    const int width = clamp(int(ceil(parameters.x)), 1, MAX_WIDTH);
+
+    for (int r = 1; r <= width; r++) {
+        for (int i = 0; i < 8; i++) {
+            const ivec2 neighbour = clamp(pixel + ivec2(round(DIRECTIONS[i] * float(r))), ivec2(0), size - 1);
+            if (texelFetch(sampler2D(Mask, Sampler), neighbour, 0).r < 0.5) {
+                Color = color;
+                return;
+            }
+        }
+    }
+
+    discard;
+}
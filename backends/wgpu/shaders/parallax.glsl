@@ -0,0 +1,38 @@
+#ifndef PARALLAX_H
+#define PARALLAX_H
+
+// Expects the textures array, Sampler and LOD_BIAS to be declared before this file is included
+
+#define PARALLAX_MIN_LAYERS 8.0
+#define PARALLAX_MAX_LAYERS 32.0
+
+// Ray-marches the height field in tangent space, returns the uv where the view ray hits it
+vec2 parallax_occlusion(const int height_map, const vec2 uv, const vec3 view, const float scale) {
+    // Scaling the gradients by 2^bias offsets the selected mip level by the bias
+    const float bias_scale = exp2(LOD_BIAS);
+    const vec2 dx = dFdx(uv) * bias_scale;
+    const vec2 dy = dFdy(uv) * bias_scale;
+
+    // Take more steps at grazing angles, where the offset is largest
+    const float layers = mix(PARALLAX_MAX_LAYERS, PARALLAX_MIN_LAYERS, abs(view.z));
+    const float layer_depth = 1.0 / layers;
+    const vec2 delta = (view.xy / max(view.z, 0.05)) * scale / layers;
+
+    vec2 current_uv = uv;
+    float current_depth = 0.0;
+    float depth = 1.0 - textureGrad(sampler2D(textures[height_map], Sampler), current_uv, dx, dy).r;
+    for (int i = 0; i < int(PARALLAX_MAX_LAYERS) && current_depth < depth; i++) {
+        current_uv -= delta;
+        depth = 1.0 - textureGrad(sampler2D(textures[height_map], Sampler), current_uv, dx, dy).r;
+        current_depth += layer_depth;
+    }
+
+    // Interpolate between the last two samples to hide the layers
+    const vec2 previous_uv = current_uv + delta;
+    const float after = depth - current_depth;
+    const float before = (1.0 - textureGrad(sampler2D(textures[height_map], Sampler), previous_uv, dx, dy).r) - current_depth + layer_depth;
+    const float weight = after / (after - before);
+    return mix(current_uv, previous_uv, weight);
+}
+
+#endif
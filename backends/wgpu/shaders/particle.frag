@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 Corner;
+layout(location = 1) in vec4 ParticleColor;
+
+layout(location = 0) out vec4 Color;
+
+void main() {
+    // Soft round sprite, fades out towards the edge of the quad
+    float falloff = 1.0 - smoothstep(0.5, 1.0, length(Corner));
+    if (falloff <= 0.0) {
+        discard;
+    }
+
+    Color = vec4(ParticleColor.rgb, ParticleColor.a * falloff);
+}
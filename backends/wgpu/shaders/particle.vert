@@ -0,0 +1,28 @@
+#version 450
+
+layout(location = 0) in vec3 Position;
+layout(location = 1) in float Size;
+layout(location = 2) in vec4 Color;
+
+layout(set = 0, binding = 0) uniform Locals {
+    mat4 View;
+    mat4 Proj;
+};
+
+layout(location = 0) out vec2 Corner;
+layout(location = 1) out vec4 ParticleColor;
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+void main() {
+    Corner = CORNERS[gl_VertexIndex];
+    ParticleColor = Color;
+
+    // Offset in view space so the quad always faces the camera
+    vec4 view_pos = View * vec4(Position, 1.0);
+    view_pos.xy += Corner * (Size * 0.5);
+    gl_Position = Proj * view_pos;
+}
@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) in flat uint IID;
+layout(location = 0) out uint Id;
+
+void main() {
+    // 0 is left for pixels without an instance
+    Id = IID + 1;
+}
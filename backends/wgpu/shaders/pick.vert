@@ -0,0 +1,45 @@
+#version 450
+
+#include "wind.glsl"
+
+// Matches MAX_CLIP_PLANES of rfw-backend
+#define MAX_CLIP_PLANES 4
+
+layout(location = 0) in vec4 Vertex;
+
+layout(set = 0, binding = 0) uniform Locals {
+    mat4 View;
+    mat4 Proj;
+    mat4 matrix_2d;
+    uvec4 light_count;
+    vec4 cam_pos;
+    uvec4 options;
+    mat4 inv_view_proj;
+    mat4 prev_view_proj;
+    vec4 material_debug;
+    vec4 clip_planes[MAX_CLIP_PLANES];
+    vec4 skybox_intensity;
+    vec4 time;
+    vec4 wind;
+};
+
+struct Transform {
+    mat4 M;
+    mat4 IM;
+    mat4 PM;
+    vec4 tint;
+    vec4 animation;
+};
+
+layout(set = 0, binding = 4) buffer readonly Instances {
+    Transform transforms[];
+};
+
+layout(location = 0) out flat uint IID;
+
+void main() {
+    vec4 vertex = transforms[gl_InstanceIndex].M * Vertex;
+    vertex.xyz += wind_offset(wind, time.x, vertex.xyz, Vertex.y, transforms[gl_InstanceIndex].animation.y);
+    gl_Position = Proj * View * vec4(vertex.xyz, 1.0);
+    IID = uint(gl_InstanceIndex);
+}
@@ -0,0 +1,30 @@
+#ifndef REVEAL_H
+#define REVEAL_H
+
+// Layout of RevealRegion::to_device of rfw-backend: x of the first vector is the shape and y
+// the brightness outside of it, negative to cut it away
+#define REVEAL_NONE 0
+#define REVEAL_SPHERE 1
+#define REVEAL_BOX 2
+
+bool revealed(const vec4 region[3], const vec3 P) {
+    const int shape = int(region[0].x);
+    if (shape == REVEAL_SPHERE) {
+        const vec3 d = P - region[1].xyz;
+        return dot(d, d) <= region[1].w * region[1].w;
+    } else if (shape == REVEAL_BOX) {
+        return all(greaterThanEqual(P, region[1].xyz)) && all(lessThanEqual(P, region[2].xyz));
+    }
+    return true;
+}
+
+bool reveal_cuts(const vec4 region[3], const vec3 P) {
+    return region[0].y < 0.0 && !revealed(region, P);
+}
+
+// Brightness multiplier of a surface at P that was not cut away
+float reveal_brightness(const vec4 region[3], const vec3 P) {
+    return region[0].y >= 0.0 && !revealed(region, P) ? region[0].y : 1.0;
+}
+
+#endif
@@ -14,6 +14,8 @@ layout (location = 0) out vec2 Depth;
 void main() {
     float d = LightSpaceV.z / LightSpaceV.w;
     d = linearizeDepth(d, info.PosRange.w);
+    // Slope-scaled bias, pushes steep surfaces further away from the light
+    d += info.Bias.z * max(abs(dFdx(d)), abs(dFdy(d)));
     const float moment1 = d;
     const float dx = dFdx(moment1);
     const float dy = dFdy(moment1);
@@ -13,6 +13,9 @@ layout(set = 0, binding = 0) uniform Locals {
 struct Transform {
     mat4 M;
     mat4 IM;
+    mat4 PM;
+    vec4 tint;
+    vec4 animation;
 };
 
 layout(set = 1, binding = 4) buffer readonly Instances {
@@ -12,7 +12,9 @@ layout (location = 1) in vec4 V;
 layout (location = 0) out vec2 Depth;
 
 void main() {
-    const float d = LightSpaceV.z / LightSpaceV.w;
+    float d = LightSpaceV.z / LightSpaceV.w;
+    // Slope-scaled bias, pushes steep surfaces further away from the light
+    d += info.Bias.z * max(abs(dFdx(d)), abs(dFdy(d)));
     const float moment1 = d;
     const float dx = dFdx(moment1);
     const float dy = dFdy(moment1);
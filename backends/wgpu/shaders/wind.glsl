@@ -0,0 +1,8 @@
+// Displacement of a vertex at world-space position P and object-space height h by the wind,
+// wind holds the direction scaled by the strength in xyz and the frequency in w
+vec3 wind_offset(const vec4 wind, const float time, const vec3 P, const float h, const float weight) {
+    // The phase varies over the ground so neighbouring plants do not sway in unison
+    const float phase = 6.2831853 * wind.w * time + dot(P.xz, vec2(0.37, 0.21));
+    const float sway = 0.5 + 0.5 * sin(phase) + 0.15 * sin(2.7 * phase + 1.3);
+    return wind.xyz * (sway * max(h, 0.0) * weight);
+}
@@ -0,0 +1,145 @@
+use rayon::prelude::*;
+use std::ops::Range;
+use wgpu::util::RenderEncoder;
+
+/// A single draw of the geometry pass.
+#[derive(Debug, Clone)]
+pub struct GeometryDraw<'a> {
+    pub pipeline: &'a wgpu::RenderPipeline,
+    /// Skin bind group with the vertex and joint buffer offsets in bytes
+    pub skin: Option<(
+        &'a wgpu::BindGroup,
+        wgpu::BufferAddress,
+        wgpu::BufferAddress,
+    )>,
+    pub vertices: Range<u32>,
+    pub instances: Range<u32>,
+}
+
+/// State shared by every draw of the geometry pass.
+#[derive(Debug, Copy, Clone)]
+pub struct GeometryResources<'a> {
+    pub uniform_bind_group: &'a wgpu::BindGroup,
+    pub texture_bind_group: &'a wgpu::BindGroup,
+    pub vertex_buffer: &'a wgpu::Buffer,
+    pub jw_buffer: &'a wgpu::Buffer,
+}
+
+impl GeometryDraw<'_> {
+    /// Orders draws by pipeline, then skin and then mesh, so that consecutive draws share as
+    /// much state as possible.
+    pub fn sort_key(&self) -> (usize, usize, u32) {
+        let pipeline = self.pipeline as *const wgpu::RenderPipeline as usize;
+        let skin = self
+            .skin
+            .map(|(skin, _, _)| skin as *const wgpu::BindGroup as usize)
+            .unwrap_or(0);
+        (pipeline, skin, self.vertices.start)
+    }
+}
+
+impl<'a> GeometryResources<'a> {
+    /// Records `draws` in order, works for both render passes and render bundles. State
+    /// shared with the previous draw is not set again.
+    pub fn record<E: RenderEncoder<'a>>(&self, encoder: &mut E, draws: &[GeometryDraw<'a>]) {
+        encoder.set_bind_group(0, self.uniform_bind_group, &[]);
+        encoder.set_bind_group(1, self.texture_bind_group, &[]);
+        encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        let mut pipeline: Option<&wgpu::RenderPipeline> = None;
+        let mut bound_skin = None;
+        let mut offset_bound = false;
+        for draw in draws {
+            if !pipeline.map_or(false, |p| std::ptr::eq(p, draw.pipeline)) {
+                encoder.set_pipeline(draw.pipeline);
+                pipeline = Some(draw.pipeline);
+            }
+
+            match draw.skin {
+                Some((skin, vertex_offset, jw_offset)) => {
+                    if bound_skin != Some((skin as *const wgpu::BindGroup, vertex_offset)) {
+                        encoder.set_vertex_buffer(0, self.vertex_buffer.slice(vertex_offset..));
+                        encoder.set_vertex_buffer(1, self.jw_buffer.slice(jw_offset..));
+                        encoder.set_bind_group(2, skin, &[]);
+                        bound_skin = Some((skin as *const wgpu::BindGroup, vertex_offset));
+                    }
+                    offset_bound = true;
+                }
+                None if offset_bound => {
+                    encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                    bound_skin = None;
+                    offset_bound = false;
+                }
+                None => {}
+            }
+
+            encoder.draw(draw.vertices.clone(), draw.instances.clone());
+        }
+    }
+}
+
+/// Pre-recorded geometry pass and depth prepass, reused until the scene gets synchronized
+/// again.
+#[derive(Debug)]
+pub struct GeometryBundles {
+    bundles: Vec<wgpu::RenderBundle>,
+    prepass: Vec<wgpu::RenderBundle>,
+}
+
+impl GeometryBundles {
+    /// Below this number of draws recording directly into the render pass is cheaper.
+    pub const MIN_DRAWS: usize = 64;
+    /// Number of draws recorded into a single bundle.
+    pub const CHUNK_SIZE: usize = 256;
+
+    /// Records `draws` and `prepass_draws` into bundles of [GeometryBundles::CHUNK_SIZE] draws
+    /// in parallel.
+    pub fn new(
+        device: &wgpu::Device,
+        resources: GeometryResources,
+        draws: &[GeometryDraw],
+        prepass_draws: &[GeometryDraw],
+    ) -> Self {
+        Self {
+            bundles: Self::record(
+                device,
+                resources,
+                draws,
+                &crate::pipeline::RenderPipeline::COLOR_FORMATS,
+            ),
+            prepass: Self::record(device, resources, prepass_draws, &[]),
+        }
+    }
+
+    fn record(
+        device: &wgpu::Device,
+        resources: GeometryResources,
+        draws: &[GeometryDraw],
+        color_formats: &[wgpu::TextureFormat],
+    ) -> Vec<wgpu::RenderBundle> {
+        draws
+            .par_chunks(Self::CHUNK_SIZE)
+            .map(|chunk| {
+                let mut encoder =
+                    device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                        label: Some("geometry-bundle-encoder"),
+                        color_formats,
+                        depth_stencil_format: Some(crate::output::WgpuOutput::DEPTH_FORMAT),
+                        sample_count: 1,
+                    });
+                resources.record(&mut encoder, chunk);
+                encoder.finish(&wgpu::RenderBundleDescriptor {
+                    label: Some("geometry-bundle"),
+                })
+            })
+            .collect()
+    }
+
+    pub fn execute<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.execute_bundles(self.bundles.iter());
+    }
+
+    pub fn execute_prepass<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.execute_bundles(self.prepass.iter());
+    }
+}
@@ -57,12 +57,155 @@ impl InstanceList2D {
     }
 }
 
+/// Regions of the 2D overlay that changed since it was last drawn.
+#[derive(Debug, Default, Clone)]
+pub struct DirtyRegions {
+    /// Bounds in the space of the 2D camera, projected when the layer is drawn
+    bounds: Vec<Aabb>,
+    /// Rectangles in pixels as (x, y, width, height)
+    rects: Vec<[u32; 4]>,
+    all: bool,
+    /// Camera the layer was last drawn with, moving it invalidates the whole layer
+    matrix: Option<Mat4>,
+}
+
+impl DirtyRegions {
+    /// Every region costs a pass over all 2D draws, beyond this many they are merged into
+    /// their bounding rectangle.
+    const MAX_REGIONS: usize = 8;
+
+    pub fn mark_all(&mut self) {
+        self.all = true;
+    }
+
+    pub fn mark_bounds(&mut self, bounds: Aabb) {
+        if !self.all && bounds.min.x <= bounds.max.x && bounds.min.y <= bounds.max.y {
+            self.bounds.push(bounds);
+        }
+    }
+
+    pub fn mark_rect(&mut self, rect: [u32; 4]) {
+        if !self.all && rect[2] > 0 && rect[3] > 0 {
+            self.rects.push(rect);
+        }
+    }
+
+    /// Takes the scissor rectangles to redraw in a `width` by `height` target, as seen by
+    /// the 2D camera `matrix`.
+    pub fn take(&mut self, matrix: Mat4, width: u32, height: u32) -> Vec<[u32; 4]> {
+        let all = self.all || self.matrix != Some(matrix);
+        self.all = false;
+        self.matrix = Some(matrix);
+
+        let bounds = std::mem::take(&mut self.bounds);
+        let mut rects = std::mem::take(&mut self.rects);
+        if all {
+            return vec![[0, 0, width, height]];
+        }
+
+        rects.extend(
+            bounds
+                .iter()
+                .filter_map(|b| Self::project(b, &matrix, width, height)),
+        );
+        let mut rects: Vec<[u32; 4]> = rects
+            .into_iter()
+            .filter_map(|r| Self::clamp(r, width, height))
+            .collect();
+
+        if rects.len() > Self::MAX_REGIONS {
+            let merged = rects.iter().fold(rects[0], |a, b| {
+                let x = a[0].min(b[0]);
+                let y = a[1].min(b[1]);
+                let right = (a[0] + a[2]).max(b[0] + b[2]);
+                let bottom = (a[1] + a[3]).max(b[1] + b[3]);
+                [x, y, right - x, bottom - y]
+            });
+            rects = vec![merged];
+        }
+
+        rects
+    }
+
+    /// Pixel rectangle covered by `bounds`, grown by a pixel to cover antialiased edges.
+    fn project(bounds: &Aabb, matrix: &Mat4, width: u32, height: u32) -> Option<[u32; 4]> {
+        let mut min = Vec2::splat(f32::INFINITY);
+        let mut max = Vec2::splat(f32::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 {
+                    bounds.min.x
+                } else {
+                    bounds.max.x
+                },
+                if i & 2 == 0 {
+                    bounds.min.y
+                } else {
+                    bounds.max.y
+                },
+                if i & 4 == 0 {
+                    bounds.min.z
+                } else {
+                    bounds.max.z
+                },
+            );
+            let ndc = matrix.project_point3(corner);
+            // Pixel rows grow downwards while NDC grows upwards
+            let pixel = Vec2::new(
+                (ndc.x * 0.5 + 0.5) * width as f32,
+                (0.5 - ndc.y * 0.5) * height as f32,
+            );
+            min = min.min(pixel);
+            max = max.max(pixel);
+        }
+
+        let min = (min - Vec2::ONE).max(Vec2::ZERO);
+        let max = (max + Vec2::ONE).min(Vec2::new(width as f32, height as f32));
+        if min.x >= max.x || min.y >= max.y {
+            return None;
+        }
+
+        let (x, y) = (min.x.floor() as u32, min.y.floor() as u32);
+        Some([x, y, max.x.ceil() as u32 - x, max.y.ceil() as u32 - y])
+    }
+
+    fn clamp(rect: [u32; 4], width: u32, height: u32) -> Option<[u32; 4]> {
+        let x = rect[0].min(width);
+        let y = rect[1].min(height);
+        let w = rect[2].min(width - x);
+        let h = rect[3].min(height - y);
+        if w > 0 && h > 0 {
+            Some([x, y, w, h])
+        } else {
+            None
+        }
+    }
+}
+
+/// Persistent target of the 2D overlay when dirty regions are tracked, only the regions that
+/// changed are redrawn before it is composited onto the output.
+#[derive(Debug)]
+struct Layer {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
 #[derive(Debug)]
 pub struct Renderer {
     // pipeline: wgpu::RenderPipeline,
     pipeline_list: wgpu::RenderPipeline,
     // layout: wgpu::PipelineLayout,
     layout_list: wgpu::PipelineLayout,
+    /// Draws into the layer, which keeps premultiplied coverage in its alpha channel
+    pipeline_layer: wgpu::RenderPipeline,
+    clear_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    composite_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    layer: Option<Layer>,
+    pub dirty: DirtyRegions,
 }
 
 impl Renderer {
@@ -93,88 +236,379 @@ impl Renderer {
             source: wgpu::ShaderSource::SpirV(frag.as_quad_bytes().into()),
         };
 
-        let pipeline_list = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("2d-pipeline"),
-            layout: Some(&layout_list),
-            vertex: wgpu::VertexState {
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<Vertex2D>() as wgpu::BufferAddress,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            offset: 0,
-                            format: wgpu::VertexFormat::Float32x3,
-                            shader_location: 0,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 12,
-                            format: wgpu::VertexFormat::Uint32,
-                            shader_location: 1,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 16,
-                            format: wgpu::VertexFormat::Float32x2,
-                            shader_location: 2,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 24,
-                            format: wgpu::VertexFormat::Float32x4,
-                            shader_location: 3,
-                        },
-                    ],
-                    step_mode: wgpu::InputStepMode::Vertex,
-                }],
-                entry_point: "main",
-                module: &device.create_shader_module(&vertex),
-            },
-            fragment: Some(wgpu::FragmentState {
-                entry_point: "main",
-                module: &device.create_shader_module(&frag),
-                targets: &[wgpu::ColorTargetState {
-                    format: super::output::WgpuOutput::OUTPUT_FORMAT,
-                    write_mask: wgpu::ColorWrite::ALL,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                    }),
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                strip_index_format: None,
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                clamp_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: super::output::WgpuOutput::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Always,
-                bias: wgpu::DepthBiasState::default(),
-                stencil: wgpu::StencilState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+        let vert = device.create_shader_module(&vertex);
+        let frag = device.create_shader_module(&frag);
+        // The fragment shader outputs premultiplied color, textures without premultiplied
+        // alpha are multiplied by their alpha before they are blended
+        let premultiplied = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let create_pipeline = |label: &str, alpha: wgpu::BlendComponent| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout_list),
+                vertex: wgpu::VertexState {
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Vertex2D>() as wgpu::BufferAddress,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                offset: 0,
+                                format: wgpu::VertexFormat::Float32x3,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: 12,
+                                format: wgpu::VertexFormat::Uint32,
+                                shader_location: 1,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: 16,
+                                format: wgpu::VertexFormat::Float32x2,
+                                shader_location: 2,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: 24,
+                                format: wgpu::VertexFormat::Float32x4,
+                                shader_location: 3,
+                            },
+                        ],
+                        step_mode: wgpu::InputStepMode::Vertex,
+                    }],
+                    entry_point: "main",
+                    module: &vert,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    entry_point: "main",
+                    module: &frag,
+                    targets: &[wgpu::ColorTargetState {
+                        format: super::output::WgpuOutput::OUTPUT_FORMAT,
+                        write_mask: wgpu::ColorWrite::ALL,
+                        blend: Some(wgpu::BlendState {
+                            color: premultiplied,
+                            alpha,
+                        }),
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    strip_index_format: None,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    clamp_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: super::output::WgpuOutput::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    bias: wgpu::DepthBiasState::default(),
+                    stencil: wgpu::StencilState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+
+        let pipeline_list = create_pipeline(
+            "2d-pipeline",
+            wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
             },
+        );
+        let pipeline_layer = create_pipeline("2d-layer-pipeline", premultiplied);
+
+        let quad_vert = include_bytes!("../shaders/quad.vert.spv");
+        let quad_frag = include_bytes!("../shaders/quad.frag.spv");
+        let clear_frag = include_bytes!("../shaders/2d_clear.frag.spv");
+        let quad_vert = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(quad_vert.as_quad_bytes().into()),
+        });
+        let quad_frag = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(quad_frag.as_quad_bytes().into()),
+        });
+        let clear_frag = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(clear_frag.as_quad_bytes().into()),
         });
 
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("2d-composite-bind-group-layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("2d-composite-sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            ..Default::default()
+        });
+
+        let quad_pipeline = |label: &str,
+                             layout: &wgpu::PipelineLayout,
+                             frag: &wgpu::ShaderModule,
+                             blend: Option<wgpu::BlendState>,
+                             depth: bool| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    buffers: &[],
+                    entry_point: "main",
+                    module: &quad_vert,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    entry_point: "main",
+                    module: frag,
+                    targets: &[wgpu::ColorTargetState {
+                        format: super::output::WgpuOutput::OUTPUT_FORMAT,
+                        write_mask: wgpu::ColorWrite::ALL,
+                        blend,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    strip_index_format: None,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    clamp_depth: false,
+                    conservative: false,
+                },
+                // The clear shares the render pass of the 2D draws and their depth buffer
+                depth_stencil: if depth {
+                    Some(wgpu::DepthStencilState {
+                        format: super::output::WgpuOutput::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Always,
+                        bias: wgpu::DepthBiasState::default(),
+                        stencil: wgpu::StencilState::default(),
+                    })
+                } else {
+                    None
+                },
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+
+        let clear_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("2d-clear-layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let clear_pipeline =
+            quad_pipeline("2d-clear-pipeline", &clear_layout, &clear_frag, None, true);
+
+        let composite_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("2d-composite-layout"),
+                bind_group_layouts: &[&composite_layout],
+                push_constant_ranges: &[],
+            });
+        let composite_pipeline = quad_pipeline(
+            "2d-composite-pipeline",
+            &composite_pipeline_layout,
+            &quad_frag,
+            Some(wgpu::BlendState {
+                color: premultiplied,
+                alpha: premultiplied,
+            }),
+            false,
+        );
+
         Self {
             // pipeline,
             pipeline_list,
             // layout,
             layout_list,
+            pipeline_layer,
+            clear_pipeline,
+            composite_pipeline,
+            composite_layout,
+            sampler,
+            layer: None,
+            dirty: DirtyRegions::default(),
+        }
+    }
+
+    /// Draws the 2D overlay into a persistent layer of `width` by `height` pixels when
+    /// `enabled`, which only redraws its dirty regions. Otherwise the overlay is drawn over
+    /// the output every frame.
+    pub fn set_layer(&mut self, device: &wgpu::Device, enabled: bool, width: u32, height: u32) {
+        if !enabled {
+            self.layer = None;
+            return;
+        }
+
+        if self
+            .layer
+            .as_ref()
+            .map_or(false, |l| l.width == width && l.height == height)
+        {
+            return;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("2d-layer"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: super::output::WgpuOutput::OUTPUT_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("2d-composite-bind-group"),
+            layout: &self.composite_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        self.layer = Some(Layer {
+            view,
+            bind_group,
+            width,
+            height,
+        });
+        self.dirty.mark_all();
+    }
+
+    pub fn has_layer(&self) -> bool {
+        self.layer.is_some()
+    }
+
+    /// Redraws the dirty regions of the layer and composites it over `output`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_layer(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bg: &wgpu::BindGroup,
+        textures_bg: &wgpu::BindGroup,
+        list: &VertexList<Vertex2D>,
+        instances: &InstanceList<Mat4>,
+        matrix: Mat4,
+        output: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+    ) {
+        let layer = match self.layer.as_ref() {
+            Some(layer) => layer,
+            None => return,
+        };
+
+        let rects = self.dirty.take(matrix, layer.width, layer.height);
+        if !rects.is_empty() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("2d-layer"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                    view: &layer.view,
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0_f32),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            let v_ranges = list.get_ranges();
+            let ranges = instances.get_ranges();
+            for [x, y, width, height] in rects {
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_pipeline(&self.clear_pipeline);
+                render_pass.draw(0..6, 0..1);
+
+                render_pass.set_pipeline(&self.pipeline_layer);
+                render_pass.set_vertex_buffer(0, list.get_vertex_buffer().buffer().slice(..));
+                render_pass.set_bind_group(0, camera_bg, &[]);
+                render_pass.set_bind_group(1, textures_bg, &[]);
+                for (i, r) in ranges.iter() {
+                    let v = v_ranges.get(i).unwrap();
+                    render_pass.draw(v.start..v.end, r.start..r.end);
+                }
+            }
         }
+
+        // Layers without any 2D instances only contain cleared pixels
+        if instances.get_ranges().is_empty() {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("2d-composite"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+                view: output,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, &layer.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -308,3 +742,34 @@ impl Mesh {
         )));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dirty_regions_project_and_merge() {
+        let mut dirty = DirtyRegions::default();
+        // The first frame and camera changes redraw everything
+        assert_eq!(dirty.take(Mat4::IDENTITY, 100, 50), vec![[0, 0, 100, 50]]);
+        assert!(dirty.take(Mat4::IDENTITY, 100, 50).is_empty());
+
+        let mut bounds = Aabb::empty();
+        bounds.grow(Vec3::new(0.0, 0.0, 0.0));
+        bounds.grow(Vec3::new(0.5, 0.5, 0.0));
+        dirty.mark_bounds(bounds);
+        assert_eq!(dirty.take(Mat4::IDENTITY, 100, 50), vec![[49, 11, 27, 15]]);
+
+        for i in 0..=DirtyRegions::MAX_REGIONS as u32 {
+            dirty.mark_rect([i * 10, 0, 5, 5]);
+        }
+        assert_eq!(dirty.take(Mat4::IDENTITY, 100, 50), vec![[0, 0, 85, 5]]);
+
+        dirty.mark_rect([90, 40, 20, 20]);
+        assert_eq!(dirty.take(Mat4::IDENTITY, 100, 50), vec![[90, 40, 10, 10]]);
+        assert_eq!(
+            dirty.take(Mat4::from_scale(Vec3::splat(2.0)), 100, 50),
+            vec![[0, 0, 100, 50]]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::mem::ManagedBuffer;
+use crate::output::{WgpuOutput, WgpuView};
+use rfw::prelude::*;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct DecalInstance {
+    world_to_decal: Mat4,
+    decal_to_world: Mat4,
+    material: u32,
+    opacity: f32,
+    _padding: [u32; 2],
+}
+
+impl Default for DecalInstance {
+    fn default() -> Self {
+        Self {
+            world_to_decal: Mat4::IDENTITY,
+            decal_to_world: Mat4::IDENTITY,
+            material: 0,
+            opacity: 0.0,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Projects decals onto the albedo and normals of the G-buffer.
+pub struct DecalPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// Pipelines of right- and left-handed scenes
+    pipelines: [wgpu::RenderPipeline; 2],
+    decals: Vec<Decal>,
+    instances: ManagedBuffer<DecalInstance>,
+}
+
+impl DecalPass {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        textures_bind_group_layout: &wgpu::BindGroupLayout,
+        output: &WgpuOutput,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("decal-bind-group-layout"),
+            entries: &[output.as_sampled_entry(0, wgpu::ShaderStage::FRAGMENT)],
+        });
+        let bind_group = Self::create_bind_group(&device, &bind_group_layout, output);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("decal-layout"),
+            bind_group_layouts: &[
+                uniform_bind_group_layout,
+                textures_bind_group_layout,
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let vert_shader: &[u8] = include_bytes!("../shaders/decal.vert.spv");
+        let frag_shader: &[u8] = include_bytes!("../shaders/decal.frag.spv");
+        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
+        });
+        let frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(frag_shader.as_quad_bytes())),
+        });
+
+        let attributes: Vec<wgpu::VertexAttribute> = (0..8)
+            .map(|i| wgpu::VertexAttribute {
+                offset: (i * std::mem::size_of::<Vec4>()) as wgpu::BufferAddress,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: i as u32,
+            })
+            .chain(
+                [
+                    wgpu::VertexAttribute {
+                        offset: 128,
+                        format: wgpu::VertexFormat::Uint32,
+                        shader_location: 8,
+                    },
+                    wgpu::VertexAttribute {
+                        offset: 132,
+                        format: wgpu::VertexFormat::Float32,
+                        shader_location: 9,
+                    },
+                ]
+                .iter()
+                .copied(),
+            )
+            .collect();
+
+        // The alpha channels of the G-buffer store material and instance data
+        let blend = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let target = wgpu::ColorTargetState {
+            format: WgpuOutput::STORAGE_FORMAT,
+            write_mask: wgpu::ColorWrite::COLOR,
+            blend: Some(blend),
+        };
+
+        // Mirrored left-handed scenes flip the winding of the boxes on screen
+        let pipeline = |front_face: wgpu::FrontFace| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("decal-pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<DecalInstance>() as wgpu::BufferAddress,
+                        attributes: &attributes,
+                        step_mode: wgpu::InputStepMode::Instance,
+                    }],
+                    entry_point: "main",
+                    module: &vert_module,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    entry_point: "main",
+                    module: &frag_module,
+                    targets: &[target.clone(), target.clone()],
+                }),
+                // Drawing the back faces of the boxes keeps decals visible with the camera
+                // inside
+                primitive: wgpu::PrimitiveState {
+                    front_face,
+                    cull_mode: Some(wgpu::Face::Front),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    strip_index_format: None,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    clamp_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+        let pipelines = [
+            pipeline(wgpu::FrontFace::Ccw),
+            pipeline(wgpu::FrontFace::Cw),
+        ];
+
+        Self {
+            bind_group_layout,
+            bind_group,
+            pipelines,
+            decals: Vec::new(),
+            instances: ManagedBuffer::new(device, queue, wgpu::BufferUsage::VERTEX, 1),
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        output: &WgpuOutput,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("decal-bind-group"),
+            layout,
+            entries: &[output.as_binding(0, WgpuView::GBuffer)],
+        })
+    }
+
+    pub fn update_bind_groups(&mut self, device: &wgpu::Device, output: &WgpuOutput) {
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, output);
+    }
+
+    pub fn set_decals(&mut self, decals: &[Decal]) {
+        self.decals = decals.to_vec();
+        if decals.len() > self.instances.len() {
+            self.instances.resize(decals.len().next_power_of_two());
+        }
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.instances.byte_size()
+    }
+
+    /// Blends the decals into the G-buffer in the order of their sort keys, decals with equal
+    /// keys from back to front as seen from `camera`.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform_bind_group: &wgpu::BindGroup,
+        texture_bind_group: &wgpu::BindGroup,
+        output: &WgpuOutput,
+        camera: Vec3,
+        handedness: Handedness,
+    ) {
+        if self.decals.is_empty() {
+            return;
+        }
+
+        let distance = |d: &Decal| (d.transform.w_axis.truncate() - camera).length_squared();
+        self.decals.sort_by(|a, b| {
+            a.sort_key.cmp(&b.sort_key).then_with(|| {
+                distance(b)
+                    .partial_cmp(&distance(a))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+        });
+
+        let count = self.decals.len();
+        for (instance, decal) in self.instances.as_mut_slice()[..count]
+            .iter_mut()
+            .zip(self.decals.iter())
+        {
+            *instance = DecalInstance {
+                world_to_decal: decal.transform.inverse(),
+                decal_to_world: decal.transform,
+                material: decal.material,
+                opacity: decal.opacity,
+                _padding: [0; 2],
+            };
+        }
+        self.instances.copy_to_device_ranged(0, count);
+
+        let load = wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: true,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("decals"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    ops: load,
+                    ..output.as_descriptor(WgpuView::Albedo)
+                },
+                wgpu::RenderPassColorAttachment {
+                    ops: load,
+                    ..output.as_descriptor(WgpuView::Normal)
+                },
+            ],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipelines[handedness as usize]);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, texture_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instances.buffer().slice(..));
+        render_pass.draw(0..36, 0..count as u32);
+    }
+}
@@ -0,0 +1,46 @@
+/// Work of a frame between [rfw::backend::Backend::begin_frame] and
+/// [rfw::backend::Backend::end_frame], which is submitted in a single submission.
+pub struct WgpuFrame {
+    frame: wgpu::SwapChainFrame,
+    /// Commands recorded by the application, submitted after the rendered frame
+    pub encoder: wgpu::CommandEncoder,
+    commands: Vec<wgpu::CommandBuffer>,
+}
+
+impl WgpuFrame {
+    pub(crate) fn new(device: &wgpu::Device, frame: wgpu::SwapChainFrame) -> Self {
+        Self {
+            frame,
+            encoder: device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("frame-encoder"),
+            }),
+            commands: Vec::new(),
+        }
+    }
+
+    /// View of the swap chain image this frame presents, e.g. to draw an overlay on top of
+    /// the rendered frame.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.frame.output.view
+    }
+
+    pub(crate) fn push(&mut self, commands: wgpu::CommandBuffer) {
+        self.commands.push(commands);
+    }
+
+    /// Submits the commands pushed so far, which keeps them ordered before subsequently
+    /// recorded rendering work.
+    pub(crate) fn flush(&mut self, queue: &wgpu::Queue) {
+        if !self.commands.is_empty() {
+            queue.submit(self.commands.drain(..));
+        }
+    }
+
+    /// Submits all work of this frame at once and presents its swap chain image.
+    pub(crate) fn submit(mut self, queue: &wgpu::Queue) {
+        self.commands.push(self.encoder.finish());
+        queue.submit(self.commands);
+        // Dropping the swap chain frame presents it
+        drop(self.frame);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::mem::ManagedBuffer;
+use crate::output::WgpuOutput;
+use rfw::prelude::*;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+struct GridUniform {
+    color: Vec4,
+    /// x: spacing, y: fade distance
+    parameters: Vec4,
+}
+
+/// Draws an infinite grid on the Y=0 plane. A fullscreen triangle intersects the view ray of
+/// every pixel with the plane, lines are found analytically and anti-aliased by their screen
+/// space derivatives.
+pub struct GridPass {
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    uniform: ManagedBuffer<GridUniform>,
+}
+
+impl GridPass {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform = ManagedBuffer::new(device.clone(), queue, wgpu::BufferUsage::UNIFORM, 1);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("grid-bind-group-layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("grid-bind-group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.binding_resource(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("grid-layout"),
+            bind_group_layouts: &[uniform_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vert_shader: &[u8] = include_bytes!("../shaders/grid.vert.spv");
+        let frag_shader: &[u8] = include_bytes!("../shaders/grid.frag.spv");
+        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
+        });
+        let frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(frag_shader.as_quad_bytes())),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("grid-pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                buffers: &[],
+                entry_point: "main",
+                module: &vert_module,
+            },
+            fragment: Some(wgpu::FragmentState {
+                entry_point: "main",
+                module: &frag_module,
+                targets: &[wgpu::ColorTargetState {
+                    format: WgpuOutput::OUTPUT_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                strip_index_format: None,
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                clamp_depth: false,
+                conservative: false,
+            },
+            // The fragment shader writes the depth of the plane, the grid is occluded by the
+            // scene but leaves the depth buffer untouched
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: WgpuOutput::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                bias: wgpu::DepthBiasState::default(),
+                stencil: wgpu::StencilState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            bind_group,
+            pipeline,
+            uniform,
+        }
+    }
+
+    /// Blends the grid over the output texture, tested against the depth of the scene.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform_bind_group: &wgpu::BindGroup,
+        output: &WgpuOutput,
+        grid: &GridConfig,
+    ) {
+        self.uniform.as_mut_slice()[0] = GridUniform {
+            color: grid.color,
+            parameters: Vec4::new(grid.spacing.max(1e-3), grid.fade_distance, 0.0, 0.0),
+        };
+        self.uniform.copy_to_device();
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("grid"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &output.output_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &output.depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
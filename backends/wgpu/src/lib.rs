@@ -12,31 +12,231 @@ use std::{
     rc::Rc,
 };
 
+mod bundle;
 mod d2;
+mod decal;
+mod frame;
+mod grid;
 mod light;
 mod list;
 mod mat;
 mod mem;
 mod mesh;
+mod mipmap;
+mod outline;
 mod output;
+mod particles;
 mod pass;
+mod pick;
 mod pipeline;
+mod query;
+mod skybox;
+mod upload;
+mod xray;
 
 use crate::mem::ManagedBuffer;
+use bundle::{GeometryBundles, GeometryDraw, GeometryResources};
 use list::*;
 use mat::*;
+use pipeline::DepthTest;
+use query::{GpuTimer, TimedPass};
+use skybox::WgpuSkybox;
+use upload::UploadFences;
 
+pub use frame::WgpuFrame;
 pub use output::WgpuOutput;
 
 #[derive(Debug)]
 pub struct WgpuSettings {
+    /// Shown by renders in [RenderMode::Default], other render modes show their own view.
+    /// Set through [WgpuBackend::set_view] or the `view` setting.
     pub view: WgpuView,
     pub enable_skinning: bool,
+    /// Constant offset subtracted from the receiver depth before the shadow comparison.
+    /// Raise when surfaces shadow themselves (acne), lower when shadows detach from their
+    /// casters (peter-panning). Defaults to 0.0002.
+    pub shadow_depth_bias: f32,
+    /// World-space distance receivers are moved along their normal before looking up the
+    /// shadow map. Scales with scene size, 0.01-0.05 works well for meter-scale scenes.
+    /// Defaults to 0.0.
+    pub shadow_normal_bias: f32,
+    /// Depth offset applied while rendering shadow maps, scaled by the depth slope of the
+    /// caster. Mostly helps acne on surfaces at grazing angles. Defaults to 0.0.
+    pub shadow_slope_bias: f32,
+    /// Snaps the shadow maps of directional lights to whole texels, so the shadow edges of
+    /// static geometry do not crawl when the shadow frustum follows the scene bounds. Costs up
+    /// to a texel of shadow map coverage at the borders. Defaults to false.
+    pub shadow_stabilize: bool,
+    /// Ray-marches the height maps of materials to offset their texture coordinates.
+    /// Defaults to true.
+    pub parallax_enabled: bool,
+    /// Number of separable blur iterations applied to the ambient occlusion, 0 leaves it
+    /// unfiltered. Defaults to 1.
+    pub ssao_blur_passes: u32,
+    /// Offset added to the mip level selected when sampling material textures, negative values
+    /// sharpen distant textures while positive values reduce shimmering. Materials with
+    /// nearest filtering also select their mip level without interpolation, for them the bias
+    /// only takes effect in whole levels. Defaults to 0.0.
+    pub texture_lod_bias: f32,
+    /// Present mode of the swap chain, see [WgpuSettings::PRESENT_MODES]. Immediate
+    /// presentation has the lowest latency, mailbox presentation triple buffers without
+    /// tearing and FIFO waits for vertical sync. wgpu does not expose the image count of its
+    /// swap chains, the present mode decides how many images are buffered. Defaults to
+    /// immediate.
+    pub present_mode: wgpu::PresentMode,
+    /// Multiplier of the skybox where it is seen in the background. The rasterizer does not
+    /// light surfaces with the skybox, so there is no lighting counterpart. Defaults to 1.0.
+    pub skybox_background_intensity: f32,
+    /// Sorts the draws of the geometry pass by pipeline, skin and mesh instead of recording
+    /// them in mesh order, which saves state changes without changing the image. Defaults to
+    /// true.
+    pub sort_draws: bool,
+    /// Renders the depth of opaque meshes before the geometry pass, which then only shades
+    /// the visible fragment of every pixel. Only pays off in scenes with a lot of overdraw,
+    /// as all geometry is drawn twice. Defaults to false.
+    pub depth_prepass: bool,
+    /// Bins the luminance of every frame for [Backend::luminance_histogram], which costs an
+    /// extra compute pass over the output. Defaults to false.
+    pub luminance_histogram: bool,
+    /// Adapts the exposure to the average brightness of the scene, the exposure of
+    /// [ColorGrading] is then applied on top as compensation. Defaults to false.
+    pub auto_exposure: bool,
+    /// Rate per second at which auto-exposure approaches the exposure of the current frame,
+    /// higher values adapt faster. Defaults to 1.0.
+    pub exposure_adaptation: f32,
+    /// Largest width or height of uploaded textures, rounded down to a power of two. Larger
+    /// textures drop their mip levels above the limit or are downscaled, applied to textures
+    /// set afterwards. 0 uploads textures at their native size, which is the default.
+    pub max_texture_size: u32,
+    /// Keeps the 2D overlay in a separate layer and only redraws the regions covered by
+    /// changed 2D meshes and instances, or marked through [Backend::mark_2d_dirty]. Saves
+    /// work for mostly static overlays at the cost of a texture the size of the output.
+    /// Defaults to false.
+    pub d2_dirty_regions: bool,
+    /// Multiplier applied to the capacity of the instance buffers when they run out of space.
+    /// Buffers never shrink, so larger factors trade memory for fewer reallocations.
+    /// Defaults to 2.0.
+    pub instance_growth_factor: f32,
+    /// Number of instances the instance buffers are allocated with. Defaults to 512.
+    pub instance_min_capacity: u32,
+    /// Renders at the window size multiplied by its scale factor. When disabled the render
+    /// resolution is the window size as passed to [Backend::resize], which leaves DPI scaling
+    /// to the application. Defaults to true.
+    pub render_at_native_scale: bool,
+    /// Culls spot and area lights per 16x16 pixel tile before the lighting pass, so every
+    /// pixel only shades the lights that reach it. Pays off with many lights, lights are
+    /// ignored where their radiance falls below 0.0005. Defaults to false.
+    pub light_culling: bool,
+    /// Caps the number of presented frames per second, the backend sleeps after presenting
+    /// until the next frame is due. Immediate and mailbox presentation do not wait for
+    /// vertical sync, so without a cap simple scenes keep the GPU fully busy. FIFO
+    /// presentation already caps the rate at the refresh rate and is not paced. Surfaces that
+    /// fall back to FIFO because they lack the selected mode are paced still, as wgpu does not
+    /// report the fallback. 0 renders as fast as possible, which is the default.
+    pub target_fps: u32,
+    pub color_grading: ColorGrading,
+    pub output_transfer: OutputTransfer,
+    pub material_debug: MaterialDebug,
+    /// Planes set through [Backend::set_clip_planes], unused planes are (0, 0, 0, 1) and
+    /// keep every point.
+    pub clip_planes: [Vec4; MAX_CLIP_PLANES],
+    /// Set through [Backend::set_reveal_region]
+    pub reveal_region: Option<RevealRegion>,
+    /// Mesh and index of the only instance drawn by the geometry pass, shadows are still cast
+    /// by the whole scene.
+    pub isolated_instance: Option<(usize, usize)>,
+    /// Set through [Backend::set_handedness]
+    pub handedness: Handedness,
+    /// Set through [Backend::set_wind]
+    pub wind: Wind,
+    /// Color of the outlines of selected instances, see [Backend::set_selected_instances]
+    pub outline_color: Vec4,
+    /// Width in pixels of the outlines of selected instances. Defaults to 2.
+    pub outline_width: f32,
+    /// Color blended over the hidden parts of instances with [InstanceEffect::Xray]
+    pub xray_color: Vec4,
+    /// Set through [Backend::set_grid], kept while the grid is hidden
+    pub grid: GridConfig,
+    /// Draws the reference grid, toggled by [Backend::set_grid] and the `grid` setting.
+    /// Defaults to false.
+    pub grid_visible: bool,
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
     scale_factor: f64,
 }
 
+impl WgpuSettings {
+    pub const SHADOW_DEPTH_BIAS: &'static str = "shadow-depth-bias";
+    pub const SHADOW_NORMAL_BIAS: &'static str = "shadow-normal-bias";
+    pub const SHADOW_SLOPE_BIAS: &'static str = "shadow-slope-bias";
+    pub const SHADOW_STABILIZE: &'static str = "shadow-stabilize";
+    pub const PARALLAX_ENABLED: &'static str = "parallax-enabled";
+    pub const SSAO_BLUR_PASSES: &'static str = "ssao-blur-passes";
+    pub const TEXTURE_LOD_BIAS: &'static str = "texture-lod-bias";
+    /// Index into [WgpuSettings::PRESENT_MODES] or one of [WgpuSettings::PRESENT_MODE_NAMES].
+    /// wgpu cannot list the present modes a surface supports, surfaces without the requested
+    /// mode fall back to FIFO (vsync).
+    pub const PRESENT_MODE: &'static str = "present-mode";
+    pub const SKYBOX_BACKGROUND_INTENSITY: &'static str = "skybox-background-intensity";
+    pub const SORT_DRAWS: &'static str = "sort-draws";
+    pub const DEPTH_PREPASS: &'static str = "depth-prepass";
+    pub const LUMINANCE_HISTOGRAM: &'static str = "luminance-histogram";
+    pub const AUTO_EXPOSURE: &'static str = "auto-exposure";
+    pub const EXPOSURE_ADAPTATION: &'static str = "exposure-adaptation";
+    pub const MAX_TEXTURE_SIZE: &'static str = "max-texture-size";
+    pub const D2_DIRTY_REGIONS: &'static str = "2d-dirty-regions";
+    pub const INSTANCE_GROWTH_FACTOR: &'static str = "instance-growth-factor";
+    pub const INSTANCE_MIN_CAPACITY: &'static str = "instance-min-capacity";
+    pub const RENDER_AT_NATIVE_SCALE: &'static str = "render-at-native-scale";
+    pub const LIGHT_CULLING: &'static str = "light-culling";
+    pub const TARGET_FPS: &'static str = "target-fps";
+    pub const VIEW: &'static str = "view";
+    pub const OUTLINE_WIDTH: &'static str = "outline-width";
+    pub const GRID: &'static str = "grid";
+
+    const DEFAULT_SHADOW_DEPTH_BIAS: f32 = 0.0002;
+    /// Present modes selectable through the [WgpuSettings::PRESENT_MODE] setting.
+    pub const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+        wgpu::PresentMode::Immediate,
+        wgpu::PresentMode::Mailbox,
+        wgpu::PresentMode::Fifo,
+    ];
+    /// Names of the present modes, indexed like [WgpuSettings::PRESENT_MODES].
+    pub const PRESENT_MODE_NAMES: [&'static str; 3] = ["immediate", "mailbox", "fifo"];
+
+    const DEFAULT_PRESENT_MODE: wgpu::PresentMode = wgpu::PresentMode::Immediate;
+    const DEFAULT_INSTANCE_GROWTH: GrowthStrategy = GrowthStrategy::new(2.0, 512);
+
+    fn shadow_bias(&self) -> Vec4 {
+        Vec4::new(
+            self.shadow_depth_bias,
+            self.shadow_normal_bias,
+            self.shadow_slope_bias,
+            0.0,
+        )
+    }
+
+    /// Size of the output for a window of `width` by `height`.
+    fn render_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let scale = if self.render_at_native_scale {
+            self.scale_factor
+        } else {
+            1.0
+        };
+        (
+            ((width as f64 * scale) as u32).max(1),
+            ((height as f64 * scale) as u32).max(1),
+        )
+    }
+
+    fn instance_growth(&self) -> GrowthStrategy {
+        GrowthStrategy::new(
+            self.instance_growth_factor,
+            self.instance_min_capacity as usize,
+        )
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default)]
 #[repr(C)]
 pub struct UniformCamera {
@@ -45,6 +245,24 @@ pub struct UniformCamera {
     pub matrix_2d: Mat4,
     pub light_count: [u32; 4],
     pub position: Vec4,
+    /// x: 1 if parallax occlusion mapping is enabled, y: skybox mode,
+    /// z: material values overridden by `material_debug`, w: bits of the texture lod bias
+    pub options: [u32; 4],
+    /// Reconstructs view directions of background pixels
+    pub inv_view_proj: Mat4,
+    /// View-projection matrix of the previous frame, used for motion vectors
+    pub prev_view_proj: Mat4,
+    /// Albedo gray level, metallic and roughness of [MaterialDebug]
+    pub material_debug: [f32; 4],
+    pub clip_planes: [Vec4; MAX_CLIP_PLANES],
+    /// x: multiplier of the skybox background
+    pub skybox_intensity: [f32; 4],
+    /// x: seconds since the backend was created, drives flipbook playback and wind
+    pub time: [f32; 4],
+    /// Layout of [Wind::to_device]
+    pub wind: [f32; 4],
+    /// Layout of [RevealRegion::to_device]
+    pub reveal: [[f32; 4]; 3],
 }
 
 bitflags! {
@@ -57,17 +275,50 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct InstanceMatrices {
     pub matrix: Mat4,
     pub normal: Mat4,
+    /// Matrix of the previous frame, used for motion vectors
+    pub prev_matrix: Mat4,
+    /// Multiplied into the albedo of this instance
+    pub color_tint: Vec4,
+    /// x: offset in seconds of the flipbook playback of this instance, y: wind weight,
+    /// z: id of its [InstanceEffect]
+    pub animation: Vec4,
+}
+
+impl Default for InstanceMatrices {
+    fn default() -> Self {
+        Self {
+            matrix: Mat4::IDENTITY,
+            normal: Mat4::IDENTITY,
+            prev_matrix: Mat4::IDENTITY,
+            color_tint: Vec4::ONE,
+            animation: Vec4::ZERO,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct InstanceExtra {
     flags: Vec<InstanceFlags3D>,
     skin_ids: Vec<Option<u16>>,
+    user_data: Vec<u64>,
     local_aabb: Aabb,
+    /// Whether `local_aabb` was derived from the mesh bounds because the supplied one was empty
+    derived_aabb: bool,
+    /// World-space bounds of all instances in this list
+    bounds: Aabb,
+}
+
+/// Instance found by [WgpuBackend::pick].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PickedInstance {
+    pub mesh: usize,
+    pub instance: usize,
+    /// Set through the user data of the scene instance
+    pub user_data: u64,
 }
 
 pub struct WgpuBackend {
@@ -75,21 +326,39 @@ pub struct WgpuBackend {
     queue: Arc<wgpu::Queue>,
     surface: wgpu::Surface,
     swap_chain: wgpu::SwapChain,
+    /// Set while the window has a zero size, the swap chain then keeps its last valid size
+    minimized: bool,
+    window_size: (u32, u32),
 
     mesh_flags: Vec<Mesh3dFlags>,
+    /// Local bounds of every 3D mesh, used for instances that are supplied without bounds
+    mesh_bounds_3d: Vec<Aabb>,
     vertices_3d: VertexList<Vertex3D, JointData>,
     instances_3d_storage: Vec<Rc<Vec<InstanceMatrices>>>,
     instances_3d: InstanceList<InstanceMatrices, InstanceExtra>,
+    /// Meshes with instances that moved since the last frame, their previous matrices are
+    /// reset once the frame is rendered
+    moving_instances: Vec<usize>,
+    /// Effects of the instances of every 3D mesh, kept when its instances are set again
+    instance_effects: Vec<Vec<InstanceEffect>>,
     vertices_2d: VertexList<Vertex2D, u32>,
+    /// Local bounds of every 2D mesh, used to find the dirty regions of the 2D layer
+    mesh_bounds_2d: Vec<Aabb>,
     instances_2d: InstanceList<Mat4>,
 
     update_flags: UpdateFlags,
 
     material_buffer: ManagedBuffer<DeviceMaterial>,
     texture_sampler: wgpu::Sampler,
+    /// Used by materials with [TextureFilter::Nearest] diffuse maps
+    nearest_sampler: wgpu::Sampler,
     textures: FlaggedStorage<WgpuTexture>,
+    /// Renders the mip levels of textures that are uploaded without them
+    mip_generator: mipmap::MipGenerator,
     texture_bind_group: wgpu::BindGroup,
     texture_bind_group_layout: wgpu::BindGroupLayout,
+    /// One bit per texture slot, set for textures with premultiplied alpha
+    texture_flags: ManagedBuffer<[u32; 4]>,
     lights: light::WgpuLights,
 
     uniform_bind_group_layout: wgpu::BindGroupLayout,
@@ -99,10 +368,21 @@ pub struct WgpuBackend {
     output: output::WgpuOutput,
     pipeline: pipeline::RenderPipeline,
     scene_bounds: Aabb,
+    /// Recorded geometry pass, cleared whenever the scene or its bind groups change
+    geometry_bundles: Option<GeometryBundles>,
 
     ssao_pass: pass::SsaoPass,
+    decal_pass: decal::DecalPass,
+    outline_pass: outline::OutlinePass,
+    xray_pass: xray::XrayPass,
+    pick_pass: pick::PickPass,
+    grid_pass: grid::GridPass,
     radiance_pass: pass::RadiancePass,
+    skybox: WgpuSkybox,
+    tonemap_pass: pass::ToneMapPass,
     blit_pass: pass::BlitPass,
+    histogram_pass: pass::HistogramPass,
+    post_process_pass: pass::PostProcessPass,
     output_pass: pass::QuadPass,
 
     skin_layout: wgpu::BindGroupLayout,
@@ -112,6 +392,15 @@ pub struct WgpuBackend {
     instances_changed: bool,
 
     d2_renderer: d2::Renderer,
+    particles: particles::ParticleRenderer,
+    timer: Option<GpuTimer>,
+    uploads: UploadFences,
+    /// Frame started by [Backend::begin_frame]
+    frame: Option<WgpuFrame>,
+    /// Start of the flipbook playback
+    start_time: std::time::Instant,
+    /// When the last frame was presented, or was due if it was presented early
+    last_present: Option<std::time::Instant>,
 
     settings: WgpuSettings,
 }
@@ -119,20 +408,34 @@ pub struct WgpuBackend {
 #[derive(Debug, Copy, Clone)]
 enum WgpuError {
     RequestDeviceError,
+    MissingFeatures(wgpu::Features),
+    InsufficientLimits,
 }
 
 impl std::error::Error for WgpuError {}
 
 impl Display for WgpuError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Could not retrieve valid device.")
+        match self {
+            WgpuError::RequestDeviceError => write!(f, "Could not retrieve valid device."),
+            WgpuError::MissingFeatures(features) => {
+                // Debug output of the flags lists the name of every missing feature
+                write!(
+                    f,
+                    "Device is missing features required by the wgpu backend: {:?}",
+                    features
+                )
+            }
+            WgpuError::InsufficientLimits => {
+                write!(f, "Device limits are below the required limits.")
+            }
+        }
     }
 }
 
 impl WgpuBackend {
     const TEXTURE_CAPACITY: usize = 128;
-    const PRESENT_MODE: wgpu::PresentMode = wgpu::PresentMode::Immediate;
-    const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+    pub const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
     const UNIFORM_CAMERA_SIZE: wgpu::BufferAddress = (std::mem::size_of::<Mat4>()
         + std::mem::size_of::<Mat4>()
         + std::mem::size_of::<[u32; 4]>()
@@ -142,19 +445,102 @@ impl WgpuBackend {
     fn create_texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("texture-bind-group-layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                count: NonZeroU32::new(Self::TEXTURE_CAPACITY as _),
-                visibility: wgpu::ShaderStage::FRAGMENT,
-                ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Uint,
-                    multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    count: NonZeroU32::new(Self::TEXTURE_CAPACITY as _),
+                    // Vertex shaders sample displacement maps
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                },
+                // Bits of the textures with premultiplied alpha
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
                 },
-            }],
+            ],
         })
     }
 
+    /// Binds the views of all textures, unused slots are filled with the placeholder texture.
+    fn update_texture_bind_group(&mut self) {
+        let mut texture_views = Vec::with_capacity(Self::TEXTURE_CAPACITY);
+        let dummy_ref = &self.textures[0].view.as_ref().as_ref().unwrap();
+        for (_, t) in self.textures.iter() {
+            texture_views.push(if let Some(view) = t.view.as_ref() {
+                view
+            } else {
+                dummy_ref
+            });
+        }
+
+        while texture_views.len() < Self::TEXTURE_CAPACITY {
+            texture_views.push(dummy_ref);
+        }
+
+        self.geometry_bundles = None;
+        self.texture_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("textures-bind-group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureViewArray(texture_views.as_slice()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.texture_flags.binding_resource(),
+                },
+            ],
+        });
+    }
+
+    /// Binds the current camera, material and instance buffers, which changes whenever one of
+    /// them is reallocated.
+    fn update_uniform_bind_group(&mut self) {
+        self.geometry_bundles = None;
+        self.uniform_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("uniform-bind-group"),
+            layout: &self.uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.camera_buffer.binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.material_buffer.binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.instances_2d.get_buffer().binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.instances_3d.get_buffer().binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&self.nearest_sampler),
+                },
+            ],
+        });
+    }
+
     fn create_uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("uniform-layout"),
@@ -176,7 +562,9 @@ impl WgpuBackend {
                     // Material mem
                     binding: 1,
                     count: None,
-                    visibility: wgpu::ShaderStage::FRAGMENT | wgpu::ShaderStage::COMPUTE,
+                    visibility: wgpu::ShaderStage::VERTEX
+                        | wgpu::ShaderStage::FRAGMENT
+                        | wgpu::ShaderStage::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         has_dynamic_offset: false,
                         ty: wgpu::BufferBindingType::Storage { read_only: true },
@@ -187,7 +575,9 @@ impl WgpuBackend {
                     // Texture sampler
                     binding: 2,
                     count: None,
-                    visibility: wgpu::ShaderStage::FRAGMENT | wgpu::ShaderStage::COMPUTE,
+                    visibility: wgpu::ShaderStage::VERTEX
+                        | wgpu::ShaderStage::FRAGMENT
+                        | wgpu::ShaderStage::COMPUTE,
                     ty: wgpu::BindingType::Sampler {
                         filtering: true,
                         comparison: false,
@@ -219,6 +609,16 @@ impl WgpuBackend {
                         min_binding_size: None,
                     },
                 },
+                wgpu::BindGroupLayoutEntry {
+                    // Nearest texture sampler
+                    binding: 5,
+                    count: None,
+                    visibility: wgpu::ShaderStage::FRAGMENT | wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                },
             ],
         })
     }
@@ -242,41 +642,104 @@ impl FromWindowHandle for WgpuBackend {
             Some(adapter) => adapter,
         };
 
-        let (render_width, render_height) = (
-            (width as f64 * scale) as u32,
-            (height as f64 * scale) as u32,
-        );
-        let width = width as u32;
-        let height = height as u32;
+        // Requesting a device with unsupported features fails without telling which ones
+        let missing = Self::required_features() - adapter.features();
+        if !missing.is_empty() {
+            return Err(Box::new(WgpuError::MissingFeatures(missing)));
+        }
+
+        // Timestamp queries are optional, frame timings are simply unavailable without them.
+        // Block compressed textures are replaced by a placeholder when unsupported.
+        let optional_features = adapter.features()
+            & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TEXTURE_COMPRESSION_BC);
 
         let (device, queue) = block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::PUSH_CONSTANTS
-                    | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
-                    | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS
-                    | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                limits: wgpu::Limits {
-                    max_sampled_textures_per_shader_stage: Self::TEXTURE_CAPACITY as _,
-                    ..Default::default()
-                },
+                features: Self::required_features() | optional_features,
+                limits: Self::required_limits(),
                 label: Some("rfw-device"),
             },
             None,
         ))
-        .unwrap();
+        .map_err(|_| WgpuError::RequestDeviceError)?;
 
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
+        Self::init_from_device(
+            Arc::new(device),
+            Arc::new(queue),
+            surface,
+            width,
+            height,
+            scale,
+        )
+    }
+}
 
-        let swap_chain = device.create_swap_chain(
-            &surface,
+impl WgpuBackend {
+    /// Features a device must support to be used by this backend. Material textures are
+    /// bound as a single array and the output passes use read-write storage textures.
+    pub fn required_features() -> wgpu::Features {
+        wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
+            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+    }
+
+    /// Limits a device must support to be used by this backend.
+    pub fn required_limits() -> wgpu::Limits {
+        wgpu::Limits {
+            max_sampled_textures_per_shader_stage: Self::TEXTURE_CAPACITY as _,
+            ..Default::default()
+        }
+    }
+
+    fn create_swap_chain(
+        device: &wgpu::Device,
+        surface: &wgpu::Surface,
+        (width, height): (u32, u32),
+        present_mode: wgpu::PresentMode,
+    ) -> wgpu::SwapChain {
+        device.create_swap_chain(
+            surface,
             &wgpu::SwapChainDescriptor {
                 width,
                 height,
                 usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-                format: output::WgpuOutput::OUTPUT_FORMAT,
-                present_mode: Self::PRESENT_MODE,
+                format: Self::OUTPUT_FORMAT,
+                present_mode,
             },
+        )
+    }
+
+    /// Initializes the backend using a device and queue owned by the host application, which
+    /// allows sharing resources such as textures with it. The device must have been created
+    /// with [WgpuBackend::required_features] and [WgpuBackend::required_limits].
+    pub fn init_from_device(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        surface: wgpu::Surface,
+        width: u32,
+        height: u32,
+        scale: f64,
+    ) -> Result<Box<Self>, Box<dyn Error>> {
+        let missing = Self::required_features() - device.features();
+        if !missing.is_empty() {
+            return Err(Box::new(WgpuError::MissingFeatures(missing)));
+        }
+
+        if device.limits().max_sampled_textures_per_shader_stage
+            < Self::required_limits().max_sampled_textures_per_shader_stage
+        {
+            return Err(Box::new(WgpuError::InsufficientLimits));
+        }
+
+        let (render_width, render_height) = (
+            (width as f64 * scale) as u32,
+            (height as f64 * scale) as u32,
+        );
+
+        let swap_chain = Self::create_swap_chain(
+            &device,
+            &surface,
+            (width, height),
+            WgpuSettings::DEFAULT_PRESENT_MODE,
         );
 
         let material_buffer: ManagedBuffer<DeviceMaterial> = ManagedBuffer::new(
@@ -301,18 +764,30 @@ impl FromWindowHandle for WgpuBackend {
                 mip_levels: dummy_tex.mip_levels,
                 bytes: dummy_tex.data.as_bytes(),
                 format: DataFormat::BGRA8,
+                filter: TextureFilter::Linear,
+                premultiplied: false,
             },
         ));
 
+        let texture_flags =
+            ManagedBuffer::new(device.clone(), queue.clone(), wgpu::BufferUsage::UNIFORM, 1);
+        texture_flags.copy_to_device();
+
         let dummy_ref: &wgpu::TextureView = &textures[0].view.as_ref().as_ref().unwrap();
         let references = vec![dummy_ref; Self::TEXTURE_CAPACITY];
         let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("textures-bind-group"),
             layout: &texture_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureViewArray(references.as_slice()),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureViewArray(references.as_slice()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: texture_flags.binding_resource(),
+                },
+            ],
         });
 
         let camera_buffer = ManagedBuffer::new(
@@ -337,12 +812,29 @@ impl FromWindowHandle for WgpuBackend {
             border_color: None,
         });
 
+        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("nearest-texture-sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            compare: None,
+            anisotropy_clamp: None,
+            border_color: None,
+        });
+
         let vertices_3d = VertexList::new(&device, &queue);
         let instances_3d_storage = Default::default();
-        let instances_3d = InstanceList::new(&device, &queue);
+        let instances_3d =
+            InstanceList::new(&device, &queue, WgpuSettings::DEFAULT_INSTANCE_GROWTH);
 
         let vertices_2d = VertexList::new(&device, &queue);
-        let instances_2d = InstanceList::new(&device, &queue);
+        let instances_2d =
+            InstanceList::new(&device, &queue, WgpuSettings::DEFAULT_INSTANCE_GROWTH);
 
         let uniform_bind_group_layout = Self::create_uniform_bind_group_layout(&device);
         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -369,11 +861,16 @@ impl FromWindowHandle for WgpuBackend {
                     binding: 4,
                     resource: instances_3d.get_buffer().binding_resource(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&nearest_sampler),
+                },
             ],
         });
 
         let skin_layout = WgpuSkin::create_layout(&device);
-        let lights = light::WgpuLights::new(10, &device, &uniform_bind_group_layout, &skin_layout);
+        let mut lights =
+            light::WgpuLights::new(10, &device, &uniform_bind_group_layout, &skin_layout);
         let output = output::WgpuOutput::new(&device, render_width, render_height);
 
         let pipeline = pipeline::RenderPipeline::new(
@@ -384,14 +881,37 @@ impl FromWindowHandle for WgpuBackend {
         );
 
         let ssao_pass = pass::SsaoPass::new(&device, &uniform_bind_group_layout, &output);
+        let decal_pass = decal::DecalPass::new(
+            device.clone(),
+            queue.clone(),
+            &uniform_bind_group_layout,
+            &texture_bind_group_layout,
+            &output,
+        );
+        let outline_pass = outline::OutlinePass::new(
+            device.clone(),
+            queue.clone(),
+            &uniform_bind_group_layout,
+            &output,
+        );
+        let xray_pass =
+            xray::XrayPass::new(device.clone(), queue.clone(), &uniform_bind_group_layout);
+        let pick_pass = pick::PickPass::new(&device, &uniform_bind_group_layout);
+        let grid_pass =
+            grid::GridPass::new(device.clone(), queue.clone(), &uniform_bind_group_layout);
+        let skybox = WgpuSkybox::new(&device, &queue);
         let radiance_pass = pass::RadiancePass::new(
             &device,
             camera_buffer.buffer(),
             material_buffer.buffer(),
             &output,
             &lights,
+            &skybox,
         );
-        let blit_pass = pass::BlitPass::new(&device, &output);
+        let tonemap_pass = pass::ToneMapPass::new(&device, &output);
+        let blit_pass = pass::BlitPass::new(&device, tonemap_pass.display_view());
+        let histogram_pass = pass::HistogramPass::new(&device, &output);
+        let post_process_pass = pass::PostProcessPass::new(&device, &output);
         let output_pass = pass::QuadPass::new(&device, &output);
 
         let d2_renderer = d2::Renderer::new(
@@ -400,33 +920,85 @@ impl FromWindowHandle for WgpuBackend {
             &texture_bind_group_layout,
         );
 
+        let particles = particles::ParticleRenderer::new(
+            device.clone(),
+            queue.clone(),
+            &uniform_bind_group_layout,
+        );
+
+        let timer = GpuTimer::new(&device, &queue);
+
         let settings = WgpuSettings {
             view: WgpuView::Output,
             enable_skinning: true,
+            shadow_depth_bias: WgpuSettings::DEFAULT_SHADOW_DEPTH_BIAS,
+            shadow_normal_bias: 0.0,
+            shadow_slope_bias: 0.0,
+            shadow_stabilize: false,
+            parallax_enabled: true,
+            ssao_blur_passes: 1,
+            texture_lod_bias: 0.0,
+            present_mode: WgpuSettings::DEFAULT_PRESENT_MODE,
+            skybox_background_intensity: 1.0,
+            sort_draws: true,
+            depth_prepass: false,
+            luminance_histogram: false,
+            auto_exposure: false,
+            exposure_adaptation: 1.0,
+            max_texture_size: 0,
+            d2_dirty_regions: false,
+            instance_growth_factor: WgpuSettings::DEFAULT_INSTANCE_GROWTH.factor,
+            instance_min_capacity: WgpuSettings::DEFAULT_INSTANCE_GROWTH.min_capacity as u32,
+            render_at_native_scale: true,
+            light_culling: false,
+            target_fps: 0,
+            color_grading: ColorGrading::default(),
+            output_transfer: OutputTransfer::default(),
+            material_debug: MaterialDebug::default(),
+            clip_planes: [Vec4::W; MAX_CLIP_PLANES],
+            reveal_region: None,
+            isolated_instance: None,
+            handedness: Handedness::default(),
+            wind: Wind::default(),
+            outline_color: Vec4::new(1.0, 0.6, 0.1, 1.0),
+            outline_width: 2.0,
+            xray_color: Vec4::new(0.3, 0.7, 1.0, 0.35),
+            grid: GridConfig::default(),
+            grid_visible: false,
             device: device.clone(),
             queue: queue.clone(),
             scale_factor: scale,
         };
+        lights.set_bias(settings.shadow_bias());
 
         Ok(Box::new(Self {
             device,
             queue,
             surface,
             swap_chain,
+            minimized: false,
+            window_size: (width, height),
 
             mesh_flags: Vec::new(),
+            mesh_bounds_3d: Vec::new(),
             vertices_3d,
             instances_3d_storage,
             instances_3d,
+            moving_instances: Vec::new(),
+            instance_effects: Vec::new(),
             vertices_2d,
+            mesh_bounds_2d: Vec::new(),
             instances_2d,
             update_flags: Default::default(),
 
             material_buffer,
             texture_sampler,
+            nearest_sampler,
             textures,
+            mip_generator: mipmap::MipGenerator::new(&device),
             texture_bind_group,
             texture_bind_group_layout,
+            texture_flags,
             lights,
 
             uniform_bind_group_layout,
@@ -435,10 +1007,20 @@ impl FromWindowHandle for WgpuBackend {
             output,
             pipeline,
             ssao_pass,
+            decal_pass,
+            outline_pass,
+            xray_pass,
+            pick_pass,
+            grid_pass,
             radiance_pass,
+            skybox,
+            tonemap_pass,
             blit_pass,
+            histogram_pass,
+            post_process_pass,
             output_pass,
             scene_bounds: Aabb::empty(),
+            geometry_bundles: None,
 
             skin_layout,
             skins: TrackedStorage::new(),
@@ -447,6 +1029,12 @@ impl FromWindowHandle for WgpuBackend {
             instances_changed: true,
 
             d2_renderer,
+            particles,
+            timer,
+            uploads: UploadFences::default(),
+            frame: None,
+            start_time: std::time::Instant::now(),
+            last_present: None,
             settings,
         }))
     }
@@ -454,6 +1042,24 @@ impl FromWindowHandle for WgpuBackend {
 
 impl Backend for WgpuBackend {
     fn set_2d_mesh(&mut self, id: usize, mesh: MeshData2D) {
+        let mut bounds = Aabb::empty();
+        for v in mesh.vertices.iter() {
+            bounds.grow(Vec3::from(v.vertex));
+        }
+
+        if self.mesh_bounds_2d.len() <= id {
+            self.mesh_bounds_2d.resize(id + 1, Aabb::empty());
+        }
+
+        // Both the previous and the new shape of every instance are redrawn
+        if self.d2_renderer.has_layer() {
+            self.mark_2d_instances_dirty(id);
+            self.mesh_bounds_2d[id] = bounds;
+            self.mark_2d_instances_dirty(id);
+        } else {
+            self.mesh_bounds_2d[id] = bounds;
+        }
+
         if self.vertices_2d.has(id) {
             self.vertices_2d
                 .update_pointer(id, mesh.vertices.to_vec(), Vec::new());
@@ -465,7 +1071,37 @@ impl Backend for WgpuBackend {
         self.update_flags |= UpdateFlags::UPDATE_2D_MESHES;
     }
 
-    fn set_2d_instances(&mut self, id: usize, instances: InstancesData2D<'_>) {
+    fn set_2d_instances(
+        &mut self,
+        id: usize,
+        instances: InstancesData2D<'_>,
+    ) -> Result<(), BackendError> {
+        if !self.vertices_2d.has(id) {
+            return Err(BackendError::InvalidMeshId(id));
+        }
+
+        // Instances that moved are redrawn at both their previous and their new position
+        let bounds = self.mesh_bounds_2d.get(id);
+        if let (Some(bounds), true) = (bounds, self.d2_renderer.has_layer()) {
+            let previous = self
+                .instances_2d
+                .get_ranges()
+                .get(&id)
+                .map(|r| r.data())
+                .unwrap_or(&[]);
+            let dirty = &mut self.d2_renderer.dirty;
+            for i in 0..previous.len().max(instances.matrices.len()) {
+                let (old, new) = (previous.get(i), instances.matrices.get(i));
+                if old == new {
+                    continue;
+                }
+
+                for matrix in old.into_iter().chain(new) {
+                    dirty.mark_bounds(transform_aabb(bounds, matrix));
+                }
+            }
+        }
+
         if self.instances_2d.has(id) {
             self.instances_2d
                 .update_instances_list(id, instances.matrices, ());
@@ -476,6 +1112,7 @@ impl Backend for WgpuBackend {
 
         self.instances_changed = true;
         self.update_flags |= UpdateFlags::UPDATE_2D_INSTANCES;
+        Ok(())
     }
 
     fn set_3d_mesh(&mut self, id: usize, mesh: MeshData3D) {
@@ -487,6 +1124,20 @@ impl Backend for WgpuBackend {
         }
 
         self.mesh_flags[id] = mesh.flags;
+
+        if self.mesh_bounds_3d.len() <= id {
+            self.mesh_bounds_3d.resize(id + 1, Aabb::empty());
+        }
+        self.mesh_bounds_3d[id] = if is_degenerate(&mesh.bounds) {
+            let mut bounds = Aabb::empty();
+            mesh.vertices
+                .iter()
+                .for_each(|v| bounds.grow(v.vertex.truncate()));
+            bounds
+        } else {
+            mesh.bounds
+        };
+
         if self.vertices_3d.has(id) {
             self.vertices_3d
                 .update_pointer(id, mesh.vertices.to_vec(), mesh.skin_data.to_vec());
@@ -498,45 +1149,76 @@ impl Backend for WgpuBackend {
         self.update_flags |= UpdateFlags::UPDATE_3D_MESHES;
     }
 
+    fn get_3d_mesh(&self, id: usize) -> Option<MeshData3D<'_>> {
+        let (vertices, skin_data) = self.vertices_3d.get_pointer(id)?;
+
+        // Triangles and material ranges are only used by path tracers and are not kept
+        Some(MeshData3D {
+            name: "",
+            bounds: self.mesh_bounds_3d[id],
+            vertices,
+            triangles: &[],
+            ranges: &[],
+            skin_data,
+            flags: self.mesh_flags[id],
+        })
+    }
+
+    fn has_3d_mesh(&self, id: usize) -> bool {
+        self.vertices_3d.has(id)
+    }
+
+    fn instance_count(&self, mesh: usize) -> usize {
+        self.instances_3d
+            .get_ranges()
+            .get(&mesh)
+            .map_or(0, |r| r.count as usize)
+    }
+
     fn unload_3d_meshes(&mut self, ids: &[usize]) {
         for id in ids.iter().copied() {
             self.instances_3d.remove_instances_list(id);
             self.vertices_3d.remove_pointer(id);
+            if let Some(storage) = self.instances_3d_storage.get_mut(id) {
+                *storage = Default::default();
+            }
+            if let Some(effects) = self.instance_effects.get_mut(id) {
+                effects.clear();
+            }
+            if let Some(bounds) = self.mesh_bounds_3d.get_mut(id) {
+                *bounds = Aabb::empty();
+            }
         }
+        self.update_flags.insert(UpdateFlags::UPDATE_3D_INSTANCES);
     }
 
-    fn set_3d_instances(&mut self, mesh: usize, instances: InstancesData3D<'_>) {
-        if mesh >= self.instances_3d_storage.len() {
-            self.instances_3d_storage
-                .resize(mesh + 1, Default::default());
-        }
-
-        let vec: Vec<InstanceMatrices> = instances
-            .matrices
-            .iter()
-            .copied()
-            .map(|m| InstanceMatrices {
-                matrix: m,
-                normal: m.inverse().transpose(),
-            })
-            .collect();
-
-        let extra = InstanceExtra {
-            flags: instances.flags.to_vec(),
-            skin_ids: instances
-                .skin_ids
-                .iter()
-                .map(|i| if i.0 >= 0 { Some(i.0 as u16) } else { None })
-                .collect(),
-            local_aabb: instances.local_aabb,
-        };
+    fn set_3d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData3D<'_>,
+    ) -> Result<(), BackendError> {
+        self.update_3d_instances(mesh, instances)?;
+        self.update_flags.insert(UpdateFlags::UPDATE_3D_INSTANCES);
+        Ok(())
+    }
 
-        if self.instances_3d.has(mesh) {
-            self.instances_3d.update_instances_list(mesh, &vec, extra);
-        } else {
-            self.instances_3d.add_instances_list(mesh, vec, extra);
+    fn set_all_3d_instances(
+        &mut self,
+        instances: &[(usize, InstancesData3D<'_>)],
+    ) -> Result<(), BackendError> {
+        let mut result = Ok(());
+        for (mesh, data) in instances.iter() {
+            result = result.and(self.update_3d_instances(*mesh, *data));
         }
 
+        // A single update of the instance buffer for all lists
+        self.update_flags.insert(UpdateFlags::UPDATE_3D_INSTANCES);
+        result
+    }
+
+    fn reserve_3d_instances(&mut self, count: usize) {
+        // Applied with the next update so the uniform bind group picks up the new buffer
+        self.instances_3d.reserve(count);
         self.update_flags.insert(UpdateFlags::UPDATE_3D_INSTANCES);
     }
 
@@ -547,75 +1229,154 @@ impl Backend for WgpuBackend {
             self.material_buffer.copy_to_device();
         }
 
-        self.uniform_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("uniform-bind-group"),
-            layout: &self.uniform_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.camera_buffer.binding_resource(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: self.material_buffer.binding_resource(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: self.instances_2d.get_buffer().binding_resource(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: self.instances_3d.get_buffer().binding_resource(),
-                },
-            ],
-        });
+        self.update_uniform_bind_group();
     }
 
-    fn set_textures(&mut self, textures: &[TextureData<'_>], changed: &BitSlice) {
-        for i in 0..textures.len() {
+    fn set_material(&mut self, index: usize, material: DeviceMaterial) {
+        if index >= self.material_buffer.len() {
+            self.material_buffer.resize((index + 1) * 2);
+            self.material_buffer.as_mut_slice()[index] = material;
+            self.material_buffer.copy_to_device();
+            // The material buffer was recreated, only the bind groups referencing it change
+            self.update_uniform_bind_group();
+            self.radiance_pass.update_bind_groups(
+                &self.device,
+                &self.output,
+                &self.lights,
+                self.camera_buffer.buffer(),
+                self.material_buffer.buffer(),
+                &self.skybox,
+            );
+            return;
+        }
+
+        self.material_buffer.as_mut_slice()[index] = material;
+        self.material_buffer.copy_to_device_ranged(index, index + 1);
+    }
+
+    fn has_texture(&self, id: usize) -> bool {
+        // Slot 0 holds a placeholder until it is set, which is safe to reference as well
+        self.textures.get(id).is_some()
+    }
+
+    fn set_textures(
+        &mut self,
+        textures: &[TextureData<'_>],
+        changed: &BitSlice,
+    ) -> Result<(), BackendError> {
+        let start = std::time::Instant::now();
+        let mut storage = Vec::new();
+        let mut uploads = TextureUploads::default();
+        // Levels rendered by the mip generator once the uploads have been copied
+        let mut mip_generation = Vec::new();
+        // The texture bind group holds a fixed number of textures
+        for i in 0..textures.len().min(Self::TEXTURE_CAPACITY) {
             if !changed[i] {
                 continue;
             }
 
-            let tex = textures[i];
+            let mut tex = textures[i];
+            let mask = 1 << (i % 32);
+            if tex.premultiplied {
+                self.texture_flags[0][i / 32] |= mask;
+            } else {
+                self.texture_flags[0][i / 32] &= !mask;
+            }
+
+            let max_size = self.settings.max_texture_size;
+            if max_size > 0 {
+                match textures[i].fit(max_size, &mut storage) {
+                    Some(fitted) => tex = fitted,
+                    None => rfw::utils::log::warn!(
+                        "texture {} ({}x{}) has no mip level within max-texture-size {}",
+                        i,
+                        tex.width,
+                        tex.height,
+                        max_size
+                    ),
+                }
+            }
 
+            if tex.filter == TextureFilter::Nearest {
+                tex.mip_levels = 1;
+            }
+
+            if !WgpuTexture::is_supported(&self.device, tex.format) {
+                rfw::utils::log::warn!(
+                    "texture {} uses unsupported format {:?}, using a placeholder",
+                    i,
+                    tex.format
+                );
+                let dummy = self.textures[0].clone();
+                self.textures.overwrite_val(i, dummy);
+                continue;
+            }
+
+            let mip_levels = mipmap::MipGenerator::levels_for(&tex);
             if let Some(t) = self.textures.get_mut(i) {
-                t.update(&self.device, &self.queue, tex);
+                t.update_levels(&self.device, &mut uploads, tex, mip_levels);
             } else {
-                self.textures
-                    .overwrite_val(i, WgpuTexture::new(&self.device, &self.queue, tex));
+                self.textures.overwrite_val(
+                    i,
+                    WgpuTexture::with_levels(&self.device, &mut uploads, tex, mip_levels),
+                );
+            }
+
+            if mip_levels > tex.mip_levels {
+                mip_generation.push((i, tex.format, mip_levels));
             }
         }
 
-        let mut texture_views = Vec::with_capacity(Self::TEXTURE_CAPACITY);
-        let dummy_ref = &self.textures[0].view.as_ref().as_ref().unwrap();
-        for (_, t) in self.textures.iter() {
-            texture_views.push(if let Some(view) = t.view.as_ref() {
-                view
-            } else {
-                dummy_ref
-            });
+        if !uploads.is_empty() {
+            let bytes = uploads.byte_size();
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("texture-upload"),
+                });
+            uploads.record(&self.device, &mut encoder);
+            for (i, format, mip_levels) in mip_generation {
+                if let Some(texture) = self.textures[i].texture() {
+                    self.mip_generator.generate(
+                        &self.device,
+                        &mut encoder,
+                        texture,
+                        format,
+                        mip_levels,
+                    );
+                }
+            }
+            self.queue.submit(std::iter::once(encoder.finish()));
+            rfw::utils::log::debug!(
+                "uploaded {} KiB of textures in {:.2} ms",
+                bytes / 1024,
+                start.elapsed().as_secs_f64() * 1000.0
+            );
         }
 
-        while texture_views.len() < Self::TEXTURE_CAPACITY {
-            texture_views.push(dummy_ref);
+        self.texture_flags.copy_to_device();
+        self.update_texture_bind_group();
+        // Sprites can show any of the changed textures
+        self.d2_renderer.dirty.mark_all();
+
+        if textures.len() > Self::TEXTURE_CAPACITY {
+            return Err(BackendError::TextureCapacityExceeded {
+                count: textures.len(),
+                capacity: Self::TEXTURE_CAPACITY,
+            });
         }
+        Ok(())
+    }
 
-        self.texture_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("textures-bind-group"),
-            layout: &self.texture_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureViewArray(texture_views.as_slice()),
-            }],
-        });
+    fn generates_mipmaps(&self) -> bool {
+        true
     }
 
     fn synchronize(&mut self) {
+        if self.update_flags.contains(UpdateFlags::UPDATE_3D_INSTANCES) {
+            self.update_scene_bounds();
+        }
+
         self.lights_changed |= self.lights.synchronize(&self.device, &self.queue);
 
         if self.lights_changed {
@@ -625,6 +1386,7 @@ impl Backend for WgpuBackend {
                 &self.lights,
                 self.camera_buffer.buffer(),
                 self.material_buffer.buffer(),
+                &self.skybox,
             );
         }
 
@@ -637,53 +1399,872 @@ impl Backend for WgpuBackend {
             self.instances_3d.update();
         }
 
+        // Masks are indexed by the offsets of instances, which are only known after the update
+        if self.lights.update_exclusions(
+            &self.device,
+            &self.queue,
+            self.instances_3d.get_ranges(),
+            self.update_flags.contains(UpdateFlags::UPDATE_3D_INSTANCES),
+        ) {
+            self.radiance_pass.update_bind_groups(
+                &self.device,
+                &self.output,
+                &self.lights,
+                self.camera_buffer.buffer(),
+                self.material_buffer.buffer(),
+                &self.skybox,
+            );
+        }
+
         if self.update_flags.contains(UpdateFlags::UPDATE_2D_INSTANCES) {
             self.instances_2d.update();
         }
 
         self.update_flags = UpdateFlags::empty();
+        self.update_uniform_bind_group();
 
-        self.uniform_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("uniform-bind-group"),
-            layout: &self.uniform_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.camera_buffer.binding_resource(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: self.material_buffer.binding_resource(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: self.instances_2d.get_buffer().binding_resource(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: self.instances_3d.get_buffer().binding_resource(),
-                },
-            ],
-        });
+        self.uploads.submit(&self.queue);
+    }
+
+    fn clear_scene(&mut self) {
+        // Let the GPU finish with the buffers that are about to be dropped
+        self.device.poll(wgpu::Maintain::Wait);
+
+        self.mesh_flags.clear();
+        self.vertices_3d = VertexList::new(&self.device, &self.queue);
+        self.mesh_bounds_3d.clear();
+        self.instances_3d_storage.clear();
+        let growth = self.settings.instance_growth();
+        self.instances_3d = InstanceList::new(&self.device, &self.queue, growth);
+        self.moving_instances.clear();
+        self.instance_effects.clear();
+        self.vertices_2d = VertexList::new(&self.device, &self.queue);
+        self.instances_2d = InstanceList::new(&self.device, &self.queue, growth);
+        self.mesh_bounds_2d.clear();
+        self.d2_renderer.dirty.mark_all();
+
+        self.material_buffer = ManagedBuffer::new(
+            self.device.clone(),
+            self.queue.clone(),
+            wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            1,
+        );
+
+        // Keep the placeholder texture, empty slots of the texture array refer to it
+        let dummy = self.textures[0].clone();
+        self.textures = FlaggedStorage::new();
+        self.textures.push(dummy);
+        self.update_texture_bind_group();
+
+        self.lights.clear(&self.device, &self.queue);
+        self.skins = TrackedStorage::new();
+        self.scene_bounds = Aabb::empty();
+        self.geometry_bundles = None;
+        self.particles.clear();
+        self.decal_pass.set_decals(&[]);
+        self.outline_pass.set_selected(&[]);
+
+        self.update_flags = UpdateFlags::all();
+        self.lights_changed = true;
+        self.instances_changed = true;
+    }
+
+    fn render(
+        &mut self,
+        camera_2d: CameraView2D,
+        camera_3d: CameraView3D,
+        mode: RenderMode,
+    ) -> Result<(), BackendError> {
+        if self.minimized {
+            return Ok(());
+        }
+
+        let scoped = self.frame.is_none();
+        if scoped {
+            self.acquire_frame()?;
+        }
+
+        if let Some(mut frame) = self.frame.take() {
+            // Earlier renders of this frame read the outputs this render overwrites
+            frame.flush(&self.queue);
+            let commands = self.record_frame(frame.view(), camera_2d, camera_3d, mode, None, true);
+            frame.push(commands);
+            self.frame = Some(frame);
+        }
+
+        if scoped {
+            self.end_frame();
+        }
+        Ok(())
+    }
+
+    fn render_views(
+        &mut self,
+        camera_2d: CameraView2D,
+        views: &[(Viewport, CameraView3D)],
+        mode: RenderMode,
+    ) -> Result<(), BackendError> {
+        if self.minimized {
+            return Ok(());
+        }
+
+        let scoped = self.frame.is_none();
+        if scoped {
+            self.acquire_frame()?;
+        }
+
+        if let Some(mut frame) = self.frame.take() {
+            let mut clear = true;
+            for (viewport, camera_3d) in views.iter() {
+                let viewport = match clamp_viewport(*viewport, self.window_size) {
+                    Some(viewport) => viewport,
+                    None => continue,
+                };
+
+                // Every view renders into the same outputs
+                frame.flush(&self.queue);
+                let commands = self.record_frame(
+                    frame.view(),
+                    camera_2d,
+                    *camera_3d,
+                    mode,
+                    Some(viewport),
+                    clear,
+                );
+                frame.push(commands);
+                clear = false;
+            }
+            self.frame = Some(frame);
+        }
+
+        if scoped {
+            self.end_frame();
+        }
+        Ok(())
     }
 
-    fn render(&mut self, camera_2d: CameraView2D, camera_3d: CameraView3D, mode: RenderMode) {
-        let output = match self.swap_chain.get_current_frame() {
-            Ok(output) => output,
-            Err(_) => return,
+    fn begin_frame(&mut self) {
+        if self.minimized || self.frame.is_some() {
+            return;
+        }
+
+        if let Err(e) = self.acquire_frame() {
+            rfw::utils::log::warn!("could not begin frame: {}", e);
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if let Some(frame) = self.frame.take() {
+            frame.submit(&self.queue);
+            self.pace_frame();
+        }
+    }
+
+    fn resize(&mut self, window_size: (u32, u32), scale_factor: f64) {
+        // The image of an open frame has to be presented before its swap chain is replaced
+        self.end_frame();
+
+        let (width, height) = window_size;
+        // Minimized windows report a zero size, swap chains cannot be created for them
+        self.minimized = width == 0 || height == 0;
+        if self.minimized {
+            return;
+        }
+
+        self.device.poll(wgpu::Maintain::Wait);
+        self.settings.scale_factor = scale_factor;
+        let (render_width, render_height) = self.settings.render_size(window_size);
+
+        self.window_size = (width, height);
+        self.swap_chain = Self::create_swap_chain(
+            &self.device,
+            &self.surface,
+            self.window_size,
+            self.settings.present_mode,
+        );
+
+        self.output
+            .resize(&self.device, render_width, render_height);
+        self.radiance_pass.update_bind_groups(
+            &self.device,
+            &self.output,
+            &self.lights,
+            self.camera_buffer.buffer(),
+            self.material_buffer.buffer(),
+            &self.skybox,
+        );
+        self.ssao_pass
+            .update_bind_groups(&self.device, &self.output);
+        self.decal_pass
+            .update_bind_groups(&self.device, &self.output);
+        self.tonemap_pass
+            .update_bind_groups(&self.device, &self.output);
+        self.blit_pass
+            .update_bind_groups(&self.device, self.tonemap_pass.display_view());
+        self.histogram_pass
+            .update_bind_groups(&self.device, &self.output);
+        self.outline_pass.update_bind_groups(&self.output);
+        self.post_process_pass
+            .update_bind_groups(&self.device, &self.output);
+        self.output_pass
+            .update_bind_groups(&self.device, &self.output);
+        self.d2_renderer.set_layer(
+            &self.device,
+            self.settings.d2_dirty_regions,
+            render_width,
+            render_height,
+        );
+    }
+
+    fn set_point_lights(&mut self, _lights: &[PointLight], _changed: &BitSlice) {
+        self.lights_changed = true;
+    }
+
+    fn set_spot_lights(&mut self, lights: &[SpotLight], changed: &BitSlice) {
+        self.lights
+            .set_spot_lights(changed, lights, &self.scene_bounds);
+        self.lights_changed = true;
+    }
+
+    fn set_area_lights(&mut self, lights: &[AreaLight], changed: &BitSlice) {
+        self.lights
+            .set_area_lights(changed, lights, &self.scene_bounds);
+        self.lights_changed = true;
+    }
+
+    fn set_directional_lights(&mut self, lights: &[DirectionalLight], changed: &BitSlice) {
+        self.lights
+            .set_directional_lights(changed, lights, &self.scene_bounds);
+        self.lights_changed = true;
+    }
+
+    fn set_light_exclusions(
+        &mut self,
+        kind: LightKind,
+        index: usize,
+        instances: &[(usize, usize)],
+    ) {
+        self.lights.set_exclusions(kind, index, instances);
+    }
+
+    fn set_skybox(&mut self, skybox: SkyboxData) {
+        if !self.skybox.set(&self.device, &self.queue, skybox) {
+            rfw::utils::log::warn!("skybox uses an unsupported format or mismatching cube faces");
+            return;
+        }
+
+        self.radiance_pass.update_bind_groups(
+            &self.device,
+            &self.output,
+            &self.lights,
+            self.camera_buffer.buffer(),
+            self.material_buffer.buffer(),
+            &self.skybox,
+        );
+    }
+
+    fn set_skins(&mut self, skins: &[SkinData], changed: &BitSlice) {
+        for i in 0..skins.len() {
+            if !changed[i] {
+                continue;
+            }
+
+            if let Some(s) = self.skins.get_mut(i) {
+                // Animated skins only change their matrices, which does not require a new
+                // buffer or bind group
+                if s.joints_fit(&skins[i]) {
+                    s.update_joints(&self.queue, skins[i].joint_matrices);
+                } else {
+                    s.update(&self.device, &self.queue, &self.skin_layout, skins[i]);
+                }
+            } else {
+                self.skins
+                    .overwrite(i, WgpuSkin::new(&self.device, &self.skin_layout, skins[i]));
+            }
+        }
+
+        self.instances_changed = true;
+    }
+
+    fn set_particles(&mut self, system: usize, particles: &[Particle], blend: ParticleBlend) {
+        self.particles.set_particles(system, particles, blend);
+    }
+
+    fn set_decals(&mut self, decals: &[Decal]) {
+        self.decal_pass.set_decals(decals);
+    }
+
+    fn frame_timings(&self) -> Option<FrameTimings> {
+        self.timer.as_ref().and_then(|t| t.read(&self.device))
+    }
+
+    fn luminance_histogram(&self) -> Option<[u32; LUMINANCE_HISTOGRAM_BINS]> {
+        if !self.settings.luminance_histogram {
+            return None;
+        }
+
+        self.histogram_pass.read(&self.device, &self.queue)
+    }
+
+    fn add_post_process(&mut self, shader: &[u8]) -> bool {
+        self.post_process_pass.add(&self.device, shader);
+        true
+    }
+
+    fn clear_post_processes(&mut self) {
+        self.post_process_pass.clear();
+    }
+
+    fn memory_usage(&self) -> MemoryReport {
+        let skins: u64 = self.skins.iter().map(|(_, s)| s.buffer_size).sum();
+        let textures: usize = self.textures.iter().map(|(_, t)| t.byte_size()).sum();
+        MemoryReport {
+            meshes: (self.vertices_3d.byte_size() + self.vertices_2d.byte_size()) as u64 + skins,
+            instances: (self.instances_3d.byte_size()
+                + self.instances_2d.byte_size()
+                + self.particles.byte_size()
+                + self.decal_pass.byte_size()) as u64,
+            materials: self.material_buffer.byte_size() as u64,
+            textures: textures as u64,
+            lights: self.lights.byte_size() as u64,
+            bvh: 0,
+        }
+    }
+
+    fn get_settings(&self) -> Vec<Setting> {
+        vec![
+            Setting::new(
+                WgpuSettings::SHADOW_DEPTH_BIAS,
+                SettingValue::Float(self.settings.shadow_depth_bias),
+                Some(0.0..0.05),
+            ),
+            Setting::new(
+                WgpuSettings::SHADOW_NORMAL_BIAS,
+                SettingValue::Float(self.settings.shadow_normal_bias),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::SHADOW_SLOPE_BIAS,
+                SettingValue::Float(self.settings.shadow_slope_bias),
+                Some(0.0..10.0),
+            ),
+            Setting::new(
+                WgpuSettings::SHADOW_STABILIZE,
+                SettingValue::Int(self.settings.shadow_stabilize as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::PARALLAX_ENABLED,
+                SettingValue::Int(self.settings.parallax_enabled as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::SSAO_BLUR_PASSES,
+                SettingValue::Int(self.settings.ssao_blur_passes as i32),
+                Some(0.0..8.0),
+            ),
+            Setting::new(
+                WgpuSettings::TEXTURE_LOD_BIAS,
+                SettingValue::Float(self.settings.texture_lod_bias),
+                Some(-4.0..4.0),
+            ),
+            Setting::new(
+                WgpuSettings::PRESENT_MODE,
+                SettingValue::Int(
+                    WgpuSettings::PRESENT_MODES
+                        .iter()
+                        .position(|m| *m == self.settings.present_mode)
+                        .unwrap_or(0) as i32,
+                ),
+                Some(0.0..(WgpuSettings::PRESENT_MODES.len() - 1) as f32),
+            )
+            .with_labels(&WgpuSettings::PRESENT_MODE_NAMES),
+            Setting::new(
+                WgpuSettings::SKYBOX_BACKGROUND_INTENSITY,
+                SettingValue::Float(self.settings.skybox_background_intensity),
+                Some(0.0..16.0),
+            ),
+            Setting::new(
+                WgpuSettings::SORT_DRAWS,
+                SettingValue::Int(self.settings.sort_draws as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::DEPTH_PREPASS,
+                SettingValue::Int(self.settings.depth_prepass as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::LUMINANCE_HISTOGRAM,
+                SettingValue::Int(self.settings.luminance_histogram as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::AUTO_EXPOSURE,
+                SettingValue::Int(self.settings.auto_exposure as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::EXPOSURE_ADAPTATION,
+                SettingValue::Float(self.settings.exposure_adaptation),
+                Some(0.1..16.0),
+            ),
+            Setting::new(
+                WgpuSettings::MAX_TEXTURE_SIZE,
+                SettingValue::Int(self.settings.max_texture_size as i32),
+                Some(0.0..16384.0),
+            ),
+            Setting::new(
+                WgpuSettings::D2_DIRTY_REGIONS,
+                SettingValue::Int(self.settings.d2_dirty_regions as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::INSTANCE_GROWTH_FACTOR,
+                SettingValue::Float(self.settings.instance_growth_factor),
+                Some(1.0..4.0),
+            ),
+            Setting::new(
+                WgpuSettings::INSTANCE_MIN_CAPACITY,
+                SettingValue::Int(self.settings.instance_min_capacity as i32),
+                Some(1.0..65536.0),
+            ),
+            Setting::new(
+                WgpuSettings::RENDER_AT_NATIVE_SCALE,
+                SettingValue::Int(self.settings.render_at_native_scale as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::LIGHT_CULLING,
+                SettingValue::Int(self.settings.light_culling as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::TARGET_FPS,
+                SettingValue::Int(self.settings.target_fps as i32),
+                Some(0.0..240.0),
+            ),
+            Setting::new(
+                WgpuSettings::OUTLINE_WIDTH,
+                SettingValue::Float(self.settings.outline_width),
+                Some(1.0..8.0),
+            ),
+            Setting::new(
+                WgpuSettings::GRID,
+                SettingValue::Int(self.settings.grid_visible as i32),
+                Some(0.0..1.0),
+            ),
+            Setting::new(
+                WgpuSettings::VIEW,
+                SettingValue::Int(self.settings.view as i32),
+                Some(0.0..(WgpuView::COUNT - 1) as f32),
+            )
+            .with_labels(&WgpuView::NAMES),
+        ]
+    }
+
+    fn set_setting(&mut self, setting: Setting) {
+        // Views are set either by index or by name
+        if setting.key() == WgpuSettings::VIEW {
+            let view = match setting.value() {
+                SettingValue::String(name) => WgpuView::from_name(name),
+                _ => setting.as_float().map(|v| WgpuView::from(v as usize)),
+            };
+            if let Some(view) = view {
+                self.set_view(view);
+            }
+            return;
+        }
+
+        // Present modes are set either by index or by name as well
+        if setting.key() == WgpuSettings::PRESENT_MODE {
+            let index = match setting.value() {
+                SettingValue::String(name) => WgpuSettings::PRESENT_MODE_NAMES
+                    .iter()
+                    .position(|n| *n == name.as_str()),
+                _ => setting
+                    .as_float()
+                    .map(|v| (v.max(0.0) as usize).min(WgpuSettings::PRESENT_MODES.len() - 1)),
+            };
+            if let Some(index) = index {
+                self.set_present_mode(WgpuSettings::PRESENT_MODES[index]);
+            }
+            return;
+        }
+
+        let value = match setting.as_float() {
+            Some(value) => value,
+            None => return,
+        };
+
+        match setting.key() {
+            WgpuSettings::SHADOW_STABILIZE => {
+                // Shadow matrices are refit and the shadow maps re-rendered
+                self.settings.shadow_stabilize = value > 0.0;
+                self.lights
+                    .set_shadow_stabilize(self.settings.shadow_stabilize, &self.scene_bounds);
+                self.lights_changed = true;
+                return;
+            }
+            WgpuSettings::PARALLAX_ENABLED => {
+                // Read from the camera uniform, applied on the next render
+                self.settings.parallax_enabled = value > 0.0;
+                return;
+            }
+            WgpuSettings::SSAO_BLUR_PASSES => {
+                self.settings.ssao_blur_passes = value.max(0.0).min(8.0) as u32;
+                return;
+            }
+            WgpuSettings::TEXTURE_LOD_BIAS => {
+                // Read from the camera uniform, samplers do not need to be recreated
+                self.settings.texture_lod_bias = value.max(-4.0).min(4.0);
+                return;
+            }
+            WgpuSettings::SKYBOX_BACKGROUND_INTENSITY => {
+                // Read from the camera uniform, applied on the next render
+                self.settings.skybox_background_intensity = value.max(0.0);
+                return;
+            }
+            WgpuSettings::SORT_DRAWS => {
+                // The draw order is baked into the geometry bundles
+                self.settings.sort_draws = value > 0.0;
+                self.geometry_bundles = None;
+                return;
+            }
+            WgpuSettings::DEPTH_PREPASS => {
+                // Selects the depth test of the pipelines baked into the geometry bundles
+                self.settings.depth_prepass = value > 0.0;
+                self.geometry_bundles = None;
+                return;
+            }
+            WgpuSettings::LUMINANCE_HISTOGRAM => {
+                self.settings.luminance_histogram = value > 0.0;
+                return;
+            }
+            WgpuSettings::TARGET_FPS => {
+                self.settings.target_fps = value.max(0.0) as u32;
+                return;
+            }
+            WgpuSettings::AUTO_EXPOSURE => {
+                self.settings.auto_exposure = value > 0.0;
+                self.tonemap_pass.set_auto_exposure(
+                    self.settings.auto_exposure,
+                    self.settings.exposure_adaptation,
+                );
+                return;
+            }
+            WgpuSettings::EXPOSURE_ADAPTATION => {
+                self.settings.exposure_adaptation = value.max(0.1).min(16.0);
+                self.tonemap_pass.set_auto_exposure(
+                    self.settings.auto_exposure,
+                    self.settings.exposure_adaptation,
+                );
+                return;
+            }
+            WgpuSettings::MAX_TEXTURE_SIZE => {
+                let size = value.max(0.0) as u32;
+                self.settings.max_texture_size = match size {
+                    0 => 0,
+                    size => 1 << (31 - size.leading_zeros()),
+                };
+                return;
+            }
+            WgpuSettings::D2_DIRTY_REGIONS => {
+                self.settings.d2_dirty_regions = value > 0.0;
+                self.d2_renderer.set_layer(
+                    &self.device,
+                    self.settings.d2_dirty_regions,
+                    self.output.width,
+                    self.output.height,
+                );
+                return;
+            }
+            WgpuSettings::INSTANCE_GROWTH_FACTOR => {
+                self.settings.instance_growth_factor = value.max(1.0).min(4.0);
+                self.update_instance_growth();
+                return;
+            }
+            WgpuSettings::INSTANCE_MIN_CAPACITY => {
+                self.settings.instance_min_capacity = value.max(1.0) as u32;
+                self.update_instance_growth();
+                return;
+            }
+            WgpuSettings::RENDER_AT_NATIVE_SCALE => {
+                let native = value > 0.0;
+                if native != self.settings.render_at_native_scale {
+                    self.settings.render_at_native_scale = native;
+                    // Recreates the output at the new render resolution
+                    self.resize(self.window_size, self.settings.scale_factor);
+                }
+                return;
+            }
+            WgpuSettings::LIGHT_CULLING => {
+                self.settings.light_culling = value > 0.0;
+                return;
+            }
+            WgpuSettings::OUTLINE_WIDTH => {
+                self.settings.outline_width = value.max(1.0).min(8.0);
+                return;
+            }
+            WgpuSettings::GRID => {
+                self.settings.grid_visible = value > 0.0;
+                return;
+            }
+            WgpuSettings::SHADOW_DEPTH_BIAS => {
+                self.settings.shadow_depth_bias = value.max(0.0).min(0.05)
+            }
+            WgpuSettings::SHADOW_NORMAL_BIAS => {
+                self.settings.shadow_normal_bias = value.max(0.0).min(1.0)
+            }
+            WgpuSettings::SHADOW_SLOPE_BIAS => {
+                self.settings.shadow_slope_bias = value.max(0.0).min(10.0)
+            }
+            _ => return,
+        }
+
+        self.lights.set_bias(self.settings.shadow_bias());
+        self.lights_changed = true;
+        self.instances_changed = true;
+    }
+
+    fn set_color_grading(&mut self, grading: ColorGrading) {
+        self.settings.color_grading = grading.clamped();
+        self.tonemap_pass
+            .set_color_grading(self.settings.color_grading, self.settings.output_transfer);
+    }
+
+    fn set_output_transfer(&mut self, transfer: OutputTransfer) {
+        self.settings.output_transfer = transfer;
+        self.tonemap_pass
+            .set_color_grading(self.settings.color_grading, self.settings.output_transfer);
+    }
+
+    fn set_material_debug(&mut self, debug: MaterialDebug) {
+        self.settings.material_debug = debug;
+    }
+
+    fn set_clip_planes(&mut self, planes: &[Vec4]) {
+        for (i, plane) in self.settings.clip_planes.iter_mut().enumerate() {
+            *plane = planes.get(i).copied().unwrap_or(Vec4::W);
+        }
+    }
+
+    fn set_reveal_region(&mut self, region: Option<RevealRegion>) {
+        self.settings.reveal_region = region;
+    }
+
+    fn mark_2d_dirty(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        self.d2_renderer.dirty.mark_rect([x, y, width, height]);
+    }
+
+    fn set_isolate_instance(&mut self, instance: Option<(usize, usize)>) {
+        if instance != self.settings.isolated_instance {
+            self.settings.isolated_instance = instance;
+            self.geometry_bundles = None;
+        }
+    }
+
+    fn set_3d_instance_effect(&mut self, mesh: usize, index: usize, effect: InstanceEffect) {
+        if mesh >= self.instance_effects.len() {
+            self.instance_effects.resize(mesh + 1, Vec::new());
+        }
+        let effects = &mut self.instance_effects[mesh];
+        if index >= effects.len() {
+            effects.resize(index + 1, InstanceEffect::Standard);
+        }
+        effects[index] = effect;
+
+        // Instances that are already set are patched in place
+        let extra = match self.instances_3d.get_ranges().get(&mesh) {
+            Some(range) if (index as u32) < range.count => range.extra.clone(),
+            _ => return,
+        };
+        let mut vec: Vec<InstanceMatrices> = self.instances_3d_storage[mesh].as_ref().clone();
+        vec[index].animation.z = effect.id() as f32;
+        self.instances_3d.update_instances_list(mesh, &vec, extra);
+        self.instances_3d_storage[mesh] = Rc::new(vec);
+        self.update_flags.insert(UpdateFlags::UPDATE_3D_INSTANCES);
+    }
+
+    fn set_handedness(&mut self, handedness: Handedness) {
+        if handedness != self.settings.handedness {
+            self.settings.handedness = handedness;
+            self.geometry_bundles = None;
+        }
+    }
+
+    fn set_wind(&mut self, wind: Wind) {
+        self.settings.wind = wind;
+    }
+
+    fn set_selected_instances(&mut self, instances: &[(usize, usize)]) {
+        self.outline_pass.set_selected(instances);
+    }
+
+    fn set_grid(&mut self, grid: Option<GridConfig>) {
+        if let Some(grid) = grid {
+            self.settings.grid = grid;
+        }
+        self.settings.grid_visible = grid.is_some();
+    }
+
+    fn capture(&self) -> Option<CapturedFrame> {
+        self.output.capture(&self.device, &self.queue)
+    }
+
+    fn capture_hdr(&self) -> Option<CapturedHdrFrame> {
+        self.output.capture_hdr(&self.device, &self.queue)
+    }
+
+    fn upload_token(&self) -> UploadToken {
+        self.uploads.token()
+    }
+
+    fn on_upload_complete(&mut self, token: UploadToken, callback: Box<dyn FnOnce() + Send>) {
+        self.uploads.on_complete(token, callback);
+        self.uploads.poll(&self.device);
+    }
+}
+
+impl WgpuBackend {
+    /// Renders the scene into `target` instead of the swap chain, e.g. to composite it in an
+    /// application's own wgpu renderer. The view must be of [WgpuBackend::OUTPUT_FORMAT] and
+    /// allow [wgpu::TextureUsage::RENDER_ATTACHMENT], the image is stretched to its size.
+    pub fn render_to_target(
+        &mut self,
+        target: &wgpu::TextureView,
+        camera_2d: CameraView2D,
+        camera_3d: CameraView3D,
+        mode: RenderMode,
+    ) {
+        let commands = self.record_frame(target, camera_2d, camera_3d, mode, None, true);
+        self.queue.submit(Some(commands));
+    }
+
+    /// Frame started by [Backend::begin_frame], its encoder records commands that are
+    /// submitted after the rendered frame by [Backend::end_frame].
+    pub fn current_frame(&mut self) -> Option<&mut WgpuFrame> {
+        self.frame.as_mut()
+    }
+
+    /// Recreates the swap chain with `present_mode` if it differs from the current one.
+    fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        if present_mode == self.settings.present_mode {
+            return;
+        }
+
+        self.settings.present_mode = present_mode;
+        self.end_frame();
+        // Minimized windows get a new swap chain when they are resized again
+        if !self.minimized {
+            self.swap_chain = Self::create_swap_chain(
+                &self.device,
+                &self.surface,
+                self.window_size,
+                present_mode,
+            );
+        }
+    }
+
+    /// Waits until the next frame is due at [WgpuSettings::target_fps]. Most of the wait is
+    /// slept, the last millisecond spins as sleeps overshoot by about that much.
+    fn pace_frame(&mut self) {
+        let now = std::time::Instant::now();
+        // Vertical sync already paces FIFO swap chains
+        if self.settings.present_mode == wgpu::PresentMode::Fifo {
+            self.last_present = Some(now);
+            return;
+        }
+
+        let last = match (self.settings.target_fps, self.last_present) {
+            (0, _) | (_, None) => {
+                self.last_present = Some(now);
+                return;
+            }
+            (_, Some(last)) => last,
         };
 
+        let interval = std::time::Duration::from_secs_f64(1.0 / self.settings.target_fps as f64);
+        let due = last + interval;
+        if due <= now {
+            // Late frames restart the schedule instead of rushing the frames after them
+            self.last_present = Some(now);
+            return;
+        }
+
+        let spin = std::time::Duration::from_millis(1);
+        if due - now > spin {
+            std::thread::sleep(due - now - spin);
+        }
+        while std::time::Instant::now() < due {
+            std::hint::spin_loop();
+        }
+        self.last_present = Some(due);
+    }
+
+    /// Starts a frame with the next image of the swap chain.
+    fn acquire_frame(&mut self) -> Result<(), BackendError> {
+        match self.swap_chain.get_current_frame() {
+            Ok(frame) => {
+                self.frame = Some(WgpuFrame::new(&self.device, frame));
+                Ok(())
+            }
+            Err(wgpu::SwapChainError::OutOfMemory) => Err(BackendError::DeviceLost),
+            Err(_) => Err(BackendError::SurfaceUnavailable),
+        }
+    }
+
+    /// Submits the scene passes and returns the commands that draw the result into `target`.
+    /// With a `viewport` of the window surface, the scene is only rendered into that region
+    /// and the rest of `target` is kept unless `clear` is set.
+    fn record_frame(
+        &mut self,
+        target: &wgpu::TextureView,
+        camera_2d: CameraView2D,
+        camera_3d: CameraView3D,
+        mode: RenderMode,
+        viewport: Option<Viewport>,
+        clear: bool,
+    ) -> wgpu::CommandBuffer {
+        self.uploads.poll(&self.device);
+
         {
             let cam = &mut self.camera_buffer.as_mut_slice()[0];
-            cam.view = camera_3d.get_rh_view_matrix();
+            let prev_view_proj = cam.proj * cam.view;
+            cam.view = camera_3d.get_view_matrix(self.settings.handedness);
             cam.proj = camera_3d.get_rh_projection();
             cam.matrix_2d = camera_2d.matrix;
             cam.light_count = self.lights.counts();
             cam.position = camera_3d.pos.extend(1.0);
+            let debug = self.settings.material_debug;
+            cam.options = [
+                self.settings.parallax_enabled as u32,
+                self.skybox.mode() as u32,
+                debug.albedo.is_some() as u32
+                    | (debug.metallic.is_some() as u32) << 1
+                    | (debug.roughness.is_some() as u32) << 2,
+                self.settings.texture_lod_bias.to_bits(),
+            ];
+            cam.material_debug = [
+                debug.albedo.unwrap_or_default(),
+                debug.metallic.unwrap_or_default(),
+                debug.roughness.unwrap_or_default(),
+                0.0,
+            ];
+            cam.clip_planes = self.settings.clip_planes;
+            cam.skybox_intensity = [self.settings.skybox_background_intensity, 0.0, 0.0, 0.0];
+            cam.time = [self.start_time.elapsed().as_secs_f32(), 0.0, 0.0, 0.0];
+            cam.wind = self.settings.wind.to_device();
+            cam.reveal = RevealRegion::to_device(self.settings.reveal_region.as_ref());
+            let view_proj = cam.proj * cam.view;
+            // The camera is still zeroed during the first frame, the previous camera of a view
+            // belongs to another view
+            cam.prev_view_proj = if prev_view_proj == Mat4::ZERO || viewport.is_some() {
+                view_proj
+            } else {
+                prev_view_proj
+            };
+            cam.inv_view_proj = view_proj.inverse();
         }
         self.camera_buffer.copy_to_device();
 
@@ -693,17 +2274,64 @@ impl Backend for WgpuBackend {
                 label: Some("render"),
             });
 
+        self.begin_timer(&mut encoder, TimedPass::Lights);
         if self.instances_changed {
             encoder.insert_debug_marker("lights");
             self.render_lights(&mut encoder);
             self.instances_changed = false;
         }
+        self.end_timer(&mut encoder, TimedPass::Lights);
 
         encoder.insert_debug_marker("render");
+        self.update_geometry_bundles();
+        let output_size = (self.output.width, self.output.height);
         self.render_scene(
             &mut encoder,
-            FrustrumG::from_matrix(camera_3d.get_rh_matrix()),
+            FrustrumG::from_matrix(camera_3d.get_matrix(self.settings.handedness)),
+            viewport.map(|v| scale_viewport(v, self.window_size, output_size)),
         );
+
+        let scene_ready = !self.vertices_3d.requires_update();
+        if scene_ready {
+            self.decal_pass.render(
+                &mut encoder,
+                &self.uniform_bind_group,
+                &self.texture_bind_group,
+                &self.output,
+                camera_3d.pos,
+                self.settings.handedness,
+            );
+        }
+
+        self.begin_timer(&mut encoder, TimedPass::Ssao);
+        if scene_ready {
+            self.ssao_pass.launch(
+                &mut encoder,
+                self.output.width,
+                self.output.height,
+                &self.uniform_bind_group,
+                self.settings.ssao_blur_passes,
+            );
+        }
+        self.end_timer(&mut encoder, TimedPass::Ssao);
+
+        self.begin_timer(&mut encoder, TimedPass::Radiance);
+        if scene_ready {
+            self.radiance_pass.launch(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                self.output.width,
+                self.output.height,
+                self.lights.counts(),
+                self.settings.light_culling,
+            );
+        }
+        self.end_timer(&mut encoder, TimedPass::Radiance);
+
+        // Particles are blended into the radiance so they are graded along with the scene
+        self.particles
+            .render(&mut encoder, &self.uniform_bind_group, &self.output);
         self.queue.submit(Some(encoder.finish()));
 
         let mut output_encoder =
@@ -712,123 +2340,400 @@ impl Backend for WgpuBackend {
                     label: Some("output-encoder"),
                 });
         output_encoder.insert_debug_marker("output");
-        if mode == RenderMode::Default {
+        self.begin_timer(&mut output_encoder, TimedPass::Blit);
+        let view = match mode {
+            RenderMode::Default => self.settings.view,
+            RenderMode::Normal => WgpuView::Normal,
+            RenderMode::Albedo => WgpuView::Albedo,
+            RenderMode::GBuffer => WgpuView::GBuffer,
+            RenderMode::ScreenSpace => WgpuView::ScreenSpace,
+            RenderMode::Ssao => WgpuView::Ssao,
+            RenderMode::FilteredSsao => WgpuView::FilteredSsao,
+            RenderMode::Motion => WgpuView::Motion,
+        };
+        if view == WgpuView::Output {
+            self.tonemap_pass.launch(
+                &self.queue,
+                &mut output_encoder,
+                self.output.width,
+                self.output.height,
+            );
             self.blit_pass
                 .render(&mut output_encoder, &self.output.output_texture_view);
         } else {
-            self.output.blit_debug(
-                &self.output.output_texture_view,
+            self.output
+                .blit_debug(&self.output.output_texture_view, &mut output_encoder, view);
+        }
+        self.end_timer(&mut output_encoder, TimedPass::Blit);
+
+        // Binned before the 2D overlay is drawn on top of the output
+        if self.settings.luminance_histogram {
+            self.histogram_pass
+                .launch(&mut output_encoder, self.output.width, self.output.height);
+        }
+
+        if mode == RenderMode::Default {
+            self.post_process_pass
+                .render(&self.queue, &mut output_encoder, &self.output);
+        }
+
+        // Helpers are drawn after post-processing so their colors are exact
+        if self.settings.grid_visible {
+            self.grid_pass.render(
                 &mut output_encoder,
-                match mode {
-                    RenderMode::Default => WgpuView::Output,
-                    RenderMode::Normal => WgpuView::Normal,
-                    RenderMode::Albedo => WgpuView::Albedo,
-                    RenderMode::GBuffer => WgpuView::GBuffer,
-                    RenderMode::ScreenSpace => WgpuView::ScreenSpace,
-                    RenderMode::Ssao => WgpuView::Ssao,
-                    RenderMode::FilteredSsao => WgpuView::FilteredSsao,
-                },
+                &self.uniform_bind_group,
+                &self.output,
+                &self.settings.grid,
             );
         }
 
-        self.d2_renderer.render_list(
+        let xray_draws = self.xray_draws();
+        self.xray_pass.render(
             &mut output_encoder,
             &self.uniform_bind_group,
-            &self.texture_bind_group,
-            &self.vertices_2d,
-            &self.instances_2d,
-            &self.output.output_texture_view,
-            &self.output.depth_texture_view,
+            &self.output,
+            self.vertices_3d.get_vertex_buffer().buffer(),
+            &xray_draws,
+            self.settings.xray_color,
+        );
+
+        let outline_draws = self.outline_draws();
+        self.outline_pass.render(
+            &mut output_encoder,
+            &self.uniform_bind_group,
+            &self.output,
+            self.vertices_3d.get_vertex_buffer().buffer(),
+            &outline_draws,
+            self.settings.outline_color,
+            self.settings.outline_width,
         );
 
+        if self.d2_renderer.has_layer() {
+            self.d2_renderer.render_layer(
+                &mut output_encoder,
+                &self.uniform_bind_group,
+                &self.texture_bind_group,
+                &self.vertices_2d,
+                &self.instances_2d,
+                camera_2d.matrix,
+                &self.output.output_texture_view,
+                &self.output.depth_texture_view,
+            );
+        } else {
+            self.d2_renderer.render_list(
+                &mut output_encoder,
+                &self.uniform_bind_group,
+                &self.texture_bind_group,
+                &self.vertices_2d,
+                &self.instances_2d,
+                &self.output.output_texture_view,
+                &self.output.depth_texture_view,
+            );
+        }
+
         self.output_pass
-            .render(&mut output_encoder, &output.output.view);
-        self.queue.submit(Some(output_encoder.finish()));
+            .render_region(&mut output_encoder, target, viewport, clear);
+        if let Some(timer) = self.timer.as_ref() {
+            timer.resolve(&mut output_encoder);
+        }
         self.lights_changed = false;
+        self.settle_instance_motion();
+        output_encoder.finish()
     }
 
-    fn resize(&mut self, window_size: (u32, u32), scale_factor: f64) {
-        self.device.poll(wgpu::Maintain::Wait);
-        self.settings.scale_factor = scale_factor;
-        let (width, height) = window_size;
-        let (render_width, render_height) = (
-            (width as f64 * scale_factor) as u32,
-            (height as f64 * scale_factor) as u32,
-        );
-
-        self.swap_chain = self.device.create_swap_chain(
-            &self.surface,
-            &wgpu::SwapChainDescriptor {
-                width: width as u32,
-                height: height as u32,
-                present_mode: Self::PRESENT_MODE,
-                format: Self::OUTPUT_FORMAT,
-                usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+    /// Renders the scene into a new texture at render resolution, which can be sampled or
+    /// copied from by the application.
+    pub fn render_to_texture(
+        &mut self,
+        camera_2d: CameraView2D,
+        camera_3d: CameraView3D,
+        mode: RenderMode,
+    ) -> wgpu::Texture {
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render-target"),
+            size: wgpu::Extent3d {
+                width: self.output.width,
+                height: self.output.height,
+                depth_or_array_layers: 1,
             },
-        );
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::OUTPUT_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT
+                | wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_SRC,
+        });
 
-        self.output
-            .resize(&self.device, render_width, render_height);
-        self.radiance_pass.update_bind_groups(
-            &self.device,
-            &self.output,
-            &self.lights,
-            self.camera_buffer.buffer(),
-            self.material_buffer.buffer(),
-        );
-        self.ssao_pass
-            .update_bind_groups(&self.device, &self.output);
-        self.blit_pass
-            .update_bind_groups(&self.device, &self.output);
-        self.output_pass
-            .update_bind_groups(&self.device, &self.output);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_to_target(&view, camera_2d, camera_3d, mode);
+        texture
     }
 
-    fn set_point_lights(&mut self, _lights: &[PointLight], _changed: &BitSlice) {
-        self.lights_changed = true;
+    pub fn device(&self) -> &Arc<wgpu::Device> {
+        &self.device
     }
 
-    fn set_spot_lights(&mut self, lights: &[SpotLight], changed: &BitSlice) {
-        self.lights
-            .set_spot_lights(changed, lights, &self.scene_bounds);
-        self.lights_changed = true;
+    pub fn queue(&self) -> &Arc<wgpu::Queue> {
+        &self.queue
     }
 
-    fn set_area_lights(&mut self, lights: &[AreaLight], changed: &BitSlice) {
-        self.lights
-            .set_area_lights(changed, lights, &self.scene_bounds);
-        self.lights_changed = true;
+    /// World-space bounds of all currently loaded instances.
+    pub fn scene_bounds(&self) -> Aabb {
+        self.scene_bounds
     }
 
-    fn set_directional_lights(&mut self, lights: &[DirectionalLight], changed: &BitSlice) {
-        self.lights
-            .set_directional_lights(changed, lights, &self.scene_bounds);
-        self.lights_changed = true;
+    /// Depth in 0..1 of the pixel at (`x`, `y`) of the last render, counted from the top-left
+    /// of the render resolution. Pixels showing the skybox have a depth of 1. Waits for the GPU.
+    pub fn read_depth(&self, x: u32, y: u32) -> Option<f32> {
+        self.output.read_depth(&self.device, &self.queue, x, y)
     }
 
-    fn set_skybox(&mut self, _skybox: TextureData) {
-        unimplemented!()
+    /// World-space position of the pixel at (`x`, `y`) with the given depth, as seen by the
+    /// camera of the last render. Combined with [WgpuBackend::read_depth] this finds the
+    /// surface under a cursor.
+    pub fn unproject(&self, x: u32, y: u32, depth: f32) -> Vec3 {
+        let camera = &self.camera_buffer.as_slice()[0];
+        let ndc_x = (x as f32 + 0.5) / self.output.width as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y as f32 + 0.5) / self.output.height as f32 * 2.0;
+        let position = camera.inv_view_proj * Vec4::new(ndc_x, ndc_y, depth, 1.0);
+        position.truncate() / position.w
     }
 
-    fn set_skins(&mut self, skins: &[SkinData], changed: &BitSlice) {
-        for i in 0..skins.len() {
-            if !changed[i] {
+    /// View shown by renders in [RenderMode::Default].
+    pub fn view(&self) -> WgpuView {
+        self.settings.view
+    }
+
+    /// Shows a G-buffer or intermediate texture instead of the final output, equal to setting
+    /// the `view` setting.
+    pub fn set_view(&mut self, view: WgpuView) {
+        self.settings.view = view;
+    }
+
+    /// Instance seen at the pixel at (`x`, `y`) of the last render, counted from the top-left
+    /// of the render resolution. Waits for the GPU. Skinned meshes are picked in their bind
+    /// pose and alpha masks are not taken into account.
+    pub fn pick(&self, x: u32, y: u32) -> Option<PickedInstance> {
+        let v_ranges = self.vertices_3d.get_ranges();
+        let i_ranges = self.instances_3d.get_ranges();
+        let draws: Vec<outline::OutlineDraw> = i_ranges
+            .iter()
+            .filter(|(_, r)| r.count > 0)
+            .filter_map(|(mesh, r)| {
+                let v = v_ranges.get(mesh)?;
+                Some((v.start..v.end, r.start..(r.start + r.count)))
+            })
+            .collect();
+
+        let index = self.pick_pass.pick(
+            &self.device,
+            &self.queue,
+            &self.uniform_bind_group,
+            &self.output,
+            self.vertices_3d.get_vertex_buffer().buffer(),
+            &draws,
+            x,
+            y,
+        )?;
+
+        i_ranges
+            .iter()
+            .find(|(_, r)| index >= r.start && index < r.start + r.count)
+            .map(|(mesh, r)| {
+                let instance = (index - r.start) as usize;
+                PickedInstance {
+                    mesh: *mesh,
+                    instance,
+                    user_data: r.extra.user_data.get(instance).copied().unwrap_or(0),
+                }
+            })
+    }
+
+    /// User data of an instance of the given mesh, as last synchronized from the scene.
+    pub fn instance_user_data(&self, mesh: usize, instance: usize) -> Option<u64> {
+        self.instances_3d
+            .get_ranges()
+            .get(&mesh)
+            .and_then(|r| r.extra.user_data.get(instance).copied())
+    }
+
+    /// Recomputes the scene bounds from the live instances. Shadow-casting lights are fitted
+    /// to these bounds, thus their shadow matrices are refreshed when the bounds change.
+    fn update_scene_bounds(&mut self) {
+        let mut bounds = Aabb::empty();
+        for (_, list) in self.instances_3d.get_ranges().iter() {
+            if list.count == 0 {
                 continue;
             }
 
-            if let Some(s) = self.skins.get_mut(i) {
-                s.update(&self.device, &self.queue, &self.skin_layout, skins[i]);
-            } else {
-                self.skins
-                    .overwrite(i, WgpuSkin::new(&self.device, &self.skin_layout, skins[i]));
+            bounds.grow(list.extra.bounds.min);
+            bounds.grow(list.extra.bounds.max);
+        }
+
+        if bounds.min != self.scene_bounds.min || bounds.max != self.scene_bounds.max {
+            self.scene_bounds = bounds;
+            self.lights.set_scene_bounds(&self.scene_bounds);
+            self.instances_changed = true;
+        }
+    }
+
+    /// Instances of meshes that are not set would be drawn without vertices.
+    fn update_3d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData3D<'_>,
+    ) -> Result<(), BackendError> {
+        if !self.vertices_3d.has(mesh) {
+            return Err(BackendError::InvalidMeshId(mesh));
+        }
+
+        if mesh >= self.instances_3d_storage.len() {
+            self.instances_3d_storage
+                .resize(mesh + 1, Default::default());
+        }
+
+        let previous = &self.instances_3d_storage[mesh];
+        let effects = self.instance_effects.get(mesh);
+        let vec: Vec<InstanceMatrices> = instances
+            .matrices
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, m)| InstanceMatrices {
+                matrix: m,
+                normal: m.inverse().transpose(),
+                prev_matrix: previous.get(i).map(|p| p.matrix).unwrap_or(m),
+                color_tint: instances.tints.get(i).copied().unwrap_or(Vec4::ONE),
+                animation: Vec4::new(
+                    instances.flipbook_times.get(i).copied().unwrap_or(0.0),
+                    instances.wind_weights.get(i).copied().unwrap_or(0.0),
+                    effects
+                        .and_then(|e| e.get(i))
+                        .map_or(0.0, |e| e.id() as f32),
+                    0.0,
+                ),
+            })
+            .collect();
+
+        if vec.iter().any(|m| m.matrix != m.prev_matrix) && !self.moving_instances.contains(&mesh) {
+            self.moving_instances.push(mesh);
+        }
+        self.instances_3d_storage[mesh] = Rc::new(vec.clone());
+
+        // Instances without valid bounds would be culled incorrectly and break shadow fitting
+        let derived_aabb = is_degenerate(&instances.local_aabb);
+        let local_aabb = if derived_aabb {
+            let was_derived = self
+                .instances_3d
+                .get_ranges()
+                .get(&mesh)
+                .map(|r| r.extra.derived_aabb)
+                .unwrap_or(false);
+            if !was_derived && !instances.matrices.is_empty() {
+                rfw::utils::log::warn!(
+                    "instances of mesh {} have empty bounds, using the bounds of the mesh",
+                    mesh
+                );
             }
+            self.mesh_bounds_3d
+                .get(mesh)
+                .copied()
+                .unwrap_or_else(Aabb::empty)
+        } else {
+            instances.local_aabb
+        };
+
+        let mut bounds = Aabb::empty();
+        for m in instances.matrices.iter() {
+            let b = transform_aabb(&local_aabb, m);
+            bounds.grow(b.min);
+            bounds.grow(b.max);
         }
 
-        self.instances_changed = true;
+        let extra = InstanceExtra {
+            bounds,
+            flags: instances.flags.to_vec(),
+            skin_ids: instances
+                .skin_ids
+                .iter()
+                .map(|i| if i.0 >= 0 { Some(i.0 as u16) } else { None })
+                .collect(),
+            user_data: instances.user_data.to_vec(),
+            local_aabb,
+            derived_aabb,
+        };
+
+        if self.instances_3d.has(mesh) {
+            self.instances_3d.update_instances_list(mesh, &vec, extra);
+        } else {
+            self.instances_3d.add_instances_list(mesh, vec, extra);
+        }
+        Ok(())
+    }
+
+    /// Sets the previous matrices of instances that moved to their current matrix, so they
+    /// stop producing motion vectors when they are not updated again.
+    fn settle_instance_motion(&mut self) {
+        if self.moving_instances.is_empty() {
+            return;
+        }
+
+        for mesh in std::mem::take(&mut self.moving_instances) {
+            let extra = match self.instances_3d.get_ranges().get(&mesh) {
+                Some(range) => range.extra.clone(),
+                None => continue,
+            };
+
+            let vec: Vec<InstanceMatrices> = self.instances_3d_storage[mesh]
+                .iter()
+                .map(|m| InstanceMatrices {
+                    prev_matrix: m.matrix,
+                    ..*m
+                })
+                .collect();
+            self.instances_3d.update_instances_list(mesh, &vec, extra);
+            self.instances_3d_storage[mesh] = Rc::new(vec);
+        }
+
+        self.instances_3d.update();
+    }
+
+    /// Existing instance buffers keep their size until they need to grow again.
+    fn update_instance_growth(&mut self) {
+        let growth = self.settings.instance_growth();
+        self.instances_3d.set_growth(growth);
+        self.instances_2d.set_growth(growth);
+    }
+
+    /// Marks the regions covered by the instances of 2D mesh `mesh` as dirty.
+    fn mark_2d_instances_dirty(&mut self, mesh: usize) {
+        let bounds = match self.mesh_bounds_2d.get(mesh) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        if let Some(range) = self.instances_2d.get_ranges().get(&mesh) {
+            for matrix in range.data() {
+                self.d2_renderer
+                    .dirty
+                    .mark_bounds(transform_aabb(bounds, matrix));
+            }
+        }
+    }
+
+    fn begin_timer(&self, encoder: &mut wgpu::CommandEncoder, pass: TimedPass) {
+        if let Some(timer) = self.timer.as_ref() {
+            timer.begin(encoder, pass);
+        }
+    }
+
+    fn end_timer(&self, encoder: &mut wgpu::CommandEncoder, pass: TimedPass) {
+        if let Some(timer) = self.timer.as_ref() {
+            timer.end(encoder, pass);
+        }
     }
-}
 
-impl WgpuBackend {
     fn render_lights(&mut self, encoder: &mut wgpu::CommandEncoder) {
         self.lights.render(
             encoder,
@@ -840,13 +2745,44 @@ impl WgpuBackend {
         );
     }
 
-    fn render_scene(&self, encoder: &mut wgpu::CommandEncoder, _frustrum: FrustrumG) {
+    /// Renders the geometry into the G-buffers, only within `viewport` of the outputs if set.
+    fn render_scene(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        _frustrum: FrustrumG,
+        viewport: Option<Viewport>,
+    ) {
         use output::*;
 
         if self.vertices_3d.requires_update() {
             return;
         }
 
+        if self.settings.depth_prepass {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("depth-prepass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(self.output.as_depth_descriptor()),
+            });
+            set_viewport(&mut render_pass, viewport);
+
+            if let Some(bundles) = self.geometry_bundles.as_ref() {
+                bundles.execute_prepass(&mut render_pass);
+            } else {
+                self.geometry_resources()
+                    .record(&mut render_pass, &self.geometry_draws(true));
+            }
+        }
+
+        // With the prepass the depth buffer already holds the scene
+        let mut depth_attachment = self.output.as_depth_descriptor();
+        if self.settings.depth_prepass {
+            depth_attachment.depth_ops = Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            });
+        }
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[
@@ -855,34 +2791,162 @@ impl WgpuBackend {
                 self.output.as_descriptor(WgpuView::GBuffer),
                 self.output.as_descriptor(WgpuView::ScreenSpace),
                 self.output.as_descriptor(WgpuView::MatParams),
+                self.output.as_descriptor(WgpuView::Lightmap),
+                self.output.as_descriptor(WgpuView::Motion),
             ],
-            depth_stencil_attachment: Some(self.output.as_depth_descriptor()),
+            depth_stencil_attachment: Some(depth_attachment),
         });
+        set_viewport(&mut render_pass, viewport);
+
+        if let Some(bundles) = self.geometry_bundles.as_ref() {
+            bundles.execute(&mut render_pass);
+        } else {
+            self.geometry_resources()
+                .record(&mut render_pass, &self.geometry_draws(false));
+        }
+    }
+
+    /// Re-records the geometry pass into render bundles if the scene changed since they were
+    /// last built. Small scenes keep being recorded directly into the render pass.
+    fn update_geometry_bundles(&mut self) {
+        if self.geometry_bundles.is_some() || self.vertices_3d.requires_update() {
+            return;
+        }
 
-        let jw_buffer = self.vertices_3d.get_jw_buffer().buffer();
-        let vertex_buffer = self.vertices_3d.get_vertex_buffer().buffer();
+        let bundles = {
+            let draws = self.geometry_draws(false);
+            if draws.len() < GeometryBundles::MIN_DRAWS {
+                return;
+            }
+
+            let prepass_draws = if self.settings.depth_prepass {
+                self.geometry_draws(true)
+            } else {
+                Vec::new()
+            };
+            GeometryBundles::new(
+                &self.device,
+                self.geometry_resources(),
+                &draws,
+                &prepass_draws,
+            )
+        };
+
+        self.geometry_bundles = Some(bundles);
+    }
+
+    fn geometry_resources(&self) -> GeometryResources {
+        GeometryResources {
+            uniform_bind_group: &self.uniform_bind_group,
+            texture_bind_group: &self.texture_bind_group,
+            vertex_buffer: self.vertices_3d.get_vertex_buffer().buffer(),
+            jw_buffer: self.vertices_3d.get_jw_buffer().buffer(),
+        }
+    }
 
-        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+    /// Collects a draw per selected instance that is still loaded, skinned meshes are outlined
+    /// in their bind pose.
+    fn outline_draws(&self) -> Vec<outline::OutlineDraw> {
+        let v_ranges = self.vertices_3d.get_ranges();
+        let i_ranges = self.instances_3d.get_ranges();
+        self.outline_pass
+            .selected()
+            .iter()
+            .filter_map(|(mesh, index)| {
+                let v = v_ranges.get(mesh)?;
+                let r = i_ranges.get(mesh)?;
+                let index = *index as u32;
+                if index < r.count {
+                    Some((v.start..v.end, (r.start + index)..(r.start + index + 1)))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
 
+    /// Collects a draw per loaded instance with [InstanceEffect::Xray], skinned meshes show
+    /// through in their bind pose.
+    fn xray_draws(&self) -> Vec<outline::OutlineDraw> {
         let v_ranges = self.vertices_3d.get_ranges();
         let i_ranges = self.instances_3d.get_ranges();
+        let mut draws = Vec::new();
+        for (mesh, effects) in self.instance_effects.iter().enumerate() {
+            let (v, r) = match (v_ranges.get(&mesh), i_ranges.get(&mesh)) {
+                (Some(v), Some(r)) => (v, r),
+                _ => continue,
+            };
+
+            for (index, effect) in effects.iter().enumerate().take(r.count as usize) {
+                if *effect == InstanceEffect::Xray {
+                    let index = r.start + index as u32;
+                    draws.push((v.start..v.end, index..(index + 1)));
+                }
+            }
+        }
+        draws
+    }
 
-        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+    /// Collects the draws of the geometry pass in the order they should be recorded,
+    /// always-on-top meshes are drawn last so they overwrite the rest of the scene. The draws
+    /// of the depth `prepass` leave out always-on-top meshes, which do not test depth.
+    fn geometry_draws(&self, prepass: bool) -> Vec<GeometryDraw> {
+        let v_ranges = self.vertices_3d.get_ranges();
+        let i_ranges = self.instances_3d.get_ranges();
+        let mut draws = Vec::with_capacity(i_ranges.len());
+        let mut on_top_draws = Vec::new();
 
         for (i, r) in i_ranges.iter() {
             if r.count == 0 {
                 continue;
             }
 
+            let (first, count) = match self.settings.isolated_instance {
+                None => (0, r.count),
+                Some((mesh, index)) if mesh == *i && (index as u32) < r.count => (index as u32, 1),
+                Some(_) => continue,
+            };
+
             let v = v_ranges.get(i).unwrap();
             let skins = r.extra.skin_ids.as_slice();
+            let flags = self.mesh_flags.get(*i).copied().unwrap_or_default();
+            let cull_mode = self.settings.handedness.raster_cull_mode(flags);
+            let on_top = flags.contains(Mesh3dFlags::ALWAYS_ON_TOP);
+            let flat_shading = flags.contains(Mesh3dFlags::FLAT_SHADING);
+            if on_top && prepass {
+                continue;
+            }
+
+            let depth = if on_top {
+                DepthTest::AlwaysOnTop
+            } else if self.settings.depth_prepass {
+                DepthTest::Prepassed
+            } else {
+                DepthTest::Regular
+            };
+            let (pipeline, anim_pipeline) = if prepass {
+                (
+                    self.pipeline.prepass_pipeline(cull_mode),
+                    self.pipeline.anim_prepass_pipeline(cull_mode),
+                )
+            } else {
+                (
+                    self.pipeline.pipeline(cull_mode, depth, flat_shading),
+                    self.pipeline.anim_pipeline(cull_mode, depth, flat_shading),
+                )
+            };
+            let target = if on_top {
+                &mut on_top_draws
+            } else {
+                &mut draws
+            };
 
             if self.settings.enable_skinning
                 && (v.jw_end - v.jw_start) > 0
                 && skins.len() == (r.count as usize)
             {
-                for i in 0..r.count {
+                for i in first..(first + count) {
+                    let instances = (r.start + i)..(r.start + i + 1);
                     if let Some(skin) = skins.get(i as usize).and_then(|i| {
                         i.and_then(|i| {
                             self.skins
@@ -891,48 +2955,93 @@ impl WgpuBackend {
                         })
                     }) {
                         // animated mesh
-                        render_pass.set_pipeline(&self.pipeline.anim_pipeline);
-                        render_pass.set_vertex_buffer(
-                            0,
-                            vertex_buffer.slice(
-                                ((v.start as usize * std::mem::size_of::<Vertex3D>())
-                                    as wgpu::BufferAddress)..,
-                            ),
-                        );
-                        render_pass.set_vertex_buffer(
-                            1,
-                            jw_buffer.slice(
-                                ((v.jw_start as usize * std::mem::size_of::<JointData>())
-                                    as wgpu::BufferAddress)..,
-                            ),
-                        );
-                        render_pass.set_bind_group(2, skin, &[]);
-                        render_pass.draw(0..(v.end - v.start), (r.start + i)..(r.start + i + 1));
+                        target.push(GeometryDraw {
+                            pipeline: anim_pipeline,
+                            skin: Some((
+                                skin,
+                                (v.start as usize * std::mem::size_of::<Vertex3D>())
+                                    as wgpu::BufferAddress,
+                                (v.jw_start as usize * std::mem::size_of::<JointData>())
+                                    as wgpu::BufferAddress,
+                            )),
+                            vertices: 0..(v.end - v.start),
+                            instances,
+                        });
                     } else {
-                        render_pass.set_pipeline(&self.pipeline.pipeline);
-                        render_pass.draw(0..(v.end - v.start), (r.start + i)..(r.start + i + 1));
+                        target.push(GeometryDraw {
+                            pipeline,
+                            skin: None,
+                            vertices: v.start..v.end,
+                            instances,
+                        });
                     }
                 }
-
-                render_pass.set_pipeline(&self.pipeline.pipeline);
-                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
             } else {
                 // static mesh
-                render_pass.set_pipeline(&self.pipeline.pipeline);
-                render_pass.draw(v.start..v.end, r.start..r.end);
+                target.push(GeometryDraw {
+                    pipeline,
+                    skin: None,
+                    vertices: v.start..v.end,
+                    instances: (r.start + first)..(r.start + first + count),
+                });
             }
         }
 
-        drop(render_pass);
+        // Always-on-top meshes do not test depth, their order decides which is visible and
+        // is left untouched
+        if self.settings.sort_draws {
+            draws.sort_by_key(GeometryDraw::sort_key);
+        }
+
+        draws.append(&mut on_top_draws);
+        draws
+    }
+}
 
-        self.ssao_pass.launch(
-            encoder,
-            self.output.width,
-            self.output.height,
-            &self.uniform_bind_group,
-        );
+/// Whether `aabb` is empty, collapsed to a single point or not finite.
+fn is_degenerate(aabb: &Aabb) -> bool {
+    let (min, max) = (Vec3::from(aabb.min), Vec3::from(aabb.max));
+    !min.is_finite() || !max.is_finite() || min.cmpgt(max).any() || min == max
+}
+
+/// Limits `viewport` to a surface of `size`, None if nothing of it is left.
+fn clamp_viewport(viewport: Viewport, size: (u32, u32)) -> Option<Viewport> {
+    let x = viewport.x.min(size.0);
+    let y = viewport.y.min(size.1);
+    let clamped = Viewport::new(x, y, viewport.w.min(size.0 - x), viewport.h.min(size.1 - y));
+    if clamped.is_empty() {
+        None
+    } else {
+        Some(clamped)
+    }
+}
+
+/// Maps `viewport` of a surface of `from` pixels to the same region of a surface of `to` pixels.
+fn scale_viewport(viewport: Viewport, from: (u32, u32), to: (u32, u32)) -> Viewport {
+    let scale = |v: u32, from: u32, to: u32| (v as u64 * to as u64 / from.max(1) as u64) as u32;
+    let x = scale(viewport.x, from.0, to.0);
+    let y = scale(viewport.y, from.1, to.1);
+    let w = scale(viewport.x + viewport.w, from.0, to.0).saturating_sub(x);
+    let h = scale(viewport.y + viewport.h, from.1, to.1).saturating_sub(y);
+    Viewport::new(x, y, w.max(1), h.max(1))
+}
 
-        self.radiance_pass
-            .launch(encoder, self.output.width, self.output.height);
+fn set_viewport(render_pass: &mut wgpu::RenderPass, viewport: Option<Viewport>) {
+    if let Some(v) = viewport {
+        render_pass.set_viewport(v.x as f32, v.y as f32, v.w as f32, v.h as f32, 0.0, 1.0);
+        render_pass.set_scissor_rect(v.x, v.y, v.w, v.h);
+    }
+}
+
+fn transform_aabb(aabb: &Aabb, matrix: &Mat4) -> Aabb {
+    let mut result = Aabb::empty();
+    for i in 0..8 {
+        let corner = Vec3::new(
+            if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+            if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+            if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+        );
+        result.grow(matrix.transform_point3(corner));
     }
+    result
 }
@@ -1,11 +1,12 @@
 use crate::InstanceMatrices;
 use crate::{
-    list::{InstanceList, VertexList},
+    list::{InstanceList, InstanceRange, VertexList},
     mesh::WgpuSkin,
     InstanceExtra,
 };
 use rfw::prelude::*;
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::num::NonZeroU32;
 use std::ops::Range;
@@ -16,9 +17,25 @@ pub struct WgpuLights {
     pub spot_lights: LightShadows<SpotLight>,
     pub area_lights: LightShadows<AreaLight>,
     pub directional_lights: LightShadows<DirectionalLight>,
+    /// Excluded (mesh, instance) pairs of every light with exclusions
+    exclusions: HashMap<(LightKind, usize), Vec<(usize, usize)>>,
+    exclusions_changed: bool,
+    /// Masks of excluded spot, area and directional lights per instance
+    exclusion_buffer: wgpu::Buffer,
+    exclusion_capacity: usize,
+    /// Number of lights of each kind allocated up front
+    capacity: usize,
+    /// Whether the spot, area and directional light counts were clamped by [WgpuLights::counts]
+    clamped: [bool; 3],
 }
 
+/// Bits of the spot, area and directional lights an instance is excluded from.
+type ExclusionMask = [[u32; 4]; 3];
+
 impl WgpuLights {
+    /// Length of the light arrays of the shaders, for every kind of light
+    pub const MAX_LIGHTS: usize = 128;
+
     pub fn new(
         capacity: usize,
         device: &wgpu::Device,
@@ -48,16 +65,80 @@ impl WgpuLights {
                 capacity,
                 true,
             ),
+            exclusions: HashMap::new(),
+            exclusions_changed: false,
+            exclusion_buffer: Self::create_exclusion_buffer(device, 1),
+            exclusion_capacity: 1,
+            capacity,
+            clamped: [false; 3],
         }
     }
 
-    pub fn counts(&self) -> [u32; 4] {
-        [
-            0,
-            self.spot_lights.len() as u32,
-            self.area_lights.len() as u32,
-            self.directional_lights.len() as u32,
-        ]
+    /// Removes all lights and exclusions, shrinking every buffer back to its initial capacity.
+    pub fn clear(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.spot_lights.clear(device, queue, self.capacity);
+        self.area_lights.clear(device, queue, self.capacity);
+        self.directional_lights.clear(device, queue, self.capacity);
+
+        self.exclusions.clear();
+        self.exclusions_changed = false;
+        self.exclusion_buffer = Self::create_exclusion_buffer(device, 1);
+        self.exclusion_capacity = 1;
+    }
+
+    fn create_exclusion_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light-exclusions"),
+            size: (capacity * std::mem::size_of::<ExclusionMask>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Size of the light buffers, shadow maps and exclusion masks in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.spot_lights.byte_size()
+            + self.area_lights.byte_size()
+            + self.directional_lights.byte_size()
+            + self.exclusion_capacity * std::mem::size_of::<ExclusionMask>()
+    }
+
+    /// Number of spot, area and directional lights the shaders may index. Lights that are not
+    /// in the light buffers yet, or beyond [WgpuLights::MAX_LIGHTS], are left out and logged.
+    pub fn counts(&mut self) -> [u32; 4] {
+        let lengths = [
+            (
+                self.spot_lights.len(),
+                self.spot_lights.device_len(),
+                "spot",
+            ),
+            (
+                self.area_lights.len(),
+                self.area_lights.device_len(),
+                "area",
+            ),
+            (
+                self.directional_lights.len(),
+                self.directional_lights.device_len(),
+                "directional",
+            ),
+        ];
+
+        let mut counts = [0; 4];
+        for (i, (len, device_len, kind)) in lengths.iter().enumerate() {
+            let clamped = device_len < len;
+            if clamped && !self.clamped[i] {
+                rfw::utils::log::warn!(
+                    "only {} of {} {} lights are available to the shaders",
+                    device_len,
+                    len,
+                    kind
+                );
+            }
+            self.clamped[i] = clamped;
+            counts[i + 1] = *device_len as u32;
+        }
+        counts
     }
 
     pub fn set_spot_lights(
@@ -87,6 +168,98 @@ impl WgpuLights {
         self.directional_lights.set(changed, lights, scene_bounds);
     }
 
+    /// Refits the shadow matrices of every light to the given scene bounds.
+    pub fn set_scene_bounds(&mut self, scene_bounds: &Aabb) {
+        self.spot_lights.set_scene_bounds(scene_bounds);
+        self.area_lights.set_scene_bounds(scene_bounds);
+        self.directional_lights.set_scene_bounds(scene_bounds);
+    }
+
+    /// Snaps the shadow maps of directional lights to whole texels, which stops their edges
+    /// from crawling while the fitted shadow frustum moves.
+    pub fn set_shadow_stabilize(&mut self, stabilize: bool, scene_bounds: &Aabb) {
+        self.directional_lights.set_stabilize(stabilize);
+        self.directional_lights.set_scene_bounds(scene_bounds);
+    }
+
+    /// Sets the shadow bias of every light, see [LightInfo::bias] for its layout.
+    pub fn set_bias(&mut self, bias: Vec4) {
+        self.spot_lights.set_bias(bias);
+        self.area_lights.set_bias(bias);
+        self.directional_lights.set_bias(bias);
+    }
+
+    pub fn set_exclusions(&mut self, kind: LightKind, index: usize, instances: &[(usize, usize)]) {
+        if instances.is_empty() {
+            self.exclusions.remove(&(kind, index));
+        } else {
+            self.exclusions.insert((kind, index), instances.to_vec());
+        }
+        self.exclusions_changed = true;
+    }
+
+    /// Writes the exclusion masks of all instances at their offset in the instance buffer,
+    /// returns true if the exclusion buffer was reallocated.
+    pub fn update_exclusions(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        ranges: &BTreeMap<usize, InstanceRange<InstanceMatrices, InstanceExtra>>,
+        instances_changed: bool,
+    ) -> bool {
+        // Without exclusions all masks stay zero, the shader treats instances past the end of
+        // the buffer as not excluded
+        if !self.exclusions_changed && (!instances_changed || self.exclusions.is_empty()) {
+            return false;
+        }
+        self.exclusions_changed = false;
+
+        let count = ranges
+            .values()
+            .map(|r| (r.start + r.count) as usize)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let mut masks: Vec<ExclusionMask> = vec![Default::default(); count];
+        for ((kind, light), instances) in self.exclusions.iter() {
+            let kind = match kind {
+                LightKind::Spot => 0,
+                LightKind::Area => 1,
+                LightKind::Directional => 2,
+                // Point lights are not rendered
+                LightKind::Point => continue,
+            };
+
+            if *light >= Self::MAX_LIGHTS {
+                continue;
+            }
+
+            for (mesh, instance) in instances.iter() {
+                if let Some(range) = ranges.get(mesh).filter(|r| *instance < r.count as usize) {
+                    masks[range.start as usize + *instance][kind][light / 32] |= 1 << (light % 32);
+                }
+            }
+        }
+
+        let reallocated = count > self.exclusion_capacity;
+        if reallocated {
+            self.exclusion_capacity = count.next_power_of_two();
+            self.exclusion_buffer = Self::create_exclusion_buffer(device, self.exclusion_capacity);
+        }
+
+        // Also clear the masks of instances that were removed
+        masks.resize(self.exclusion_capacity, Default::default());
+        queue.write_buffer(&self.exclusion_buffer, 0, masks.as_bytes());
+        reallocated
+    }
+
+    pub fn exclusions_binding(&self, binding: u32) -> wgpu::BindGroupEntry {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: self.exclusion_buffer.as_entire_binding(),
+        }
+    }
+
     pub fn synchronize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
         if !self.spot_lights.needs_update()
             && !self.area_lights.needs_update()
@@ -149,7 +322,12 @@ pub struct LightShadows<T: Sized + Light + Clone + Debug + Default> {
     lights: TrackedStorage<T>,
     light_buffer: wgpu::Buffer,
     light_buffer_size: wgpu::BufferAddress,
+    /// Number of lights copied into the light buffer by the last synchronization
+    uploaded: usize,
     info: Vec<LightInfo>,
+    bias: Vec4,
+    /// Snaps orthographic shadow projections to texels, see [LightInfo::stabilize]
+    stabilize: bool,
     shadow_maps: ShadowMapArray,
 }
 
@@ -173,7 +351,10 @@ impl<T: Sized + Light + Clone + Debug + Default> LightShadows<T> {
             lights: TrackedStorage::new(),
             light_buffer,
             light_buffer_size,
+            uploaded: 0,
             info: Vec::new(),
+            bias: Vec4::ZERO,
+            stabilize: false,
             shadow_maps: ShadowMapArray::new(
                 device,
                 capacity,
@@ -198,9 +379,58 @@ impl<T: Sized + Light + Clone + Debug + Default> LightShadows<T> {
             Some(val) => *val,
             None => false,
         }) {
-            let info = self.lights[i].get_light_info(scene_bounds);
+            let info = self.light_info(&self.lights[i], scene_bounds);
+            self.info[i] = info;
+        }
+    }
+
+    fn light_info(&self, light: &T, scene_bounds: &Aabb) -> LightInfo {
+        let mut info = light.get_light_info(scene_bounds);
+        info.bias = self.bias;
+        if self.stabilize {
+            info.stabilize(ShadowMapArray::WIDTH as u32);
+        }
+        info
+    }
+
+    /// Removes all lights, shrinking the light buffer and shadow maps to `capacity` lights.
+    pub fn clear(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, capacity: usize) {
+        self.lights = TrackedStorage::new();
+        self.info.clear();
+        self.uploaded = 0;
+        self.shadow_maps.shrink(device, queue, capacity);
+
+        let light_buffer_size = (capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress;
+        if light_buffer_size < self.light_buffer_size {
+            self.light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("light-mem"),
+                size: light_buffer_size,
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+                mapped_at_creation: false,
+            });
+            self.light_buffer_size = light_buffer_size;
+        }
+    }
+
+    pub fn set_scene_bounds(&mut self, scene_bounds: &Aabb) {
+        for (i, light) in self.lights.iter() {
+            let info = self.light_info(light, scene_bounds);
             self.info[i] = info;
         }
+        self.lights.trigger_changed_all();
+    }
+
+    /// Only valid for lights with orthographic shadow projections, takes effect with the next
+    /// [LightShadows::set] or [LightShadows::set_scene_bounds].
+    pub fn set_stabilize(&mut self, stabilize: bool) {
+        self.stabilize = stabilize;
+    }
+
+    pub fn set_bias(&mut self, bias: Vec4) {
+        self.bias = bias;
+        self.info.iter_mut().for_each(|info| info.bias = bias);
+        // Shadow maps need to be re-rendered with the new slope-scaled bias
+        self.lights.trigger_changed_all();
     }
 
     pub fn needs_update(&self) -> bool {
@@ -254,6 +484,7 @@ impl<T: Sized + Light + Clone + Debug + Default> LightShadows<T> {
 
         encoder.copy_buffer_to_buffer(&staging_buffer, 0, &self.light_buffer, 0, light_buffer_size);
         self.shadow_maps.update_infos(self.info.as_slice(), queue);
+        self.uploaded = self.lights.len();
 
         changed
     }
@@ -262,6 +493,21 @@ impl<T: Sized + Light + Clone + Debug + Default> LightShadows<T> {
         self.lights.len()
     }
 
+    /// Number of lights that are uploaded with their shadow maps and fit the light arrays of
+    /// the shaders. Lights set since the last synchronization may not be uploaded yet.
+    pub fn device_len(&self) -> usize {
+        let capacity = self.light_buffer_size as usize / std::mem::size_of::<T>().max(1);
+        self.len()
+            .min(self.uploaded)
+            .min(capacity)
+            .min(self.shadow_maps.len())
+            .min(WgpuLights::MAX_LIGHTS)
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.light_buffer_size as usize + self.shadow_maps.byte_size()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.lights.is_empty()
     }
@@ -297,8 +543,9 @@ impl<T: Sized + Light + Clone + Debug + Default> LightShadows<T> {
         flags: &[Mesh3dFlags],
         skins: &[WgpuSkin],
     ) {
+        // Shadow maps only exist for uploaded lights
         self.shadow_maps.render(
-            0..self.lights.len() as u32,
+            0..self.uploaded.min(self.shadow_maps.len()) as u32,
             encoder,
             uniform_bind_group,
             vertices,
@@ -828,6 +1075,12 @@ impl ShadowMapArray {
         self.views.len()
     }
 
+    /// Size of the shadow, filter and depth maps in bytes, counting a depth layer per map.
+    pub fn byte_size(&self) -> usize {
+        let texel_size = 2 * std::mem::size_of::<[f32; 2]>() + std::mem::size_of::<f32>();
+        self.len() * Self::WIDTH * Self::HEIGHT * texel_size
+    }
+
     // Resizes texture to accommodate new size (do not do this too often, very expensive operation!)
     pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: usize) -> bool {
         if size <= self.len() {
@@ -836,7 +1089,23 @@ impl ShadowMapArray {
 
         // Allocate more memory to make sure this does not run too often
         let size = size.max(self.len() * 2);
+        self.reallocate(device, queue, size);
+        true
+    }
+
+    /// Releases the shadow maps beyond `size`, returns true if the array was reallocated.
+    pub fn shrink(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: usize) -> bool {
+        let size = size.max(1);
+        if size >= self.len() {
+            return false;
+        }
 
+        self.reallocate(device, queue, size);
+        true
+    }
+
+    /// Recreates the maps with room for `size` lights, keeping the maps that still fit.
+    fn reallocate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: usize) {
         let map = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("shadow_map"),
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT
@@ -959,7 +1228,8 @@ impl ShadowMapArray {
         });
 
         // Copy over shadow maps for already existing maps
-        for i in 0..self.len() {
+        let kept = self.len().min(size);
+        for i in 0..kept {
             encoder.copy_texture_to_texture(
                 wgpu::ImageCopyTexture {
                     mip_level: 0,
@@ -1092,7 +1362,7 @@ impl ShadowMapArray {
             0,
             &uniform_buffer,
             0,
-            (self.views.len() * Self::UNIFORM_ELEMENT_SIZE) as wgpu::BufferAddress,
+            (kept * Self::UNIFORM_ELEMENT_SIZE) as wgpu::BufferAddress,
         );
 
         queue.submit(std::iter::once(encoder.finish()));
@@ -1113,7 +1383,6 @@ impl ShadowMapArray {
         self.filter_map = filter_map;
 
         self.light_infos.resize(size, LightInfo::default());
-        true
     }
 
     pub fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
@@ -4,6 +4,37 @@ use num_integer::Integer;
 use std::fmt::Debug;
 use std::{collections::BTreeMap, sync::Arc};
 
+/// Controls how device buffers grow once their contents no longer fit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GrowthStrategy {
+    /// Multiplier applied to the current capacity when it is exceeded, values below 1.0 only
+    /// grow to the required size.
+    pub factor: f32,
+    /// Smallest capacity a buffer is allocated with.
+    pub min_capacity: usize,
+}
+
+impl GrowthStrategy {
+    pub const fn new(factor: f32, min_capacity: usize) -> Self {
+        Self {
+            factor,
+            min_capacity,
+        }
+    }
+
+    /// Capacity of a buffer that currently holds `current` elements and needs to hold
+    /// `required`. Buffers are never shrunk, so scenes that oscillate around a size only
+    /// reallocate once.
+    pub fn capacity(&self, current: usize, required: usize) -> usize {
+        if required <= current {
+            return current;
+        }
+
+        let grown = (current as f64 * self.factor.max(1.0) as f64).ceil() as usize;
+        grown.max(required).max(self.min_capacity)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RangeDescriptor<T: Sized, JW: Sized> {
     pub ptr: Vec<T>,
@@ -40,6 +71,7 @@ pub struct VertexList<T: Debug + Copy + Sized + Default, JW: Debug + Copy + Size
     total_vertices: usize,
     total_jw: usize,
     update_flags: VertexListFlags,
+    growth: GrowthStrategy,
 }
 
 #[allow(dead_code)]
@@ -67,6 +99,7 @@ impl<T: Debug + Copy + Sized + Default, JW: Debug + Copy + Sized + Default> Vert
             total_vertices: 0,
             total_jw: 0,
             update_flags: Default::default(),
+            growth: GrowthStrategy::new(2.0, 2048),
         }
     }
 
@@ -129,6 +162,13 @@ impl<T: Debug + Copy + Sized + Default, JW: Debug + Copy + Sized + Default> Vert
         self.update_flags = VertexListFlags::CALCULATE_RANGES | VertexListFlags::UPDATE_DATA;
     }
 
+    /// Host copies of the vertices and joint weights of `id`.
+    pub fn get_pointer(&self, id: usize) -> Option<(&[T], &[JW])> {
+        self.pointers
+            .get(&id)
+            .map(|desc| (desc.ptr.as_slice(), desc.jw_ptr.as_slice()))
+    }
+
     pub fn remove_pointer(&mut self, id: usize) -> bool {
         self.pointers.remove(&id).is_some() && self.draw_ranges.remove(&id).is_some()
         // no need to recalculate ranges
@@ -189,11 +229,12 @@ impl<T: Debug + Copy + Sized + Default, JW: Debug + Copy + Sized + Default> Vert
 
         if self.buffer.len() < self.total_vertices {
             self.buffer
-                .resize(self.total_vertices.next_multiple_of(&2048));
+                .resize(self.growth.capacity(self.buffer.len(), self.total_vertices));
         }
 
         if self.jw_buffer.len() < self.total_jw {
-            self.jw_buffer.resize(self.total_jw.next_multiple_of(&2048));
+            self.jw_buffer
+                .resize(self.growth.capacity(self.jw_buffer.len(), self.total_jw));
         }
 
         for (i, desc) in self.pointers.iter_mut() {
@@ -239,6 +280,11 @@ impl<T: Debug + Copy + Sized + Default, JW: Debug + Copy + Sized + Default> Vert
     pub(crate) fn get_jw_buffer(&self) -> &ManagedBuffer<JW> {
         &self.jw_buffer
     }
+
+    /// Size of the vertex and joint buffers in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.buffer.byte_size() + self.jw_buffer.byte_size()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -254,35 +300,31 @@ pub struct InstanceRange<
     pub extra: Ex,
 }
 
+/// Offsets of the instance lists of an [InstanceList] in its device buffer, kept apart from
+/// the device resources.
 #[derive(Debug)]
-pub struct InstanceList<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default = ()>
-{
-    device_buffer: ManagedBuffer<T>,
+struct InstanceLayout<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default = ()> {
     lists: BTreeMap<usize, InstanceRange<T, Ex>>,
     total: usize,
+    reserved: usize,
     recalculate_ranges: bool,
 }
 
-impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> InstanceList<T, Ex> {
-    pub fn new(device: &Arc<wgpu::Device>, queue: &Arc<wgpu::Queue>) -> Self {
+impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> Default
+    for InstanceLayout<T, Ex>
+{
+    fn default() -> Self {
         Self {
-            device_buffer: ManagedBuffer::new(
-                device.clone(),
-                queue.clone(),
-                wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
-                512,
-            ),
             lists: Default::default(),
             total: 0,
+            reserved: 0,
             recalculate_ranges: true,
         }
     }
+}
 
-    pub fn has(&self, id: usize) -> bool {
-        self.lists.get(&id).is_some()
-    }
-
-    pub fn add_instances_list(&mut self, id: usize, data: Vec<T>, extra: Ex) {
+impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> InstanceLayout<T, Ex> {
+    fn add(&mut self, id: usize, data: Vec<T>, extra: Ex) {
         let count = data.len() as u32;
         self.lists.insert(
             id,
@@ -295,42 +337,35 @@ impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> Ins
                 extra,
             },
         );
+        self.recalculate_ranges = true;
     }
 
-    pub fn update_instances_list(&mut self, id: usize, data: &[T], extra: Ex) {
+    fn update(&mut self, id: usize, data: &[T], extra: Ex) {
         let list = self.lists.get_mut(&id).unwrap();
 
+        // Lists keep their capacity when they shrink, otherwise instance counts that oscillate
+        // around a multiple of 4 would move every list each frame
         if (data.len() as u32) > list.capacity {
+            list.capacity = (data.len() as u32).next_multiple_of(&4);
             self.recalculate_ranges = true;
         }
         list.data.resize(data.len(), Default::default());
         list.data.copy_from_slice(data);
         list.count = data.len() as _;
-        list.capacity = list.count.next_multiple_of(&4);
         list.extra = extra;
     }
 
-    pub fn remove_instances_list(&mut self, id: usize) -> bool {
-        self.lists.remove(&id).is_some()
-    }
-
-    pub fn get_ranges(&self) -> &BTreeMap<usize, InstanceRange<T, Ex>> {
-        &self.lists
+    fn remove(&mut self, id: usize) -> bool {
+        let removed = self.lists.remove(&id).is_some();
+        self.recalculate_ranges |= removed;
+        removed
     }
 
-    pub fn get_buffer(&self) -> &ManagedBuffer<T> {
-        &self.device_buffer
-    }
-
-    pub fn update(&mut self) {
-        if self.recalculate_ranges {
-            self.update_ranges();
+    fn update_ranges(&mut self) {
+        if !self.recalculate_ranges {
+            return;
         }
 
-        self.update_data();
-    }
-
-    fn update_ranges(&mut self) {
         let mut current_offset = 0;
         for (_, desc) in self.lists.iter_mut() {
             desc.start = current_offset;
@@ -342,17 +377,103 @@ impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> Ins
         self.recalculate_ranges = false;
     }
 
+    /// Number of instances the device buffer needs to hold.
+    fn required(&self) -> usize {
+        self.total.max(self.reserved)
+    }
+}
+
+#[derive(Debug)]
+pub struct InstanceList<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default = ()>
+{
+    device_buffer: ManagedBuffer<T>,
+    layout: InstanceLayout<T, Ex>,
+    growth: GrowthStrategy,
+}
+
+impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> InstanceRange<T, Ex> {
+    /// Host copy of the instances in this range.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> InstanceList<T, Ex> {
+    pub fn new(
+        device: &Arc<wgpu::Device>,
+        queue: &Arc<wgpu::Queue>,
+        growth: GrowthStrategy,
+    ) -> Self {
+        Self {
+            device_buffer: ManagedBuffer::new(
+                device.clone(),
+                queue.clone(),
+                wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+                growth.min_capacity.max(1),
+            ),
+            layout: InstanceLayout::default(),
+            growth,
+        }
+    }
+
+    /// Changes how the device buffer grows, which takes effect at the next reallocation.
+    pub fn set_growth(&mut self, growth: GrowthStrategy) {
+        self.growth = growth;
+    }
+
+    /// Makes sure the device buffer holds at least `count` instances after the next
+    /// [InstanceList::update], so apps that know their instance count up front do not
+    /// reallocate while the scene fills up.
+    pub fn reserve(&mut self, count: usize) {
+        self.layout.reserved = self.layout.reserved.max(count);
+    }
+
+    pub fn has(&self, id: usize) -> bool {
+        self.layout.lists.get(&id).is_some()
+    }
+
+    pub fn add_instances_list(&mut self, id: usize, data: Vec<T>, extra: Ex) {
+        self.layout.add(id, data, extra);
+    }
+
+    pub fn update_instances_list(&mut self, id: usize, data: &[T], extra: Ex) {
+        self.layout.update(id, data, extra);
+    }
+
+    pub fn remove_instances_list(&mut self, id: usize) -> bool {
+        self.layout.remove(id)
+    }
+
+    pub fn get_ranges(&self) -> &BTreeMap<usize, InstanceRange<T, Ex>> {
+        &self.layout.lists
+    }
+
+    pub fn get_buffer(&self) -> &ManagedBuffer<T> {
+        &self.device_buffer
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.device_buffer.byte_size()
+    }
+
+    pub fn update(&mut self) {
+        self.layout.update_ranges();
+        self.update_data();
+    }
+
     fn update_data(&mut self) {
-        if self.total == 0 {
-            return;
+        let required = self.layout.required();
+        if self.device_buffer.len() < required {
+            self.device_buffer
+                .resize(self.growth.capacity(self.device_buffer.len(), required));
         }
 
-        if self.device_buffer.len() < self.total {
-            self.device_buffer.resize(self.total.next_multiple_of(&512));
+        if self.layout.total == 0 {
+            return;
         }
 
         {
-            for (_, desc) in self.lists.iter() {
+            for (_, desc) in self.layout.lists.iter() {
                 let offset = desc.start as usize;
                 let offset_plus_count = offset + desc.count as usize;
 
@@ -364,3 +485,82 @@ impl<T: Debug + Copy + Sized + Default, Ex: Debug + Clone + Sized + Default> Ins
         self.device_buffer.copy_to_device();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn growth_does_not_reallocate_around_boundary() {
+        let growth = GrowthStrategy::new(2.0, 512);
+        let mut capacity = growth.min_capacity;
+        let mut reallocations = 0;
+
+        // Instance counts that alternate around the initial capacity every frame
+        for frame in 0..100 {
+            let required = if frame % 2 == 0 { 520 } else { 500 };
+            let new_capacity = growth.capacity(capacity, required);
+            if new_capacity != capacity {
+                reallocations += 1;
+                capacity = new_capacity;
+            }
+        }
+
+        assert_eq!(reallocations, 1);
+        assert_eq!(capacity, 1024);
+    }
+
+    #[test]
+    fn instance_lists_do_not_reallocate_around_boundary() {
+        let growth = GrowthStrategy::new(2.0, 512);
+        let mut layout: InstanceLayout<u32> = InstanceLayout::default();
+        let mut capacity = growth.min_capacity;
+        let mut reallocations = 0;
+        let mut update = |layout: &mut InstanceLayout<u32>| {
+            layout.update_ranges();
+            let new_capacity = growth.capacity(capacity, layout.required());
+            if new_capacity != capacity {
+                reallocations += 1;
+                capacity = new_capacity;
+            }
+            capacity
+        };
+
+        layout.add(0, vec![0; 500], ());
+        assert_eq!(update(&mut layout), 512);
+
+        // A second list that is added and removed every frame, crossing the capacity
+        for frame in 0..100 {
+            if frame % 2 == 0 {
+                layout.add(1, vec![1; 20], ());
+            } else {
+                assert!(layout.remove(1));
+            }
+            // The first list shrinks and grows within its capacity
+            layout.update(0, &vec![0; 496 + (frame % 2) * 4], ());
+            update(&mut layout);
+
+            let first = &layout.lists[&0];
+            assert_eq!(first.start, 0);
+            if let Some(second) = layout.lists.get(&1) {
+                assert_eq!(second.start, first.capacity);
+            }
+        }
+
+        assert_eq!(update(&mut layout), 1024);
+        assert_eq!(reallocations, 1);
+    }
+
+    #[test]
+    fn growth_respects_factor_and_floor() {
+        let growth = GrowthStrategy::new(2.0, 64);
+        assert_eq!(growth.capacity(0, 1), 64);
+        assert_eq!(growth.capacity(64, 65), 128);
+        assert_eq!(growth.capacity(64, 300), 300);
+        assert_eq!(growth.capacity(128, 100), 128);
+
+        // Factors below 1.0 grow to exactly the required size
+        let exact = GrowthStrategy::new(0.5, 0);
+        assert_eq!(exact.capacity(100, 101), 101);
+    }
+}
@@ -1,10 +1,12 @@
-use rfw::prelude::TextureData;
+use rfw::prelude::{DataFormat, TextureData};
 use std::num::NonZeroU32;
 use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct WgpuTexture {
     dims: (u32, u32),
+    mip_levels: u32,
+    format: DataFormat,
     texture: Arc<Option<wgpu::Texture>>,
     pub(crate) view: Arc<Option<wgpu::TextureView>>,
 }
@@ -13,6 +15,8 @@ impl Default for WgpuTexture {
     fn default() -> Self {
         Self {
             dims: (0, 0),
+            mip_levels: 0,
+            format: DataFormat::BGRA8,
             texture: Arc::new(None),
             view: Arc::new(None),
         }
@@ -21,56 +25,152 @@ impl Default for WgpuTexture {
 
 impl WgpuTexture {
     pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, tex: TextureData) -> Self {
+        let mut uploads = TextureUploads::default();
+        let texture = Self::with_levels(device, &mut uploads, tex, tex.mip_levels);
+        uploads.submit(device, queue);
+        texture
+    }
+
+    /// Creates a texture with `mip_levels` levels, the levels `tex` does not provide can be
+    /// rendered afterwards by [crate::mipmap::MipGenerator::generate]. The data of `tex` is
+    /// copied once `uploads` is recorded.
+    pub fn with_levels(
+        device: &wgpu::Device,
+        uploads: &mut TextureUploads,
+        tex: TextureData,
+        mip_levels: u32,
+    ) -> Self {
         let mut texture = Self::default();
-        texture.init(device, queue, tex);
+        texture.init(device, uploads, tex, mip_levels);
         texture
     }
 
     pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, tex: TextureData) {
-        if self.texture.is_none() || tex.width != self.dims.0 || tex.height != self.dims.1 {
-            self.init(device, queue, tex);
+        let mut uploads = TextureUploads::default();
+        self.update_levels(device, &mut uploads, tex, tex.mip_levels);
+        uploads.submit(device, queue);
+    }
+
+    /// Updates the texture, see [WgpuTexture::with_levels].
+    pub fn update_levels(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut TextureUploads,
+        tex: TextureData,
+        mip_levels: u32,
+    ) {
+        if self.texture.is_none()
+            || tex.width != self.dims.0
+            || tex.height != self.dims.1
+            || mip_levels != self.mip_levels
+            || tex.format != self.format
+        {
+            self.init(device, uploads, tex, mip_levels);
             return;
         }
 
-        let texture: &Option<wgpu::Texture> = &self.texture;
-        let texture: &wgpu::Texture = texture.as_ref().unwrap();
+        uploads.push(&self.texture, &tex, 0);
+    }
+
+    /// Texture format used for the given data format.
+    pub fn format(format: DataFormat) -> wgpu::TextureFormat {
+        match format {
+            DataFormat::BGRA8 => wgpu::TextureFormat::Bgra8Unorm,
+            DataFormat::RGBA8 => wgpu::TextureFormat::Rgba8Unorm,
+            DataFormat::BC1 => wgpu::TextureFormat::Bc1RgbaUnorm,
+            DataFormat::BC3 => wgpu::TextureFormat::Bc3RgbaUnorm,
+            DataFormat::BC7 => wgpu::TextureFormat::Bc7RgbaUnorm,
+            DataFormat::RGBA16F => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+
+    /// Whether textures of the given format can be created on this device.
+    pub fn is_supported(device: &wgpu::Device, format: DataFormat) -> bool {
+        !format.is_compressed()
+            || device
+                .features()
+                .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    }
 
-        let mut width = tex.width;
-        let mut height = tex.height;
-        let mut local_offset = 0_u64;
-        for i in 0..tex.mip_levels {
-            let offset = local_offset * std::mem::size_of::<u32>() as u64;
+    /// Uploads every mip level of `tex` into an array layer of `texture`, compressed data
+    /// carries its own mip levels.
+    pub(crate) fn write_levels(
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        tex: &TextureData,
+        layer: u32,
+    ) {
+        let block = tex.format.block_dimensions();
+        for i in 0..tex.mip_levels as usize {
+            let offset = tex.byte_offset_for_level(i);
+            let bytes_per_row = tex.bytes_per_row(i);
+            let rows = tex.rows(i);
+            let end = offset + bytes_per_row * rows;
 
-            let end = (width as usize * height as usize * std::mem::size_of::<u32>()) as u64;
+            // Copies of block compressed textures cover whole blocks
+            let (width, height) = tex.mip_level_width_height(i);
+            let round = |v: usize| ((v.max(1) as u32 + block - 1) / block) * block;
 
             queue.write_texture(
                 wgpu::ImageCopyTexture {
                     mip_level: i as u32,
-                    origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                    texture: &texture,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                    texture,
                 },
-                &tex.bytes[(offset as usize)..(offset + end) as usize],
+                &tex.bytes[offset..end],
                 wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: NonZeroU32::new(
-                        (width as usize * std::mem::size_of::<u32>()) as u32,
-                    ),
-                    rows_per_image: NonZeroU32::new(tex.height),
+                    bytes_per_row: NonZeroU32::new(bytes_per_row as u32),
+                    rows_per_image: NonZeroU32::new(rows as u32),
                 },
                 wgpu::Extent3d {
-                    width,
-                    height,
+                    width: round(width),
+                    height: round(height),
                     depth_or_array_layers: 1,
                 },
             );
+        }
+    }
 
-            local_offset += (width * height) as wgpu::BufferAddress;
-            width >>= 1;
-            height >>= 1;
+    pub(crate) fn texture(&self) -> Option<&wgpu::Texture> {
+        self.texture.as_ref().as_ref()
+    }
+
+    /// Size of all mip levels of this texture in bytes.
+    pub fn byte_size(&self) -> usize {
+        if self.texture.is_none() {
+            return 0;
         }
+
+        let block = self.format.block_dimensions();
+        (0..self.mip_levels)
+            .map(|level| {
+                let width = (self.dims.0 >> level).max(1);
+                let height = (self.dims.1 >> level).max(1);
+                let blocks = ((width + block - 1) / block) * ((height + block - 1) / block);
+                blocks as usize * self.format.block_size()
+            })
+            .sum()
     }
 
-    fn init(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, tex: TextureData) {
+    fn init(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut TextureUploads,
+        tex: TextureData,
+        mip_levels: u32,
+    ) {
+        let mip_levels = mip_levels.max(tex.mip_levels);
+        let mut usage = wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST;
+        if mip_levels > tex.mip_levels {
+            // Missing levels are rendered to
+            usage |= wgpu::TextureUsage::RENDER_ATTACHMENT;
+        }
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: wgpu::Extent3d {
@@ -78,61 +178,160 @@ impl WgpuTexture {
                 height: tex.height,
                 depth_or_array_layers: 1,
             },
-            mip_level_count: tex.mip_levels,
+            mip_level_count: mip_levels,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8Unorm,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            format: Self::format(tex.format),
+            usage,
         });
 
-        let mut width = tex.width;
-        let mut height = tex.height;
-        let mut local_offset = 0_u64;
-        for i in 0..tex.mip_levels {
-            let offset = local_offset * std::mem::size_of::<u32>() as u64;
-
-            let end = (width as usize * height as usize * std::mem::size_of::<u32>()) as u64;
-
-            queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    mip_level: i as u32,
-                    origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                    texture: &texture,
-                },
-                &tex.bytes[(offset as usize)..(offset + end) as usize],
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: NonZeroU32::new(
-                        (width as usize * std::mem::size_of::<u32>()) as u32,
-                    ),
-                    rows_per_image: NonZeroU32::new(tex.height),
-                },
-                wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-            );
-
-            local_offset += (width * height) as wgpu::BufferAddress;
-            width >>= 1;
-            height >>= 1;
-        }
-
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
             label: None,
-            format: Some(wgpu::TextureFormat::Bgra8Unorm),
+            format: Some(Self::format(tex.format)),
             dimension: Some(wgpu::TextureViewDimension::D2),
             aspect: Default::default(),
             base_mip_level: 0,
             base_array_layer: 0,
             array_layer_count: None,
-            mip_level_count: NonZeroU32::new(tex.mip_levels),
+            mip_level_count: NonZeroU32::new(mip_levels),
         });
 
         self.dims = (tex.width, tex.height);
+        self.mip_levels = mip_levels;
+        self.format = tex.format;
         self.texture = Arc::new(Some(texture));
         self.view = Arc::new(Some(view));
+        uploads.push(&self.texture, &tex, 0);
+    }
+}
+
+#[derive(Debug)]
+struct TextureCopy {
+    texture: Arc<Option<wgpu::Texture>>,
+    offset: wgpu::BufferAddress,
+    mip_level: u32,
+    layer: u32,
+    bytes_per_row: u32,
+    rows: u32,
+    extent: wgpu::Extent3d,
+}
+
+/// Gathers the mip levels of many textures in a single staging buffer, copied to their
+/// textures by one command encoder instead of a queue write per level.
+#[derive(Debug, Default)]
+pub struct TextureUploads {
+    bytes: Vec<u8>,
+    copies: Vec<TextureCopy>,
+}
+
+impl TextureUploads {
+    pub fn is_empty(&self) -> bool {
+        self.copies.is_empty()
+    }
+
+    /// Size of the staging buffer in bytes.
+    pub fn byte_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Queues every mip level of `tex` for an array layer of `texture`. Rows are padded to
+    /// [wgpu::COPY_BYTES_PER_ROW_ALIGNMENT], levels of compressed data cover whole blocks.
+    pub fn push(&mut self, texture: &Arc<Option<wgpu::Texture>>, tex: &TextureData, layer: u32) {
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+        let block = tex.format.block_dimensions();
+        for i in 0..tex.mip_levels as usize {
+            let offset = tex.byte_offset_for_level(i);
+            let bytes_per_row = tex.bytes_per_row(i);
+            let rows = tex.rows(i);
+            let padded_bytes_per_row = ((bytes_per_row + align - 1) / align) * align;
+
+            let (width, height) = tex.mip_level_width_height(i);
+            let round = |v: usize| ((v.max(1) as u32 + block - 1) / block) * block;
+
+            let start = self.bytes.len();
+            self.bytes.resize(start + padded_bytes_per_row * rows, 0);
+            for row in 0..rows {
+                let src = offset + row * bytes_per_row;
+                let dst = start + row * padded_bytes_per_row;
+                self.bytes[dst..(dst + bytes_per_row)]
+                    .copy_from_slice(&tex.bytes[src..(src + bytes_per_row)]);
+            }
+
+            self.copies.push(TextureCopy {
+                texture: texture.clone(),
+                offset: start as wgpu::BufferAddress,
+                mip_level: i as u32,
+                layer,
+                bytes_per_row: padded_bytes_per_row as u32,
+                rows: rows as u32,
+                extent: wgpu::Extent3d {
+                    width: round(width),
+                    height: round(height),
+                    depth_or_array_layers: 1,
+                },
+            });
+        }
+    }
+
+    /// Records the copies of all queued levels into `encoder`. The staging buffer is released
+    /// once the copies have been executed.
+    pub fn record(self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("texture-staging-mem"),
+            size: self.bytes.len() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        staging_buffer
+            .slice(..)
+            .get_mapped_range_mut()
+            .copy_from_slice(self.bytes.as_slice());
+        staging_buffer.unmap();
+
+        for copy in self.copies.iter() {
+            let texture = match copy.texture.as_ref() {
+                Some(texture) => texture,
+                None => continue,
+            };
+
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &staging_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: copy.offset,
+                        bytes_per_row: NonZeroU32::new(copy.bytes_per_row),
+                        rows_per_image: NonZeroU32::new(copy.rows),
+                    },
+                },
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: copy.mip_level,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: copy.layer,
+                    },
+                },
+                copy.extent,
+            );
+        }
+    }
+
+    /// Records and submits all queued copies in their own command buffer.
+    pub fn submit(self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("texture-upload"),
+        });
+        self.record(device, &mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
     }
 }
 
@@ -140,6 +339,8 @@ impl Clone for WgpuTexture {
     fn clone(&self) -> Self {
         Self {
             dims: self.dims,
+            mip_levels: self.mip_levels,
+            format: self.format,
             texture: self.texture.clone(),
             view: self.view.clone(),
         }
@@ -137,4 +137,18 @@ impl WgpuSkin {
             );
         }
     }
+
+    /// Whether `skin` can be written with [WgpuSkin::update_joints], i.e. it has as many
+    /// joints as this skin and its buffer and bind group can be kept.
+    pub fn joints_fit(&self, skin: &SkinData) -> bool {
+        self.buffer.is_some() && self.joint_matrices.len() == skin.joint_matrices.len()
+    }
+
+    /// Writes new joint matrices to the existing buffer, the number of joints may not change.
+    pub fn update_joints(&mut self, queue: &wgpu::Queue, joint_matrices: &[Mat4]) {
+        self.joint_matrices.copy_from_slice(joint_matrices);
+        if let Some(buffer) = self.buffer.as_ref() {
+            queue.write_buffer(buffer, 0, self.joint_matrices.as_bytes());
+        }
+    }
 }
@@ -0,0 +1,402 @@
+use rfw::prelude::{DataFormat, Texture, TextureData, TextureFilter};
+use rfw::utils::BytesConversion;
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+
+/// Builds the mip chain of uploaded textures on the GPU. Every level is rendered from the
+/// level above it with a bilinear sample at the center of each texel, which averages the
+/// 2x2 texels it covers like the box filter of [Texture::generate_mipmaps].
+pub struct MipGenerator {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipelines: Vec<(wgpu::TextureFormat, wgpu::RenderPipeline)>,
+}
+
+impl MipGenerator {
+    /// Formats mip levels can be rendered to, block compressed data carries its own levels.
+    const FORMATS: [wgpu::TextureFormat; 3] = [
+        wgpu::TextureFormat::Bgra8Unorm,
+        wgpu::TextureFormat::Rgba8Unorm,
+        wgpu::TextureFormat::Rgba16Float,
+    ];
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mip-bind-group-layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mip-sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            ..Default::default()
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mip-pipeline-layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vert_shader: &[u8] = include_bytes!("../shaders/quad.vert.spv");
+        let frag_shader: &[u8] = include_bytes!("../shaders/quad.frag.spv");
+        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
+        });
+        let frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(frag_shader.as_quad_bytes())),
+        });
+
+        let pipelines = Self::FORMATS
+            .iter()
+            .map(|format| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("mip-pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        buffers: &[],
+                        entry_point: "main",
+                        module: &vert_module,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        entry_point: "main",
+                        module: &frag_module,
+                        targets: &[wgpu::ColorTargetState {
+                            format: *format,
+                            write_mask: wgpu::ColorWrite::ALL,
+                            blend: None,
+                        }],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        cull_mode: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        strip_index_format: None,
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        clamp_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                });
+                (*format, pipeline)
+            })
+            .collect();
+
+        Self {
+            bind_group_layout,
+            sampler,
+            pipelines,
+        }
+    }
+
+    /// Number of levels to generate for `tex`, 1 if it already has a mip chain or cannot be
+    /// rendered to. Matches the number of levels the CPU path generates.
+    pub fn levels_for(tex: &TextureData) -> u32 {
+        if tex.mip_levels > 1 || tex.filter == TextureFilter::Nearest || tex.format.is_compressed()
+        {
+            return tex.mip_levels;
+        }
+
+        let max_levels = 32 - tex.width.max(tex.height).max(1).leading_zeros();
+        Texture::MIP_LEVELS.min(max_levels).max(1)
+    }
+
+    /// Records passes into `encoder` that render levels 1 up to `mip_levels` of `texture` from
+    /// its first level.
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: DataFormat,
+        mip_levels: u32,
+    ) {
+        let format = crate::mat::WgpuTexture::format(format);
+        let pipeline = match self.pipelines.iter().find(|(f, _)| *f == format) {
+            Some((_, pipeline)) => pipeline,
+            None => return,
+        };
+
+        let views: Vec<wgpu::TextureView> = (0..mip_levels)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("mip-view"),
+                    format: Some(format),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    aspect: wgpu::TextureAspect::All,
+                    base_mip_level: level,
+                    mip_level_count: NonZeroU32::new(1),
+                    base_array_layer: 0,
+                    array_layer_count: NonZeroU32::new(1),
+                })
+            })
+            .collect();
+
+        for level in 1..views.len() {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("mip-bind-group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[level - 1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("mip-pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &views[level],
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                    resolve_target: None,
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use rfw::prelude::TextureFormat;
+
+    fn texture_data(texture: &Texture) -> TextureData<'_> {
+        TextureData {
+            width: texture.width,
+            height: texture.height,
+            mip_levels: texture.mip_levels,
+            bytes: texture.data.as_bytes(),
+            format: DataFormat::BGRA8,
+            filter: TextureFilter::Linear,
+            premultiplied: false,
+        }
+    }
+
+    /// Copies every level of `texture` to the host as tightly packed rows.
+    fn read_levels(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        data: &TextureData,
+    ) -> Option<Vec<Vec<u8>>> {
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mip-readback"),
+        });
+        let buffers: Vec<(wgpu::Buffer, usize, usize, usize)> = (0..data.mip_levels as usize)
+            .map(|level| {
+                let (w, h) = data.mip_level_width_height(level);
+                let padded_row_size = (w * 4 + align - 1) / align * align;
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("mip-readback-buffer"),
+                    size: (padded_row_size * h) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+                    mapped_at_creation: false,
+                });
+                encoder.copy_texture_to_buffer(
+                    wgpu::ImageCopyTexture {
+                        texture,
+                        mip_level: level as u32,
+                        origin: wgpu::Origin3d::ZERO,
+                    },
+                    wgpu::ImageCopyBuffer {
+                        buffer: &buffer,
+                        layout: wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: NonZeroU32::new(padded_row_size as u32),
+                            rows_per_image: None,
+                        },
+                    },
+                    wgpu::Extent3d {
+                        width: w as u32,
+                        height: h as u32,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                (buffer, w, h, padded_row_size)
+            })
+            .collect();
+        queue.submit(Some(encoder.finish()));
+
+        let mut levels = Vec::with_capacity(buffers.len());
+        for (buffer, w, h, padded_row_size) in buffers.iter() {
+            let slice = buffer.slice(..);
+            let mapping = slice.map_async(wgpu::MapMode::Read);
+            device.poll(wgpu::Maintain::Wait);
+            if block_on(mapping).is_err() {
+                return None;
+            }
+
+            let mut level = Vec::with_capacity(w * h * 4);
+            for row in slice.get_mapped_range().chunks(*padded_row_size) {
+                level.extend_from_slice(&row[..w * 4]);
+            }
+            buffer.unmap();
+            levels.push(level);
+        }
+        Some(levels)
+    }
+
+    #[test]
+    fn rendered_levels_match_cpu_box_filter() {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let adapter = match block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: None,
+            power_preference: wgpu::PowerPreference::default(),
+        })) {
+            Some(adapter) => adapter,
+            None => {
+                eprintln!("No adapter available, skipping mip generation test");
+                return;
+            }
+        };
+        let (device, queue) = match block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        )) {
+            Ok(device) => device,
+            Err(_) => {
+                eprintln!("No device available, skipping mip generation test");
+                return;
+            }
+        };
+
+        let (width, height) = (64, 32);
+        let bytes: Vec<u8> = (0..width * height * 4)
+            .map(|i| ((i * 37 + (i / 256) * 11) % 256) as u8)
+            .collect();
+        let texture = Texture::from_bytes(&bytes, width, height, TextureFormat::BGRA, 4);
+        let data = TextureData {
+            mip_levels: MipGenerator::levels_for(&texture_data(&texture)),
+            ..texture_data(&texture)
+        };
+        assert!(data.mip_levels > 1);
+
+        let gpu_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("mip-test-texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: data.mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: crate::mat::WgpuTexture::format(DataFormat::BGRA8),
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::RENDER_ATTACHMENT
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &gpu_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data.bytes[..(width * height * 4) as usize],
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width * 4),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let generator = MipGenerator::new(&device);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mip-test"),
+        });
+        generator.generate(
+            &device,
+            &mut encoder,
+            &gpu_texture,
+            DataFormat::BGRA8,
+            data.mip_levels,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let levels = read_levels(&device, &queue, &gpu_texture, &data).unwrap();
+        assert_eq!(levels[0].as_slice(), &data.bytes[..levels[0].len()]);
+
+        // Every rendered level is compared against the CPU box filter of the level above it
+        for level in 1..levels.len() {
+            let (w, h) = data.mip_level_width_height(level - 1);
+            let mut above = Texture::from_bytes(
+                &levels[level - 1],
+                w as u32,
+                h as u32,
+                TextureFormat::BGRA,
+                4,
+            );
+            above.generate_mipmaps(2);
+            let above = texture_data(&above);
+            let expected = &above.bytes[above.byte_offset_for_level(1)..][..levels[level].len()];
+
+            let error = levels[level]
+                .iter()
+                .zip(expected.iter())
+                .map(|(r, e)| (*r as i32 - *e as i32).abs())
+                .max();
+            assert!(error <= Some(1), "level {} differs by {:?}", level, error);
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use crate::mem::ManagedBuffer;
+use crate::output::WgpuOutput;
+use rfw::prelude::*;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Matches the uniform of the outline shaders
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+struct OutlineUniform {
+    color: Vec4,
+    offset: Vec4,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+struct EdgeUniform {
+    color: Vec4,
+    /// x: width in pixels
+    parameters: Vec4,
+}
+
+/// A draw of the outline pass, vertices in the 3D vertex buffer and instances in the instance
+/// buffer.
+pub type OutlineDraw = (Range<u32>, Range<u32>);
+
+/// Outlines selected instances. The instances are drawn once into the stencil buffer and a mask
+/// texture, after which a fullscreen pass colors the pixels where the stencil is set and a pixel
+/// within the outline width is not. The stencil itself can't be sampled, hence the mask texture.
+pub struct OutlinePass {
+    device: Arc<wgpu::Device>,
+    _mask_texture: wgpu::Texture,
+    mask_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    mask_bind_group: wgpu::BindGroup,
+    edge_bind_group_layout: wgpu::BindGroupLayout,
+    edge_bind_group: wgpu::BindGroup,
+    /// Writes the selected instances into the stencil and the mask texture
+    mask_pipeline: wgpu::RenderPipeline,
+    edge_pipeline: wgpu::RenderPipeline,
+    _mask_uniform: ManagedBuffer<OutlineUniform>,
+    edge_uniform: ManagedBuffer<EdgeUniform>,
+    selected: Vec<(usize, usize)>,
+}
+
+impl OutlinePass {
+    const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        output: &WgpuOutput,
+    ) -> Self {
+        let mut mask_uniform =
+            ManagedBuffer::new(device.clone(), queue.clone(), wgpu::BufferUsage::UNIFORM, 1);
+        mask_uniform.as_mut_slice()[0] = OutlineUniform {
+            color: Vec4::ONE,
+            offset: Vec4::ZERO,
+        };
+        mask_uniform.copy_to_device();
+        let edge_uniform = ManagedBuffer::new(device.clone(), queue, wgpu::BufferUsage::UNIFORM, 1);
+
+        let mask_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("outline-mask-bind-group-layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let mask_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("outline-mask-bind-group"),
+            layout: &mask_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: mask_uniform.binding_resource(),
+            }],
+        });
+
+        let edge_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("outline-edge-bind-group-layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: false,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("outline-mask-sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            ..Default::default()
+        });
+        let (mask_texture, mask_view) = Self::create_mask(&device, output);
+        let edge_bind_group = Self::create_edge_bind_group(
+            &device,
+            &edge_bind_group_layout,
+            &edge_uniform,
+            &mask_view,
+            &sampler,
+        );
+
+        let mask_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("outline-mask-layout"),
+            bind_group_layouts: &[uniform_bind_group_layout, &mask_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let edge_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("outline-edge-layout"),
+            bind_group_layouts: &[&edge_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader_module = |spirv: &[u8]| {
+            device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                flags: Default::default(),
+                label: None,
+                source: wgpu::ShaderSource::SpirV(Cow::from(spirv.as_quad_bytes())),
+            })
+        };
+        let mask_vert_module = shader_module(include_bytes!("../shaders/outline.vert.spv"));
+        let mask_frag_module = shader_module(include_bytes!("../shaders/outline.frag.spv"));
+        let edge_vert_module = shader_module(include_bytes!("../shaders/quad.vert.spv"));
+        let edge_frag_module = shader_module(include_bytes!("../shaders/outline_edge.frag.spv"));
+
+        let primitive = wgpu::PrimitiveState {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            strip_index_format: None,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            clamp_depth: false,
+            conservative: false,
+        };
+        let depth_stencil = |compare, pass_op| {
+            let stencil = wgpu::StencilFaceState {
+                compare,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op,
+            };
+            Some(wgpu::DepthStencilState {
+                format: WgpuOutput::STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil,
+                    back: stencil,
+                    read_mask: !0,
+                    write_mask: !0,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            })
+        };
+        let multisample = wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        };
+
+        let attributes = [wgpu::VertexAttribute {
+            offset: 0,
+            format: wgpu::VertexFormat::Float32x4,
+            shader_location: 0,
+        }];
+
+        // Draws front and back faces, which keeps the mask independent of handedness
+        let mask_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("outline-mask-pipeline"),
+            layout: Some(&mask_pipeline_layout),
+            vertex: wgpu::VertexState {
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex3D>() as wgpu::BufferAddress,
+                    attributes: &attributes,
+                    step_mode: wgpu::InputStepMode::Vertex,
+                }],
+                entry_point: "main",
+                module: &mask_vert_module,
+            },
+            fragment: Some(wgpu::FragmentState {
+                entry_point: "main",
+                module: &mask_frag_module,
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::MASK_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: None,
+                }],
+            }),
+            primitive,
+            depth_stencil: depth_stencil(
+                wgpu::CompareFunction::Always,
+                wgpu::StencilOperation::Replace,
+            ),
+            multisample,
+        });
+
+        // Only runs inside the selection, the stencil rejects every other pixel
+        let edge_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("outline-edge-pipeline"),
+            layout: Some(&edge_pipeline_layout),
+            vertex: wgpu::VertexState {
+                buffers: &[],
+                entry_point: "main",
+                module: &edge_vert_module,
+            },
+            fragment: Some(wgpu::FragmentState {
+                entry_point: "main",
+                module: &edge_frag_module,
+                targets: &[wgpu::ColorTargetState {
+                    format: WgpuOutput::OUTPUT_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                }],
+            }),
+            primitive,
+            depth_stencil: depth_stencil(
+                wgpu::CompareFunction::Equal,
+                wgpu::StencilOperation::Keep,
+            ),
+            multisample,
+        });
+
+        Self {
+            device,
+            _mask_texture: mask_texture,
+            mask_view,
+            sampler,
+            mask_bind_group,
+            edge_bind_group_layout,
+            edge_bind_group,
+            mask_pipeline,
+            edge_pipeline,
+            _mask_uniform: mask_uniform,
+            edge_uniform,
+            selected: Vec::new(),
+        }
+    }
+
+    fn create_mask(
+        device: &wgpu::Device,
+        output: &WgpuOutput,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("outline-mask"),
+            size: wgpu::Extent3d {
+                width: output.width,
+                height: output.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::MASK_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    fn create_edge_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform: &ManagedBuffer<EdgeUniform>,
+        mask_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("outline-edge-bind-group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.binding_resource(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(mask_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Recreates the mask texture at the size of `output`.
+    pub fn update_bind_groups(&mut self, output: &WgpuOutput) {
+        let (mask_texture, mask_view) = Self::create_mask(&self.device, output);
+        self.edge_bind_group = Self::create_edge_bind_group(
+            &self.device,
+            &self.edge_bind_group_layout,
+            &self.edge_uniform,
+            &mask_view,
+            &self.sampler,
+        );
+        self._mask_texture = mask_texture;
+        self.mask_view = mask_view;
+    }
+
+    pub fn set_selected(&mut self, instances: &[(usize, usize)]) {
+        self.selected = instances.to_vec();
+    }
+
+    pub fn selected(&self) -> &[(usize, usize)] {
+        self.selected.as_slice()
+    }
+
+    /// Draws outlines of `width` pixels along the inside of the silhouettes of `draws` into the
+    /// output texture.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform_bind_group: &wgpu::BindGroup,
+        output: &WgpuOutput,
+        vertex_buffer: &wgpu::Buffer,
+        draws: &[OutlineDraw],
+        color: Vec4,
+        width: f32,
+    ) {
+        if draws.is_empty() {
+            return;
+        }
+
+        self.edge_uniform.as_mut_slice()[0] = EdgeUniform {
+            color,
+            parameters: Vec4::new(width, 0.0, 0.0, 0.0),
+        };
+        self.edge_uniform.copy_to_device();
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("outline-mask"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.mask_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &output.stencil_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: true,
+                    }),
+                }),
+            });
+
+            render_pass.set_pipeline(&self.mask_pipeline);
+            render_pass.set_bind_group(0, uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.mask_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_stencil_reference(1);
+            for (vertices, instances) in draws.iter() {
+                render_pass.draw(vertices.clone(), instances.clone());
+            }
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("outline-edges"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &output.output_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &output.stencil_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: false,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: false,
+                }),
+            }),
+        });
+
+        render_pass.set_pipeline(&self.edge_pipeline);
+        render_pass.set_bind_group(0, &self.edge_bind_group, &[]);
+        render_pass.set_stencil_reference(1);
+        render_pass.draw(0..6, 0..1);
+    }
+}
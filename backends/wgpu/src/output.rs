@@ -1,5 +1,6 @@
 use rfw::prelude::*;
 use std::borrow::Cow;
+use std::num::NonZeroU32;
 
 #[derive(Debug)]
 pub struct WgpuOutput {
@@ -22,6 +23,10 @@ pub struct WgpuOutput {
     pub depth_texture: wgpu::Texture,
     pub depth_texture_view: wgpu::TextureView,
 
+    /// Stencil mask of the outline pass
+    pub stencil_texture: wgpu::Texture,
+    pub stencil_view: wgpu::TextureView,
+
     pub albedo_texture: wgpu::Texture,
     pub albedo_view: wgpu::TextureView,
 
@@ -48,6 +53,12 @@ pub struct WgpuOutput {
 
     pub mat_param_texture: wgpu::Texture,
     pub mat_param_view: wgpu::TextureView,
+
+    pub lightmap_texture: wgpu::Texture,
+    pub lightmap_view: wgpu::TextureView,
+
+    pub motion_texture: wgpu::Texture,
+    pub motion_view: wgpu::TextureView,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -61,10 +72,39 @@ pub enum WgpuView {
     Ssao = 6,
     FilteredSsao = 7,
     MatParams = 8,
+    Lightmap = 9,
+    Motion = 10,
 }
 
 impl WgpuView {
-    pub const COUNT: usize = 9;
+    pub const COUNT: usize = 11;
+
+    /// Names of the views, indexed by their integer value.
+    pub const NAMES: [&'static str; Self::COUNT] = [
+        "output",
+        "albedo",
+        "normal",
+        "gbuffer",
+        "radiance",
+        "screen-space",
+        "ssao",
+        "filtered-ssao",
+        "mat-params",
+        "lightmap",
+        "motion",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[*self as usize]
+    }
+
+    /// Looks up a view by its name in [WgpuView::NAMES].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(WgpuView::from)
+    }
 }
 
 impl From<isize> for WgpuView {
@@ -79,6 +119,8 @@ impl From<isize> for WgpuView {
             6 => WgpuView::Ssao,
             7 => WgpuView::FilteredSsao,
             8 => WgpuView::MatParams,
+            9 => WgpuView::Lightmap,
+            10 => WgpuView::Motion,
             _ => WgpuView::Output,
         }
     }
@@ -96,6 +138,8 @@ impl From<usize> for WgpuView {
             6 => WgpuView::Ssao,
             7 => WgpuView::FilteredSsao,
             8 => WgpuView::MatParams,
+            9 => WgpuView::Lightmap,
+            10 => WgpuView::Motion,
             _ => WgpuView::Output,
         }
     }
@@ -106,6 +150,7 @@ impl WgpuOutput {
     pub const STORAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
     pub const SSAO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    pub const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
     pub const MAT_PARAM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 
     pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
@@ -292,6 +337,10 @@ impl WgpuOutput {
             array_layer_count: None,
         });
 
+        let stencil_texture =
+            Self::create_depth_texture(device, Self::STENCIL_FORMAT, width, height);
+        let stencil_view = stencil_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
         let albedo_texture = Self::create_texture(device, Self::STORAGE_FORMAT, width, height);
         let albedo_view = albedo_texture.create_view(&wgpu::TextureViewDescriptor {
             label: None,
@@ -412,6 +461,32 @@ impl WgpuOutput {
             array_layer_count: None,
         });
 
+        let lightmap_texture = Self::create_texture(device, Self::STORAGE_FORMAT, width, height);
+        let lightmap_view = lightmap_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            format: Some(Self::STORAGE_FORMAT),
+            dimension: None,
+            aspect: wgpu::TextureAspect::All,
+
+            base_mip_level: 0,
+            mip_level_count: None,
+            base_array_layer: 0,
+            array_layer_count: None,
+        });
+
+        let motion_texture = Self::create_texture(device, Self::STORAGE_FORMAT, width, height);
+        let motion_view = motion_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            format: Some(Self::STORAGE_FORMAT),
+            dimension: None,
+            aspect: wgpu::TextureAspect::All,
+
+            base_mip_level: 0,
+            mip_level_count: None,
+            base_array_layer: 0,
+            array_layer_count: None,
+        });
+
         let debug_bind_groups = (0..WgpuView::COUNT)
             .into_iter()
             .map(|i| {
@@ -435,6 +510,8 @@ impl WgpuOutput {
                                 6 => &ssao_output_view,
                                 7 => &ssao_filtered_output_view,
                                 8 => &mat_param_view,
+                                9 => &lightmap_view,
+                                10 => &motion_view,
                                 _ => &output_texture_view,
                             }),
                         },
@@ -460,6 +537,8 @@ impl WgpuOutput {
             output_sampler,
             depth_texture,
             depth_texture_view,
+            stencil_texture,
+            stencil_view,
             albedo_texture,
             albedo_view,
             normal_texture,
@@ -478,6 +557,10 @@ impl WgpuOutput {
             ssao_filtered_output_view,
             mat_param_texture,
             mat_param_view,
+            lightmap_texture,
+            lightmap_view,
+            motion_texture,
+            motion_view,
         }
     }
 
@@ -498,11 +581,214 @@ impl WgpuOutput {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            // Copied into by the post-process passes
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT
+                | wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::COPY_DST,
+        })
+    }
+
+    /// Copies the output texture to the host as tightly packed RGBA rows, waits for the GPU.
+    pub fn capture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<CapturedFrame> {
+        let (width, height) = (self.width, self.height);
+        let row_size = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_size = (row_size + align - 1) / align * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture-buffer"),
+            size: (padded_row_size * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("capture"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.output_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if futures::executor::block_on(mapping).is_err() {
+            return None;
+        }
+
+        let mut pixels = Vec::with_capacity((row_size * height) as usize);
+        {
+            let range = slice.get_mapped_range();
+            for row in range.chunks(padded_row_size as usize) {
+                // Output is BGRA
+                for bgra in row[..row_size as usize].chunks_exact(4) {
+                    pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+                }
+            }
+        }
+        buffer.unmap();
+
+        Some(CapturedFrame {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Copies the radiance texture to the host as tightly packed linear RGBA floats, this is the
+    /// lighting result before the blit pass applies exposure and tonemapping. Waits for the GPU.
+    pub fn capture_hdr(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<CapturedHdrFrame> {
+        let (width, height) = (self.width, self.height);
+        // Rgba16Float
+        let row_size = width * 8;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row_size = (row_size + align - 1) / align * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture-hdr-buffer"),
+            size: (padded_row_size * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("capture-hdr"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.radiance_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if futures::executor::block_on(mapping).is_err() {
+            return None;
+        }
+
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        {
+            let range = slice.get_mapped_range();
+            for row in range.chunks(padded_row_size as usize) {
+                for half in row[..row_size as usize].chunks_exact(2) {
+                    pixels.push(half_to_f32(u16::from_le_bytes([half[0], half[1]])));
+                }
+            }
+        }
+        buffer.unmap();
+
+        Some(CapturedHdrFrame {
+            width,
+            height,
+            pixels,
         })
     }
 
-    fn create_texture(
+    /// Copies a single texel of the depth texture to the host, waits for the GPU. Returns None
+    /// for pixels outside of the output.
+    pub fn read_depth(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        x: u32,
+        y: u32,
+    ) -> Option<f32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        // A single row, padded to the copy alignment
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("depth-read-buffer"),
+            size: align as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("read-depth"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.depth_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(align),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if futures::executor::block_on(mapping).is_err() {
+            return None;
+        }
+
+        // Depth32Float
+        let depth = {
+            let range = slice.get_mapped_range();
+            f32::from_le_bytes([range[0], range[1], range[2], range[3]])
+        };
+        buffer.unmap();
+
+        Some(depth)
+    }
+
+    pub(crate) fn create_texture(
         device: &wgpu::Device,
         format: wgpu::TextureFormat,
         width: u32,
@@ -521,7 +807,8 @@ impl WgpuOutput {
             format,
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT
                 | wgpu::TextureUsage::SAMPLED
-                | wgpu::TextureUsage::STORAGE,
+                | wgpu::TextureUsage::STORAGE
+                | wgpu::TextureUsage::COPY_SRC,
         })
     }
 
@@ -542,7 +829,7 @@ impl WgpuOutput {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
         })
     }
 
@@ -578,6 +865,11 @@ impl WgpuOutput {
         });
         self.depth_texture = depth_texture;
 
+        let stencil_texture =
+            Self::create_depth_texture(device, Self::STENCIL_FORMAT, width, height);
+        self.stencil_view = stencil_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.stencil_texture = stencil_texture;
+
         let albedo_texture = Self::create_texture(device, Self::STORAGE_FORMAT, width, height);
         self.albedo_view = albedo_texture.create_view(&wgpu::TextureViewDescriptor {
             label: None,
@@ -706,6 +998,34 @@ impl WgpuOutput {
         });
         self.mat_param_texture = mat_param_texture;
 
+        let lightmap_texture = Self::create_texture(device, Self::STORAGE_FORMAT, width, height);
+        self.lightmap_view = lightmap_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            format: Some(Self::STORAGE_FORMAT),
+            dimension: None,
+            aspect: wgpu::TextureAspect::All,
+
+            base_mip_level: 0,
+            mip_level_count: None,
+            base_array_layer: 0,
+            array_layer_count: None,
+        });
+        self.lightmap_texture = lightmap_texture;
+
+        let motion_texture = Self::create_texture(device, Self::STORAGE_FORMAT, width, height);
+        self.motion_view = motion_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            format: Some(Self::STORAGE_FORMAT),
+            dimension: None,
+            aspect: wgpu::TextureAspect::All,
+
+            base_mip_level: 0,
+            mip_level_count: None,
+            base_array_layer: 0,
+            array_layer_count: None,
+        });
+        self.motion_texture = motion_texture;
+
         self.debug_bind_groups = (0..WgpuView::COUNT)
             .into_iter()
             .map(|i| {
@@ -729,6 +1049,8 @@ impl WgpuOutput {
                                 6 => &self.ssao_output_view,
                                 7 => &self.ssao_filtered_output_view,
                                 8 => &self.mat_param_view,
+                                9 => &self.lightmap_view,
+                                10 => &self.motion_view,
                                 _ => &self.output_texture_view,
                             }),
                         },
@@ -754,6 +1076,8 @@ impl WgpuOutput {
                 WgpuView::Ssao => &self.ssao_output_view,
                 WgpuView::FilteredSsao => &self.ssao_filtered_output_view,
                 WgpuView::MatParams => &self.mat_param_view,
+                WgpuView::Lightmap => &self.lightmap_view,
+                WgpuView::Motion => &self.motion_view,
             },
             resolve_target: None,
             ops: wgpu::Operations {
@@ -833,6 +1157,8 @@ impl WgpuOutput {
                 WgpuView::Ssao => &self.ssao_output_view,
                 WgpuView::FilteredSsao => &self.ssao_filtered_output_view,
                 WgpuView::MatParams => &self.mat_param_view,
+                WgpuView::Lightmap => &self.lightmap_view,
+                WgpuView::Motion => &self.motion_view,
             }),
         }
     }
@@ -867,3 +1193,23 @@ impl WgpuOutput {
         render_pass.draw(0..6, 0..1);
     }
 }
+
+/// Converts an IEEE 754 half precision float to single precision.
+fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1F) as u32;
+    let mantissa = (half & 0x3FF) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal, representable as a normal single precision float
+        (0, m) => {
+            let shift = m.leading_zeros() - 21;
+            sign | ((113 - shift) << 23) | (((m << shift) & 0x3FF) << 13)
+        }
+        (0x1F, m) => sign | 0x7F80_0000 | (m << 13),
+        (e, m) => sign | ((e + 112) << 23) | (m << 13),
+    };
+
+    f32::from_bits(bits)
+}
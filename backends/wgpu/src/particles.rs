@@ -0,0 +1,236 @@
+use crate::mem::ManagedBuffer;
+use crate::output::WgpuOutput;
+use rfw::prelude::*;
+use std::sync::Arc;
+
+#[derive(Debug)]
+struct ParticleSystem {
+    buffer: ManagedBuffer<Particle>,
+    count: u32,
+    blend: ParticleBlend,
+}
+
+#[derive(Debug)]
+pub struct ParticleRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    alpha_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    systems: Vec<Option<ParticleSystem>>,
+}
+
+impl ParticleRenderer {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("particle-layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vert = include_bytes!("../shaders/particle.vert.spv");
+        let frag = include_bytes!("../shaders/particle.frag.spv");
+        let vert = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(vert.as_quad_bytes().into()),
+        });
+        let frag = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(frag.as_quad_bytes().into()),
+        });
+
+        let create_pipeline = |label: &str, blend: wgpu::BlendState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Particle>() as wgpu::BufferAddress,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                offset: 0,
+                                format: wgpu::VertexFormat::Float32x3,
+                                shader_location: 0,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: 12,
+                                format: wgpu::VertexFormat::Float32,
+                                shader_location: 1,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: 16,
+                                format: wgpu::VertexFormat::Float32x4,
+                                shader_location: 2,
+                            },
+                        ],
+                        // Every particle is a single instance of a 6 vertex quad
+                        step_mode: wgpu::InputStepMode::Instance,
+                    }],
+                    entry_point: "main",
+                    module: &vert,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    entry_point: "main",
+                    module: &frag,
+                    targets: &[wgpu::ColorTargetState {
+                        format: WgpuOutput::STORAGE_FORMAT,
+                        write_mask: wgpu::ColorWrite::COLOR,
+                        blend: Some(blend),
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    strip_index_format: None,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    clamp_depth: false,
+                    conservative: false,
+                },
+                // Particles are occluded by the scene but do not occlude each other
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: WgpuOutput::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    bias: wgpu::DepthBiasState::default(),
+                    stencil: wgpu::StencilState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            })
+        };
+
+        let alpha_pipeline = create_pipeline(
+            "particle-alpha-pipeline",
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            },
+        );
+        let additive_pipeline = create_pipeline(
+            "particle-additive-pipeline",
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            },
+        );
+
+        Self {
+            device,
+            queue,
+            alpha_pipeline,
+            additive_pipeline,
+            systems: Vec::new(),
+        }
+    }
+
+    pub fn set_particles(&mut self, system: usize, particles: &[Particle], blend: ParticleBlend) {
+        if particles.is_empty() {
+            if let Some(s) = self.systems.get_mut(system) {
+                *s = None;
+            }
+            return;
+        }
+
+        if system >= self.systems.len() {
+            self.systems.resize_with(system + 1, || None);
+        }
+
+        let device = &self.device;
+        let queue = &self.queue;
+        let s = self.systems[system].get_or_insert_with(|| ParticleSystem {
+            buffer: ManagedBuffer::new(
+                device.clone(),
+                queue.clone(),
+                wgpu::BufferUsage::VERTEX,
+                particles.len().next_power_of_two(),
+            ),
+            count: 0,
+            blend,
+        });
+
+        // Grow in powers of two to avoid recreating the buffer for slowly growing systems
+        if particles.len() > s.buffer.len() {
+            s.buffer.resize(particles.len().next_power_of_two());
+        }
+
+        s.buffer.as_mut_slice()[..particles.len()].copy_from_slice(particles);
+        s.buffer.copy_to_device_ranged(0, particles.len());
+        s.count = particles.len() as u32;
+        s.blend = blend;
+    }
+
+    pub fn clear(&mut self) {
+        self.systems.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.iter().all(|s| s.is_none())
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.systems
+            .iter()
+            .flatten()
+            .map(|s| s.buffer.byte_size())
+            .sum()
+    }
+
+    /// Draws all particle systems on top of the radiance of the scene.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bg: &wgpu::BindGroup,
+        output: &WgpuOutput,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("particles"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+                view: &output.radiance_view,
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &output.depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_bind_group(0, camera_bg, &[]);
+        for s in self.systems.iter().flatten() {
+            render_pass.set_pipeline(match s.blend {
+                ParticleBlend::Alpha => &self.alpha_pipeline,
+                ParticleBlend::Additive => &self.additive_pipeline,
+            });
+            render_pass.set_vertex_buffer(0, s.buffer.buffer().slice(..));
+            render_pass.draw(0..6, 0..s.count);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use super::{
     light::{ShadowMapArray, WgpuLights},
     output::{WgpuOutput, WgpuView},
+    skybox::WgpuSkybox,
 };
 use rfw::prelude::*;
 use std::borrow::Cow;
@@ -135,12 +136,28 @@ impl QuadPass {
     }
 
     pub fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        self.render_region(encoder, output, None, true);
+    }
+
+    /// Only draws within `scissor` of `output`, clears the rest of `output` if `clear` is set
+    /// and keeps its contents otherwise.
+    pub fn render_region(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        scissor: Option<Viewport>,
+        clear: bool,
+    ) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[wgpu::RenderPassColorAttachment {
                 view: output,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: if clear {
+                        wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
                     store: true,
                 },
                 resolve_target: None,
@@ -150,36 +167,348 @@ impl QuadPass {
 
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
+        if let Some(scissor) = scissor {
+            render_pass.set_scissor_rect(scissor.x, scissor.y, scissor.w, scissor.h);
+        }
         render_pass.draw(0..6, 0..1);
     }
 }
 
-pub struct BlitPass {
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct GradingUniform {
+    exposure: f32,
+    contrast: f32,
+    saturation: f32,
+    tonemap: u32,
+    transfer: u32,
+    gamma: f32,
+    auto_exposure: u32,
+    adaptation: f32,
+    delta_time: f32,
+    _padding: [u32; 3],
+}
+
+impl GradingUniform {
+    fn new(grading: ColorGrading, transfer: OutputTransfer) -> Self {
+        let (transfer, gamma) = match transfer {
+            OutputTransfer::Srgb => (0, 1.0),
+            OutputTransfer::Linear => (1, 1.0),
+            OutputTransfer::Gamma(gamma) => (2, gamma.max(1e-3)),
+            OutputTransfer::Rec709 => (3, 1.0),
+        };
+
+        Self {
+            exposure: grading.exposure,
+            contrast: grading.contrast,
+            saturation: grading.saturation,
+            tonemap: grading.tonemap as u32,
+            transfer,
+            gamma,
+            auto_exposure: 0,
+            adaptation: 0.0,
+            delta_time: 0.0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Exposes, tonemaps and grades the lighting result into the display texture read by
+/// [BlitPass]. With auto-exposure enabled, a histogram of the log luminance of every frame
+/// drives the exposure, which then follows the scene brightness over time.
+pub struct ToneMapPass {
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
-    pipeline: wgpu::RenderPipeline,
+    histogram_pipeline: wgpu::ComputePipeline,
+    adapt_pipeline: wgpu::ComputePipeline,
+    tonemap_pipeline: wgpu::ComputePipeline,
+    grading_buffer: wgpu::Buffer,
+    /// Adapted exposure followed by the luminance bins of the current frame
+    exposure_buffer: wgpu::Buffer,
+    _display_texture: wgpu::Texture,
+    display_view: wgpu::TextureView,
+    grading: ColorGrading,
+    transfer: OutputTransfer,
+    auto_exposure: bool,
+    adaptation: f32,
+    /// Set when auto-exposure gets enabled, the exposure then snaps to the current frame
+    reset_exposure: bool,
+    last_launch: Option<std::time::Instant>,
 }
 
-impl BlitPass {
+impl ToneMapPass {
+    /// Matches EXPOSURE_BINS of exposure.glsl
+    const EXPOSURE_BINS: usize = 64;
+    const GRADING_SIZE: wgpu::BufferAddress =
+        std::mem::size_of::<GradingUniform>() as wgpu::BufferAddress;
+    const EXPOSURE_HEADER_SIZE: wgpu::BufferAddress = 16;
+    const EXPOSURE_SIZE: wgpu::BufferAddress = Self::EXPOSURE_HEADER_SIZE
+        + (Self::EXPOSURE_BINS * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+
     pub fn new(device: &wgpu::Device, output: &WgpuOutput) -> Self {
+        let grading_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("color-grading-mem"),
+            contents: rfw::utils::as_bytes(&GradingUniform::new(
+                ColorGrading::default(),
+                OutputTransfer::default(),
+            )),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let exposure_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("exposure-mem"),
+            contents: &[0; Self::EXPOSURE_SIZE as usize],
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+        });
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("blit-bind-group-layout"),
+            label: Some("tonemap-bind-group-layout"),
             entries: &[
-                output.as_storage_entry(0, wgpu::ShaderStage::FRAGMENT, WgpuView::Albedo, true),
-                output.as_storage_entry(1, wgpu::ShaderStage::FRAGMENT, WgpuView::Radiance, true),
-                output.as_storage_entry(2, wgpu::ShaderStage::FRAGMENT, WgpuView::Ssao, true),
+                output.as_storage_entry(0, wgpu::ShaderStage::COMPUTE, WgpuView::Albedo, true),
+                output.as_storage_entry(1, wgpu::ShaderStage::COMPUTE, WgpuView::Radiance, true),
+                output.as_storage_entry(2, wgpu::ShaderStage::COMPUTE, WgpuView::Ssao, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(Self::GRADING_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(Self::EXPOSURE_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: WgpuOutput::STORAGE_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
             ],
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("blit-bind-group"),
-            layout: &bind_group_layout,
+        let (display_texture, display_view) = Self::create_display(device, output);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &grading_buffer,
+            &exposure_buffer,
+            &display_view,
+            output,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label: &str, shader: &[u8]| {
+            let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                flags: Default::default(),
+                label: None,
+                source: wgpu::ShaderSource::SpirV(Cow::from(shader.as_quad_bytes())),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                entry_point: "main",
+                module: &module,
+            })
+        };
+
+        let histogram_pipeline = create_pipeline(
+            "exposure-histogram-pipeline",
+            include_bytes!("../shaders/exposure_histogram.comp.spv"),
+        );
+        let adapt_pipeline = create_pipeline(
+            "exposure-adapt-pipeline",
+            include_bytes!("../shaders/exposure_adapt.comp.spv"),
+        );
+        let tonemap_pipeline = create_pipeline(
+            "tonemap-pipeline",
+            include_bytes!("../shaders/tonemap.comp.spv"),
+        );
+
+        Self {
+            bind_group_layout,
+            bind_group,
+            histogram_pipeline,
+            adapt_pipeline,
+            tonemap_pipeline,
+            grading_buffer,
+            exposure_buffer,
+            _display_texture: display_texture,
+            display_view,
+            grading: ColorGrading::default(),
+            transfer: OutputTransfer::default(),
+            auto_exposure: false,
+            adaptation: 1.0,
+            reset_exposure: false,
+            last_launch: None,
+        }
+    }
+
+    fn create_display(
+        device: &wgpu::Device,
+        output: &WgpuOutput,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = WgpuOutput::create_texture(
+            device,
+            WgpuOutput::STORAGE_FORMAT,
+            output.width,
+            output.height,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        grading_buffer: &wgpu::Buffer,
+        exposure_buffer: &wgpu::Buffer,
+        display_view: &wgpu::TextureView,
+        output: &WgpuOutput,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap-bind-group"),
+            layout,
             entries: &[
                 output.as_binding(0, WgpuView::Albedo),
                 output.as_binding(1, WgpuView::Radiance),
                 output.as_binding(2, WgpuView::Ssao),
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: grading_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: exposure_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(display_view),
+                },
             ],
+        })
+    }
+
+    /// Recreates the display texture at the size of `output`, [BlitPass] needs to update its
+    /// bind groups afterwards.
+    pub fn update_bind_groups(&mut self, device: &wgpu::Device, output: &WgpuOutput) {
+        let (display_texture, display_view) = Self::create_display(device, output);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.grading_buffer,
+            &self.exposure_buffer,
+            &display_view,
+            output,
+        );
+        self._display_texture = display_texture;
+        self.display_view = display_view;
+    }
+
+    pub fn display_view(&self) -> &wgpu::TextureView {
+        &self.display_view
+    }
+
+    pub fn set_color_grading(&mut self, grading: ColorGrading, transfer: OutputTransfer) {
+        self.grading = grading;
+        self.transfer = transfer;
+    }
+
+    /// `adaptation` is the rate per second at which the exposure approaches its target.
+    pub fn set_auto_exposure(&mut self, enabled: bool, adaptation: f32) {
+        self.reset_exposure |= enabled && !self.auto_exposure;
+        self.auto_exposure = enabled;
+        self.adaptation = adaptation;
+    }
+
+    pub fn launch(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        width: u32,
+        height: u32,
+    ) {
+        let now = std::time::Instant::now();
+        let delta_time = self
+            .last_launch
+            .map(|t| now.duration_since(t).as_secs_f32())
+            .unwrap_or(0.0);
+        self.last_launch = Some(now);
+
+        let uniform = GradingUniform {
+            auto_exposure: self.auto_exposure as u32,
+            adaptation: self.adaptation,
+            delta_time,
+            ..GradingUniform::new(self.grading, self.transfer)
+        };
+        queue.write_buffer(&self.grading_buffer, 0, rfw::utils::as_bytes(&uniform));
+        if self.reset_exposure {
+            queue.write_buffer(
+                &self.exposure_buffer,
+                0,
+                &[0; Self::EXPOSURE_HEADER_SIZE as usize],
+            );
+            self.reset_exposure = false;
+        }
+
+        let width = (width as f32 / 16.0).ceil() as u32;
+        let height = (height as f32 / 16.0).ceil() as u32;
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("tonemap"),
         });
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        if self.auto_exposure {
+            compute_pass.set_pipeline(&self.histogram_pipeline);
+            compute_pass.dispatch(width, height, 1);
+            compute_pass.set_pipeline(&self.adapt_pipeline);
+            compute_pass.dispatch(1, 1, 1);
+        }
+        compute_pass.set_pipeline(&self.tonemap_pipeline);
+        compute_pass.dispatch(width, height, 1);
+    }
+}
+
+/// Copies the display texture of [ToneMapPass] to the output.
+pub struct BlitPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl BlitPass {
+    pub fn new(device: &wgpu::Device, display: &wgpu::TextureView) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("blit-bind-group-layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::ReadOnly,
+                    format: WgpuOutput::STORAGE_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, display);
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
@@ -244,16 +573,23 @@ impl BlitPass {
         }
     }
 
-    pub fn update_bind_groups(&mut self, device: &wgpu::Device, output: &WgpuOutput) {
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        display: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("blit-bind-group"),
-            layout: &self.bind_group_layout,
-            entries: &[
-                output.as_binding(0, WgpuView::Albedo),
-                output.as_binding(1, WgpuView::Radiance),
-                output.as_binding(2, WgpuView::Ssao),
-            ],
-        });
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(display),
+            }],
+        })
+    }
+
+    pub fn update_bind_groups(&mut self, device: &wgpu::Device, display: &wgpu::TextureView) {
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, display);
     }
 
     pub fn render(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
@@ -276,6 +612,279 @@ impl BlitPass {
     }
 }
 
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct PostProcessUniform {
+    resolution: [f32; 2],
+    time: f32,
+    frame: u32,
+}
+
+/// Application defined fragment shaders applied in order to the graded output, see
+/// [rfw::backend::Backend::add_post_process] for the layout they are bound with.
+pub struct PostProcessPass {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    vert_module: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    /// Bind groups reading the output and the intermediate texture, passes alternate between
+    /// them
+    bind_groups: [wgpu::BindGroup; 2],
+    pipelines: Vec<wgpu::RenderPipeline>,
+    start: std::time::Instant,
+    frame: u32,
+}
+
+impl PostProcessPass {
+    const UNIFORM_SIZE: wgpu::BufferAddress =
+        std::mem::size_of::<PostProcessUniform>() as wgpu::BufferAddress;
+
+    pub fn new(device: &wgpu::Device, output: &WgpuOutput) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post-process-bind-group-layout"),
+            entries: &[
+                output.as_sampled_entry(0, wgpu::ShaderStage::FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(Self::UNIFORM_SIZE),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post-process-sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 0.0,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post-process-mem"),
+            size: Self::UNIFORM_SIZE,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_groups = Self::create_bind_groups(
+            device,
+            &bind_group_layout,
+            &sampler,
+            &uniform_buffer,
+            output,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post-process-layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vert_shader: &[u8] = include_bytes!("../shaders/quad.vert.spv");
+        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
+        });
+
+        Self {
+            bind_group_layout,
+            pipeline_layout,
+            vert_module,
+            sampler,
+            uniform_buffer,
+            bind_groups,
+            pipelines: Vec::new(),
+            start: std::time::Instant::now(),
+            frame: 0,
+        }
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+        output: &WgpuOutput,
+    ) -> [wgpu::BindGroup; 2] {
+        let create = |view: &wgpu::TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post-process-bind-group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        [
+            create(&output.output_texture_view),
+            create(&output.intermediate_view),
+        ]
+    }
+
+    pub fn update_bind_groups(&mut self, device: &wgpu::Device, output: &WgpuOutput) {
+        self.bind_groups = Self::create_bind_groups(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.uniform_buffer,
+            output,
+        );
+    }
+
+    /// Appends a pass running the SPIR-V fragment shader `shader`.
+    pub fn add(&mut self, device: &wgpu::Device, shader: &[u8]) {
+        let frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: Some("post-process-shader"),
+            source: wgpu::util::make_spirv(shader),
+        });
+
+        self.pipelines.push(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("post-process-pipeline"),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    buffers: &[],
+                    entry_point: "main",
+                    module: &self.vert_module,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    entry_point: "main",
+                    module: &frag_module,
+                    targets: &[wgpu::ColorTargetState {
+                        format: WgpuOutput::OUTPUT_FORMAT,
+                        write_mask: wgpu::ColorWrite::ALL,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent::REPLACE,
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                    }],
+                }),
+                depth_stencil: None,
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    strip_index_format: None,
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    clamp_depth: false,
+                    conservative: false,
+                },
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+            }),
+        );
+    }
+
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Runs every pass over the output texture, which holds the result of the last pass
+    /// afterwards.
+    pub fn render(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &WgpuOutput,
+    ) {
+        if self.pipelines.is_empty() {
+            return;
+        }
+
+        let uniform = PostProcessUniform {
+            resolution: [output.width as f32, output.height as f32],
+            time: self.start.elapsed().as_secs_f32(),
+            frame: self.frame,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, rfw::utils::as_bytes(&uniform));
+        self.frame = self.frame.wrapping_add(1);
+
+        for (i, pipeline) in self.pipelines.iter().enumerate() {
+            // Even passes read the output texture and write the intermediate texture
+            let target = if i % 2 == 0 {
+                &output.intermediate_view
+            } else {
+                &output.output_texture_view
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("post-process"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                    resolve_target: None,
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &self.bind_groups[i % 2], &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+
+        if self.pipelines.len() % 2 == 1 {
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &output.intermediate_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &output.output_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::Extent3d {
+                    width: output.width,
+                    height: output.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+}
+
 pub struct SsaoPass {
     sampler: wgpu::Sampler,
     bind_group_layout: wgpu::BindGroupLayout,
@@ -504,21 +1113,16 @@ impl SsaoPass {
         });
     }
 
+    /// Computes the ambient occlusion followed by `blur_passes` horizontal and vertical blurs,
+    /// which ping-pong through [WgpuView::FilteredSsao] and end up in [WgpuView::Ssao].
     pub fn launch(
         &self,
         encoder: &mut wgpu::CommandEncoder,
         width: u32,
         height: u32,
         uniform_bind_group: &wgpu::BindGroup,
+        blur_passes: u32,
     ) {
-        encoder.copy_buffer_to_buffer(
-            &self.filter_direction_x,
-            0,
-            &self.filter_uniform_direction_buffer,
-            0,
-            8,
-        );
-
         {
             let mut ssao_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
             ssao_pass.set_pipeline(&self.pipeline);
@@ -527,40 +1131,48 @@ impl SsaoPass {
             ssao_pass.dispatch(((width * height) as f32 / 64.0).ceil() as u32, 1, 1);
         }
 
-        {
-            let mut ssao_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
-            ssao_pass.set_pipeline(&self.filter_pipeline);
-            ssao_pass.set_bind_group(0, &self.filter_bind_group1, &[]);
-            ssao_pass.dispatch(
-                (width as f32 / 8.0).ceil() as u32,
-                (height as f32 / 8.0).ceil() as u32,
-                1,
+        for _ in 0..blur_passes {
+            self.blur(
+                encoder,
+                width,
+                height,
+                &self.filter_direction_x,
+                &self.filter_bind_group1,
+            );
+            self.blur(
+                encoder,
+                width,
+                height,
+                &self.filter_direction_y,
+                &self.filter_bind_group2,
             );
         }
+    }
 
-        encoder.copy_buffer_to_buffer(
-            &self.filter_direction_y,
-            0,
-            &self.filter_uniform_direction_buffer,
-            0,
-            8,
-        );
+    fn blur(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        width: u32,
+        height: u32,
+        direction: &wgpu::Buffer,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        encoder.copy_buffer_to_buffer(direction, 0, &self.filter_uniform_direction_buffer, 0, 8);
 
-        {
-            let mut ssao_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
-            ssao_pass.set_pipeline(&self.filter_pipeline);
-            ssao_pass.set_bind_group(0, &self.filter_bind_group2, &[]);
-            ssao_pass.dispatch(
-                (width as f32 / 8.0).ceil() as u32,
-                (height as f32 / 8.0).ceil() as u32,
-                1,
-            );
-        }
+        let mut ssao_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        ssao_pass.set_pipeline(&self.filter_pipeline);
+        ssao_pass.set_bind_group(0, bind_group, &[]);
+        ssao_pass.dispatch(
+            (width as f32 / 8.0).ceil() as u32,
+            (height as f32 / 8.0).ceil() as u32,
+            1,
+        );
     }
 }
 
 pub struct RadiancePass {
     pipeline: wgpu::ComputePipeline,
+    culling_pipeline: wgpu::ComputePipeline,
     shadow_sampler: wgpu::Sampler,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
@@ -568,16 +1180,31 @@ pub struct RadiancePass {
     lights_bind_group: wgpu::BindGroup,
     uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
+    tiles_bind_group_layout: wgpu::BindGroupLayout,
+    tiles_bind_group: wgpu::BindGroup,
+    culling_bind_group_layout: wgpu::BindGroupLayout,
+    culling_bind_group: wgpu::BindGroup,
+    tiles_buffer: wgpu::Buffer,
+    tile_lights_buffer: wgpu::Buffer,
+    tile_lights_size: wgpu::BufferAddress,
+    tiles: [u32; 4],
 }
 
 impl RadiancePass {
+    /// Width and height in pixels of the tiles lights are culled for, matches light_tiles.glsl.
+    pub const TILE_SIZE: u32 = 16;
+    /// Lights of each kind the shaders can hold.
+    const MAX_LIGHTS: u32 = 128;
+
     pub fn new(
         device: &wgpu::Device,
         camera_buffer: &wgpu::Buffer,
         material_buffer: &wgpu::Buffer,
         output: &WgpuOutput,
         lights: &WgpuLights,
+        skybox: &WgpuSkybox,
     ) -> Self {
+        let [sky_equirect, sky_cube, sky_sampler] = WgpuSkybox::layout_entries(3);
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
@@ -610,9 +1237,13 @@ impl RadiancePass {
                         WgpuView::Radiance,
                         false,
                     ),
+                    sky_equirect,
+                    sky_cube,
+                    sky_sampler,
                 ],
             });
 
+        let [sky_equirect, sky_cube, sky_sampler] = skybox.bindings(3);
         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &uniform_bind_group_layout,
@@ -626,6 +1257,9 @@ impl RadiancePass {
                     resource: material_buffer.as_entire_binding(),
                 },
                 output.as_binding(2, WgpuView::Radiance),
+                sky_equirect,
+                sky_cube,
+                sky_sampler,
             ],
         });
 
@@ -636,6 +1270,7 @@ impl RadiancePass {
                 output.as_storage_entry(1, wgpu::ShaderStage::COMPUTE, WgpuView::Normal, true),
                 output.as_storage_entry(2, wgpu::ShaderStage::COMPUTE, WgpuView::GBuffer, true),
                 output.as_storage_entry(3, wgpu::ShaderStage::COMPUTE, WgpuView::MatParams, true),
+                output.as_storage_entry(4, wgpu::ShaderStage::COMPUTE, WgpuView::Lightmap, true),
             ],
         });
 
@@ -742,10 +1377,42 @@ impl RadiancePass {
                             min_binding_size: None,
                         },
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 13,
+                        count: None,
+                        visibility: wgpu::ShaderStage::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            has_dynamic_offset: false,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                        },
+                    },
                 ],
             });
         let shadow_sampler = ShadowMapArray::create_sampler(device);
 
+        let tiles_bind_group_layout = Self::tiles_layout(device, true);
+        let culling_bind_group_layout = Self::tiles_layout(device, false);
+        let tiles_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("light-tiles-mem"),
+            contents: rfw::utils::as_bytes(&[0_u32; 4]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let tile_lights_size = 16;
+        let tile_lights_buffer = Self::create_tile_lights_buffer(device, tile_lights_size);
+        let tiles_bind_group = Self::tiles_bind_group(
+            device,
+            &tiles_bind_group_layout,
+            &tile_lights_buffer,
+            &tiles_buffer,
+        );
+        let culling_bind_group = Self::tiles_bind_group(
+            device,
+            &culling_bind_group_layout,
+            &tile_lights_buffer,
+            &tiles_buffer,
+        );
+
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
             label: Some("output-bind-group"),
@@ -754,6 +1421,7 @@ impl RadiancePass {
                 output.as_binding(1, WgpuView::Normal),
                 output.as_binding(2, WgpuView::GBuffer),
                 output.as_binding(3, WgpuView::MatParams),
+                output.as_binding(4, WgpuView::Lightmap),
             ],
         });
 
@@ -776,6 +1444,7 @@ impl RadiancePass {
                 lights.area_lights.infos_binding(10),
                 lights.spot_lights.infos_binding(11),
                 lights.directional_lights.infos_binding(12),
+                lights.exclusions_binding(13),
             ],
         });
 
@@ -785,6 +1454,7 @@ impl RadiancePass {
                 &uniform_bind_group_layout,
                 &bind_group_layout,
                 &lights_bind_group_layout,
+                &tiles_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
@@ -803,8 +1473,35 @@ impl RadiancePass {
             module: &module,
         });
 
+        let culling_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("light-culling-pipeline-layout"),
+                bind_group_layouts: &[
+                    &uniform_bind_group_layout,
+                    &bind_group_layout,
+                    &lights_bind_group_layout,
+                    &culling_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let spirv: &[u8] = include_bytes!("../shaders/light_culling.comp.spv");
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(spirv.as_quad_bytes())),
+        });
+
+        let culling_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("light-culling-pipeline"),
+            layout: Some(&culling_pipeline_layout),
+            entry_point: "main",
+            module: &module,
+        });
+
         Self {
             pipeline,
+            culling_pipeline,
             shadow_sampler,
             bind_group_layout,
             bind_group,
@@ -812,7 +1509,118 @@ impl RadiancePass {
             lights_bind_group,
             uniform_bind_group_layout,
             uniform_bind_group,
+            tiles_bind_group_layout,
+            tiles_bind_group,
+            culling_bind_group_layout,
+            culling_bind_group,
+            tiles_buffer,
+            tile_lights_buffer,
+            tile_lights_size,
+            tiles: [0; 4],
+        }
+    }
+
+    /// Layout of the per-tile light lists, written by the culling pass and read by the
+    /// lighting pass.
+    fn tiles_layout(device: &wgpu::Device, read_only: bool) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light-tiles-bind-group-layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    count: None,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        has_dynamic_offset: false,
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        min_binding_size: None,
+                    },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        has_dynamic_offset: false,
+                        ty: wgpu::BufferBindingType::Uniform,
+                        min_binding_size: None,
+                    },
+                },
+            ],
+        })
+    }
+
+    fn tiles_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        tile_lights_buffer: &wgpu::Buffer,
+        tiles_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("light-tiles-bind-group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: tile_lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: tiles_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_tile_lights_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tile-lights-mem"),
+            size,
+            usage: wgpu::BufferUsage::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Sizes the per-tile light lists for an output of `width` x `height` pixels and the
+    /// given light counts, the lists only grow.
+    fn update_tiles(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        light_counts: [u32; 4],
+        culling: bool,
+    ) {
+        let tiles_x = (width + Self::TILE_SIZE - 1) / Self::TILE_SIZE;
+        let tiles_y = (height + Self::TILE_SIZE - 1) / Self::TILE_SIZE;
+        let stride =
+            2 + light_counts[1].min(Self::MAX_LIGHTS) + light_counts[2].min(Self::MAX_LIGHTS);
+        let tiles = [tiles_x, tiles_y, stride, culling as u32];
+        if tiles == self.tiles {
+            return;
         }
+
+        let size = (tiles_x * tiles_y * stride) as wgpu::BufferAddress * 4;
+        if culling && size > self.tile_lights_size {
+            self.tile_lights_size = size;
+            self.tile_lights_buffer = Self::create_tile_lights_buffer(device, size);
+            self.tiles_bind_group = Self::tiles_bind_group(
+                device,
+                &self.tiles_bind_group_layout,
+                &self.tile_lights_buffer,
+                &self.tiles_buffer,
+            );
+            self.culling_bind_group = Self::tiles_bind_group(
+                device,
+                &self.culling_bind_group_layout,
+                &self.tile_lights_buffer,
+                &self.tiles_buffer,
+            );
+        }
+
+        queue.write_buffer(&self.tiles_buffer, 0, rfw::utils::as_bytes(&tiles));
+        self.tiles = tiles;
     }
 
     pub fn update_bind_groups(
@@ -822,7 +1630,9 @@ impl RadiancePass {
         lights: &WgpuLights,
         camera_buffer: &wgpu::Buffer,
         material_buffer: &wgpu::Buffer,
+        skybox: &WgpuSkybox,
     ) {
+        let [sky_equirect, sky_cube, sky_sampler] = skybox.bindings(3);
         self.uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &self.uniform_bind_group_layout,
@@ -836,6 +1646,9 @@ impl RadiancePass {
                     resource: material_buffer.as_entire_binding(),
                 },
                 output.as_binding(2, WgpuView::Radiance),
+                sky_equirect,
+                sky_cube,
+                sky_sampler,
             ],
         });
 
@@ -847,6 +1660,7 @@ impl RadiancePass {
                 output.as_binding(1, WgpuView::Normal),
                 output.as_binding(2, WgpuView::GBuffer),
                 output.as_binding(3, WgpuView::MatParams),
+                output.as_binding(4, WgpuView::Lightmap),
             ],
         });
 
@@ -869,16 +1683,38 @@ impl RadiancePass {
                 lights.area_lights.infos_binding(10),
                 lights.spot_lights.infos_binding(11),
                 lights.directional_lights.infos_binding(12),
+                lights.exclusions_binding(13),
             ],
         });
     }
 
-    pub fn launch(&self, encoder: &mut wgpu::CommandEncoder, width: u32, height: u32) {
+    /// Lights the G-buffers, with `culling` every pixel only iterates the spot and area lights
+    /// that touch its tile.
+    #[allow(clippy::too_many_arguments)]
+    pub fn launch(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        width: u32,
+        height: u32,
+        light_counts: [u32; 4],
+        culling: bool,
+    ) {
+        self.update_tiles(device, queue, width, height, light_counts, culling);
+
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
-        compute_pass.set_pipeline(&self.pipeline);
         compute_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
         compute_pass.set_bind_group(1, &self.bind_group, &[]);
         compute_pass.set_bind_group(2, &self.lights_bind_group, &[]);
+        if culling {
+            compute_pass.set_pipeline(&self.culling_pipeline);
+            compute_pass.set_bind_group(3, &self.culling_bind_group, &[]);
+            compute_pass.dispatch(self.tiles[0], self.tiles[1], 1);
+        }
+
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(3, &self.tiles_bind_group, &[]);
         compute_pass.dispatch(
             (width as f32 / 8.0).ceil() as u32,
             (height as f32 / 8.0).ceil() as u32,
@@ -886,3 +1722,195 @@ impl RadiancePass {
         );
     }
 }
+
+/// Bins the luminance of the output texture, see [Backend::luminance_histogram].
+pub struct HistogramPass {
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    histogram_buffer: wgpu::Buffer,
+    zero_buffer: wgpu::Buffer,
+}
+
+impl HistogramPass {
+    const HISTOGRAM_SIZE: wgpu::BufferAddress =
+        (LUMINANCE_HISTOGRAM_BINS * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+
+    pub fn new(device: &wgpu::Device, output: &WgpuOutput) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("histogram-mem"),
+            size: Self::HISTOGRAM_SIZE,
+            usage: wgpu::BufferUsage::STORAGE
+                | wgpu::BufferUsage::COPY_SRC
+                | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Copied over the histogram before every launch to reset its bins
+        let zero_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("histogram-zero-mem"),
+            contents: &[0; Self::HISTOGRAM_SIZE as usize],
+            usage: wgpu::BufferUsage::COPY_SRC,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("histogram-bind-group-layout"),
+            entries: &[
+                output.as_sampled_entry(0, wgpu::ShaderStage::COMPUTE),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    count: None,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: false,
+                        comparison: false,
+                    },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    count: None,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(Self::HISTOGRAM_SIZE),
+                    },
+                },
+            ],
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &sampler,
+            &histogram_buffer,
+            output,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader: &[u8] = include_bytes!("../shaders/histogram.comp.spv");
+        let shader_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(shader.as_quad_bytes())),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("histogram-pipeline"),
+            layout: Some(&pipeline_layout),
+            entry_point: "main",
+            module: &shader_module,
+        });
+
+        Self {
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            histogram_buffer,
+            zero_buffer,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        histogram_buffer: &wgpu::Buffer,
+        output: &WgpuOutput,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("histogram-bind-group"),
+            layout,
+            entries: &[
+                output.as_binding(0, WgpuView::Output),
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn update_bind_groups(&mut self, device: &wgpu::Device, output: &WgpuOutput) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.histogram_buffer,
+            output,
+        );
+    }
+
+    pub fn launch(&self, encoder: &mut wgpu::CommandEncoder, width: u32, height: u32) {
+        encoder.copy_buffer_to_buffer(
+            &self.zero_buffer,
+            0,
+            &self.histogram_buffer,
+            0,
+            Self::HISTOGRAM_SIZE,
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch(
+            (width as f32 / 16.0).ceil() as u32,
+            (height as f32 / 16.0).ceil() as u32,
+            1,
+        );
+    }
+
+    /// Copies the bins of the most recent launch to the host, waits for the GPU.
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<[u32; LUMINANCE_HISTOGRAM_BINS]> {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("histogram-readback"),
+            size: Self::HISTOGRAM_SIZE,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("histogram-readback"),
+        });
+        encoder.copy_buffer_to_buffer(&self.histogram_buffer, 0, &buffer, 0, Self::HISTOGRAM_SIZE);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if futures::executor::block_on(mapping).is_err() {
+            return None;
+        }
+
+        let mut bins = [0; LUMINANCE_HISTOGRAM_BINS];
+        {
+            let range = slice.get_mapped_range();
+            for (bin, bytes) in bins.iter_mut().zip(range.chunks_exact(4)) {
+                *bin = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+        }
+        buffer.unmap();
+
+        Some(bins)
+    }
+}
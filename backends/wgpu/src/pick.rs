@@ -0,0 +1,214 @@
+use crate::outline::OutlineDraw;
+use crate::output::WgpuOutput;
+use rfw::prelude::*;
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+
+/// Finds the instance seen at a pixel. The instances are drawn again with the camera of the last
+/// render into a texture of instance ids, of which a single texel is read back. Ids are the
+/// index in the instance buffer plus one, 0 is left for pixels without an instance.
+pub struct PickPass {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl PickPass {
+    const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+    pub fn new(device: &wgpu::Device, uniform_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pick-layout"),
+            bind_group_layouts: &[uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vert_shader: &[u8] = include_bytes!("../shaders/pick.vert.spv");
+        let frag_shader: &[u8] = include_bytes!("../shaders/pick.frag.spv");
+        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
+        });
+        let frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(frag_shader.as_quad_bytes())),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("pick-pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex3D>() as wgpu::BufferAddress,
+                    attributes: &[wgpu::VertexAttribute {
+                        offset: 0,
+                        format: wgpu::VertexFormat::Float32x4,
+                        shader_location: 0,
+                    }],
+                    step_mode: wgpu::InputStepMode::Vertex,
+                }],
+                entry_point: "main",
+                module: &vert_module,
+            },
+            fragment: Some(wgpu::FragmentState {
+                entry_point: "main",
+                module: &frag_module,
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::ID_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: None,
+                }],
+            }),
+            // Front and back faces are drawn, which keeps the pass independent of handedness
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                strip_index_format: None,
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: WgpuOutput::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                bias: wgpu::DepthBiasState::default(),
+                stencil: wgpu::StencilState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self { pipeline }
+    }
+
+    /// Returns the index in the instance buffer of the closest of `draws` at pixel (x, y) of
+    /// `output`, waits for the GPU. Returns None for pixels outside of the output or without
+    /// an instance.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform_bind_group: &wgpu::BindGroup,
+        output: &WgpuOutput,
+        vertex_buffer: &wgpu::Buffer,
+        draws: &[OutlineDraw],
+        x: u32,
+        y: u32,
+    ) -> Option<u32> {
+        if x >= output.width || y >= output.height || draws.is_empty() {
+            return None;
+        }
+
+        let size = wgpu::Extent3d {
+            width: output.width,
+            height: output.height,
+            depth_or_array_layers: 1,
+        };
+        let id_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("pick-ids"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::ID_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("pick-depth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: WgpuOutput::DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+        });
+        let id_view = id_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // A single row, padded to the copy alignment
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick-read-buffer"),
+            size: align as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("pick"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("pick"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &id_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            // Only the picked pixel is shaded
+            render_pass.set_scissor_rect(x, y, 1, 1);
+            for (vertices, instances) in draws.iter() {
+                render_pass.draw(vertices.clone(), instances.clone());
+            }
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &id_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(align),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if futures::executor::block_on(mapping).is_err() {
+            return None;
+        }
+
+        let id = {
+            let range = slice.get_mapped_range();
+            u32::from_le_bytes([range[0], range[1], range[2], range[3]])
+        };
+        buffer.unmap();
+
+        id.checked_sub(1)
+    }
+}
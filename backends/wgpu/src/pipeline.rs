@@ -2,14 +2,43 @@ use super::output::*;
 use rfw::prelude::*;
 use std::borrow::Cow;
 
+/// How a geometry pass pipeline tests depth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DepthTest {
+    /// Keeps the nearest fragment
+    Regular,
+    /// Passes everywhere, so the mesh is drawn over the rest of the scene
+    AlwaysOnTop,
+    /// Only passes for the fragments that won the depth prepass, which avoids shading them
+    /// more than once
+    Prepassed,
+}
+
 pub struct RenderPipeline {
-    pub pipeline: wgpu::RenderPipeline,
-    pub anim_pipeline: wgpu::RenderPipeline,
+    /// Static mesh pipelines, see [RenderPipeline::index]
+    pipelines: Vec<wgpu::RenderPipeline>,
+    /// Skinned mesh pipelines, see [RenderPipeline::index]
+    anim_pipelines: Vec<wgpu::RenderPipeline>,
+    /// Depth-only static mesh pipelines per cull mode
+    prepass_pipelines: Vec<wgpu::RenderPipeline>,
+    /// Depth-only skinned mesh pipelines per cull mode
+    anim_prepass_pipelines: Vec<wgpu::RenderPipeline>,
     pub layout: wgpu::PipelineLayout,
     pub anim_layout: wgpu::PipelineLayout,
 }
 
 impl RenderPipeline {
+    /// Formats of the geometry pass color targets, in attachment order.
+    pub const COLOR_FORMATS: [wgpu::TextureFormat; 7] = [
+        WgpuOutput::STORAGE_FORMAT,
+        WgpuOutput::STORAGE_FORMAT,
+        WgpuOutput::STORAGE_FORMAT,
+        WgpuOutput::STORAGE_FORMAT,
+        WgpuOutput::MAT_PARAM_FORMAT,
+        WgpuOutput::STORAGE_FORMAT,
+        WgpuOutput::STORAGE_FORMAT,
+    ];
+
     pub fn new(
         device: &wgpu::Device,
         uniform_layout: &wgpu::BindGroupLayout,
@@ -18,6 +47,8 @@ impl RenderPipeline {
     ) -> Self {
         let vert_shader: &[u8] = include_bytes!("../shaders/mesh.vert.spv");
         let frag_shader: &[u8] = include_bytes!("../shaders/deferred.frag.spv");
+        let flat_frag_shader: &[u8] = include_bytes!("../shaders/deferred_flat.frag.spv");
+        let prepass_shader: &[u8] = include_bytes!("../shaders/depth_prepass.frag.spv");
 
         let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             flags: Default::default(),
@@ -29,6 +60,21 @@ impl RenderPipeline {
             label: None,
             source: wgpu::ShaderSource::SpirV(Cow::from(frag_shader.as_quad_bytes())),
         });
+        let flat_frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(flat_frag_shader.as_quad_bytes())),
+        });
+        let prepass_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(prepass_shader.as_quad_bytes())),
+        });
+        let prepass_fragment_state = wgpu::FragmentState {
+            module: &prepass_module,
+            entry_point: "main",
+            targets: &[],
+        };
 
         let fragment_state = wgpu::FragmentState {
             module: &frag_module,
@@ -79,23 +125,71 @@ impl RenderPipeline {
                         alpha: wgpu::BlendComponent::REPLACE,
                     }),
                 },
+                wgpu::ColorTargetState {
+                    // Lightmap
+                    format: WgpuOutput::STORAGE_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent::REPLACE,
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                },
+                wgpu::ColorTargetState {
+                    // Motion
+                    format: WgpuOutput::STORAGE_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent::REPLACE,
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                },
             ],
         };
+        let flat_fragment_state = wgpu::FragmentState {
+            module: &flat_frag_module,
+            ..fragment_state.clone()
+        };
 
-        let primitive_state = wgpu::PrimitiveState {
+        let cull_modes = [CullMode::None, CullMode::Front, CullMode::Back];
+        let depth_tests = [
+            DepthTest::Regular,
+            DepthTest::AlwaysOnTop,
+            DepthTest::Prepassed,
+        ];
+        // Grouped by depth test in the order of [DepthTest], smooth shaded variants first
+        let variants: Vec<(CullMode, DepthTest, bool)> = [false, true]
+            .iter()
+            .flat_map(|flat| {
+                depth_tests.iter().flat_map(move |depth| {
+                    cull_modes.iter().map(move |mode| (*mode, *depth, *flat))
+                })
+            })
+            .collect();
+        let primitive_state = |mode: CullMode| wgpu::PrimitiveState {
             topology: wgpu::PrimitiveTopology::TriangleList,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
+            cull_mode: match mode {
+                CullMode::None => None,
+                CullMode::Front => Some(wgpu::Face::Front),
+                CullMode::Back => Some(wgpu::Face::Back),
+            },
             polygon_mode: wgpu::PolygonMode::Fill,
             strip_index_format: None,
             clamp_depth: false,
             conservative: false,
         };
 
-        let depth_state = wgpu::DepthStencilState {
+        // Always-on-top meshes pass the depth test everywhere, they still write depth so the
+        // passes reading it see them in front of the scene. Prepassed meshes find their depth
+        // already written by the prepass.
+        let depth_state = |depth: DepthTest| wgpu::DepthStencilState {
             format: WgpuOutput::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::LessEqual,
+            depth_write_enabled: depth != DepthTest::Prepassed,
+            depth_compare: match depth {
+                DepthTest::Regular => wgpu::CompareFunction::LessEqual,
+                DepthTest::AlwaysOnTop => wgpu::CompareFunction::Always,
+                DepthTest::Prepassed => wgpu::CompareFunction::Equal,
+            },
             stencil: Default::default(),
             bias: wgpu::DepthBiasState::default(),
         };
@@ -106,59 +200,125 @@ impl RenderPipeline {
             alpha_to_coverage_enabled: false,
         };
 
+        let vertex_attributes = [
+            wgpu::VertexAttribute {
+                offset: 0,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: 0,
+            },
+            wgpu::VertexAttribute {
+                offset: 16,
+                format: wgpu::VertexFormat::Float32x3,
+                shader_location: 1,
+            },
+            wgpu::VertexAttribute {
+                offset: 28,
+                format: wgpu::VertexFormat::Uint32,
+                shader_location: 2,
+            },
+            wgpu::VertexAttribute {
+                offset: 32,
+                format: wgpu::VertexFormat::Float32x2,
+                shader_location: 3,
+            },
+            wgpu::VertexAttribute {
+                offset: 48,
+                format: wgpu::VertexFormat::Float32x4,
+                shader_location: 4,
+            },
+            wgpu::VertexAttribute {
+                offset: 40,
+                format: wgpu::VertexFormat::Float32x2,
+                shader_location: 5,
+            },
+        ];
+        let vertex_buffer = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex3D>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &vertex_attributes,
+        };
+        let joint_buffer = wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<JointData>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    format: wgpu::VertexFormat::Uint32x4,
+                    shader_location: 6,
+                },
+                wgpu::VertexAttribute {
+                    offset: 16,
+                    format: wgpu::VertexFormat::Float32x4,
+                    shader_location: 7,
+                },
+            ],
+        };
+        let static_buffers = [vertex_buffer.clone()];
+        let anim_buffers = [vertex_buffer, joint_buffer];
+
+        // Prepass pipelines write depth like the regular variants, without color targets
+        let create_pipeline = |label: &str,
+                               layout: &wgpu::PipelineLayout,
+                               vertex: wgpu::VertexState,
+                               mode: CullMode,
+                               depth: Option<DepthTest>,
+                               flat_shading: bool| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex,
+                fragment: Some(match depth {
+                    Some(_) if flat_shading => flat_fragment_state.clone(),
+                    Some(_) => fragment_state.clone(),
+                    None => prepass_fragment_state.clone(),
+                }),
+                primitive: primitive_state(mode),
+                depth_stencil: Some(depth_state(depth.unwrap_or(DepthTest::Regular))),
+                multisample: multisample_state,
+            })
+        };
+
         let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             // bind_group_layouts: &[uniform_layout, instance_layout, texture_layout],
             bind_group_layouts: &[uniform_layout, texture_layout],
             push_constant_ranges: &[],
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("mesh-pipeline"),
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &vert_module,
-                entry_point: "main",
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<Vertex3D>() as wgpu::BufferAddress,
-                    step_mode: wgpu::InputStepMode::Vertex,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            offset: 0,
-                            format: wgpu::VertexFormat::Float32x4,
-                            shader_location: 0,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 16,
-                            format: wgpu::VertexFormat::Float32x3,
-                            shader_location: 1,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 28,
-                            format: wgpu::VertexFormat::Uint32,
-                            shader_location: 2,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 32,
-                            format: wgpu::VertexFormat::Float32x2,
-                            shader_location: 3,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 40,
-                            format: wgpu::VertexFormat::Float32x4,
-                            shader_location: 4,
-                        },
-                    ],
-                }],
-            },
-            fragment: Some(fragment_state.clone()),
-            primitive: primitive_state,
-            depth_stencil: Some(depth_state.clone()),
-            multisample: multisample_state,
-        });
+        let static_vertex = wgpu::VertexState {
+            module: &vert_module,
+            entry_point: "main",
+            buffers: &static_buffers,
+        };
+        let pipelines = variants
+            .iter()
+            .map(|(mode, depth, flat)| {
+                create_pipeline(
+                    "mesh-pipeline",
+                    &layout,
+                    static_vertex.clone(),
+                    *mode,
+                    Some(*depth),
+                    *flat,
+                )
+            })
+            .collect();
+        let prepass_pipelines = cull_modes
+            .iter()
+            .map(|mode| {
+                create_pipeline(
+                    "mesh-prepass-pipeline",
+                    &layout,
+                    static_vertex.clone(),
+                    *mode,
+                    None,
+                    false,
+                )
+            })
+            .collect();
 
         let vert_shader: &[u8] = include_bytes!("../shaders/mesh_anim.vert.spv");
 
-        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        let anim_vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             flags: Default::default(),
             label: None,
             source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
@@ -169,73 +329,77 @@ impl RenderPipeline {
             bind_group_layouts: &[uniform_layout, texture_layout, skin_layout],
             push_constant_ranges: &[],
         });
-        let anim_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("anim-mesh-pipeline"),
-            layout: Some(&anim_layout),
-            vertex: wgpu::VertexState {
-                module: &vert_module,
-                entry_point: "main",
-                buffers: &[
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<Vertex3D>() as wgpu::BufferAddress,
-                        step_mode: wgpu::InputStepMode::Vertex,
-                        attributes: &[
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                format: wgpu::VertexFormat::Float32x4,
-                                shader_location: 0,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: 16,
-                                format: wgpu::VertexFormat::Float32x3,
-                                shader_location: 1,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: 28,
-                                format: wgpu::VertexFormat::Uint32,
-                                shader_location: 2,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: 32,
-                                format: wgpu::VertexFormat::Float32x2,
-                                shader_location: 3,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: 40,
-                                format: wgpu::VertexFormat::Float32x4,
-                                shader_location: 4,
-                            },
-                        ],
-                    },
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<JointData>() as wgpu::BufferAddress,
-                        step_mode: wgpu::InputStepMode::Vertex,
-                        attributes: &[
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                format: wgpu::VertexFormat::Uint32x4,
-                                shader_location: 5,
-                            },
-                            wgpu::VertexAttribute {
-                                offset: 16,
-                                format: wgpu::VertexFormat::Float32x4,
-                                shader_location: 6,
-                            },
-                        ],
-                    },
-                ],
-            },
-            fragment: Some(fragment_state),
-            primitive: primitive_state,
-            depth_stencil: Some(depth_state),
-            multisample: multisample_state,
-        });
-
+        let anim_vertex = wgpu::VertexState {
+            module: &anim_vert_module,
+            entry_point: "main",
+            buffers: &anim_buffers,
+        };
+        let anim_pipelines = variants
+            .iter()
+            .map(|(mode, depth, flat)| {
+                create_pipeline(
+                    "anim-mesh-pipeline",
+                    &anim_layout,
+                    anim_vertex.clone(),
+                    *mode,
+                    Some(*depth),
+                    *flat,
+                )
+            })
+            .collect();
+        let anim_prepass_pipelines = cull_modes
+            .iter()
+            .map(|mode| {
+                create_pipeline(
+                    "anim-mesh-prepass-pipeline",
+                    &anim_layout,
+                    anim_vertex.clone(),
+                    *mode,
+                    None,
+                    false,
+                )
+            })
+            .collect();
         Self {
-            pipeline,
-            anim_pipeline,
+            pipelines,
+            anim_pipelines,
+            prepass_pipelines,
+            anim_prepass_pipelines,
             layout,
             anim_layout,
         }
     }
+
+    /// Index of the pipeline variant for `mode`, grouped by depth test and shading.
+    fn index(mode: CullMode, depth: DepthTest, flat_shading: bool) -> usize {
+        mode as usize + depth as usize * 3 + flat_shading as usize * 9
+    }
+
+    /// Flat shaded pipelines replace the interpolated normals by face normals.
+    pub fn pipeline(
+        &self,
+        mode: CullMode,
+        depth: DepthTest,
+        flat_shading: bool,
+    ) -> &wgpu::RenderPipeline {
+        &self.pipelines[Self::index(mode, depth, flat_shading)]
+    }
+
+    pub fn anim_pipeline(
+        &self,
+        mode: CullMode,
+        depth: DepthTest,
+        flat_shading: bool,
+    ) -> &wgpu::RenderPipeline {
+        &self.anim_pipelines[Self::index(mode, depth, flat_shading)]
+    }
+
+    /// Depth-only pipeline of the depth prepass.
+    pub fn prepass_pipeline(&self, mode: CullMode) -> &wgpu::RenderPipeline {
+        &self.prepass_pipelines[mode as usize]
+    }
+
+    pub fn anim_prepass_pipeline(&self, mode: CullMode) -> &wgpu::RenderPipeline {
+        &self.anim_prepass_pipelines[mode as usize]
+    }
 }
@@ -0,0 +1,130 @@
+use futures::executor::block_on;
+use rfw::backend::FrameTimings;
+use std::cell::Cell;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum TimedPass {
+    Lights = 0,
+    Ssao = 1,
+    Radiance = 2,
+    Blit = 3,
+}
+
+impl TimedPass {
+    pub const COUNT: u32 = 4;
+}
+
+/// Records GPU timestamps at the start and end of the passes in [TimedPass].
+#[derive(Debug)]
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    has_data: Cell<bool>,
+}
+
+impl GpuTimer {
+    const QUERY_COUNT: u32 = TimedPass::COUNT * 2;
+    const BUFFER_SIZE: wgpu::BufferAddress =
+        (Self::QUERY_COUNT as usize * std::mem::size_of::<u64>()) as wgpu::BufferAddress;
+
+    /// Returns None if the device was not created with timestamp query support.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            ty: wgpu::QueryType::Timestamp,
+            count: Self::QUERY_COUNT,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timestamp-resolve-buffer"),
+            size: Self::BUFFER_SIZE,
+            usage: wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timestamp-read-buffer"),
+            size: Self::BUFFER_SIZE,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            read_buffer,
+            period: queue.get_timestamp_period(),
+            has_data: Cell::new(false),
+        })
+    }
+
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder, pass: TimedPass) {
+        encoder.write_timestamp(&self.query_set, pass as u32 * 2);
+    }
+
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder, pass: TimedPass) {
+        encoder.write_timestamp(&self.query_set, pass as u32 * 2 + 1);
+    }
+
+    /// Resolves all timestamps of this frame, must be recorded after every pass has ended.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..Self::QUERY_COUNT,
+            &self.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.read_buffer,
+            0,
+            Self::BUFFER_SIZE,
+        );
+        self.has_data.set(true);
+    }
+
+    /// Reads back the timings of the last resolved frame, this waits for the GPU to finish.
+    pub fn read(&self, device: &wgpu::Device) -> Option<FrameTimings> {
+        if !self.has_data.get() {
+            return None;
+        }
+
+        let slice = self.read_buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if block_on(mapping).is_err() {
+            return None;
+        }
+
+        let timings = {
+            let range = slice.get_mapped_range();
+            let stamps: &[u64] = unsafe {
+                std::slice::from_raw_parts(range.as_ptr() as *const u64, Self::QUERY_COUNT as usize)
+            };
+
+            let ms = |pass: TimedPass| {
+                let begin = stamps[pass as usize * 2];
+                let end = stamps[pass as usize * 2 + 1];
+                (end.saturating_sub(begin) as f64 * self.period as f64 / 1_000_000.0) as f32
+            };
+
+            FrameTimings {
+                lights: ms(TimedPass::Lights),
+                ssao: ms(TimedPass::Ssao),
+                radiance: ms(TimedPass::Radiance),
+                blit: ms(TimedPass::Blit),
+            }
+        };
+        self.read_buffer.unmap();
+
+        Some(timings)
+    }
+}
@@ -0,0 +1,182 @@
+use crate::mat::WgpuTexture;
+use rfw::prelude::{DataFormat, SkyboxData, TextureData};
+use std::num::NonZeroU32;
+
+/// Projection of the current skybox, matches the `options.y` flag of the lighting pass.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SkyboxMode {
+    None = 0,
+    Equirectangular = 1,
+    Cube = 2,
+}
+
+/// Environment sampled by the radiance pass for pixels that are not covered by geometry.
+/// Both views always exist, the unused one is a 1x1 black placeholder.
+#[derive(Debug)]
+pub struct WgpuSkybox {
+    mode: SkyboxMode,
+    _equirect: wgpu::Texture,
+    equirect_view: wgpu::TextureView,
+    _cube: wgpu::Texture,
+    cube_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl WgpuSkybox {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let black = [0_u8; 4];
+        let placeholder = TextureData {
+            width: 1,
+            height: 1,
+            mip_levels: 1,
+            bytes: &black,
+            format: DataFormat::BGRA8,
+            filter: Default::default(),
+            premultiplied: false,
+        };
+
+        let (equirect, equirect_view) = Self::create(device, queue, &[placeholder]);
+        let (cube, cube_view) = Self::create(device, queue, &[placeholder; 6]);
+
+        Self {
+            mode: SkyboxMode::None,
+            _equirect: equirect,
+            equirect_view,
+            _cube: cube,
+            cube_view,
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("skybox-sampler"),
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn mode(&self) -> SkyboxMode {
+        self.mode
+    }
+
+    /// Replaces the skybox, returns false if it was ignored because its format is not
+    /// supported or its cube faces do not match.
+    pub fn set(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, skybox: SkyboxData) -> bool {
+        match skybox {
+            SkyboxData::Equirectangular(tex) => {
+                if !WgpuTexture::is_supported(device, tex.format) {
+                    return false;
+                }
+
+                let (texture, view) = Self::create(device, queue, &[tex]);
+                self._equirect = texture;
+                self.equirect_view = view;
+                self.mode = SkyboxMode::Equirectangular;
+            }
+            SkyboxData::Cube(faces) => {
+                if !skybox.is_valid() || !WgpuTexture::is_supported(device, faces[0].format) {
+                    return false;
+                }
+
+                let (texture, view) = Self::create(device, queue, &faces);
+                self._cube = texture;
+                self.cube_view = view;
+                self.mode = SkyboxMode::Cube;
+            }
+        }
+
+        true
+    }
+
+    /// Creates a 2D texture for a single layer or a cube texture for 6 layers.
+    fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &[TextureData],
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let first = &layers[0];
+        let format = WgpuTexture::format(first.format);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("skybox"),
+            size: wgpu::Extent3d {
+                width: first.width,
+                height: first.height,
+                depth_or_array_layers: layers.len() as u32,
+            },
+            mip_level_count: first.mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        for (i, layer) in layers.iter().enumerate() {
+            WgpuTexture::write_levels(queue, &texture, layer, i as u32);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("skybox-view"),
+            format: Some(format),
+            dimension: Some(if layers.len() == 6 {
+                wgpu::TextureViewDimension::Cube
+            } else {
+                wgpu::TextureViewDimension::D2
+            }),
+            aspect: Default::default(),
+            base_mip_level: 0,
+            mip_level_count: NonZeroU32::new(first.mip_levels),
+            base_array_layer: 0,
+            array_layer_count: NonZeroU32::new(layers.len() as u32),
+        });
+
+        (texture, view)
+    }
+
+    /// Layout entries of the equirectangular texture, cube texture and sampler.
+    pub fn layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 3] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        [
+            texture(first_binding, wgpu::TextureViewDimension::D2),
+            texture(first_binding + 1, wgpu::TextureViewDimension::Cube),
+            wgpu::BindGroupLayoutEntry {
+                binding: first_binding + 2,
+                visibility: wgpu::ShaderStage::COMPUTE,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    pub fn bindings(&self, first_binding: u32) -> [wgpu::BindGroupEntry; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first_binding,
+                resource: wgpu::BindingResource::TextureView(&self.equirect_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.cube_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 2,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
@@ -0,0 +1,72 @@
+use rfw::backend::UploadToken;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type UploadCallback = Box<dyn FnOnce() + Send>;
+
+struct PendingUpload {
+    generation: u64,
+    done: Pin<Box<dyn Future<Output = ()> + Send>>,
+    callbacks: Vec<UploadCallback>,
+}
+
+/// Tracks which synchronized uploads the GPU has finished.
+#[derive(Default)]
+pub struct UploadFences {
+    /// Generation of the uploads that have not been submitted yet
+    generation: u64,
+    waiting: Vec<UploadCallback>,
+    /// Submitted uploads in submission order
+    pending: VecDeque<PendingUpload>,
+}
+
+impl UploadFences {
+    pub fn token(&self) -> UploadToken {
+        UploadToken(self.generation)
+    }
+
+    pub fn on_complete(&mut self, token: UploadToken, callback: UploadCallback) {
+        if token.0 >= self.generation {
+            self.waiting.push(callback);
+        } else if let Some(upload) = self.pending.iter_mut().find(|u| u.generation == token.0) {
+            upload.callbacks.push(callback);
+        } else {
+            callback();
+        }
+    }
+
+    /// Flushes all pending queue writes and starts a new generation of uploads.
+    pub fn submit(&mut self, queue: &wgpu::Queue) {
+        queue.submit(None);
+        self.pending.push_back(PendingUpload {
+            generation: self.generation,
+            done: Box::pin(queue.on_submitted_work_done()),
+            callbacks: std::mem::take(&mut self.waiting),
+        });
+        self.generation += 1;
+    }
+
+    /// Runs the callbacks of all finished uploads without blocking.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+
+        let waker = futures::task::noop_waker();
+        let mut context = Context::from_waker(&waker);
+        while let Some(upload) = self.pending.front_mut() {
+            if let Poll::Pending = upload.done.as_mut().poll(&mut context) {
+                // Submissions finish in order
+                break;
+            }
+
+            if let Some(upload) = self.pending.pop_front() {
+                upload.callbacks.into_iter().for_each(|c| c());
+            }
+        }
+    }
+}
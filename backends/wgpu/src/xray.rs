@@ -0,0 +1,178 @@
+use crate::mem::ManagedBuffer;
+use crate::outline::OutlineDraw;
+use crate::output::WgpuOutput;
+use rfw::prelude::*;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Matches the uniform of the outline shaders
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+struct XrayUniform {
+    color: Vec4,
+    offset: Vec4,
+}
+
+/// Shows the parts of instances with [InstanceEffect::Xray] that are hidden behind other
+/// geometry. The instances are drawn again with the outline shaders, only where they fail
+/// the depth test against the scene, blending a flat color over what occludes them.
+pub struct XrayPass {
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    uniform: ManagedBuffer<XrayUniform>,
+}
+
+impl XrayPass {
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform = ManagedBuffer::new(device.clone(), queue, wgpu::BufferUsage::UNIFORM, 1);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("xray-bind-group-layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("xray-bind-group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.binding_resource(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("xray-layout"),
+            bind_group_layouts: &[uniform_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vert_shader: &[u8] = include_bytes!("../shaders/outline.vert.spv");
+        let frag_shader: &[u8] = include_bytes!("../shaders/outline.frag.spv");
+        let vert_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(vert_shader.as_quad_bytes())),
+        });
+        let frag_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            flags: Default::default(),
+            label: None,
+            source: wgpu::ShaderSource::SpirV(Cow::from(frag_shader.as_quad_bytes())),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("xray-pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex3D>() as wgpu::BufferAddress,
+                    attributes: &[wgpu::VertexAttribute {
+                        offset: 0,
+                        format: wgpu::VertexFormat::Float32x4,
+                        shader_location: 0,
+                    }],
+                    step_mode: wgpu::InputStepMode::Vertex,
+                }],
+                entry_point: "main",
+                module: &vert_module,
+            },
+            fragment: Some(wgpu::FragmentState {
+                entry_point: "main",
+                module: &frag_module,
+                targets: &[wgpu::ColorTargetState {
+                    format: WgpuOutput::OUTPUT_FORMAT,
+                    write_mask: wgpu::ColorWrite::ALL,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                }],
+            }),
+            // Front and back faces are drawn, which keeps the pass independent of handedness
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                strip_index_format: None,
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: WgpuOutput::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Greater,
+                bias: wgpu::DepthBiasState::default(),
+                stencil: wgpu::StencilState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        Self {
+            bind_group,
+            pipeline,
+            uniform,
+        }
+    }
+
+    /// Blends `color` over the output texture wherever `draws` are hidden by the scene.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform_bind_group: &wgpu::BindGroup,
+        output: &WgpuOutput,
+        vertex_buffer: &wgpu::Buffer,
+        draws: &[OutlineDraw],
+        color: Vec4,
+    ) {
+        if draws.is_empty() {
+            return;
+        }
+
+        self.uniform.as_mut_slice()[0] = XrayUniform {
+            color,
+            offset: Vec4::ZERO,
+        };
+        self.uniform.copy_to_device();
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("xray"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &output.output_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &output.depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        for (vertices, instances) in draws.iter() {
+            render_pass.draw(vertices.clone(), instances.clone());
+        }
+    }
+}
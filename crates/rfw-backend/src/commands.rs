@@ -0,0 +1,252 @@
+use crate::*;
+use rfw_math::*;
+use rtbvh::Aabb;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Owned inputs of the scene-update methods of [Backend], so they can be sent between threads.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Set2DMesh {
+        id: usize,
+        vertices: Vec<Vertex2D>,
+        tex_id: Option<usize>,
+    },
+    Set2DInstances {
+        mesh: usize,
+        matrices: Vec<Mat4>,
+    },
+    Set3DMesh {
+        id: usize,
+        name: String,
+        bounds: Aabb,
+        vertices: Vec<Vertex3D>,
+        triangles: Vec<RTTriangle>,
+        ranges: Vec<VertexMesh>,
+        skin_data: Vec<JointData>,
+        flags: Mesh3dFlags,
+    },
+    Unload3DMeshes(Vec<usize>),
+    Set3DInstances {
+        mesh: usize,
+        matrices: Vec<Mat4>,
+        skin_ids: Vec<SkinID>,
+        flags: Vec<InstanceFlags3D>,
+        user_data: Vec<u64>,
+        tints: Vec<Vec4>,
+        flipbook_times: Vec<f32>,
+        wind_weights: Vec<f32>,
+        local_aabb: Aabb,
+    },
+    SetMaterial {
+        index: usize,
+        material: DeviceMaterial,
+    },
+    /// Replaces all point lights, lights that are marked in `changed` get updated
+    SetPointLights {
+        lights: Vec<PointLight>,
+        changed: BitVec,
+    },
+    SetSpotLights {
+        lights: Vec<SpotLight>,
+        changed: BitVec,
+    },
+    SetAreaLights {
+        lights: Vec<AreaLight>,
+        changed: BitVec,
+    },
+    SetDirectionalLights {
+        lights: Vec<DirectionalLight>,
+        changed: BitVec,
+    },
+    SetParticles {
+        system: usize,
+        particles: Vec<Particle>,
+        blend: ParticleBlend,
+    },
+    SetDecals(Vec<Decal>),
+}
+
+impl Command {
+    pub fn set_2d_mesh(id: usize, data: MeshData2D<'_>) -> Self {
+        Self::Set2DMesh {
+            id,
+            vertices: data.vertices.to_vec(),
+            tex_id: data.tex_id,
+        }
+    }
+
+    pub fn set_2d_instances(mesh: usize, instances: InstancesData2D<'_>) -> Self {
+        Self::Set2DInstances {
+            mesh,
+            matrices: instances.matrices.to_vec(),
+        }
+    }
+
+    pub fn set_3d_mesh(id: usize, data: MeshData3D<'_>) -> Self {
+        Self::Set3DMesh {
+            id,
+            name: data.name.to_string(),
+            bounds: data.bounds,
+            vertices: data.vertices.to_vec(),
+            triangles: data.triangles.to_vec(),
+            ranges: data.ranges.to_vec(),
+            skin_data: data.skin_data.to_vec(),
+            flags: data.flags,
+        }
+    }
+
+    pub fn set_3d_instances(mesh: usize, instances: InstancesData3D<'_>) -> Self {
+        Self::Set3DInstances {
+            mesh,
+            matrices: instances.matrices.to_vec(),
+            skin_ids: instances.skin_ids.to_vec(),
+            flags: instances.flags.to_vec(),
+            user_data: instances.user_data.to_vec(),
+            tints: instances.tints.to_vec(),
+            flipbook_times: instances.flipbook_times.to_vec(),
+            wind_weights: instances.wind_weights.to_vec(),
+            local_aabb: instances.local_aabb,
+        }
+    }
+
+    /// Calls the [Backend] method this command was created from, returns its error for
+    /// fallible methods.
+    pub fn apply<B: Backend + ?Sized>(self, backend: &mut B) -> Result<(), BackendError> {
+        match self {
+            Command::Set2DMesh {
+                id,
+                vertices,
+                tex_id,
+            } => backend.set_2d_mesh(
+                id,
+                MeshData2D {
+                    vertices: vertices.as_slice(),
+                    tex_id,
+                },
+            ),
+            Command::Set2DInstances { mesh, matrices } => {
+                return backend.set_2d_instances(
+                    mesh,
+                    InstancesData2D {
+                        matrices: matrices.as_slice(),
+                    },
+                )
+            }
+            Command::Set3DMesh {
+                id,
+                name,
+                bounds,
+                vertices,
+                triangles,
+                ranges,
+                skin_data,
+                flags,
+            } => backend.set_3d_mesh(
+                id,
+                MeshData3D {
+                    name: name.as_str(),
+                    bounds,
+                    vertices: vertices.as_slice(),
+                    triangles: triangles.as_slice(),
+                    ranges: ranges.as_slice(),
+                    skin_data: skin_data.as_slice(),
+                    flags,
+                },
+            ),
+            Command::Unload3DMeshes(ids) => backend.unload_3d_meshes(ids.as_slice()),
+            Command::Set3DInstances {
+                mesh,
+                matrices,
+                skin_ids,
+                flags,
+                user_data,
+                tints,
+                flipbook_times,
+                wind_weights,
+                local_aabb,
+            } => {
+                return backend.set_3d_instances(
+                    mesh,
+                    InstancesData3D {
+                        matrices: matrices.as_slice(),
+                        skin_ids: skin_ids.as_slice(),
+                        flags: flags.as_slice(),
+                        user_data: user_data.as_slice(),
+                        tints: tints.as_slice(),
+                        flipbook_times: flipbook_times.as_slice(),
+                        wind_weights: wind_weights.as_slice(),
+                        local_aabb,
+                    },
+                )
+            }
+            Command::SetMaterial { index, material } => backend.set_material(index, material),
+            Command::SetPointLights { lights, changed } => {
+                backend.set_point_lights(lights.as_slice(), changed.as_bitslice())
+            }
+            Command::SetSpotLights { lights, changed } => {
+                backend.set_spot_lights(lights.as_slice(), changed.as_bitslice())
+            }
+            Command::SetAreaLights { lights, changed } => {
+                backend.set_area_lights(lights.as_slice(), changed.as_bitslice())
+            }
+            Command::SetDirectionalLights { lights, changed } => {
+                backend.set_directional_lights(lights.as_slice(), changed.as_bitslice())
+            }
+            Command::SetParticles {
+                system,
+                particles,
+                blend,
+            } => backend.set_particles(system, particles.as_slice(), blend),
+            Command::SetDecals(decals) => backend.set_decals(decals.as_slice()),
+        }
+
+        Ok(())
+    }
+}
+
+/// Sending half of a command queue, cheap to clone and to move to worker threads, e.g. to
+/// stream in meshes from an asset loading thread.
+#[derive(Debug, Clone)]
+pub struct CommandQueue {
+    sender: Sender<Command>,
+}
+
+impl CommandQueue {
+    /// Queues `command`, returns false if the receiving backend no longer exists.
+    pub fn submit(&self, command: Command) -> bool {
+        self.sender.send(command).is_ok()
+    }
+}
+
+/// Receiving half of a command queue, owned by the render thread.
+#[derive(Debug)]
+pub struct CommandReceiver {
+    receiver: Receiver<Command>,
+}
+
+impl CommandReceiver {
+    /// Applies all queued commands to `backend` in the order they were submitted, returns the
+    /// number of applied commands. Call [Backend::synchronize] afterwards if any were applied.
+    /// Commands that fail do not stop the others from being applied, their errors are passed
+    /// to `on_error`.
+    pub fn apply<B: Backend + ?Sized, F: FnMut(BackendError)>(
+        &self,
+        backend: &mut B,
+        mut on_error: F,
+    ) -> usize {
+        let mut count = 0;
+        for command in self.receiver.try_iter() {
+            if let Err(e) = command.apply(backend) {
+                on_error(e);
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+/// Creates a command queue, its sending half can be cloned for every submitting thread.
+pub fn command_queue() -> (CommandQueue, CommandReceiver) {
+    let (sender, receiver) = channel();
+    (CommandQueue { sender }, CommandReceiver { receiver })
+}
@@ -0,0 +1,40 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors returned by the fallible methods of [crate::Backend]. Backends apply the valid part
+/// of a request before returning an error, e.g. the textures within their capacity.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BackendError {
+    /// Instances were set for a mesh that is not set or was unloaded
+    InvalidMeshId(usize),
+    /// More textures were set than the backend can bind, textures beyond `capacity` are ignored
+    TextureCapacityExceeded { count: usize, capacity: usize },
+    /// The request needs a feature the backend or device does not support
+    FeatureUnsupported(&'static str),
+    /// No image of the window surface could be acquired, e.g. while the window is being
+    /// resized. Rendering usually succeeds again on the next frame.
+    SurfaceUnavailable,
+    /// The device ran out of memory or was lost, the backend has to be recreated
+    DeviceLost,
+}
+
+impl std::error::Error for BackendError {}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::InvalidMeshId(id) => write!(f, "Mesh {} does not exist.", id),
+            BackendError::TextureCapacityExceeded { count, capacity } => write!(
+                f,
+                "{} textures exceed the capacity of {} textures.",
+                count, capacity
+            ),
+            BackendError::FeatureUnsupported(feature) => {
+                write!(f, "Backend does not support {}.", feature)
+            }
+            BackendError::SurfaceUnavailable => {
+                write!(f, "Could not acquire an image of the window surface.")
+            }
+            BackendError::DeviceLost => write!(f, "Device was lost."),
+        }
+    }
+}
@@ -1,11 +1,19 @@
 pub use bitvec::prelude::*;
+pub use commands::*;
+pub use error::*;
 pub use lights::*;
 pub use raw_window_handle::*;
+pub use settings::*;
 pub use structs::*;
 
+mod commands;
+mod error;
 mod lights;
+mod settings;
 mod structs;
 
+use rfw_math::Vec4;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum RenderMode {
     Default = 0,
@@ -15,6 +23,8 @@ pub enum RenderMode {
     ScreenSpace = 4,
     Ssao = 5,
     FilteredSsao = 6,
+    /// Screen-space velocity of every pixel since the previous frame
+    Motion = 7,
 }
 
 impl Default for RenderMode {
@@ -23,6 +33,248 @@ impl Default for RenderMode {
     }
 }
 
+/// GPU time spent in the individual passes of a frame, in milliseconds.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct FrameTimings {
+    pub lights: f32,
+    pub ssao: f32,
+    pub radiance: f32,
+    pub blit: f32,
+}
+
+impl FrameTimings {
+    /// Total GPU time of all measured passes in milliseconds.
+    pub fn total(&self) -> f32 {
+        self.lights + self.ssao + self.radiance + self.blit
+    }
+}
+
+/// GPU memory allocated by a backend for scene resources, in bytes per category.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Vertex, joint and skin buffers
+    pub meshes: u64,
+    pub instances: u64,
+    pub materials: u64,
+    /// Textures including all of their mip levels
+    pub textures: u64,
+    /// Light buffers and shadow maps
+    pub lights: u64,
+    /// Acceleration structures of ray tracing backends
+    pub bvh: u64,
+}
+
+impl MemoryReport {
+    /// Total size of all categories in bytes.
+    pub fn total(&self) -> u64 {
+        self.meshes + self.instances + self.materials + self.textures + self.lights + self.bvh
+    }
+}
+
+/// A frame read back from a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed 8-bit sRGB RGBA pixels, row by row starting at the top
+    pub pixels: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// Encodes this frame as a PNG file.
+    #[cfg(feature = "png")]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        image::save_buffer_with_format(
+            path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+        .map_err(|e| match e {
+            image::ImageError::IoError(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::Other, e),
+        })
+    }
+}
+
+/// Linear HDR radiance read back from a backend, before exposure and tonemapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedHdrFrame {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed linear RGBA pixels, row by row starting at the top
+    pub pixels: Vec<f32>,
+}
+
+impl CapturedHdrFrame {
+    /// Writes this frame to an OpenEXR file.
+    #[cfg(feature = "exr")]
+    pub fn save_exr<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let width = self.width as usize;
+        exr::prelude::write_rgba_file(path, width, self.height as usize, |x, y| {
+            let i = (x + y * width) * 4;
+            (
+                self.pixels[i],
+                self.pixels[i + 1],
+                self.pixels[i + 2],
+                self.pixels[i + 3],
+            )
+        })
+        .map_err(|e| match e {
+            exr::error::Error::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::Other, e.to_string()),
+        })
+    }
+}
+
+/// Identifies the uploads issued through a backend's `set_*` functions
+/// before the next call to [Backend::synchronize].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct UploadToken(pub u64);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum Tonemap {
+    /// Output is clamped to [0, 1]
+    None = 0,
+    Reinhard = 1,
+    /// Narkowicz' fit of the ACES filmic curve
+    Aces = 2,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self {
+        Tonemap::None
+    }
+}
+
+/// Transfer function the final image is encoded with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputTransfer {
+    /// The piecewise sRGB curve displays expect
+    Srgb,
+    /// Linear values, e.g. for compositing
+    Linear,
+    /// Pure power curve, values are encoded as `linear^(1 / gamma)`
+    Gamma(f32),
+    /// The BT.709 camera curve used for video
+    Rec709,
+}
+
+impl Default for OutputTransfer {
+    fn default() -> Self {
+        OutputTransfer::Srgb
+    }
+}
+
+impl OutputTransfer {
+    /// Encodes a linear value in [0, 1].
+    pub fn encode(&self, linear: f32) -> f32 {
+        let c = linear.max(0.0).min(1.0);
+        match *self {
+            OutputTransfer::Srgb => {
+                if c <= 0.0031308 {
+                    c * 12.92
+                } else {
+                    1.055 * c.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            OutputTransfer::Linear => c,
+            OutputTransfer::Gamma(gamma) => c.powf(1.0 / gamma.max(1e-3)),
+            OutputTransfer::Rec709 => {
+                if c < 0.018 {
+                    c * 4.5
+                } else {
+                    1.099 * c.powf(0.45) - 0.099
+                }
+            }
+        }
+    }
+}
+
+/// Color grading applied to the final image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorGrading {
+    /// Exposure in stops, the image is scaled by 2^exposure before tonemapping
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    /// Contrast around middle grey (0.18), 1.0 leaves the image untouched
+    pub contrast: f32,
+    /// 0.0 results in a grayscale image, 1.0 leaves the image untouched
+    pub saturation: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tonemap: Tonemap::None,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+impl ColorGrading {
+    pub const EXPOSURE_RANGE: std::ops::Range<f32> = -16.0..16.0;
+    pub const CONTRAST_RANGE: std::ops::Range<f32> = 0.0..4.0;
+    pub const SATURATION_RANGE: std::ops::Range<f32> = 0.0..4.0;
+
+    /// Returns a copy with all values clamped to their valid range,
+    /// non-finite values are replaced by their default.
+    pub fn clamped(&self) -> Self {
+        let default = Self::default();
+        let clamp = |v: f32, range: std::ops::Range<f32>, default: f32| {
+            if v.is_finite() {
+                v.max(range.start).min(range.end)
+            } else {
+                default
+            }
+        };
+
+        Self {
+            exposure: clamp(self.exposure, Self::EXPOSURE_RANGE, default.exposure),
+            tonemap: self.tonemap,
+            contrast: clamp(self.contrast, Self::CONTRAST_RANGE, default.contrast),
+            saturation: clamp(self.saturation, Self::SATURATION_RANGE, default.saturation),
+        }
+    }
+}
+
+/// Overrides of all materials while shading, the stored materials are left untouched.
+/// Helps telling apart issues with materials from issues with lighting.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MaterialDebug {
+    /// Replaces the albedo of all materials, including their diffuse maps, by this gray level
+    pub albedo: Option<f32>,
+    pub metallic: Option<f32>,
+    pub roughness: Option<f32>,
+}
+
+impl MaterialDebug {
+    /// Shades all surfaces with a flat, rough, dielectric 50% gray.
+    pub fn flat_gray() -> Self {
+        Self {
+            albedo: Some(0.5),
+            metallic: Some(0.0),
+            roughness: Some(1.0),
+        }
+    }
+
+    /// Returns true if no material values are overridden.
+    pub fn is_disabled(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Number of clip planes backends support, see [Backend::set_clip_planes].
+pub const MAX_CLIP_PLANES: usize = 4;
+
+/// Number of buckets of [Backend::luminance_histogram].
+pub const LUMINANCE_HISTOGRAM_BINS: usize = 256;
+
 pub trait FromWindowHandle {
     fn init<W: HasRawWindowHandle>(
         window: &W,
@@ -36,28 +288,138 @@ pub trait Backend {
     fn set_2d_mesh(&mut self, id: usize, data: MeshData2D<'_>);
 
     /// Sets an instance with a 4x4 transformation matrix in column-major format
-    fn set_2d_instances(&mut self, mesh: usize, instances: InstancesData2D<'_>);
+    fn set_2d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData2D<'_>,
+    ) -> Result<(), BackendError>;
 
     fn set_3d_mesh(&mut self, id: usize, data: MeshData3D<'_>);
 
+    /// Returns mesh `id` as the backend stores it. The data borrows the copies the backend
+    /// made in [Backend::set_3d_mesh], it is not read back from GPU memory. Backends only keep
+    /// what they render with, data they do not keep is left empty and the bounds are
+    /// recomputed from the vertices. Returns None for unknown meshes or if the backend does
+    /// not keep a copy of its meshes.
+    fn get_3d_mesh(&self, _id: usize) -> Option<MeshData3D<'_>> {
+        None
+    }
+
     fn unload_3d_meshes(&mut self, ids: &[usize]);
 
-    /// Sets an instance with a 4x4 transformation matrix in column-major format
-    fn set_3d_instances(&mut self, mesh: usize, instances: InstancesData3D<'_>);
+    /// Whether mesh `id` is set and not unloaded.
+    fn has_3d_mesh(&self, id: usize) -> bool {
+        self.get_3d_mesh(id).is_some()
+    }
+
+    /// Number of instances currently set for mesh `mesh`, 0 for unknown meshes.
+    fn instance_count(&self, _mesh: usize) -> usize {
+        0
+    }
+
+    /// Sets an instance with a 4x4 transformation matrix in column-major format.
+    /// Fails with [BackendError::InvalidMeshId] if mesh `mesh` is not set.
+    fn set_3d_instances(
+        &mut self,
+        mesh: usize,
+        instances: InstancesData3D<'_>,
+    ) -> Result<(), BackendError>;
+
+    /// Sets the instances of multiple meshes at once,
+    /// backends can override this to only update their instance storage once.
+    /// The instances of valid meshes are set even if others fail, the first error is returned.
+    fn set_all_3d_instances(
+        &mut self,
+        instances: &[(usize, InstancesData3D<'_>)],
+    ) -> Result<(), BackendError> {
+        let mut result = Ok(());
+        for (mesh, data) in instances.iter() {
+            result = result.and(self.set_3d_instances(*mesh, *data));
+        }
+        result
+    }
+
+    /// Pre-sizes the storage of 3D instances for `count` instances in total, so scenes with a
+    /// known instance count do not grow their buffers while they fill up.
+    fn reserve_3d_instances(&mut self, _count: usize) {}
 
     /// Updates materials
     fn set_materials(&mut self, materials: &[DeviceMaterial], changed: &BitSlice);
 
+    /// Updates a single material without re-uploading the full material table.
+    /// Backends without partial updates ignore this, materials then only change through
+    /// [Backend::set_materials].
+    fn set_material(&mut self, _index: usize, _material: DeviceMaterial) {}
+
     /// Updates textures
     /// Textures in BGRA format, 8 bytes per channel, 32 bytes per texel.
-    fn set_textures(&mut self, textures: &[TextureData<'_>], changed: &BitSlice);
+    /// Fails with [BackendError::TextureCapacityExceeded] if the backend cannot bind all of
+    /// `textures`, the textures within its capacity are still updated.
+    fn set_textures(
+        &mut self,
+        textures: &[TextureData<'_>],
+        changed: &BitSlice,
+    ) -> Result<(), BackendError>;
+
+    /// Whether texture `id` was uploaded through [Backend::set_textures]. Backends that do
+    /// not track their textures return false.
+    fn has_texture(&self, _id: usize) -> bool {
+        false
+    }
+
+    /// Whether this backend builds the mip chain of linearly filtered textures that are passed
+    /// to [Backend::set_textures] with a single level. The scene then skips generating mip
+    /// levels on the CPU.
+    fn generates_mipmaps(&self) -> bool {
+        false
+    }
 
     /// Synchronizes scene after updating meshes, instances, materials and lights
     /// This is an expensive step as it can involve operations such as acceleration structure rebuilds
     fn synchronize(&mut self);
 
-    /// Renders an image to the window surface
-    fn render(&mut self, view_2d: CameraView2D, view_3d: CameraView3D, mode: RenderMode);
+    /// Removes all meshes, instances, materials, textures, lights and skins and releases their
+    /// GPU memory, while keeping the device, swap chain and pipelines alive.
+    /// Call [Backend::synchronize] afterwards to push the empty scene.
+    fn clear_scene(&mut self) {}
+
+    /// Renders an image to the window surface. Nothing is rendered while the window is
+    /// minimized, which is not an error.
+    fn render(
+        &mut self,
+        view_2d: CameraView2D,
+        view_3d: CameraView3D,
+        mode: RenderMode,
+    ) -> Result<(), BackendError>;
+
+    /// Renders the scene once per view into its viewport of the window surface, all within a
+    /// single frame. Cameras should use the aspect ratio of their viewport, the 2D overlay is
+    /// drawn as with [Backend::render] and only shows within the viewports. Backends without
+    /// support render the first view to the whole surface.
+    fn render_views(
+        &mut self,
+        view_2d: CameraView2D,
+        views: &[(Viewport, CameraView3D)],
+        mode: RenderMode,
+    ) -> Result<(), BackendError> {
+        match views.first() {
+            Some((_, view_3d)) => self.render(view_2d, *view_3d, mode),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates and compiles every pipeline variant the backend may use up front, so the first
+    /// frame using a feature does not stall on pipeline creation. Called once after the backend
+    /// is created, a no-op for backends that create all pipelines on initialization.
+    fn warm_pipelines(&self) {}
+
+    /// Starts a frame, [Backend::render] calls until [Backend::end_frame] are submitted to the
+    /// device together with the commands the application records through backend specific
+    /// handles. Without an explicit frame, every render is a frame of its own.
+    fn begin_frame(&mut self) {}
+
+    /// Submits and presents the frame started by [Backend::begin_frame].
+    fn end_frame(&mut self) {}
 
     /// Resizes framebuffer, uses scale factor provided in init function.
     fn resize(&mut self, window_size: (u32, u32), scale_factor: f64);
@@ -74,9 +436,197 @@ pub trait Backend {
     /// Updates directional lights, only lights with their 'changed' flag set to true have changed
     fn set_directional_lights(&mut self, lights: &[DirectionalLight], changed: &BitSlice);
 
-    // Sets the scene skybox
-    fn set_skybox(&mut self, skybox: TextureData<'_>);
+    /// Excludes instances, given as (mesh, instance) pairs, from the light of `kind` at `index`.
+    /// Replaces the earlier exclusions of that light, an empty slice lets it affect every
+    /// instance again.
+    fn set_light_exclusions(
+        &mut self,
+        _kind: LightKind,
+        _index: usize,
+        _instances: &[(usize, usize)],
+    ) {
+    }
+
+    /// Sets the scene skybox, either an equirectangular texture or the six faces of a cube map
+    fn set_skybox(&mut self, skybox: SkyboxData<'_>);
 
     // Sets skins
     fn set_skins(&mut self, skins: &[SkinData<'_>], changed: &BitSlice);
+
+    /// Sets the particles of particle system `system`, replacing its previous particles.
+    /// An empty slice removes the system.
+    fn set_particles(&mut self, _system: usize, _particles: &[Particle], _blend: ParticleBlend) {}
+
+    /// Sets the decals of the scene, replacing the previous set. Overlapping decals are
+    /// blended back to front unless [Decal::sort_key] orders them. Only backends with a
+    /// G-buffer project decals, path tracers such as gpu-rt ignore them for now.
+    fn set_decals(&mut self, _decals: &[Decal]) {}
+
+    /// Reads back the most recently rendered frame at render resolution.
+    /// Returns None if the backend does not support reading back its output.
+    fn capture(&self) -> Option<CapturedFrame> {
+        None
+    }
+
+    /// Reads back the linear radiance of the most recently rendered frame at render resolution,
+    /// before exposure, tonemapping and color grading are applied.
+    /// Returns None if the backend does not support reading back its output.
+    fn capture_hdr(&self) -> Option<CapturedHdrFrame> {
+        None
+    }
+
+    /// Captures the most recently rendered frame and writes it to `path` as a PNG.
+    #[cfg(feature = "png")]
+    fn screenshot(&self, path: &std::path::Path) -> std::io::Result<()> {
+        match self.capture() {
+            Some(frame) => frame.save_png(path),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "backend does not support capturing frames",
+            )),
+        }
+    }
+
+    /// Returns GPU timings of the most recently rendered frame.
+    /// Returns None if the backend or device does not support timestamp queries.
+    fn frame_timings(&self) -> Option<FrameTimings> {
+        None
+    }
+
+    /// Returns the luminance histogram of the most recently rendered frame. Bucket `i` counts
+    /// the pixels whose displayed luminance rounds to `i / 255`, so the last bucket holds the
+    /// clipped pixels. Returns None if the backend does not compute a histogram or it is
+    /// disabled through its settings.
+    fn luminance_histogram(&self) -> Option<[u32; LUMINANCE_HISTOGRAM_BINS]> {
+        None
+    }
+
+    /// Returns an estimate of the GPU memory used by the scene resources of this backend,
+    /// such as textures and vertex buffers. Backends that do not track their allocations
+    /// return an empty report.
+    fn memory_usage(&self) -> MemoryReport {
+        MemoryReport::default()
+    }
+
+    /// Returns the runtime-tunable settings of this backend together with their current values.
+    fn get_settings(&self) -> Vec<Setting> {
+        Vec::new()
+    }
+
+    /// Updates a setting previously retrieved through [Backend::get_settings],
+    /// unknown keys are ignored.
+    fn set_setting(&mut self, _setting: Setting) {}
+
+    /// Returns the current values of all settings of this backend.
+    fn export_settings(&self) -> SettingsSnapshot {
+        SettingsSnapshot::new(&self.get_settings())
+    }
+
+    /// Applies every value of `snapshot` before the next frame is rendered. Numeric values are
+    /// clamped to the advertised ranges of their settings through [Setting::set] before they
+    /// are passed to [Backend::set_setting], keys this backend does not know are ignored.
+    fn apply_settings(&mut self, snapshot: &SettingsSnapshot) {
+        for setting in self.get_settings() {
+            let value = match snapshot.get(setting.key()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let mut clamped = setting.clone();
+            clamped.set(value.clone());
+            if clamped.value() != setting.value() {
+                self.set_setting(clamped);
+            }
+        }
+    }
+
+    /// Appends a post-process pass running the SPIR-V fragment shader `shader` on the final
+    /// color, after color grading and before the 2D overlay. Passes run in the order they were
+    /// added and only in [RenderMode::Default]. The shader's `main` entry point receives the
+    /// screen UV at location 0, writes its color to location 0 and is bound with:
+    ///
+    /// ```glsl
+    /// layout(set = 0, binding = 0) uniform texture2D Color; // output of the previous pass
+    /// layout(set = 0, binding = 1) uniform sampler Sampler; // linear, clamped to edge
+    /// layout(set = 0, binding = 2) uniform Locals {
+    ///     vec2 resolution; // render resolution in pixels
+    ///     float time;      // seconds since the backend was created
+    ///     uint frame;
+    /// };
+    /// ```
+    ///
+    /// Returns false if the backend does not support custom post-processing.
+    fn add_post_process(&mut self, _shader: &[u8]) -> bool {
+        false
+    }
+
+    /// Removes all passes added through [Backend::add_post_process].
+    fn clear_post_processes(&mut self) {}
+
+    /// Sets the color grading of the final output, values are clamped to their valid range.
+    fn set_color_grading(&mut self, _grading: ColorGrading) {}
+
+    /// Sets the transfer function of the final image, applied after color grading. Backends
+    /// presenting to sRGB surfaces compensate for the encoding of the surface, so the stored
+    /// values follow `transfer` exactly. Defaults to [OutputTransfer::Srgb].
+    fn set_output_transfer(&mut self, _transfer: OutputTransfer) {}
+
+    /// Overrides the albedo, metalness or roughness of all materials, pass
+    /// `MaterialDebug::default()` to shade with the stored materials again.
+    fn set_material_debug(&mut self, _debug: MaterialDebug) {}
+
+    /// Cuts away all surfaces on the negative side of any of `planes`, a point `p` is kept
+    /// if `plane.truncate().dot(p) + plane.w >= 0`. Planes beyond [MAX_CLIP_PLANES] are ignored,
+    /// an empty slice disables clipping.
+    fn set_clip_planes(&mut self, _planes: &[Vec4]) {}
+
+    /// Only shows the surfaces inside `region`, those outside of it are cut away or dimmed.
+    /// Applies to the whole scene, surfaces outside the region still cast shadows. `None`
+    /// shows the whole scene again, which is the default.
+    fn set_reveal_region(&mut self, _region: Option<RevealRegion>) {}
+
+    /// Marks a region of the 2D overlay in pixels of the render resolution to be redrawn, for
+    /// backends that only redraw the parts of the overlay that changed. Changes to 2D meshes
+    /// and instances mark their regions automatically.
+    fn mark_2d_dirty(&mut self, _x: u32, _y: u32, _width: u32, _height: u32) {}
+
+    /// Renders only instance `index` of mesh `mesh` to isolate problems with a single object,
+    /// `None` renders the whole scene again.
+    fn set_isolate_instance(&mut self, _instance: Option<(usize, usize)>) {}
+
+    /// Shades instance `index` of mesh `mesh` with `effect`. Effects are kept when the
+    /// instances of the mesh are set again, instances default to [InstanceEffect::Standard].
+    /// Backends without support for an effect shade the instance as usual.
+    fn set_3d_instance_effect(&mut self, _mesh: usize, _index: usize, _effect: InstanceEffect) {}
+
+    /// Sets the handedness of the coordinate system the scene and its cameras are placed in.
+    /// Left-handed scenes are mirrored into the right-handed space of the backend and culled
+    /// with the opposite winding, so they do not show up mirrored. Defaults to
+    /// [Handedness::Right].
+    fn set_handedness(&mut self, _handedness: Handedness) {}
+
+    /// Sets the wind that sways instances, scaled by the wind weight of each instance in
+    /// [InstancesData3D::wind_weights]. Instances without a weight are not affected.
+    fn set_wind(&mut self, _wind: Wind) {}
+
+    /// Outlines the given instances, as (mesh, instance index) pairs, e.g. to highlight the
+    /// selection of an editor. Replaces the previous selection, an empty slice removes all
+    /// outlines.
+    fn set_selected_instances(&mut self, _instances: &[(usize, usize)]) {}
+
+    /// Draws an infinite reference grid on the Y=0 plane, occluded by the scene. `None` hides
+    /// the grid, which is the default.
+    fn set_grid(&mut self, _grid: Option<GridConfig>) {}
+
+    /// Returns the token of all uploads issued since the last call to [Backend::synchronize].
+    fn upload_token(&self) -> UploadToken {
+        UploadToken::default()
+    }
+
+    /// Calls `callback` once the GPU has finished the uploads identified by `token`,
+    /// after which their CPU-side source data can be freed. The uploads only get submitted
+    /// by [Backend::synchronize]. Backends that upload synchronously call it right away.
+    fn on_upload_complete(&mut self, _token: UploadToken, callback: Box<dyn FnOnce() + Send>) {
+        callback();
+    }
 }
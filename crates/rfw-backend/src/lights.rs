@@ -3,6 +3,30 @@ use rfw_math::*;
 use rtbvh::Aabb;
 use std::fmt::Display;
 
+/// Kind of a light, lights are identified by their kind and index.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LightKind {
+    Point,
+    Spot,
+    Area,
+    Directional,
+}
+
+/// Shape of an [AreaLight]. Triangle lights are created from emissive geometry, rectangles and
+/// disks are parametric lights that are not backed by a mesh.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum AreaLightShape {
+    /// Spanned by `vertex0`, `vertex1` and `vertex2`
+    Triangle = 0,
+    /// Centered at `position`, `vertex0` and `vertex1` hold the half extents along its edges
+    Rectangle = 1,
+    /// Centered at `position`, `vertex0` and `vertex1` are orthogonal radius vectors
+    Disk = 2,
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Copy, Clone)]
 #[repr(C)]
@@ -23,10 +47,11 @@ pub struct AreaLight {
     mesh_id: i32,
     pub radiance: Vec3,
     // 72
-    _dummy1: i32,
+    shape: i32,
     pub vertex2: Vec3,
     // 84
-    _dummy2: i32,
+    /// Narrows the emission around the normal, see [AreaLight::with_focus]
+    pub focus: f32,
 }
 
 impl Default for AreaLight {
@@ -41,9 +66,9 @@ impl Default for AreaLight {
             vertex1: Vec3::ZERO, // 60
             mesh_id: -1,
             radiance: Vec3::ZERO, // 72
-            _dummy1: 0,
+            shape: AreaLightShape::Triangle as i32,
             vertex2: Vec3::ZERO, // 84
-            _dummy2: 0,
+            focus: 0.0,
         }
     }
 }
@@ -52,7 +77,7 @@ impl Display for AreaLight {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "AreaLight {{ position: {}, energy: {}, normal: {}, area: {}, vertex0: {}, inst_idx: {}, vertex1: {}, radiance: {}, vertex2: {} }}",
+            "AreaLight {{ position: {}, energy: {}, normal: {}, area: {}, vertex0: {}, inst_idx: {}, vertex1: {}, radiance: {}, vertex2: {}, shape: {:?}, focus: {} }}",
             self.position,
             self.energy,
             self.normal,
@@ -62,6 +87,8 @@ impl Display for AreaLight {
             self.vertex1,
             self.radiance,
             self.vertex2,
+            self.shape(),
+            self.focus,
         )
     }
 }
@@ -90,11 +117,98 @@ impl AreaLight {
             vertex1,
             mesh_id,
             radiance,
-            _dummy1: 1,
+            shape: AreaLightShape::Triangle as i32,
             vertex2,
-            _dummy2: 2,
+            focus: 0.0,
+        }
+    }
+
+    /// Rectangular light centered at `center`, `right` and `up` are the half extents along its
+    /// edges. Light is emitted towards `right.cross(up)`.
+    pub fn rectangle(center: Vec3, right: Vec3, up: Vec3, radiance: Vec3) -> AreaLight {
+        let radiance = radiance.abs();
+        Self {
+            position: center,
+            energy: radiance.length(),
+            normal: right.cross(up).normalize(),
+            area: 4.0 * right.length() * up.length(),
+            vertex0: right,
+            inst_idx: -1,
+            vertex1: up,
+            mesh_id: -1,
+            radiance,
+            shape: AreaLightShape::Rectangle as i32,
+            vertex2: Vec3::ZERO,
+            focus: 0.0,
+        }
+    }
+
+    /// Disk shaped light centered at `center`, emitting light towards `normal`.
+    pub fn disk(center: Vec3, normal: Vec3, radius: f32, radiance: Vec3) -> AreaLight {
+        let radiance = radiance.abs();
+        let normal = normal.normalize();
+        // Any vector that is not parallel to the normal works to construct a tangent frame
+        let helper = if normal.x.abs() > 0.9 {
+            Vec3::Y
+        } else {
+            Vec3::X
+        };
+        let tangent = normal.cross(helper).normalize();
+        let bitangent = normal.cross(tangent);
+
+        Self {
+            position: center,
+            energy: radiance.length(),
+            normal,
+            area: std::f32::consts::PI * radius * radius,
+            vertex0: tangent * radius,
+            inst_idx: -1,
+            vertex1: bitangent * radius,
+            mesh_id: -1,
+            radiance,
+            shape: AreaLightShape::Disk as i32,
+            vertex2: Vec3::ZERO,
+            focus: 0.0,
         }
     }
+
+    /// Focuses the emission of this light around its normal like a softbox with a grid: radiance
+    /// leaving at angle `theta` to the normal is scaled by `(focus + 2) / 2 * cos(theta)^focus`,
+    /// which keeps the emitted power of the light. 0 emits uniformly, which is the default.
+    /// Lights created from emissive meshes are always uniform.
+    pub fn with_focus(mut self, focus: f32) -> Self {
+        self.focus = focus.max(0.0);
+        self
+    }
+
+    /// Scale of the radiance leaving the light at an angle with cosine `cos_theta` to its
+    /// normal, see [AreaLight::with_focus].
+    pub fn focus_factor(&self, cos_theta: f32) -> f32 {
+        if self.focus <= 0.0 {
+            return 1.0;
+        }
+
+        (self.focus + 2.0) * 0.5 * cos_theta.max(0.0).powf(self.focus)
+    }
+
+    pub fn shape(&self) -> AreaLightShape {
+        match self.shape {
+            1 => AreaLightShape::Rectangle,
+            2 => AreaLightShape::Disk,
+            _ => AreaLightShape::Triangle,
+        }
+    }
+
+    /// Makes this light subtract its contribution instead of adding it, which only the
+    /// rasterizing backends support. Path tracers treat negative lights as regular lights.
+    pub fn set_negative(&mut self, negative: bool) {
+        let radiance = self.radiance.abs();
+        self.radiance = if negative { -radiance } else { radiance };
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.radiance.cmplt(Vec3::ZERO).any()
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -128,10 +242,22 @@ impl PointLight {
         }
     }
 
+    /// Subtracts this light's contribution instead of adding it, see [AreaLight::set_negative].
+    pub fn set_negative(&mut self, negative: bool) {
+        let radiance = self.radiance.abs();
+        self.radiance = if negative { -radiance } else { radiance };
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.radiance.cmplt(Vec3::ZERO).any()
+    }
+
     pub fn set_radiance(&mut self, radiance: Vec3) {
+        let negative = self.is_negative();
         let radiance = radiance.abs();
         self.radiance = radiance;
         self.energy = radiance.length();
+        self.set_negative(negative);
     }
 
     pub fn get_matrix(&self, _: &Aabb) -> [Mat4; 6] {
@@ -206,7 +332,11 @@ pub struct SpotLight {
     pub cos_outer: f32,
     pub direction: Vec3,
     pub energy: f32,
-}
+    /// Radius of the light source. Larger sources cast softer shadows, 0 is an infinitely
+    /// small source with hard shadows.
+    pub radius: f32,
+    _padding: [f32; 3],
+} // 64 Bytes
 
 impl Default for SpotLight {
     fn default() -> Self {
@@ -217,6 +347,8 @@ impl Default for SpotLight {
             cos_outer: 0.0,
             direction: Vec3::ZERO,
             energy: 0.0,
+            radius: 0.0,
+            _padding: [0.0; 3],
         }
     }
 }
@@ -225,13 +357,14 @@ impl Display for SpotLight {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "SpotLight {{ position: {}, cos_inner: {}, radiance: {}, cos_outer: {}, direction: {}, energy: {} }}",
+            "SpotLight {{ position: {}, cos_inner: {}, radiance: {}, cos_outer: {}, direction: {}, energy: {}, radius: {} }}",
             self.position,
             self.cos_inner,
             self.radiance,
             self.cos_outer,
             self.direction,
-            self.energy
+            self.energy,
+            self.radius,
         )
     }
 }
@@ -256,9 +389,26 @@ impl SpotLight {
             cos_outer: outer_angle.cos(),
             direction: direction.normalize(),
             energy: radiance.length(),
+            radius: 0.0,
+            _padding: [0.0; 3],
         }
     }
 
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    /// Subtracts this light's contribution instead of adding it, see [AreaLight::set_negative].
+    pub fn set_negative(&mut self, negative: bool) {
+        let radiance = self.radiance.abs();
+        self.radiance = if negative { -radiance } else { radiance };
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.radiance.cmplt(Vec3::ZERO).any()
+    }
+
     pub fn translate_x(&mut self, offset: f32) {
         self.position[0] += offset;
     }
@@ -297,7 +447,10 @@ pub struct DirectionalLight {
     pub direction: Vec3,
     pub energy: f32,
     pub radiance: Vec3,
-    _dummy: f32,
+    /// Angle in radians that the light source subtends, e.g.
+    /// [DirectionalLight::SUN_ANGULAR_DIAMETER]. Larger sources cast softer shadows, 0 is an
+    /// infinitely small source with hard shadows.
+    pub angular_diameter: f32,
 } // 32 Bytes
 
 impl Default for DirectionalLight {
@@ -306,7 +459,7 @@ impl Default for DirectionalLight {
             direction: Vec3::ZERO,
             energy: 0.0,
             radiance: Vec3::ZERO,
-            _dummy: 0.0,
+            angular_diameter: 0.0,
         }
     }
 }
@@ -315,23 +468,41 @@ impl Display for DirectionalLight {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "DirectionalLight {{ direction: {}, energy: {}, radiance: {} }}",
-            self.direction, self.energy, self.radiance,
+            "DirectionalLight {{ direction: {}, energy: {}, radiance: {}, angular_diameter: {} }}",
+            self.direction, self.energy, self.radiance, self.angular_diameter,
         )
     }
 }
 
 impl DirectionalLight {
+    /// Angular diameter of the sun as seen from the earth, about 0.53 degrees.
+    pub const SUN_ANGULAR_DIAMETER: f32 = 0.00925;
+
     pub fn new(direction: Vec3, radiance: Vec3) -> DirectionalLight {
         let radiance = radiance.abs();
         Self {
             direction: direction.normalize(),
             energy: radiance.length(),
             radiance,
-            _dummy: 0.0,
+            angular_diameter: 0.0,
         }
     }
 
+    pub fn with_angular_diameter(mut self, radians: f32) -> Self {
+        self.angular_diameter = radians.max(0.0);
+        self
+    }
+
+    /// Subtracts this light's contribution instead of adding it, see [AreaLight::set_negative].
+    pub fn set_negative(&mut self, negative: bool) {
+        let radiance = self.radiance.abs();
+        self.radiance = if negative { -radiance } else { radiance };
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.radiance.cmplt(Vec3::ZERO).any()
+    }
+
     pub fn rotate_x(&mut self, degrees: f32) {
         let rotation = Mat4::from_rotation_x(degrees.to_radians());
         let direction = rotation * self.direction.extend(0.0);
@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingValue {
+    Float(f32),
+    Int(i32),
+    String(String),
+}
+
+impl Display for SettingValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingValue::Float(v) => write!(f, "{}", v),
+            SettingValue::Int(v) => write!(f, "{}", v),
+            SettingValue::String(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// A named, runtime-tunable backend option.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    key: String,
+    value: SettingValue,
+    range: Option<Range<f32>>,
+    labels: Vec<String>,
+}
+
+impl Setting {
+    pub fn new(key: &str, value: SettingValue, range: Option<Range<f32>>) -> Self {
+        Self {
+            key: key.to_string(),
+            value,
+            range,
+            labels: Vec::new(),
+        }
+    }
+
+    /// Names the integer values of this setting, the label at index `i` describes value `i`.
+    pub fn with_labels(mut self, labels: &[&str]) -> Self {
+        self.labels = labels.iter().map(|l| l.to_string()).collect();
+        self
+    }
+
+    pub fn key(&self) -> &str {
+        self.key.as_str()
+    }
+
+    pub fn value(&self) -> &SettingValue {
+        &self.value
+    }
+
+    /// Sets the value of this setting, numeric values are clamped to the range of this setting.
+    pub fn set(&mut self, value: SettingValue) {
+        self.value = match (value, self.range.as_ref()) {
+            (SettingValue::Float(v), Some(r)) => SettingValue::Float(v.max(r.start).min(r.end)),
+            (SettingValue::Int(v), Some(r)) => {
+                SettingValue::Int(v.max(r.start as i32).min(r.end as i32))
+            }
+            (v, _) => v,
+        };
+    }
+
+    /// Valid range of numeric values, if any.
+    pub fn range(&self) -> Option<Range<f32>> {
+        self.range.clone()
+    }
+
+    /// Names of the integer values of this setting, empty if the values are not named.
+    pub fn labels(&self) -> &[String] {
+        self.labels.as_slice()
+    }
+
+    /// Name of the current value, if it has one.
+    pub fn label(&self) -> Option<&str> {
+        match self.value {
+            SettingValue::Int(v) if v >= 0 => self.labels.get(v as usize).map(|l| l.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self.value {
+            SettingValue::Float(v) => Some(v),
+            SettingValue::Int(v) => Some(v as f32),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.key, self.value)
+    }
+}
+
+/// The values of all settings of a backend, retrieved through [crate::Backend::export_settings].
+/// Snapshots can be stored as presets and restored at once with [crate::Backend::apply_settings].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SettingsSnapshot {
+    values: Vec<(String, SettingValue)>,
+}
+
+impl SettingsSnapshot {
+    pub fn new(settings: &[Setting]) -> Self {
+        Self {
+            values: settings
+                .iter()
+                .map(|s| (s.key().to_string(), s.value().clone()))
+                .collect(),
+        }
+    }
+
+    /// Value of the setting named `key`, if this snapshot holds it.
+    pub fn get(&self, key: &str) -> Option<&SettingValue> {
+        self.values.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Stores `value` for the setting named `key`, replacing its previous value.
+    pub fn set(&mut self, key: &str, value: SettingValue) {
+        match self.values.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.values.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SettingValue)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
@@ -44,6 +44,14 @@ pub struct InstancesData3D<'a> {
     pub matrices: &'a [Mat4],
     pub skin_ids: &'a [SkinID],
     pub flags: &'a [InstanceFlags3D],
+    /// Application defined payload per instance, returned by queries on the backend
+    pub user_data: &'a [u64],
+    /// Color multiplied into the albedo of each instance, white leaves its materials unchanged
+    pub tints: &'a [Vec4],
+    /// Offset in seconds of the flipbook playback of each instance, see [Flipbook]
+    pub flipbook_times: &'a [f32],
+    /// Share of the [Wind] each instance is swayed by, instances with a weight of 0 stay still
+    pub wind_weights: &'a [f32],
     pub local_aabb: Aabb,
 }
 
@@ -192,6 +200,56 @@ impl From<SkinID> for usize {
 pub enum DataFormat {
     BGRA8 = 0,
     RGBA8 = 1,
+    /// Block compressed RGB with 1-bit alpha, 8 bytes per 4x4 block
+    BC1 = 2,
+    /// Block compressed RGBA, 16 bytes per 4x4 block
+    BC3 = 3,
+    /// Block compressed high quality RGBA, 16 bytes per 4x4 block
+    BC7 = 4,
+    /// Half precision floating point RGBA, used for HDR environments
+    RGBA16F = 5,
+}
+
+impl DataFormat {
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, DataFormat::BC1 | DataFormat::BC3 | DataFormat::BC7)
+    }
+
+    /// Width and height in pixels of a single block, 1 for uncompressed formats.
+    pub fn block_dimensions(&self) -> u32 {
+        if self.is_compressed() {
+            4
+        } else {
+            1
+        }
+    }
+
+    /// Size in bytes of a single block, or of a single pixel for uncompressed formats.
+    pub fn block_size(&self) -> usize {
+        match self {
+            DataFormat::BGRA8 | DataFormat::RGBA8 => 4,
+            DataFormat::RGBA16F => 8,
+            DataFormat::BC1 => 8,
+            DataFormat::BC3 | DataFormat::BC7 => 16,
+        }
+    }
+}
+
+/// Filtering used when sampling a texture.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(C)]
+pub enum TextureFilter {
+    /// Trilinear filtering over the full mip chain
+    Linear = 0,
+    /// Nearest filtering of the first mip level only, keeps pixel-art and UI textures crisp
+    Nearest = 1,
+}
+
+impl Default for TextureFilter {
+    fn default() -> Self {
+        TextureFilter::Linear
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -202,9 +260,35 @@ pub struct TextureData<'a> {
     pub mip_levels: u32,
     pub bytes: &'a [u8],
     pub format: DataFormat,
+    pub filter: TextureFilter,
+    /// The color channels are already multiplied by alpha. Blended passes composite
+    /// premultiplied color, straight alpha textures are premultiplied when they are sampled.
+    pub premultiplied: bool,
 }
 
 impl TextureData<'_> {
+    /// Number of bytes in a single row of blocks of the given mip level.
+    pub fn bytes_per_row(&self, mip_level: usize) -> usize {
+        let block = self.format.block_dimensions() as usize;
+        let width = self.mip_level_width(mip_level).max(1);
+        ((width + block - 1) / block) * self.format.block_size()
+    }
+
+    /// Number of rows of blocks in the given mip level.
+    pub fn rows(&self, mip_level: usize) -> usize {
+        let block = self.format.block_dimensions() as usize;
+        let height = self.mip_level_height(mip_level).max(1);
+        (height + block - 1) / block
+    }
+
+    /// Offset in bytes of the given mip level, mip levels are tightly packed after each other.
+    pub fn byte_offset_for_level(&self, mip_level: usize) -> usize {
+        assert!(mip_level <= self.mip_levels as usize);
+        (0..mip_level)
+            .map(|i| self.bytes_per_row(i) * self.rows(i))
+            .sum()
+    }
+
     pub fn offset_for_level(&self, mip_level: usize) -> usize {
         assert!(mip_level <= self.mip_levels as usize);
         let mut offset = 0;
@@ -248,6 +332,102 @@ impl TextureData<'_> {
     }
 }
 
+impl<'a> TextureData<'a> {
+    /// Fits this texture within `max_size` by dropping the stored mip levels that are larger,
+    /// or by halving the smallest stored level with a box filter when no level fits. Halved
+    /// textures are written to `storage` and have a single mip level. Returns None if a
+    /// block compressed texture has no stored level that fits.
+    pub fn fit<'b>(&self, max_size: u32, storage: &'b mut Vec<u8>) -> Option<TextureData<'b>>
+    where
+        'a: 'b,
+    {
+        let max_size = max_size.max(1) as usize;
+        let fits = |(w, h): (usize, usize)| w <= max_size && h <= max_size;
+
+        let levels = self.mip_levels.max(1) as usize;
+        if let Some(level) = (0..levels).find(|l| fits(self.mip_level_width_height(*l))) {
+            let (width, height) = self.mip_level_width_height(level);
+            return Some(TextureData {
+                width: width as u32,
+                height: height as u32,
+                mip_levels: (levels - level) as u32,
+                bytes: &self.bytes[self.byte_offset_for_level(level)..],
+                ..*self
+            });
+        }
+
+        // Half precision channels cannot be averaged as bytes
+        if !matches!(self.format, DataFormat::BGRA8 | DataFormat::RGBA8) {
+            return None;
+        }
+
+        let level = levels - 1;
+        let (mut width, mut height) = self.mip_level_width_height(level);
+        let offset = self.byte_offset_for_level(level);
+        storage.clear();
+        storage.extend_from_slice(&self.bytes[offset..(offset + width * height * 4)]);
+
+        while !fits((width, height)) {
+            let (w, h) = ((width / 2).max(1), (height / 2).max(1));
+            let mut halved = vec![0; w * h * 4];
+            for y in 0..h {
+                for x in 0..w {
+                    for c in 0..4 {
+                        let texel = |x: usize, y: usize| {
+                            storage[(y.min(height - 1) * width + x.min(width - 1)) * 4 + c] as u32
+                        };
+                        let sum = texel(x * 2, y * 2)
+                            + texel(x * 2 + 1, y * 2)
+                            + texel(x * 2, y * 2 + 1)
+                            + texel(x * 2 + 1, y * 2 + 1);
+                        halved[(y * w + x) * 4 + c] = ((sum + 2) / 4) as u8;
+                    }
+                }
+            }
+
+            *storage = halved;
+            width = w;
+            height = h;
+        }
+
+        Some(TextureData {
+            width: width as u32,
+            height: height as u32,
+            mip_levels: 1,
+            bytes: storage.as_slice(),
+            ..*self
+        })
+    }
+}
+
+/// Environment seen by rays and pixels that do not hit any geometry.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SkyboxData<'a> {
+    /// A single texture in equirectangular projection
+    Equirectangular(TextureData<'a>),
+    /// Six square faces of equal size and format in the order +X, -X, +Y, -Y, +Z, -Z
+    Cube([TextureData<'a>; 6]),
+}
+
+impl<'a> SkyboxData<'a> {
+    /// Returns whether all faces of a cube map share their size, mip levels and format.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            SkyboxData::Equirectangular(_) => true,
+            SkyboxData::Cube(faces) => {
+                let first = &faces[0];
+                first.width == first.height
+                    && faces.iter().all(|f| {
+                        f.width == first.width
+                            && f.height == first.height
+                            && f.mip_levels == first.mip_levels
+                            && f.format == first.format
+                    })
+            }
+        }
+    }
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Copy, Clone, Default, PartialEq)]
 #[repr(C)]
@@ -259,11 +439,12 @@ pub struct Vertex3D {
     pub mat_id: u32,
     // 32
     pub uv: Vec2,
-    pub pad0: f32,
-    pub pad1: f32,
     // 40
+    /// Secondary uv set used for lightmaps, equal to uv when a mesh has no secondary set
+    pub uv1: Vec2,
+    // 48
     pub tangent: Vec4,
-    // 56
+    // 64
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -320,6 +501,18 @@ bitflags::bitflags! {
     pub struct Mesh3dFlags: u32 {
         const SHADOW_CASTER = 1;
         const ALLOW_SKINNING = 2;
+        /// Render both sides of every triangle, for open or double-sided meshes
+        const CULL_NONE = 4;
+        /// Cull front faces instead of back faces, for meshes with inverted winding
+        const CULL_FRONT = 8;
+        /// Draw over the rest of the scene regardless of depth, for gizmos and markers.
+        /// Always-on-top meshes are drawn after all other meshes.
+        const ALWAYS_ON_TOP = 16;
+        /// Front faces are wound clockwise, for assets authored with the opposite convention
+        const CLOCKWISE = 32;
+        /// Shade with the normal of each triangle instead of interpolated vertex normals, for
+        /// hard-edged meshes such as CAD or low-poly assets
+        const FLAT_SHADING = 64;
     }
 }
 
@@ -329,6 +522,152 @@ impl Default for Mesh3dFlags {
     }
 }
 
+impl Mesh3dFlags {
+    /// Faces culled when rendering this mesh, meshes are assumed to be closed
+    /// and get back-face culling unless either culling flag is set.
+    pub fn cull_mode(&self) -> CullMode {
+        if self.contains(Self::CULL_NONE) {
+            CullMode::None
+        } else if self.contains(Self::CULL_FRONT) {
+            CullMode::Front
+        } else {
+            CullMode::Back
+        }
+    }
+
+    pub fn set_cull_mode(&mut self, mode: CullMode) {
+        self.remove(Self::CULL_NONE | Self::CULL_FRONT);
+        match mode {
+            CullMode::None => self.insert(Self::CULL_NONE),
+            CullMode::Front => self.insert(Self::CULL_FRONT),
+            CullMode::Back => {}
+        }
+    }
+
+    pub fn front_face(&self) -> FrontFace {
+        if self.contains(Self::CLOCKWISE) {
+            FrontFace::Cw
+        } else {
+            FrontFace::Ccw
+        }
+    }
+
+    pub fn set_front_face(&mut self, front_face: FrontFace) {
+        self.set(Self::CLOCKWISE, front_face == FrontFace::Cw);
+    }
+
+    /// Faces to cull when rasterizing with counter-clockwise front faces,
+    /// which swaps front and back for clockwise meshes.
+    pub fn raster_cull_mode(&self) -> CullMode {
+        match (self.cull_mode(), self.front_face()) {
+            (CullMode::Front, FrontFace::Cw) => CullMode::Back,
+            (CullMode::Back, FrontFace::Cw) => CullMode::Front,
+            (mode, _) => mode,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u32)]
+pub enum CullMode {
+    None = 0,
+    Front = 1,
+    Back = 2,
+}
+
+impl CullMode {
+    pub const COUNT: usize = 3;
+}
+
+impl Default for CullMode {
+    fn default() -> Self {
+        Self::Back
+    }
+}
+
+/// Winding order of the vertices of front facing triangles.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u32)]
+pub enum FrontFace {
+    /// Counter-clockwise, the convention of glTF and most other formats
+    Ccw = 0,
+    Cw = 1,
+}
+
+impl Default for FrontFace {
+    fn default() -> Self {
+        Self::Ccw
+    }
+}
+
+/// Handedness of the coordinate system a scene is authored in.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u32)]
+pub enum Handedness {
+    /// X right, Y up and Z towards the viewer, the convention of rfw and glTF
+    Right = 0,
+    /// X right, Y up and Z away from the viewer, as used by Direct3D, Unity and Unreal
+    Left = 1,
+}
+
+impl Default for Handedness {
+    fn default() -> Self {
+        Self::Right
+    }
+}
+
+impl Handedness {
+    /// Mirrors coordinates of this handedness into the right-handed space backends render in.
+    pub fn to_right_handed(&self) -> Mat4 {
+        match self {
+            Handedness::Right => Mat4::IDENTITY,
+            Handedness::Left => Mat4::from_scale(Vec3::new(1.0, 1.0, -1.0)),
+        }
+    }
+
+    /// Faces to cull when rasterizing a mesh with `flags` in a scene of this handedness.
+    /// Mirroring a left-handed scene flips the winding of its triangles on screen, so
+    /// front and back are swapped.
+    pub fn raster_cull_mode(&self, flags: Mesh3dFlags) -> CullMode {
+        match (self, flags.raster_cull_mode()) {
+            (Handedness::Left, CullMode::Front) => CullMode::Back,
+            (Handedness::Left, CullMode::Back) => CullMode::Front,
+            (_, mode) => mode,
+        }
+    }
+}
+
+/// Shading model of an instance, see [crate::Backend::set_3d_instance_effect].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[repr(u32)]
+pub enum InstanceEffect {
+    /// Physically based shading of the materials of the instance
+    Standard = 0,
+    /// Cel shading, diffuse lighting quantized into a few hard bands
+    Toon = 1,
+    /// The albedo of the materials without any lighting, e.g. for UI in the world
+    Unlit = 2,
+    /// Shaded as usual, parts hidden behind other geometry show through as a silhouette
+    Xray = 3,
+}
+
+impl Default for InstanceEffect {
+    fn default() -> Self {
+        Self::Standard
+    }
+}
+
+impl InstanceEffect {
+    /// Effect id of this effect as the shaders of backends branch on it.
+    pub fn id(&self) -> u32 {
+        *self as u32
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MeshData3D<'a> {
     pub name: &'a str,
@@ -366,6 +705,62 @@ pub struct MeshData2D<'a> {
     pub tex_id: Option<usize>,
 }
 
+/// A single point sprite, drawn as a camera facing quad.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[repr(C)]
+pub struct Particle {
+    pub position: Vec3,
+    /// Width and height of the quad in world units
+    pub size: f32,
+    /// Linear color, alpha scales the contribution for both blend modes
+    pub color: Vec4,
+}
+
+/// How the particles of a system are combined with the rendered scene.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ParticleBlend {
+    /// Blended over the scene, particles are drawn in the order they are given
+    Alpha,
+    /// Added to the scene, suited for fire, sparks and other emissive effects
+    Additive,
+}
+
+impl Default for ParticleBlend {
+    fn default() -> Self {
+        ParticleBlend::Alpha
+    }
+}
+
+/// A box that projects a material onto the surfaces inside of it, e.g. for bullet holes or
+/// signs. The material is projected along the local Z axis of the box and covers its XY face.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Decal {
+    /// Transform from a unit cube centered at the origin to the box in world space
+    pub transform: Mat4,
+    /// Material providing the color, alpha and normal map of the decal
+    pub material: u32,
+    /// Multiplied into the alpha of the material
+    pub opacity: f32,
+    /// Overrides the automatic back to front order, decals are blended in ascending order
+    /// of their keys and only decals with equal keys are sorted by distance. Leaving every
+    /// key at 0 keeps the distance order.
+    pub sort_key: i32,
+}
+
+impl Default for Decal {
+    fn default() -> Self {
+        Self {
+            transform: Mat4::IDENTITY,
+            material: 0,
+            opacity: 1.0,
+            sort_key: 0,
+        }
+    }
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -374,7 +769,13 @@ pub struct DeviceMaterial {
     // 16
     pub absorption: [f32; 4],
     // 32
-    pub specular: [f32; 4],
+    /// Specular color of dielectrics, scaled by the KHR_materials_specular factor when an
+    /// index of refraction is set
+    pub specular: [f32; 3],
+    // 44
+    /// Index of refraction used to derive the reflectance of dielectrics,
+    /// 0 keeps the default reflectance of `specular * 0.08`
+    pub ior: f32,
     // 48
     pub parameters: [u32; 4], // 64
 
@@ -390,7 +791,214 @@ pub struct DeviceMaterial {
     // 84
     pub sheen_map: i32,
     // 88
-    pub _dummy: [i32; 2], // 96
+    pub lightmap_map: i32,
+    // 92
+    /// Height map used for parallax occlusion mapping, scaled by `parallax_scale`
+    pub height_map: i32, // 96
+
+    /// Columns, rows, frame count and frame rate of a [Flipbook], no columns disable it
+    pub flipbook: [f32; 4], // 112
+
+    /// Height map that offsets vertices along their normals
+    pub displacement_map: i32,
+    // 116
+    /// Object-space offset of a height of 1 in the displacement map
+    pub displacement_scale: f32,
+    // 120
+    /// Depth of the height field of `height_map` relative to its uv extent
+    pub parallax_scale: f32,
+    // 124
+    pub _padding: f32, // 128
+}
+
+/// Plays the textures of a material as an animated sequence. The frames are laid out in an
+/// atlas of `columns` by `rows` cells, left to right and top to bottom, of which the first
+/// `frames` are played at `fps` frames per second in a loop.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Flipbook {
+    pub columns: u32,
+    pub rows: u32,
+    pub frames: u32,
+    pub fps: f32,
+}
+
+impl Flipbook {
+    pub fn new(columns: u32, rows: u32, frames: u32, fps: f32) -> Self {
+        Self {
+            columns,
+            rows,
+            frames,
+            fps,
+        }
+    }
+
+    /// Layout of [DeviceMaterial::flipbook], the frame count is limited to the atlas cells
+    pub fn to_device(&self) -> [f32; 4] {
+        let columns = self.columns.max(1);
+        let rows = self.rows.max(1);
+        let frames = self.frames.min(columns * rows).max(1);
+        [columns as f32, rows as f32, frames as f32, self.fps]
+    }
+}
+
+/// Wind that sways the vertices of instances with a wind weight, e.g. foliage. Vertices are
+/// displaced by their object-space height above the origin of their mesh, so roots placed at
+/// the origin stay in place.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Wind {
+    /// World-space direction the wind blows towards
+    pub direction: Vec3,
+    /// Displacement per unit of height at the peak of a sway, 0 disables wind
+    pub strength: f32,
+    /// Sways per second
+    pub frequency: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::X,
+            strength: 0.0,
+            frequency: 1.0,
+        }
+    }
+}
+
+impl Wind {
+    pub fn new(direction: Vec3, strength: f32, frequency: f32) -> Self {
+        Self {
+            direction,
+            strength,
+            frequency,
+        }
+    }
+
+    /// Normalized direction scaled by the strength in xyz, frequency in w
+    pub fn to_device(&self) -> [f32; 4] {
+        let offset = self.direction.normalize_or_zero() * self.strength;
+        [offset.x, offset.y, offset.z, self.frequency]
+    }
+}
+
+/// Infinite reference grid on the Y=0 plane, see [crate::Backend::set_grid].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GridConfig {
+    /// World-space distance between two lines
+    pub spacing: f32,
+    /// Color of the lines, alpha blended over the scene
+    pub color: Vec4,
+    /// Distance from the camera at which the grid has faded out completely
+    pub fade_distance: f32,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            color: Vec4::new(0.5, 0.5, 0.5, 0.75),
+            fade_distance: 100.0,
+        }
+    }
+}
+
+impl GridConfig {
+    pub fn new(spacing: f32, color: Vec4, fade_distance: f32) -> Self {
+        Self {
+            spacing,
+            color,
+            fade_distance,
+        }
+    }
+}
+
+/// Closed volume of a [RevealRegion].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RevealShape {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// Axis aligned box between two corners
+    Box {
+        min: Vec3,
+        max: Vec3,
+    },
+}
+
+/// World-space volume that reveals the scene, see [crate::Backend::set_reveal_region].
+/// Surfaces outside of it are cut away or dimmed, e.g. for fog of war.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RevealRegion {
+    pub shape: RevealShape,
+    /// Brightness multiplier of the surfaces outside the region, `None` cuts them away
+    pub outside: Option<f32>,
+}
+
+impl RevealRegion {
+    pub fn sphere<T: Into<[f32; 3]>>(center: T, radius: f32) -> Self {
+        Self {
+            shape: RevealShape::Sphere {
+                center: Vec3::from(center.into()),
+                radius,
+            },
+            outside: None,
+        }
+    }
+
+    pub fn cuboid<T: Into<[f32; 3]>>(min: T, max: T) -> Self {
+        Self {
+            shape: RevealShape::Box {
+                min: Vec3::from(min.into()),
+                max: Vec3::from(max.into()),
+            },
+            outside: None,
+        }
+    }
+
+    /// Dims the surfaces outside the region by `brightness` instead of cutting them away.
+    pub fn with_dimming(mut self, brightness: f32) -> Self {
+        self.outside = Some(brightness.max(0.0));
+        self
+    }
+
+    /// Whether `point` lies inside the region, its boundary included.
+    pub fn contains(&self, point: Vec3) -> bool {
+        match self.shape {
+            RevealShape::Sphere { center, radius } => {
+                (point - center).length_squared() <= radius * radius
+            }
+            RevealShape::Box { min, max } => point.cmpge(min).all() && point.cmple(max).all(),
+        }
+    }
+
+    /// Layout used by the shaders of backends. x: shape, 0 without a region, 1 for spheres
+    /// and 2 for boxes, y: brightness outside the region or -1 to cut it away. The second and
+    /// third vectors hold the center and radius of spheres or the corners of boxes.
+    pub fn to_device(region: Option<&Self>) -> [[f32; 4]; 3] {
+        let region = match region {
+            Some(region) => region,
+            None => return [[0.0; 4]; 3],
+        };
+
+        let outside = region.outside.unwrap_or(-1.0);
+        match region.shape {
+            RevealShape::Sphere { center, radius } => [
+                [1.0, outside, 0.0, 0.0],
+                center.extend(radius).into(),
+                [0.0; 4],
+            ],
+            RevealShape::Box { min, max } => [
+                [2.0, outside, 0.0, 0.0],
+                min.extend(0.0).into(),
+                max.extend(0.0).into(),
+            ],
+        }
+    }
 }
 
 impl Default for DeviceMaterial {
@@ -398,7 +1006,8 @@ impl Default for DeviceMaterial {
         Self {
             color: [0.0; 4],
             absorption: [0.0; 4],
-            specular: [0.0; 4],
+            specular: [0.0; 3],
+            ior: 0.0,
             parameters: [0; 4],
             flags: 0,
             diffuse_map: -1,
@@ -406,7 +1015,13 @@ impl Default for DeviceMaterial {
             metallic_roughness_map: -1,
             emissive_map: -1,
             sheen_map: -1,
-            _dummy: [0; 2],
+            lightmap_map: -1,
+            height_map: -1,
+            flipbook: [0.0; 4],
+            displacement_map: -1,
+            displacement_scale: 0.0,
+            parallax_scale: 0.0,
+            _padding: 0.0,
         }
     }
 }
@@ -481,6 +1096,25 @@ pub struct CameraView2D {
     pub matrix: Mat4,
 }
 
+/// Rectangle of the window surface in pixels, with its origin at the top left.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Viewport {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+}
+
 #[derive(Default, Debug, Copy, Clone)]
 #[repr(C)]
 pub struct CameraView3D {
@@ -501,7 +1135,9 @@ pub struct CameraView3D {
     pub inv_width: f32,
     pub inv_height: f32,
     // 80
+    /// Distance to the near clipping plane in world units
     pub near_plane: f32,
+    /// Distance to the far clipping plane in world units
     pub far_plane: f32,
     pub aspect_ratio: f32,
     // FOV in radians
@@ -516,6 +1152,14 @@ pub struct CameraView3D {
 
 #[allow(dead_code)]
 impl CameraView3D {
+    /// Overrides the near and far clipping planes in world units, used by every projection
+    /// derived from this view.
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.near_plane = near.max(f32::EPSILON);
+        self.far_plane = far.max(self.near_plane * (1.0 + 1e-3));
+        self
+    }
+
     pub fn generate_lens_ray(&self, x: u32, y: u32, r0: f32, r1: f32, r2: f32, r3: f32) -> Ray {
         let blade = (r0 * 9.0).round();
         let r2 = (r2 - blade * (1.0 / 9.0)) * 9.0;
@@ -773,6 +1417,23 @@ impl CameraView3D {
 
         Mat4::look_at_lh(pos, pos + dir, up)
     }
+
+    /// View matrix of a camera placed in a scene of `handedness`. Left-handed scenes are
+    /// mirrored along Z, so they appear as they do in the tools they were authored in.
+    pub fn get_view_matrix(&self, handedness: Handedness) -> Mat4 {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        let mirror = handedness.to_right_handed();
+        let pos = mirror.transform_point3(self.pos);
+        let dir = mirror.transform_vector3(self.direction);
+
+        Mat4::look_at_rh(pos, pos + dir, up) * mirror
+    }
+
+    /// Projection and view matrix of a camera placed in a scene of `handedness`.
+    pub fn get_matrix(&self, handedness: Handedness) -> Mat4 {
+        self.get_rh_projection() * self.get_view_matrix(handedness)
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -826,6 +1487,11 @@ impl SkinnedTriangles3D {
         let mut triangles = triangles.to_vec();
 
         triangles.iter_mut().enumerate().for_each(|(i, t)| {
+            // Keeps the orientation of meshes whose geometric normals were flipped
+            let flipped = t
+                .normal
+                .dot(RTTriangle::normal(t.vertex0, t.vertex1, t.vertex2))
+                < 0.0;
             let i0 = i / 3;
             let i1 = i + 1;
             let i2 = i + 2;
@@ -870,6 +1536,9 @@ impl SkinnedTriangles3D {
                 .extend(t.tangent2[3]);
 
             t.normal = RTTriangle::normal(t.vertex0, t.vertex1, t.vertex2);
+            if flipped {
+                t.normal = -t.normal;
+            }
         });
 
         SkinnedTriangles3D { triangles }
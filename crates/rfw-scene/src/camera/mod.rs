@@ -20,7 +20,9 @@ pub struct Camera3D {
     pub aspect_ratio: f32,
     pub aperture: f32,
     pub focal_distance: f32,
+    /// Distance to the near clipping plane in world units
     pub near_plane: f32,
+    /// Distance to the far clipping plane in world units
     pub far_plane: f32,
     pub speed: f32,
 }
@@ -140,6 +142,18 @@ impl Camera3D {
         self
     }
 
+    /// Sets the near and far clipping planes in world units. The near plane is kept above zero
+    /// and the far plane beyond the near plane, as the projection is undefined otherwise.
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.near_plane = near.max(f32::EPSILON);
+        self.far_plane = far.max(self.near_plane * (1.0 + 1e-3));
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.set_clip_planes(near, far);
+        self
+    }
+
     pub fn get_transform(&mut self) -> Transform<Self> {
         Transform {
             translation: self.pos.into(),
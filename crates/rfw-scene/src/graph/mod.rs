@@ -341,6 +341,9 @@ pub struct NodeGraph {
     pub animations: TrackedStorage<Animation>,
     pub skins: TrackedStorage<Skin>,
     pub active_animation: Option<usize>,
+    /// Skins of instances with an animation offset, keyed by mesh and instance id
+    instance_skins: HashMap<(u32, u32), u32>,
+    time: f32,
 }
 
 impl ToScene for LoadResult {
@@ -411,6 +414,8 @@ impl Default for NodeGraph {
             animations: TrackedStorage::new(),
             skins: TrackedStorage::new(),
             active_animation: None,
+            instance_skins: HashMap::new(),
+            time: 0.0,
         }
     }
 }
@@ -495,6 +500,7 @@ impl NodeGraph {
             instances,
             skins,
         );
+        self.update_instance_skins(instances, skins);
 
         for i in self.nodes[id].child_nodes.iter() {
             if self.nodes.get_changed(*i as usize) {
@@ -616,7 +622,10 @@ impl NodeGraph {
                             if let Some(mut instance) =
                                 instances.get(m.instance_id.unwrap() as usize)
                             {
-                                instance.set_skin(SkinID(skin as i32));
+                                // Instances with an offset are given a skin of their own
+                                if instance.get_animation_offset() == 0.0 {
+                                    instance.set_skin(SkinID(skin as i32));
+                                }
                             }
                         }
                     }
@@ -629,11 +638,113 @@ impl NodeGraph {
         changed
     }
 
+    /// Evaluates the animation at the time of the graph plus the offset of every skinned instance
+    /// that has one, and writes the resulting joint matrices to a skin owned by that instance.
+    /// Instances without an offset keep sharing the skin of their node.
+    fn update_instance_skins(
+        &mut self,
+        instances: &RwLock<&mut FlaggedStorage<InstanceList3D>>,
+        skins: &RwLock<&mut TrackedStorage<Skin>>,
+    ) {
+        let animation = match self.active_animation {
+            Some(animation) => animation,
+            None => return,
+        };
+
+        let instances = instances.read().unwrap();
+        let mut skins = skins.write().unwrap();
+
+        // Group instances by offset, so the nodes only get evaluated once per offset
+        let mut offsets: HashMap<u32, Vec<(usize, u32, u32)>> = HashMap::new();
+        for (node_id, node) in self.nodes.iter() {
+            let node_skin = match node.skin {
+                Some(skin) => skin,
+                None => continue,
+            };
+
+            for mesh in node.meshes.iter() {
+                let instance_id = match mesh.instance_id {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let mut instance = match instances
+                    .get(mesh.object_id as usize)
+                    .and_then(|list| list.get(instance_id as usize))
+                {
+                    Some(instance) => instance,
+                    None => continue,
+                };
+
+                let offset = instance.get_animation_offset();
+                if offset != 0.0 {
+                    offsets.entry(offset.to_bits()).or_default().push((
+                        node_id,
+                        mesh.object_id,
+                        instance_id,
+                    ));
+                } else if let Some(skin) =
+                    self.instance_skins.remove(&(mesh.object_id, instance_id))
+                {
+                    skins.erase(skin as usize).unwrap();
+                    instance.set_skin(SkinID(node_skin as i32));
+                }
+            }
+        }
+
+        for (offset, offset_instances) in offsets {
+            let mut nodes = self.nodes.clone();
+            self.animations[animation]
+                .set_time(self.time + f32::from_bits(offset), nodes.as_mut_slice());
+            Self::combine_matrices(self.root_node as usize, Mat4::IDENTITY, &mut nodes);
+
+            for (node_id, object_id, instance_id) in offset_instances {
+                let node_skin = nodes[node_id].skin.unwrap() as usize;
+                let skin_id = *self
+                    .instance_skins
+                    .entry((object_id, instance_id))
+                    .or_insert_with(|| {
+                        let skin = skins[node_skin].clone();
+                        skins.push(skin) as u32
+                    }) as usize;
+
+                let inverse_transform = nodes[node_id].combined_matrix.inverse();
+                let Skin {
+                    joint_nodes,
+                    inverse_bind_matrices,
+                    joint_matrices,
+                    ..
+                } = &mut skins[skin_id];
+                joint_nodes.iter().enumerate().for_each(|(i, joint_id)| {
+                    joint_matrices[i] = inverse_transform
+                        * nodes[*joint_id as usize].combined_matrix
+                        * inverse_bind_matrices[i];
+                });
+
+                if let Some(mut instance) = instances[object_id as usize].get(instance_id as usize)
+                {
+                    instance.set_skin(SkinID(skin_id as i32));
+                }
+            }
+        }
+    }
+
+    fn combine_matrices(index: usize, accumulated_matrix: Mat4, nodes: &mut TrackedStorage<Node>) {
+        nodes[index].update_matrix();
+        let combined_matrix = accumulated_matrix * nodes[index].local_matrix;
+        nodes[index].combined_matrix = combined_matrix;
+
+        for i in 0..nodes[index].child_nodes.len() {
+            let child_id = nodes[index].child_nodes[i] as usize;
+            Self::combine_matrices(child_id, combined_matrix, nodes);
+        }
+    }
+
     pub fn root_node(&self) -> usize {
         self.root_node as usize
     }
 
     pub fn update_animation(&mut self, time: f32) {
+        self.time = time;
         if let Some(animation) = self.active_animation {
             self.animations[animation].set_time(time, self.nodes.as_mut_slice());
             self.nodes.trigger_changed(self.root_node as usize); // Trigger a change
@@ -951,6 +1062,10 @@ impl SceneGraph {
         // Remove instances part of this sub graph
         if let Some(graph) = self.sub_graphs.get(id) {
             if let Ok(graph) = graph.lock() {
+                for skin_id in graph.instance_skins.values() {
+                    skins.erase(*skin_id as usize).unwrap();
+                }
+
                 for (_, node) in graph.nodes.iter() {
                     if let Some(skin_id) = node.skin {
                         skins.erase(skin_id as usize).unwrap();
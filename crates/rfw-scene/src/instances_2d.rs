@@ -173,6 +173,14 @@ impl InstanceList2D {
         }
     }
 
+    /// Marks all instances as changed.
+    pub fn set_changed(&mut self) {
+        let list = unsafe { (*self.list.get()).flags.as_mut_slice() };
+        for v in list.iter_mut() {
+            *v = InstanceFlags2D::all();
+        }
+    }
+
     pub fn take_removed(&mut self) -> Vec<usize> {
         let list = unsafe { self.list.get().as_mut().unwrap() };
         let mut vec = Vec::new();
@@ -69,6 +69,11 @@ impl InstanceList3D {
         list.ptr.store(id + 1, Ordering::Release);
         list.flags[id] = InstanceFlags3D::all();
         list.matrices[id] = Mat4::IDENTITY;
+        list.user_data[id] = 0;
+        list.tints[id] = Vec4::ONE;
+        list.flipbook_times[id] = 0.0;
+        list.wind_weights[id] = 0.0;
+        list.animation_offsets[id] = 0.0;
 
         InstanceHandle3D {
             index: id,
@@ -81,6 +86,11 @@ impl InstanceList3D {
         list.matrices[handle.index] = Mat4::ZERO;
         list.skin_ids[handle.index] = SkinID::INVALID;
         list.flags[handle.index] = InstanceFlags3D::all();
+        list.user_data[handle.index] = 0;
+        list.tints[handle.index] = Vec4::ONE;
+        list.flipbook_times[handle.index] = 0.0;
+        list.wind_weights[handle.index] = 0.0;
+        list.animation_offsets[handle.index] = 0.0;
         list.free_slots.push(handle.index);
         list.removed.push(handle.index);
     }
@@ -90,6 +100,11 @@ impl InstanceList3D {
         list.matrices.resize(new_size, Mat4::IDENTITY);
         list.skin_ids.resize(new_size, SkinID::INVALID);
         list.flags.resize(new_size, InstanceFlags3D::empty());
+        list.user_data.resize(new_size, 0);
+        list.tints.resize(new_size, Vec4::ONE);
+        list.flipbook_times.resize(new_size, 0.0);
+        list.wind_weights.resize(new_size, 0.0);
+        list.animation_offsets.resize(new_size, 0.0);
     }
 
     pub fn get(&self, index: usize) -> Option<InstanceHandle3D> {
@@ -119,6 +134,31 @@ impl InstanceList3D {
         unsafe { &(*list).flags[0..(*list).len()] }
     }
 
+    pub fn user_data(&self) -> &[u64] {
+        let list = self.list.get();
+        unsafe { &(*list).user_data[0..(*list).len()] }
+    }
+
+    pub fn tints(&self) -> &[Vec4] {
+        let list = self.list.get();
+        unsafe { &(*list).tints[0..(*list).len()] }
+    }
+
+    pub fn flipbook_times(&self) -> &[f32] {
+        let list = self.list.get();
+        unsafe { &(*list).flipbook_times[0..(*list).len()] }
+    }
+
+    pub fn wind_weights(&self) -> &[f32] {
+        let list = self.list.get();
+        unsafe { &(*list).wind_weights[0..(*list).len()] }
+    }
+
+    pub fn animation_offsets(&self) -> &[f32] {
+        let list = self.list.get();
+        unsafe { &(*list).animation_offsets[0..(*list).len()] }
+    }
+
     pub fn set_all_flags(&mut self, flag: InstanceFlags3D) {
         let list = self.list.get();
         let flags = unsafe { &mut (*list).flags[0..(*list).len()] };
@@ -156,6 +196,14 @@ impl InstanceList3D {
         }
     }
 
+    /// Marks all instances as changed.
+    pub fn set_changed(&mut self) {
+        let list = unsafe { (*self.list.get()).flags.as_mut_slice() };
+        for v in list.iter_mut() {
+            *v = InstanceFlags3D::all();
+        }
+    }
+
     pub fn take_removed(&mut self) -> Vec<usize> {
         let list = unsafe { self.list.get().as_mut().unwrap() };
         let mut vec = Vec::new();
@@ -170,6 +218,11 @@ pub struct List3D {
     matrices: Vec<Mat4>,
     skin_ids: Vec<SkinID>,
     flags: Vec<InstanceFlags3D>,
+    user_data: Vec<u64>,
+    tints: Vec<Vec4>,
+    flipbook_times: Vec<f32>,
+    wind_weights: Vec<f32>,
+    animation_offsets: Vec<f32>,
 
     ptr: AtomicUsize,
     free_slots: Vec<usize>,
@@ -183,6 +236,11 @@ impl Clone for List3D {
             matrices: self.matrices.clone(),
             skin_ids: self.skin_ids.clone(),
             flags: self.flags.clone(),
+            user_data: self.user_data.clone(),
+            tints: self.tints.clone(),
+            flipbook_times: self.flipbook_times.clone(),
+            wind_weights: self.wind_weights.clone(),
+            animation_offsets: self.animation_offsets.clone(),
 
             ptr,
             free_slots: self.free_slots.clone(),
@@ -251,7 +309,10 @@ impl InstanceHandle3D {
     #[inline]
     pub fn set_skin(&mut self, skin: SkinID) {
         let list = unsafe { self.ptr.get().as_mut().unwrap() };
-        list.skin_ids[self.index] = skin;
+        if list.skin_ids[self.index] != skin {
+            list.skin_ids[self.index] = skin;
+            list.flags[self.index] |= InstanceFlags3D::TRANSFORMED;
+        }
     }
 
     #[inline]
@@ -279,6 +340,76 @@ impl InstanceHandle3D {
         }
     }
 
+    /// Attaches an application defined value to this instance, e.g. an entity id,
+    /// which is returned by backend queries such as raycasts.
+    #[inline]
+    pub fn set_user_data(&mut self, data: u64) {
+        let list = unsafe { self.ptr.get().as_mut().unwrap() };
+        list.user_data[self.index] = data;
+        list.flags[self.index] |= InstanceFlags3D::TRANSFORMED;
+    }
+
+    #[inline]
+    pub fn get_user_data(&self) -> u64 {
+        unsafe { (*self.ptr.get()).user_data[self.index] }
+    }
+
+    /// Multiplies the albedo of this instance by `tint`, e.g. for team colors or damage
+    /// flashes, without duplicating its materials. Instances are white by default.
+    #[inline]
+    pub fn set_tint(&mut self, tint: Vec4) {
+        let list = unsafe { self.ptr.get().as_mut().unwrap() };
+        list.tints[self.index] = tint;
+        list.flags[self.index] |= InstanceFlags3D::TRANSFORMED;
+    }
+
+    #[inline]
+    pub fn get_tint(&self) -> Vec4 {
+        unsafe { (*self.ptr.get()).tints[self.index] }
+    }
+
+    /// Offsets the flipbook playback of this instance by `time` seconds, so instances sharing
+    /// an animated material do not play in lockstep.
+    #[inline]
+    pub fn set_flipbook_time(&mut self, time: f32) {
+        let list = unsafe { self.ptr.get().as_mut().unwrap() };
+        list.flipbook_times[self.index] = time;
+        list.flags[self.index] |= InstanceFlags3D::TRANSFORMED;
+    }
+
+    #[inline]
+    pub fn get_flipbook_time(&self) -> f32 {
+        unsafe { (*self.ptr.get()).flipbook_times[self.index] }
+    }
+
+    /// Sets how strongly this instance is swayed by the wind of the backend, e.g. 1 for foliage.
+    /// Instances have a weight of 0 by default and are not affected by wind.
+    #[inline]
+    pub fn set_wind_weight(&mut self, weight: f32) {
+        let list = unsafe { self.ptr.get().as_mut().unwrap() };
+        list.wind_weights[self.index] = weight;
+        list.flags[self.index] |= InstanceFlags3D::TRANSFORMED;
+    }
+
+    #[inline]
+    pub fn get_wind_weight(&self) -> f32 {
+        unsafe { (*self.ptr.get()).wind_weights[self.index] }
+    }
+
+    /// Offsets the skeletal animation of this instance by `offset` seconds, so instances of the
+    /// same graph do not animate in lockstep. Skinned instances with an offset get joint matrices
+    /// of their own when the graph is synchronized.
+    #[inline]
+    pub fn set_animation_offset(&mut self, offset: f32) {
+        let list = unsafe { self.ptr.get().as_mut().unwrap() };
+        list.animation_offsets[self.index] = offset;
+    }
+
+    #[inline]
+    pub fn get_animation_offset(&self) -> f32 {
+        unsafe { (*self.ptr.get()).animation_offsets[self.index] }
+    }
+
     #[inline]
     pub fn get_skin_id(&self) -> SkinID {
         unsafe { (*self.ptr.get()).skin_ids[self.index] }
@@ -305,6 +436,11 @@ impl InstanceHandle3D {
         list.matrices[self.index] = Mat4::ZERO;
         list.skin_ids[self.index] = SkinID::INVALID;
         list.flags[self.index] = InstanceFlags3D::all();
+        list.user_data[self.index] = 0;
+        list.tints[self.index] = Vec4::ONE;
+        list.flipbook_times[self.index] = 0.0;
+        list.wind_weights[self.index] = 0.0;
+        list.animation_offsets[self.index] = 0.0;
         list.free_slots.push(self.index);
         list.removed.push(self.index);
     }
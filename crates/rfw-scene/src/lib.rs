@@ -47,6 +47,18 @@ use std::{
 pub struct PointLightRef(usize);
 pub struct SpotLightRef(usize);
 pub struct DirectionalLightRef(usize);
+pub struct AreaLightRef(usize);
+
+/// Everything [Scene::load_gltf] added to the scene.
+pub struct GltfScene {
+    pub graph: GraphHandle,
+    pub meshes: Vec<MeshId3D>,
+    pub materials: Vec<usize>,
+    pub textures: Vec<usize>,
+    pub point_lights: Vec<PointLightRef>,
+    pub spot_lights: Vec<SpotLightRef>,
+    pub directional_lights: Vec<DirectionalLightRef>,
+}
 
 #[derive(Debug, Clone)]
 pub enum SceneError {
@@ -299,6 +311,72 @@ impl Scene {
         Err(SceneError::NoFileLoader(extension))
     }
 
+    /// Loads a glTF or GLB file and adds its node hierarchy as a new graph. Meshes, skins,
+    /// materials, textures, animations and KHR_lights_punctual lights of the file are added to
+    /// the scene, instances receive the world transforms of their nodes when the graph gets
+    /// synchronized. Lights are placed at the transforms of their nodes at load time.
+    pub fn load_gltf<S: AsRef<Path>>(&mut self, path: S) -> Result<GltfScene, SceneError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gltf") | Some("glb") => {}
+            Some(extension) => return Err(SceneError::NoFileLoader(extension.to_string())),
+            None => return Err(SceneError::NoFileExtension),
+        }
+
+        let lights = loaders::gltf::load_lights(path)?;
+        let (first_material, first_texture) = (self.materials.len(), self.materials.len_textures());
+        let result = self.load(path)?;
+        let meshes = match &result {
+            LoadResult::Object(mesh) => vec![*mesh],
+            LoadResult::Scene(scene) => scene.meshes.clone(),
+        };
+
+        let mut scene = GltfScene {
+            graph: self.add_3d(&result),
+            meshes,
+            materials: (first_material..self.materials.len()).collect(),
+            textures: (first_texture..self.materials.len_textures()).collect(),
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+            directional_lights: Vec::new(),
+        };
+
+        for light in lights {
+            match light {
+                loaders::gltf::GltfLight::Point { position, radiance } => {
+                    scene
+                        .point_lights
+                        .push(self.add_point_light(position, radiance));
+                }
+                loaders::gltf::GltfLight::Spot {
+                    position,
+                    direction,
+                    radiance,
+                    inner_angle,
+                    outer_angle,
+                } => {
+                    scene.spot_lights.push(self.add_spot_light(
+                        position,
+                        direction,
+                        radiance,
+                        inner_angle,
+                        outer_angle,
+                    ));
+                }
+                loaders::gltf::GltfLight::Directional {
+                    direction,
+                    radiance,
+                } => {
+                    scene
+                        .directional_lights
+                        .push(self.add_directional_light(direction, radiance));
+                }
+            }
+        }
+
+        Ok(scene)
+    }
+
     pub fn add_3d<T: ToScene>(&mut self, scene: &T) -> GraphHandle {
         let scene = scene.to_scene(&mut self.meshes_3d, &mut self.instances_3d, &mut self.skins);
 
@@ -555,6 +633,20 @@ impl Scene {
         )
     }
 
+    /// Adds a parametric area light such as [AreaLight::rectangle] or [AreaLight::disk].
+    /// These lights are kept in front of the lights generated from emissive triangles.
+    pub fn add_area_light(&mut self, light: AreaLight) -> AreaLightRef {
+        let id = self
+            .lights
+            .area_lights
+            .iter()
+            .filter(|(_, l)| l.shape() != AreaLightShape::Triangle)
+            .count();
+        self.lights.area_lights.push(light);
+        self.update_lights();
+        AreaLightRef(id)
+    }
+
     pub fn reset_changed(&mut self) {
         self.lights.point_lights.reset_changed();
         self.lights.spot_lights.reset_changed();
@@ -572,15 +664,40 @@ impl Scene {
             .for_each(|(_, i)| i.reset_changed());
     }
 
+    /// Marks the complete scene as changed, so it gets uploaded again, e.g. to a new backend.
+    pub fn set_changed(&mut self) {
+        self.lights.point_lights.trigger_changed_all();
+        self.lights.spot_lights.trigger_changed_all();
+        self.lights.area_lights.trigger_changed_all();
+        self.lights.directional_lights.trigger_changed_all();
+        self.materials.set_changed();
+        self.skins.trigger_changed_all();
+        self.meshes_2d.trigger_changed_all();
+        self.meshes_3d.trigger_changed_all();
+        self.instances_2d
+            .iter_mut()
+            .for_each(|(_, i)| i.set_changed());
+        self.instances_3d
+            .iter_mut()
+            .for_each(|(_, i)| i.set_changed());
+    }
+
     pub fn update_lights(&mut self) {
+        // Parametric lights are not generated from geometry, keep them as they are
+        let mut area_lights: Vec<AreaLight> = self
+            .lights
+            .area_lights
+            .iter()
+            .map(|(_, l)| *l)
+            .filter(|l| l.shape() != AreaLightShape::Triangle)
+            .collect();
+
         let light_flags = self.materials.light_flags();
         if light_flags.not_any() {
-            self.lights.area_lights = TrackedStorage::new();
+            self.lights.area_lights = TrackedStorage::from(area_lights);
             return;
         }
 
-        let mut area_lights: Vec<AreaLight> = Vec::new();
-
         let mut triangle_light_ids: Vec<(u32, u32, u32)> = Vec::new();
         let meshes = &self.meshes_3d;
         let instances = &self.instances_3d;
@@ -609,7 +726,10 @@ impl Scene {
                             let vertex1: Vec3 = (transform * v1.vertex).truncate();
                             let vertex2: Vec3 = (transform * v2.vertex).truncate();
 
-                            let normal = RTTriangle::normal(vertex0, vertex1, vertex2);
+                            let mut normal = RTTriangle::normal(vertex0, vertex1, vertex2);
+                            if m.flags.front_face() == FrontFace::Cw {
+                                normal = -normal;
+                            }
                             let position = (vertex0 + vertex1 + vertex2) * (1.0 / 3.0);
                             let color = self.materials[v.mat_id as usize].color;
 
@@ -11,12 +11,40 @@ pub struct LightInfo {
     pub pm: Mat4,
     pub pos: Vec3,
     pub range: f32,
-    // 80
-    _padding0: [Vec4; 3],
+    /// Shadow bias of this light: x = depth bias, y = normal bias, z = slope-scaled bias
+    pub bias: Vec4,
+    // 96
+    /// x: size of the light source in shadow map space, which scales the penumbrae of soft
+    /// shadows. 0 casts hard shadows.
+    pub softness: Vec4,
+    _padding0: Vec4,
     _padding1: Mat4,
     _padding2: Mat4,
 }
 
+impl LightInfo {
+    /// Snaps the orthographic shadow projection to whole texels of a `resolution` wide shadow
+    /// map, so the shadow edges of static geometry do not crawl when the shadow frustum moves.
+    /// Does not apply to perspective projections.
+    pub fn stabilize(&mut self, resolution: u32) {
+        let half = resolution as f32 * 0.5;
+        // Shadow map coordinate of the world origin in texels
+        let origin = self.pm.transform_point3(Vec3::ZERO).truncate() * half;
+        let offset = (origin.round() - origin) / half;
+        self.pm = Mat4::from_translation(offset.extend(0.0)) * self.pm;
+    }
+}
+
+/// Width of a light source of `size` in shadow map space of a perspective projection with field
+/// of view `fov` and far plane `far`. Penumbrae in shadow map space are this softness times
+/// `(receiver - blocker) / (blocker * receiver)` of their linear depths in 0..1.
+fn perspective_softness(size: f32, fov: f32, far: f32) -> f32 {
+    if size <= 0.0 || far <= 0.0 {
+        return 0.0;
+    }
+    size / (2.0 * (fov * 0.5).tan() * far)
+}
+
 pub trait Light {
     fn set_radiance(&mut self, radiance: Vec3);
     fn get_matrix(&self, scene_bounds: &Aabb) -> Mat4;
@@ -32,7 +60,9 @@ impl Default for LightInfo {
             pm: Mat4::IDENTITY,
             pos: Vec3::ZERO,
             range: 0.0,
-            _padding0: [Vec4::ZERO; 3],
+            bias: Vec4::ZERO,
+            softness: Vec4::ZERO,
+            _padding0: Vec4::ZERO,
             _padding1: Mat4::IDENTITY,
             _padding2: Mat4::IDENTITY,
         }
@@ -60,9 +90,11 @@ pub struct CubeLightInfo {
 
 impl Light for AreaLight {
     fn set_radiance(&mut self, radiance: Vec3) {
+        let negative = self.is_negative();
         let radiance = radiance.abs();
         self.radiance = radiance;
         self.energy = radiance.length();
+        self.set_negative(negative);
     }
 
     fn get_matrix(&self, _: &Aabb) -> Mat4 {
@@ -87,6 +119,16 @@ impl Light for AreaLight {
             pm: self.get_matrix(scene_bounds),
             pos: self.position,
             range: self.energy * self.area,
+            softness: Vec4::new(
+                perspective_softness(
+                    self.area.sqrt(),
+                    150.0_f32.to_radians(),
+                    self.energy * self.area,
+                ),
+                0.0,
+                0.0,
+                0.0,
+            ),
             ..LightInfo::default()
         }
     }
@@ -128,9 +170,11 @@ impl Light for AreaLight {
 
 impl Light for SpotLight {
     fn set_radiance(&mut self, radiance: Vec3) {
+        let negative = self.is_negative();
         let radiance = radiance.abs();
         self.radiance = radiance;
         self.energy = radiance.length();
+        self.set_negative(negative);
     }
 
     fn get_matrix(&self, _: &Aabb) -> Mat4 {
@@ -154,6 +198,16 @@ impl Light for SpotLight {
             pm: self.get_matrix(scene_bounds),
             pos: self.position,
             range: self.energy * 2.0,
+            softness: Vec4::new(
+                perspective_softness(
+                    2.0 * self.radius,
+                    self.cos_outer.acos() * 2.0,
+                    self.energy * 2.0,
+                ),
+                0.0,
+                0.0,
+                0.0,
+            ),
             ..LightInfo::default()
         }
     }
@@ -198,9 +252,11 @@ impl Light for SpotLight {
 
 impl Light for DirectionalLight {
     fn set_radiance(&mut self, radiance: Vec3) {
+        let negative = self.is_negative();
         let radiance = radiance.abs();
         self.radiance = radiance;
         self.energy = radiance.length();
+        self.set_negative(negative);
     }
 
     fn get_matrix(&self, scene_bounds: &Aabb) -> Mat4 {
@@ -235,6 +291,9 @@ impl Light for DirectionalLight {
             pm: self.get_matrix(scene_bounds),
             pos: center,
             range: l,
+            // The orthographic projection is as wide as it is deep, so the penumbra grows by the
+            // tangent of half the angular diameter per unit of depth in shadow map space
+            softness: Vec4::new((self.angular_diameter * 0.5).tan(), 0.0, 0.0, 0.0),
             ..LightInfo::default()
         }
     }
@@ -285,4 +344,105 @@ mod tests {
     fn is_aligned() {
         assert!(std::mem::size_of::<LightInfo>() == 256);
     }
+
+    #[test]
+    fn stabilized_shadows_keep_texel_grid() {
+        use crate::Light;
+        use rfw_backend::DirectionalLight;
+        use rfw_math::*;
+        use rtbvh::Aabb;
+
+        let light = DirectionalLight::new(Vec3::new(0.3, -1.0, 0.2), Vec3::ONE);
+        let bounds = |offset: f32| {
+            let mut aabb = Aabb::new();
+            aabb.grow(Vec3::splat(-4.0 + offset));
+            aabb.grow(Vec3::splat(4.0 + offset));
+            aabb
+        };
+
+        // Texel coordinate of a static point in a 1024 wide shadow map
+        let point = Vec3::new(1.3, 0.2, -0.7);
+        let texel = |offset: f32| {
+            let mut info = light.get_light_info(&bounds(offset));
+            info.stabilize(1024);
+            info.pm.transform_point3(point).truncate() * 512.0
+        };
+
+        // Moving the fitted frustum by a fraction of a texel only moves the point by whole texels
+        let moved = texel(0.0) - texel(0.0013);
+        assert!((moved - moved.round()).length() < 1e-2);
+    }
+
+    #[test]
+    fn light_size_scales_softness() {
+        use crate::Light;
+        use rfw_backend::{DirectionalLight, SpotLight};
+        use rfw_math::*;
+        use rtbvh::Aabb;
+
+        assert_eq!(std::mem::size_of::<SpotLight>(), 64);
+
+        let bounds = Aabb::new();
+        let spot = SpotLight::new(Vec3::ZERO, -Vec3::Y, 30.0, 45.0, Vec3::ONE);
+        assert_eq!(spot.get_light_info(&bounds).softness.x, 0.0);
+        let small = spot.with_radius(0.1).get_light_info(&bounds).softness.x;
+        let large = spot.with_radius(0.4).get_light_info(&bounds).softness.x;
+        assert!(small > 0.0);
+        assert!((large - 4.0 * small).abs() < 1e-5);
+
+        let sun = DirectionalLight::new(-Vec3::Y, Vec3::ONE)
+            .with_angular_diameter(DirectionalLight::SUN_ANGULAR_DIAMETER);
+        assert!(sun.get_light_info(&bounds).softness.x > 0.0);
+    }
+
+    #[test]
+    fn shaped_area_lights() {
+        use rfw_backend::{AreaLight, AreaLightShape};
+        use rfw_math::*;
+
+        assert_eq!(std::mem::size_of::<AreaLight>(), 96);
+
+        let rect = AreaLight::rectangle(Vec3::ZERO, Vec3::X * 2.0, Vec3::Y, Vec3::ONE);
+        assert_eq!(rect.shape(), AreaLightShape::Rectangle);
+        assert!((rect.area - 8.0).abs() < 1e-5);
+        assert!((rect.normal - Vec3::Z).length() < 1e-5);
+
+        let disk = AreaLight::disk(Vec3::ZERO, Vec3::new(0.0, -2.0, 0.0), 0.5, Vec3::ONE);
+        assert_eq!(disk.shape(), AreaLightShape::Disk);
+        assert!((disk.area - std::f32::consts::PI * 0.25).abs() < 1e-5);
+        assert!(disk.vertex0.dot(disk.normal).abs() < 1e-5);
+        assert!(disk.vertex1.dot(disk.vertex0).abs() < 1e-5);
+        assert!((disk.vertex1.length() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn focused_area_lights_keep_power() {
+        use rfw_backend::AreaLight;
+        use rfw_math::*;
+
+        let light = AreaLight::rectangle(Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::ONE);
+        assert_eq!(light.focus_factor(0.5), 1.0);
+
+        // Integrates the focused cosine lobe over the hemisphere, which equals PI for
+        // lights emitting the same power as a Lambertian emitter
+        for focus in [1.0, 4.0, 16.0].iter() {
+            let light = light.with_focus(*focus);
+            let steps = 4096;
+            let power: f32 = (0..steps)
+                .map(|i| {
+                    let theta = (i as f32 + 0.5) / steps as f32 * std::f32::consts::FRAC_PI_2;
+                    let d_theta = std::f32::consts::FRAC_PI_2 / steps as f32;
+                    let cos = theta.cos();
+                    light.focus_factor(cos)
+                        * cos
+                        * theta.sin()
+                        * d_theta
+                        * 2.0
+                        * std::f32::consts::PI
+                })
+                .sum();
+            assert!((power - std::f32::consts::PI).abs() < 1e-2);
+            assert!(light.focus_factor(1.0) > 1.0);
+        }
+    }
 }
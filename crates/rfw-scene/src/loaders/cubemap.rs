@@ -0,0 +1,348 @@
+use rfw_backend::{DataFormat, SkyboxData, TextureData, TextureFilter};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub enum CubemapError {
+    Io(String),
+    UnknownExtension,
+    InvalidHeader,
+    /// File is a regular 2D texture or an incomplete cube map
+    NotACubemap,
+    UnsupportedFormat(u32),
+    /// KTX2 supercompression schemes are not supported
+    Supercompressed(u32),
+    UnexpectedEof,
+}
+
+impl std::fmt::Display for CubemapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CubemapError::Io(e) => write!(f, "Could not read cube map: {}", e),
+            CubemapError::UnknownExtension => write!(f, "Expected a .dds or .ktx2 file"),
+            CubemapError::InvalidHeader => write!(f, "Invalid file header"),
+            CubemapError::NotACubemap => write!(f, "File does not contain 6 cube faces"),
+            CubemapError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported pixel format {}", format)
+            }
+            CubemapError::Supercompressed(scheme) => {
+                write!(f, "Unsupported supercompression scheme {}", scheme)
+            }
+            CubemapError::UnexpectedEof => write!(f, "File ends before all faces were read"),
+        }
+    }
+}
+
+impl std::error::Error for CubemapError {}
+
+/// Cube map with its mip chains, loaded from a DDS or KTX2 container.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cubemap {
+    /// Width and height of a face
+    pub size: u32,
+    pub mip_levels: u32,
+    pub format: DataFormat,
+    /// Tightly packed mip chains of the faces in the order +X, -X, +Y, -Y, +Z, -Z
+    pub faces: [Vec<u8>; 6],
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, CubemapError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(CubemapError::UnexpectedEof)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, CubemapError> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(low | (high << 32))
+}
+
+impl Cubemap {
+    const KTX2_IDENTIFIER: [u8; 12] = [
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+
+    /// Loads a cube map, the container is determined by the file extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CubemapError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let bytes = std::fs::read(path).map_err(|e| CubemapError::Io(e.to_string()))?;
+
+        match extension.as_deref() {
+            Some("dds") => Self::from_dds(&bytes),
+            Some("ktx2") => Self::from_ktx2(&bytes),
+            _ => Err(CubemapError::UnknownExtension),
+        }
+    }
+
+    /// Size in bytes of the mip chain of a single face.
+    fn face_size(&self) -> usize {
+        self.face_data(&[])
+            .byte_offset_for_level(self.mip_levels as usize)
+    }
+
+    fn face_data<'a>(&self, bytes: &'a [u8]) -> TextureData<'a> {
+        TextureData {
+            width: self.size,
+            height: self.size,
+            mip_levels: self.mip_levels,
+            bytes,
+            format: self.format,
+            filter: TextureFilter::Linear,
+            premultiplied: false,
+        }
+    }
+
+    /// Skybox that can be passed to a backend directly.
+    pub fn as_skybox(&self) -> SkyboxData<'_> {
+        SkyboxData::Cube([
+            self.face_data(&self.faces[0]),
+            self.face_data(&self.faces[1]),
+            self.face_data(&self.faces[2]),
+            self.face_data(&self.faces[3]),
+            self.face_data(&self.faces[4]),
+            self.face_data(&self.faces[5]),
+        ])
+    }
+
+    /// Parses a DDS file with the cube map flags set, faces store their full mip chains
+    /// one after another.
+    pub fn from_dds(bytes: &[u8]) -> Result<Self, CubemapError> {
+        const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+        const DDPF_FOURCC: u32 = 0x4;
+        const DDPF_RGB: u32 = 0x40;
+        const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0x200 | 0xFC00;
+        const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+        if bytes.get(0..4) != Some(&b"DDS "[..]) || read_u32(bytes, 4)? != 124 {
+            return Err(CubemapError::InvalidHeader);
+        }
+
+        let flags = read_u32(bytes, 8)?;
+        let height = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 16)?;
+        let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 {
+            read_u32(bytes, 28)?.max(1)
+        } else {
+            1
+        };
+
+        let pf_flags = read_u32(bytes, 80)?;
+        let four_cc = bytes.get(84..88).ok_or(CubemapError::UnexpectedEof)?;
+        let caps2 = read_u32(bytes, 112)?;
+        let mut data_offset = 128;
+        let mut is_cube = caps2 & DDSCAPS2_CUBEMAP_ALL_FACES == DDSCAPS2_CUBEMAP_ALL_FACES;
+
+        let format = if pf_flags & DDPF_FOURCC != 0 {
+            match four_cc {
+                b"DXT1" => DataFormat::BC1,
+                b"DXT5" => DataFormat::BC3,
+                // D3DFMT_A16B16G16R16F
+                [113, 0, 0, 0] => DataFormat::RGBA16F,
+                b"DX10" => {
+                    let dxgi_format = read_u32(bytes, 128)?;
+                    let misc_flags = read_u32(bytes, 136)?;
+                    let array_size = read_u32(bytes, 140)?;
+                    is_cube = misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0 && array_size == 1;
+                    data_offset += 20;
+
+                    match dxgi_format {
+                        10 => DataFormat::RGBA16F,
+                        28 | 29 => DataFormat::RGBA8,
+                        71 | 72 => DataFormat::BC1,
+                        77 | 78 => DataFormat::BC3,
+                        87 | 91 => DataFormat::BGRA8,
+                        98 | 99 => DataFormat::BC7,
+                        f => return Err(CubemapError::UnsupportedFormat(f)),
+                    }
+                }
+                _ => return Err(CubemapError::UnsupportedFormat(read_u32(bytes, 84)?)),
+            }
+        } else if pf_flags & DDPF_RGB != 0 && read_u32(bytes, 88)? == 32 {
+            match read_u32(bytes, 92)? {
+                0xFF => DataFormat::RGBA8,
+                0xFF0000 => DataFormat::BGRA8,
+                mask => return Err(CubemapError::UnsupportedFormat(mask)),
+            }
+        } else {
+            return Err(CubemapError::UnsupportedFormat(pf_flags));
+        };
+
+        if !is_cube || width != height {
+            return Err(CubemapError::NotACubemap);
+        }
+
+        let mut cubemap = Self {
+            size: width,
+            mip_levels,
+            format,
+            faces: Default::default(),
+        };
+
+        let face_size = cubemap.face_size();
+        for (i, face) in cubemap.faces.iter_mut().enumerate() {
+            let start = data_offset + i * face_size;
+            *face = bytes
+                .get(start..start + face_size)
+                .ok_or(CubemapError::UnexpectedEof)?
+                .to_vec();
+        }
+
+        Ok(cubemap)
+    }
+
+    /// Parses a KTX2 file with 6 faces, every mip level stores the faces one after another.
+    pub fn from_ktx2(bytes: &[u8]) -> Result<Self, CubemapError> {
+        if bytes.get(0..12) != Some(&Self::KTX2_IDENTIFIER[..]) {
+            return Err(CubemapError::InvalidHeader);
+        }
+
+        let vk_format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        let depth = read_u32(bytes, 28)?;
+        let layers = read_u32(bytes, 32)?;
+        let face_count = read_u32(bytes, 36)?;
+        let mip_levels = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+
+        if supercompression != 0 {
+            return Err(CubemapError::Supercompressed(supercompression));
+        }
+
+        if face_count != 6 || layers > 1 || depth > 1 || width != height {
+            return Err(CubemapError::NotACubemap);
+        }
+
+        let format = match vk_format {
+            37 | 43 => DataFormat::RGBA8,
+            44 | 50 => DataFormat::BGRA8,
+            97 => DataFormat::RGBA16F,
+            131..=134 => DataFormat::BC1,
+            137 | 138 => DataFormat::BC3,
+            145 | 146 => DataFormat::BC7,
+            f => return Err(CubemapError::UnsupportedFormat(f)),
+        };
+
+        let mut cubemap = Self {
+            size: width,
+            mip_levels,
+            format,
+            faces: Default::default(),
+        };
+
+        let face_size = cubemap.face_size();
+        cubemap
+            .faces
+            .iter_mut()
+            .for_each(|f| f.reserve_exact(face_size));
+
+        // The level index follows the 80 byte header
+        for level in 0..mip_levels as usize {
+            let level_offset = read_u64(bytes, 80 + level * 24)? as usize;
+            let data = cubemap.face_data(&[]);
+            let image_size = data.bytes_per_row(level) * data.rows(level);
+
+            for (i, face) in cubemap.faces.iter_mut().enumerate() {
+                let start = level_offset + i * image_size;
+                face.extend_from_slice(
+                    bytes
+                        .get(start..start + image_size)
+                        .ok_or(CubemapError::UnexpectedEof)?,
+                );
+            }
+        }
+
+        Ok(cubemap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dds_rgba_cubemap() {
+        let mut bytes = vec![0_u8; 128];
+        bytes[0..4].copy_from_slice(b"DDS ");
+        let mut write = |offset: usize, value: u32| {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        write(4, 124);
+        write(8, 0x20000);
+        write(12, 2);
+        write(16, 2);
+        write(28, 2);
+        write(80, 0x40 | 0x1);
+        write(88, 32);
+        write(92, 0xFF);
+        write(112, 0x200 | 0xFC00);
+
+        // 2x2 and 1x1 levels for each face, filled with the index of the face
+        for face in 0..6_u8 {
+            bytes.extend(std::iter::repeat(face).take((4 + 1) * 4));
+        }
+
+        let cubemap = Cubemap::from_dds(&bytes).unwrap();
+        assert_eq!(cubemap.size, 2);
+        assert_eq!(cubemap.mip_levels, 2);
+        assert_eq!(cubemap.format, DataFormat::RGBA8);
+        for (i, face) in cubemap.faces.iter().enumerate() {
+            assert_eq!(face.len(), 20);
+            assert!(face.iter().all(|b| *b == i as u8));
+        }
+        assert!(cubemap.as_skybox().is_valid());
+
+        // Truncated face data
+        assert!(Cubemap::from_dds(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn ktx2_rgba_cubemap() {
+        let mut bytes = vec![0_u8; 128];
+        bytes[0..12].copy_from_slice(&Cubemap::KTX2_IDENTIFIER);
+        let mut write = |offset: usize, value: u32| {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        write(12, 37);
+        write(16, 1);
+        write(20, 2);
+        write(24, 2);
+        write(36, 6);
+        write(40, 2);
+
+        // The 1x1 level is stored before the 2x2 level, like KTX2 writers do
+        write(80, 128 + 6 * 4);
+        write(88, 6 * 16);
+        write(96, 6 * 16);
+        write(104, 128);
+        write(112, 6 * 4);
+        write(120, 6 * 4);
+
+        // Every face of a level is filled with the index of its face and level
+        for (level, size) in [(1_u8, 4), (0, 16)].iter() {
+            for face in 0..6_u8 {
+                bytes.extend(std::iter::repeat(face * 16 + level).take(*size));
+            }
+        }
+
+        let cubemap = Cubemap::from_ktx2(&bytes).unwrap();
+        assert_eq!(cubemap.size, 2);
+        assert_eq!(cubemap.mip_levels, 2);
+        assert_eq!(cubemap.format, DataFormat::RGBA8);
+        for (i, face) in cubemap.faces.iter().enumerate() {
+            let face_index = i as u8 * 16;
+            assert_eq!(face.len(), 20);
+            assert!(face[..16].iter().all(|b| *b == face_index));
+            assert!(face[16..].iter().all(|b| *b == face_index + 1));
+        }
+        assert!(cubemap.as_skybox().is_valid());
+
+        // Truncated face data
+        assert!(Cubemap::from_ktx2(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
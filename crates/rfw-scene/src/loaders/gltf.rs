@@ -6,7 +6,7 @@ use rfw_backend::MeshId3D;
 use rfw_math::*;
 use rfw_utils::collections::TrackedStorage;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Copy, Clone)]
 pub struct GltfLoader {}
@@ -78,8 +78,12 @@ impl ObjectLoader for GltfLoader {
             meshes.push(mesh_storage.push(Mesh3D::from(mesh)) as u32);
         }
 
-        let mut node_descriptors = Vec::with_capacity(scene.nodes.len());
-        node_descriptors.push(load_node(&meshes, &scene.nodes[0]));
+        // Every root node of the scene, a file without nodes results in an empty graph
+        let node_descriptors = scene
+            .nodes
+            .iter()
+            .map(|node| load_node(&meshes, node))
+            .collect();
 
         let meshes = meshes.iter().map(|i| MeshId3D::from(*i as usize)).collect();
         Ok(LoadResult::Scene(SceneDescriptor {
@@ -128,3 +132,83 @@ fn load_node(meshes: &Vec<u32>, node: &l3d::load::NodeDescriptor) -> NodeDescrip
         id: node.id,
     }
 }
+
+/// A KHR_lights_punctual light with the transform of its node applied, angles are in degrees.
+#[derive(Debug, Copy, Clone)]
+pub enum GltfLight {
+    Point {
+        position: Vec3,
+        radiance: Vec3,
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        radiance: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    Directional {
+        direction: Vec3,
+        radiance: Vec3,
+    },
+}
+
+/// Reads the lights attached to the nodes of the default scene of a glTF or GLB file.
+/// l3d does not parse the KHR_lights_punctual extension, so the file is parsed again.
+pub fn load_lights(path: &Path) -> Result<Vec<GltfLight>, SceneError> {
+    let document = match ::gltf::Gltf::open(path) {
+        Ok(gltf) => gltf.document,
+        Err(_) => return Err(SceneError::LoadError(path.to_path_buf())),
+    };
+
+    let scene = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut lights = Vec::new();
+    for node in scene.nodes() {
+        load_node_lights(&node, Mat4::IDENTITY, &mut lights);
+    }
+    Ok(lights)
+}
+
+fn load_node_lights(node: &::gltf::Node, parent_matrix: Mat4, lights: &mut Vec<GltfLight>) {
+    let matrix = parent_matrix * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(light) = node.light() {
+        let radiance = Vec3::from(light.color()) * light.intensity();
+        // Lights shine down the -Z axis of their node
+        let position = matrix.transform_point3(Vec3::ZERO);
+        let direction = matrix.transform_vector3(-Vec3::Z).normalize();
+
+        lights.push(match light.kind() {
+            ::gltf::khr_lights_punctual::Kind::Point => GltfLight::Point { position, radiance },
+            ::gltf::khr_lights_punctual::Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let outer_angle = outer_cone_angle.to_degrees();
+                GltfLight::Spot {
+                    position,
+                    direction,
+                    radiance,
+                    // Spot lights need a falloff, glTF allows equal angles
+                    inner_angle: inner_cone_angle.to_degrees().min(outer_angle * 0.99),
+                    outer_angle,
+                }
+            }
+            ::gltf::khr_lights_punctual::Kind::Directional => GltfLight::Directional {
+                direction,
+                radiance,
+            },
+        });
+    }
+
+    for child in node.children() {
+        load_node_lights(&child, matrix, lights);
+    }
+}
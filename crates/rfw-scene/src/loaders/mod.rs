@@ -4,6 +4,7 @@ use rfw_backend::MeshId3D;
 use rfw_utils::collections::TrackedStorage;
 use std::path::PathBuf;
 
+pub mod cubemap;
 pub mod gltf;
 pub mod obj;
 
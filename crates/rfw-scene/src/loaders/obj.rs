@@ -3,14 +3,42 @@ use crate::{
     Mesh3dFlags, SceneError, {LoadResult, Mesh3D, ObjectLoader},
 };
 use l3d::mat::{Flip, Texture, TextureSource};
-use rfw_backend::MeshId3D;
+use rfw_backend::{DeviceMaterial, MeshId3D};
 use rfw_math::*;
 use rfw_utils::collections::TrackedStorage;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Copy, Clone)]
 pub struct ObjLoader {}
 
+impl Mesh3D {
+    /// Loads a Wavefront OBJ file and its MTL materials without a scene, ready to be passed to
+    /// a backend. Groups become sub-meshes, consecutive groups that share a material are
+    /// merged. Material ids of the mesh index the returned materials, which index the returned
+    /// textures. Both start with a default material and texture at index 0.
+    pub fn load_obj<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<(Mesh3D, Vec<DeviceMaterial>, Vec<Texture>), SceneError> {
+        let path = path.as_ref().to_path_buf();
+        let mut materials = Materials::new();
+        let mut meshes = TrackedStorage::new();
+
+        let mesh_id = ObjLoader::default()
+            .load(path.clone(), &mut materials, &mut meshes)?
+            .object()
+            .ok()
+            .and_then(|id| id.as_index())
+            .ok_or(SceneError::LoadError(path))?;
+
+        materials.update_device_materials();
+        Ok((
+            std::mem::take(&mut meshes[mesh_id]),
+            materials.get_device_materials().to_vec(),
+            materials.get_textures().to_vec(),
+        ))
+    }
+}
+
 impl std::fmt::Display for ObjLoader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "obj-loader")
@@ -174,6 +202,7 @@ impl ObjectLoader for ObjLoader {
                     } else {
                         None
                     },
+                    lightmap: None,
                 },
             );
             mat_manager.get_mut(mat_index, |m| {
@@ -265,3 +294,46 @@ impl ObjectLoader for ObjLoader {
         Err(SceneError::UnknownError)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obj_groups_become_sub_meshes() {
+        // Unique per process, so concurrent test runs do not share files
+        let dir = std::env::temp_dir().join(format!("rfw-obj-groups-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("quads.mtl"),
+            "newmtl red\nKd 1 0 0\n\nnewmtl green\nKd 0 1 0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("quads.obj"),
+            "mtllib quads.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             g first\nusemtl red\nf 1 2 3 4\n\
+             g second\nusemtl green\nf 1 3 2\n",
+        )
+        .unwrap();
+
+        let result = Mesh3D::load_obj(dir.join("quads.obj"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (mesh, materials, textures) = result.unwrap();
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(materials.len(), 3);
+        assert!(!textures.is_empty());
+
+        // The quad of the first group is triangulated, sub-meshes must not overlap
+        assert_eq!(mesh.ranges.len(), 2);
+        assert_eq!((mesh.ranges[0].first, mesh.ranges[0].last), (0, 6));
+        assert_eq!((mesh.ranges[1].first, mesh.ranges[1].last), (6, 9));
+        assert_ne!(mesh.ranges[0].mat_id, mesh.ranges[1].mat_id);
+        assert!(mesh
+            .ranges
+            .iter()
+            .all(|r| (r.mat_id as usize) < materials.len()));
+    }
+}
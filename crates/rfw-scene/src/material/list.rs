@@ -1,7 +1,7 @@
 use crate::{MaterialFlags, MaterialProps};
 use bitvec::prelude::*;
 use l3d::mat::{Flip, Material, Texture, TextureSource};
-use rfw_backend::DeviceMaterial;
+use rfw_backend::{DeviceMaterial, Flipbook, TextureFilter};
 use rfw_math::*;
 use rfw_utils::collections::{
     ChangedIterator, FlaggedIterator, FlaggedIteratorMut, FlaggedStorage, TrackedStorage,
@@ -23,6 +23,22 @@ pub struct Materials {
     tex_path_mapping: HashMap<PathBuf, usize>,
     textures: TrackedStorage<Texture>,
     tex_material_mapping: FlaggedStorage<HashSet<u32>>,
+    lightmaps: HashMap<usize, i32>,
+    /// Height map texture and parallax scale per material
+    height_maps: HashMap<usize, (i32, f32)>,
+    /// Displacement map texture and scale per material
+    displacement_maps: HashMap<usize, (i32, f32)>,
+    /// Index of refraction per material, materials without one use the default reflectance
+    iors: HashMap<usize, f32>,
+    /// Textures that are sampled without filtering
+    nearest_textures: HashSet<usize>,
+    /// Textures whose color is already multiplied by their alpha
+    premultiplied_textures: HashSet<usize>,
+    shadow_catchers: HashSet<usize>,
+    /// Animated texture sequences per material
+    flipbooks: HashMap<usize, Flipbook>,
+    /// Generate mip levels when textures are added, disabled for backends that generate them
+    generate_mipmaps: bool,
 }
 
 impl Display for Materials {
@@ -283,6 +299,7 @@ pub struct TextureDescriptor {
     pub metallic_roughness_map: Option<TextureSource>,
     pub emissive_map: Option<TextureSource>,
     pub sheen_map: Option<TextureSource>,
+    pub lightmap: Option<TextureSource>,
 }
 
 impl TextureDescriptor {
@@ -335,6 +352,12 @@ impl TextureDescriptor {
         self.sheen_map = Some(tex);
         self
     }
+
+    /// Baked lighting sampled using the secondary uv set of a mesh
+    pub fn with_lightmap(mut self, tex: TextureSource) -> Self {
+        self.lightmap = Some(tex);
+        self
+    }
 }
 
 impl Default for TextureDescriptor {
@@ -345,6 +368,7 @@ impl Default for TextureDescriptor {
             metallic_roughness_map: None,
             emissive_map: None,
             sheen_map: None,
+            lightmap: None,
         }
     }
 }
@@ -360,6 +384,15 @@ impl Materials {
             tex_path_mapping: HashMap::new(),
             textures: TrackedStorage::new(),
             tex_material_mapping: FlaggedStorage::new(),
+            lightmaps: HashMap::new(),
+            height_maps: HashMap::new(),
+            displacement_maps: HashMap::new(),
+            iors: HashMap::new(),
+            nearest_textures: HashSet::new(),
+            premultiplied_textures: HashSet::new(),
+            shadow_catchers: HashSet::new(),
+            flipbooks: HashMap::new(),
+            generate_mipmaps: true,
         }
     }
 
@@ -384,6 +417,15 @@ impl Materials {
             tex_path_mapping: HashMap::new(),
             textures,
             tex_material_mapping: FlaggedStorage::new(),
+            lightmaps: HashMap::new(),
+            height_maps: HashMap::new(),
+            displacement_maps: HashMap::new(),
+            iors: HashMap::new(),
+            nearest_textures: HashSet::new(),
+            premultiplied_textures: HashSet::new(),
+            shadow_catchers: HashSet::new(),
+            flipbooks: HashMap::new(),
+            generate_mipmaps: true,
         }
     }
 
@@ -418,6 +460,7 @@ impl Materials {
         let metallic_roughness_map = textures.metallic_roughness_map;
         let emissive_map = textures.emissive_map;
         let sheen_map = textures.sheen_map;
+        let lightmap = textures.lightmap;
 
         let mut material = Material::default();
         material.color = color.extend(1.0).into();
@@ -480,13 +523,194 @@ impl Materials {
             -1
         };
 
+        let lightmap_tex = if let Some(lightmap) = lightmap {
+            match lightmap {
+                TextureSource::Loaded(tex) => self.push_texture(tex) as i32,
+                TextureSource::Filesystem(path, flip) => {
+                    self.get_texture_index(&path, flip).unwrap_or_else(|_| -1)
+                }
+            }
+        } else {
+            -1
+        };
+
         material.diffuse_tex = diffuse_tex as i16;
         material.normal_tex = normal_tex as i16;
         material.metallic_roughness_tex = metallic_roughness_tex as i16;
         material.emissive_tex = emissive_tex as i16;
         material.sheen_tex = sheen_tex as i16;
 
-        self.push(material)
+        let index = self.push(material);
+        if lightmap_tex >= 0 {
+            self.set_lightmap(index, Some(lightmap_tex as usize));
+        }
+        index
+    }
+
+    /// Whether `material` exists and `texture`, if any, refers to an existing texture.
+    fn is_valid_map(&self, material: usize, texture: Option<usize>) -> bool {
+        self.materials.get(material).is_some()
+            && texture.map(|t| t < self.textures.len()).unwrap_or(true)
+    }
+
+    /// Sets the lightmap texture of a material, lightmaps are sampled using the secondary uv set
+    /// of a mesh and added to the direct lighting of a surface. Returns false and leaves the
+    /// material unchanged if the material or texture does not exist.
+    pub fn set_lightmap(&mut self, material: usize, texture: Option<usize>) -> bool {
+        if !self.is_valid_map(material, texture) {
+            return false;
+        }
+
+        if let Some(old) = self.lightmaps.remove(&material) {
+            self.tex_material_mapping[old as usize].remove(&(material as u32));
+        }
+
+        if let Some(texture) = texture {
+            self.lightmaps.insert(material, texture as i32);
+            self.tex_material_mapping[texture].insert(material as u32);
+        }
+
+        self.materials.trigger_changed(material);
+        true
+    }
+
+    pub fn get_lightmap(&self, material: usize) -> Option<usize> {
+        self.lightmaps.get(&material).map(|t| *t as usize)
+    }
+
+    /// Sets the height map of a material, used for parallax occlusion mapping by backends that
+    /// support it. `scale` is the depth of the height field relative to its uv extent.
+    /// Returns false and leaves the material unchanged if the material or texture does not exist.
+    pub fn set_height_map(&mut self, material: usize, texture: Option<usize>, scale: f32) -> bool {
+        if !self.is_valid_map(material, texture) {
+            return false;
+        }
+
+        if let Some((old, _)) = self.height_maps.remove(&material) {
+            self.tex_material_mapping[old as usize].remove(&(material as u32));
+        }
+
+        if let Some(texture) = texture {
+            self.height_maps
+                .insert(material, (texture as i32, scale.max(0.0)));
+            self.tex_material_mapping[texture].insert(material as u32);
+        }
+
+        self.materials.trigger_changed(material);
+        true
+    }
+
+    /// Returns the height map texture and parallax scale of a material.
+    pub fn get_height_map(&self, material: usize) -> Option<(usize, f32)> {
+        self.height_maps
+            .get(&material)
+            .map(|(t, scale)| (*t as usize, *scale))
+    }
+
+    /// Sets the displacement map of a material, its red channel offsets vertices along their
+    /// normals by `height * scale` in object space. Displacement only adds detail where the
+    /// mesh has the vertices for it, so it is meant for dense meshes such as terrain. Only the
+    /// wgpu backend displaces vertices, its shadow maps keep the undisplaced surface.
+    /// Returns false and leaves the material unchanged if the material or texture does not exist.
+    pub fn set_displacement_map(
+        &mut self,
+        material: usize,
+        texture: Option<usize>,
+        scale: f32,
+    ) -> bool {
+        if !self.is_valid_map(material, texture) {
+            return false;
+        }
+
+        if let Some((old, _)) = self.displacement_maps.remove(&material) {
+            self.tex_material_mapping[old as usize].remove(&(material as u32));
+        }
+
+        if let Some(texture) = texture {
+            self.displacement_maps
+                .insert(material, (texture as i32, scale));
+            self.tex_material_mapping[texture].insert(material as u32);
+        }
+
+        self.materials.trigger_changed(material);
+        true
+    }
+
+    /// Returns the displacement map texture and scale of a material.
+    pub fn get_displacement_map(&self, material: usize) -> Option<(usize, f32)> {
+        self.displacement_maps
+            .get(&material)
+            .map(|(t, scale)| (*t as usize, *scale))
+    }
+
+    /// Sets the index of refraction of a material, following KHR_materials_ior. Dielectrics then
+    /// reflect ((ior - 1) / (ior + 1))^2 at normal incidence, scaled by the specular color of the
+    /// material which takes the role of the KHR_materials_specular color and factor.
+    /// Materials without an index of refraction reflect `specular * 0.08`. Returns false and
+    /// leaves the material unchanged if it does not exist.
+    pub fn set_ior(&mut self, material: usize, ior: Option<f32>) -> bool {
+        if self.materials.get(material).is_none() {
+            return false;
+        }
+
+        match ior {
+            Some(ior) => self.iors.insert(material, ior.max(1.0)),
+            None => self.iors.remove(&material),
+        };
+
+        self.materials.trigger_changed(material);
+        true
+    }
+
+    pub fn get_ior(&self, material: usize) -> Option<f32> {
+        self.iors.get(&material).copied()
+    }
+
+    /// Turns a material into a shadow catcher for compositing over footage: the camera sees the
+    /// background through it, while the shadows other instances cast onto it are written to the
+    /// alpha channel and their reflections to the color. Without a visible background the output
+    /// can be composited over the footage as premultiplied alpha. The flag is passed on to the
+    /// backends, the rasterizers in this tree still shade these as regular surfaces. Returns
+    /// false and leaves the material unchanged if it does not exist.
+    pub fn set_shadow_catcher(&mut self, material: usize, catcher: bool) -> bool {
+        if self.materials.get(material).is_none() {
+            return false;
+        }
+
+        if catcher {
+            self.shadow_catchers.insert(material);
+        } else {
+            self.shadow_catchers.remove(&material);
+        }
+
+        self.materials.trigger_changed(material);
+        true
+    }
+
+    pub fn is_shadow_catcher(&self, material: usize) -> bool {
+        self.shadow_catchers.contains(&material)
+    }
+
+    /// Plays the textures of a material as a flipbook, its maps except for the height map and
+    /// lightmap are sampled from the cell of the current frame. The playback of each instance
+    /// can be offset with [crate::InstanceHandle3D::set_flipbook_time]. Only the wgpu backend
+    /// animates flipbooks. Returns false and leaves the material unchanged if it does not exist.
+    pub fn set_flipbook(&mut self, material: usize, flipbook: Option<Flipbook>) -> bool {
+        if self.materials.get(material).is_none() {
+            return false;
+        }
+
+        match flipbook {
+            Some(flipbook) => self.flipbooks.insert(material, flipbook),
+            None => self.flipbooks.remove(&material),
+        };
+
+        self.materials.trigger_changed(material);
+        true
+    }
+
+    pub fn get_flipbook(&self, material: usize) -> Option<Flipbook> {
+        self.flipbooks.get(&material).copied()
     }
 
     pub fn push(&mut self, mat: Material) -> usize {
@@ -514,18 +738,104 @@ impl Materials {
         i
     }
 
-    pub fn push_texture(&mut self, mut texture: Texture) -> usize {
-        if texture.width < 64 || texture.height < 64 {
-            texture = texture.resized(64.max(texture.width), 64.max(texture.height));
+    pub fn push_texture(&mut self, texture: Texture) -> usize {
+        self.push_texture_with_filter(texture, TextureFilter::Linear)
+    }
+
+    /// Adds a texture that is sampled using `filter`. Nearest filtered textures are kept at
+    /// their original size without mip levels, materials take the filter of their diffuse map.
+    pub fn push_texture_with_filter(
+        &mut self,
+        mut texture: Texture,
+        filter: TextureFilter,
+    ) -> usize {
+        if filter == TextureFilter::Linear {
+            if texture.width < 64 || texture.height < 64 {
+                texture = texture.resized(64.max(texture.width), 64.max(texture.height));
+            }
+
+            if self.generate_mipmaps {
+                texture.generate_mipmaps(Texture::MIP_LEVELS);
+            }
         }
 
-        texture.generate_mipmaps(Texture::MIP_LEVELS);
         let i = self.textures.len();
         self.textures.push(texture);
         self.tex_material_mapping.overwrite_val(i, HashSet::new());
+        if filter == TextureFilter::Nearest {
+            self.nearest_textures.insert(i);
+        }
         i
     }
 
+    /// Sets whether mip levels are generated on the CPU for textures.
+    /// Disable this for backends that generate them on upload, see
+    /// [rfw_backend::Backend::generates_mipmaps]. Enabling it generates the levels of linearly
+    /// filtered textures that are added without them, textures that already have levels keep
+    /// them.
+    pub fn set_generate_mipmaps(&mut self, generate: bool) {
+        if generate && !self.generate_mipmaps {
+            for i in 0..self.textures.len() {
+                if self.nearest_textures.contains(&i) {
+                    continue;
+                }
+
+                let single_level = match self.textures.get(i) {
+                    Some(texture) => texture.mip_levels <= 1,
+                    None => false,
+                };
+                if single_level {
+                    if let Some(texture) = self.textures.get_mut(i) {
+                        texture.generate_mipmaps(Texture::MIP_LEVELS);
+                    }
+                }
+            }
+        }
+
+        self.generate_mipmaps = generate;
+    }
+
+    pub fn generates_mipmaps(&self) -> bool {
+        self.generate_mipmaps
+    }
+
+    pub fn get_texture_filter(&self, index: usize) -> TextureFilter {
+        if self.nearest_textures.contains(&index) {
+            TextureFilter::Nearest
+        } else {
+            TextureFilter::Linear
+        }
+    }
+
+    /// Marks texture `index` as having premultiplied alpha, textures have straight alpha by
+    /// default. Blended passes, such as 2D sprites and decals, then composite its color as is
+    /// instead of multiplying it by alpha first, which avoids dark or bright fringes around
+    /// transparent edges. Materials take the convention of their diffuse map.
+    pub fn set_texture_premultiplied(&mut self, index: usize, premultiplied: bool) {
+        if index >= self.textures.len() {
+            return;
+        }
+
+        let changed = if premultiplied {
+            self.premultiplied_textures.insert(index)
+        } else {
+            self.premultiplied_textures.remove(&index)
+        };
+
+        if changed {
+            self.textures.trigger_changed(index);
+            if let Some(materials) = self.tex_material_mapping.get(index) {
+                for m in materials.iter() {
+                    self.materials.trigger_changed(*m as usize);
+                }
+            }
+        }
+    }
+
+    pub fn is_texture_premultiplied(&self, index: usize) -> bool {
+        self.premultiplied_textures.contains(&index)
+    }
+
     pub fn get(&self, index: usize) -> Option<&Material> {
         self.materials.get(index)
     }
@@ -620,7 +930,9 @@ impl Materials {
                     tex = tex.resized(64.max(tex.width), 64.max(tex.height));
                 }
 
-                tex.generate_mipmaps(Texture::MIP_LEVELS);
+                if self.generate_mipmaps {
+                    tex.generate_mipmaps(Texture::MIP_LEVELS);
+                }
 
                 let index = self.textures.push(tex);
                 self.tex_material_mapping
@@ -682,7 +994,32 @@ impl Materials {
 
     pub fn update_device_materials(&mut self) {
         for (i, m) in self.materials.iter_changed() {
-            self.device_materials.overwrite(i, into_device_material(m));
+            let lightmap = self.lightmaps.get(&i).copied().unwrap_or(-1);
+            let height_map = self.height_maps.get(&i).copied();
+            let mut material = into_device_material(m, lightmap, height_map);
+            material.ior = self.iors.get(&i).copied().unwrap_or(0.0);
+            if m.diffuse_tex >= 0 && self.nearest_textures.contains(&(m.diffuse_tex as usize)) {
+                material.flags |= 1 << MaterialProps::NearestFilter as u32;
+            }
+            if m.diffuse_tex >= 0
+                && self
+                    .premultiplied_textures
+                    .contains(&(m.diffuse_tex as usize))
+            {
+                material.flags |= 1 << MaterialProps::PremultipliedAlpha as u32;
+            }
+            if self.shadow_catchers.contains(&i) {
+                material.flags |= 1 << MaterialProps::ShadowCatcher as u32;
+            }
+            if let Some(flipbook) = self.flipbooks.get(&i) {
+                material.flipbook = flipbook.to_device();
+            }
+            if let Some((map, scale)) = self.displacement_maps.get(&i) {
+                material.flags |= 1 << MaterialProps::HasDisplacementMap as u32;
+                material.displacement_map = *map;
+                material.displacement_scale = *scale;
+            }
+            self.device_materials.overwrite(i, material);
         }
     }
 
@@ -752,7 +1089,11 @@ impl IndexMut<usize> for Materials {
     }
 }
 
-pub(crate) fn into_device_material(mat: &Material) -> DeviceMaterial {
+pub(crate) fn into_device_material(
+    mat: &Material,
+    lightmap: i32,
+    height_map: Option<(i32, f32)>,
+) -> DeviceMaterial {
     let to_char = |f: f32| -> u8 { (f * 255.0).min(255.0) as u8 };
     let to_u32 = |a: f32, b: f32, c: f32, d: f32| -> u32 {
         let a = to_char(a) as u32;
@@ -797,11 +1138,20 @@ pub(crate) fn into_device_material(mat: &Material) -> DeviceMaterial {
     if mat.sheen_tex >= 0 {
         flags.set(MaterialProps::HasSheenMap, true);
     }
+    if lightmap >= 0 {
+        flags.set(MaterialProps::HasLightmap, true);
+    }
+
+    let (height_map, parallax_scale) = height_map.unwrap_or((-1, 0.0));
+    if height_map >= 0 {
+        flags.set(MaterialProps::HasHeightMap, true);
+    }
 
     DeviceMaterial {
         color: mat.color,
         absorption: mat.absorption,
-        specular: mat.specular,
+        specular: [mat.specular[0], mat.specular[1], mat.specular[2]],
+        ior: 0.0,
         parameters,
         flags: flags.into(),
         diffuse_map: mat.diffuse_tex as i32,
@@ -809,6 +1159,12 @@ pub(crate) fn into_device_material(mat: &Material) -> DeviceMaterial {
         metallic_roughness_map: mat.metallic_roughness_tex as i32,
         emissive_map: mat.emissive_tex as i32,
         sheen_map: mat.sheen_tex as i32,
-        ..Default::default()
+        lightmap_map: lightmap,
+        height_map,
+        flipbook: [0.0; 4],
+        displacement_map: -1,
+        displacement_scale: 0.0,
+        parallax_scale,
+        _padding: 0.0,
     }
 }
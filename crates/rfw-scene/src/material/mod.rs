@@ -31,6 +31,14 @@ pub enum MaterialProps {
     HasMetallicMap = 3,
     HasEmissiveMap = 4,
     HasSheenMap = 5,
+    HasLightmap = 6,
+    HasHeightMap = 7,
+    NearestFilter = 8,
+    /// See [Materials::set_shadow_catcher]
+    ShadowCatcher = 9,
+    HasDisplacementMap = 10,
+    /// The diffuse map has premultiplied alpha, see [Materials::set_texture_premultiplied]
+    PremultipliedAlpha = 11,
 }
 
 impl Default for MaterialFlags {
@@ -43,13 +51,19 @@ impl Display for MaterialFlags {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "MaterialFlags {{ HasDiffuseMap: {}, HasNormalMap: {}, HasRoughnessMap: {}, HasMetallicMap: {}, HasEmissiveMap: {}, HasSheenMap: {} }}",
+            "MaterialFlags {{ HasDiffuseMap: {}, HasNormalMap: {}, HasRoughnessMap: {}, HasMetallicMap: {}, HasEmissiveMap: {}, HasSheenMap: {}, HasLightmap: {}, HasHeightMap: {}, NearestFilter: {}, ShadowCatcher: {}, HasDisplacementMap: {}, PremultipliedAlpha: {} }}",
             self.get(MaterialProps::HasDiffuseMap),
             self.get(MaterialProps::HasNormalMap),
             self.get(MaterialProps::HasRoughnessMap),
             self.get(MaterialProps::HasMetallicMap),
             self.get(MaterialProps::HasEmissiveMap),
             self.get(MaterialProps::HasSheenMap),
+            self.get(MaterialProps::HasLightmap),
+            self.get(MaterialProps::HasHeightMap),
+            self.get(MaterialProps::NearestFilter),
+            self.get(MaterialProps::ShadowCatcher),
+            self.get(MaterialProps::HasDisplacementMap),
+            self.get(MaterialProps::PremultipliedAlpha),
         )
     }
 }
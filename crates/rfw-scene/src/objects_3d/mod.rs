@@ -1,13 +1,14 @@
 use l3d::load::MeshDescriptor;
 use rayon::prelude::*;
 use rfw_backend::{
-    JointData, Mesh3dFlags, RTTriangle, SkinData, SkinnedMesh3D, SkinnedTriangles3D, Vertex3D,
-    VertexMesh,
+    CullMode, FrontFace, JointData, Mesh3dFlags, RTTriangle, SkinData, SkinnedMesh3D,
+    SkinnedTriangles3D, Vertex3D, VertexMesh,
 };
 use rfw_math::*;
 
 mod plane;
 mod quad;
+mod simplify;
 mod sphere;
 
 pub use plane::*;
@@ -35,6 +36,44 @@ pub trait ToMesh3D {
     fn into_mesh_3d(self) -> Mesh3D;
 }
 
+/// Splits triangles into sub-meshes of consecutive triangles that share a material.
+fn material_ranges<F: Fn(usize) -> Vec3>(material_ids: &[u32], vertex: F) -> Vec<VertexMesh> {
+    let mut meshes = Vec::new();
+    let mut start = 0;
+    let mut bounds = Aabb::new();
+
+    for (i, mat_id) in material_ids.iter().copied().enumerate() {
+        if mat_id != material_ids[start] {
+            meshes.push(VertexMesh {
+                first: start as u32 * 3,
+                last: i as u32 * 3,
+                mat_id: material_ids[start],
+                bounds,
+                padding: 0,
+            });
+
+            bounds = Aabb::new();
+            start = i;
+        }
+
+        for j in 0..3 {
+            bounds.grow(vertex(i * 3 + j));
+        }
+    }
+
+    if !material_ids.is_empty() {
+        meshes.push(VertexMesh {
+            first: start as u32 * 3,
+            last: material_ids.len() as u32 * 3,
+            mat_id: material_ids[start],
+            bounds,
+            padding: 0,
+        });
+    }
+
+    meshes
+}
+
 impl ToMesh3D for Mesh3D {
     fn into_mesh_3d(self) -> Mesh3D {
         self
@@ -275,58 +314,13 @@ impl Mesh3D {
                 normal,
                 mat_id: material_ids[i / 3],
                 uv: uvs[i],
+                uv1: uvs[i],
                 tangent: tangents[i],
                 ..Default::default()
             };
         });
 
-        let mut last_id = material_ids[0];
-        let mut start = 0;
-        let mut range = 0;
-        let mut meshes: Vec<VertexMesh> = Vec::new();
-        let mut v_bounds = Aabb::new();
-
-        for i in 0..material_ids.len() {
-            range += 1;
-            for j in 0..3 {
-                v_bounds.grow(vertices[i * 3 + j]);
-            }
-
-            if last_id != material_ids[i] {
-                meshes.push(VertexMesh {
-                    first: start * 3,
-                    last: (start + range) * 3,
-                    mat_id: last_id,
-                    bounds: v_bounds,
-                    padding: 0,
-                });
-
-                v_bounds = Aabb::new();
-                last_id = material_ids[i];
-                start = i as u32;
-                range = 1;
-            }
-        }
-
-        if meshes.is_empty() {
-            // There only is 1 mesh available
-            meshes.push(VertexMesh {
-                first: 0,
-                last: vertices.len() as u32,
-                mat_id: material_ids[0],
-                bounds,
-                padding: 0,
-            });
-        } else if (start + range) != (material_ids.len() as u32 - 1) {
-            // Add last mesh to list
-            meshes.push(VertexMesh {
-                first: start * 3,
-                last: (start + range) * 3,
-                mat_id: last_id,
-                bounds: v_bounds,
-                padding: 0,
-            })
-        }
+        let meshes = material_ranges(&material_ids, |i| vertices[i]);
 
         let mut triangles = vec![RTTriangle::default(); vertices.len() / 3];
         triangles.iter_mut().enumerate().for_each(|(i, triangle)| {
@@ -456,7 +450,10 @@ impl Mesh3D {
             materials: Default::default(),
             ranges: Default::default(),
             bounds: Aabb::new(),
-            flags: Mesh3dFlags::all(),
+            flags: Mesh3dFlags::all()
+                - Mesh3dFlags::ALWAYS_ON_TOP
+                - Mesh3dFlags::CLOCKWISE
+                - Mesh3dFlags::FLAT_SHADING,
             name: String::new(),
         }
     }
@@ -492,6 +489,15 @@ impl Mesh3D {
         )
     }
 
+    /// Sets the secondary (lightmap) uv set, uvs are assigned to vertices in order.
+    /// Superfluous uvs are ignored and vertices without a uv receive `Vec2::ZERO`.
+    pub fn with_uv1(mut self, uvs: &[Vec2]) -> Self {
+        self.vertices.iter_mut().enumerate().for_each(|(i, v)| {
+            v.uv1 = uvs.get(i).copied().unwrap_or(Vec2::ZERO);
+        });
+        self
+    }
+
     pub fn with_flags(mut self, flags: Mesh3dFlags) -> Self {
         self.flags |= flags;
         self
@@ -501,6 +507,34 @@ impl Mesh3D {
         self.flags.remove(flags);
         self
     }
+
+    /// Overrides which faces get culled, e.g. to fix meshes imported with inverted winding.
+    pub fn with_cull_mode(mut self, mode: CullMode) -> Self {
+        self.flags.set_cull_mode(mode);
+        self
+    }
+
+    /// Selects the winding of front faces for culling and geometric normals, for assets
+    /// authored with clockwise winding that would otherwise render inside-out.
+    pub fn with_front_face(mut self, front_face: FrontFace) -> Self {
+        if self.flags.front_face() != front_face {
+            self.triangles.iter_mut().for_each(|t| t.normal = -t.normal);
+        }
+        self.flags.set_front_face(front_face);
+        self
+    }
+
+    /// Draws this mesh over the rest of the scene regardless of depth.
+    pub fn with_always_on_top(mut self, on_top: bool) -> Self {
+        self.flags.set(Mesh3dFlags::ALWAYS_ON_TOP, on_top);
+        self
+    }
+
+    /// Shades this mesh with face normals instead of its interpolated vertex normals.
+    pub fn with_flat_shading(mut self, flat: bool) -> Self {
+        self.flags.set(Mesh3dFlags::FLAT_SHADING, flat);
+        self
+    }
 }
 
 impl Bounds for Mesh3D {
@@ -726,63 +760,20 @@ impl From<MeshDescriptor> for Mesh3D {
                 normal: normals[i],
                 mat_id: material_ids[i / 3],
                 uv: Vec2::from(desc.uvs[i]),
+                uv1: Vec2::from(desc.uvs[i]),
                 tangent: Vec4::from(desc.tangents[i]),
                 ..Default::default()
             };
         });
 
-        let mut last_id = material_ids[0];
-        let mut start = 0;
-        let mut range = 0;
-        let mut meshes: Vec<VertexMesh> = Vec::new();
-        let mut v_bounds = Aabb::new();
-
-        (0..material_ids.len()).into_iter().for_each(|i| {
-            range += 1;
-            for j in 0..3 {
-                v_bounds.grow(vec3(
-                    desc.vertices[i * 3 + j][0],
-                    desc.vertices[i * 3 + j][1],
-                    desc.vertices[i * 3 + j][2],
-                ));
-            }
-
-            if last_id != material_ids[i] {
-                meshes.push(VertexMesh {
-                    first: start * 3,
-                    last: (start + range) * 3,
-                    mat_id: last_id as _,
-                    bounds: v_bounds,
-                    padding: 0,
-                });
-
-                v_bounds = Aabb::new();
-                last_id = material_ids[i];
-                start = i as u32;
-                range = 1;
-            }
+        let meshes = material_ranges(&material_ids, |i| {
+            vec3(
+                desc.vertices[i][0],
+                desc.vertices[i][1],
+                desc.vertices[i][2],
+            )
         });
 
-        if meshes.is_empty() {
-            // There only is 1 mesh available
-            meshes.push(VertexMesh {
-                first: 0,
-                last: desc.vertices.len() as u32,
-                mat_id: material_ids[0],
-                bounds,
-                padding: 0,
-            });
-        } else if (start + range) != (material_ids.len() as u32 - 1) {
-            // Add last mesh to list
-            meshes.push(VertexMesh {
-                first: start * 3,
-                last: (start + range) * 3,
-                mat_id: last_id,
-                bounds: v_bounds,
-                padding: 0,
-            })
-        }
-
         let mut triangles = vec![RTTriangle::default(); desc.vertices.len() / 3];
         triangles.iter_mut().enumerate().for_each(|(i, triangle)| {
             let i0 = i * 3;
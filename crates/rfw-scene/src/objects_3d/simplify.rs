@@ -0,0 +1,477 @@
+use crate::objects_3d::Mesh3D;
+use rfw_math::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Symmetric 4x4 error quadric, only the upper triangle is stored.
+#[derive(Debug, Default, Copy, Clone)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(n: Vec3, d: f32, weight: f32) -> Self {
+        let (a, b, c, d) = (n.x as f64, n.y as f64, n.z as f64, d as f64);
+        let w = weight as f64;
+        Self([
+            w * a * a,
+            w * a * b,
+            w * a * c,
+            w * a * d,
+            w * b * b,
+            w * b * c,
+            w * b * d,
+            w * c * c,
+            w * c * d,
+            w * d * d,
+        ])
+    }
+
+    fn error(&self, p: Vec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+impl std::ops::Add for Quadric {
+    type Output = Quadric;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self.0
+            .iter_mut()
+            .zip(rhs.0.iter())
+            .for_each(|(a, b)| *a += *b);
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Vertex {
+    position: Vec3,
+    normal: Vec3,
+    uv: Vec2,
+    uv1: Vec2,
+    joints: [u16; 4],
+    weights: [f32; 4],
+}
+
+impl Vertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let (joints, weights) = if t < 0.5 {
+            (self.joints, self.weights)
+        } else {
+            (other.joints, other.weights)
+        };
+
+        Self {
+            position: self.position.lerp(other.position, t),
+            normal: self.normal.lerp(other.normal, t).normalize_or_zero(),
+            uv: self.uv.lerp(other.uv, t),
+            uv1: self.uv1.lerp(other.uv1, t),
+            joints,
+            weights,
+        }
+    }
+}
+
+/// A candidate collapse of vertex `from` into vertex `to`.
+#[derive(Debug, Copy, Clone)]
+struct Collapse {
+    error: f64,
+    from: u32,
+    to: u32,
+    /// Interpolation factor between `from` (0.0) and `to` (1.0) of the resulting vertex
+    t: f32,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.error == other.error
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, reverse the ordering to pop the cheapest collapse first
+        other
+            .error
+            .partial_cmp(&self.error)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+struct Simplifier {
+    vertices: Vec<Vertex>,
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    removed: Vec<bool>,
+    versions: Vec<u32>,
+    adjacency: Vec<Vec<u32>>,
+    triangles: Vec<[u32; 3]>,
+    triangle_removed: Vec<bool>,
+}
+
+impl Simplifier {
+    fn neighbours(&self, v: u32) -> HashSet<u32> {
+        self.adjacency[v as usize]
+            .iter()
+            .filter(|t| !self.triangle_removed[**t as usize])
+            .flat_map(|t| self.triangles[*t as usize].iter().copied())
+            .filter(|n| *n != v)
+            .collect()
+    }
+
+    fn evaluate(&self, a: u32, b: u32) -> Option<Collapse> {
+        let (from, to) = match (self.locked[a as usize], self.locked[b as usize]) {
+            (false, _) => (a, b),
+            (true, false) => (b, a),
+            (true, true) => return None,
+        };
+
+        let q = self.quadrics[from as usize] + self.quadrics[to as usize];
+        let v0 = &self.vertices[from as usize];
+        let v1 = &self.vertices[to as usize];
+
+        // A locked vertex may not move, otherwise pick the cheapest of both ends and the midpoint
+        let candidates: &[f32] = if self.locked[to as usize] {
+            &[1.0]
+        } else {
+            &[0.0, 0.5, 1.0]
+        };
+
+        let (t, error) = candidates
+            .iter()
+            .map(|t| (*t, q.error(v0.position.lerp(v1.position, *t))))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))?;
+
+        Some(Collapse {
+            error,
+            from,
+            to,
+            t,
+            versions: (self.versions[from as usize], self.versions[to as usize]),
+        })
+    }
+
+    fn is_valid(&self, c: &Collapse, position: Vec3) -> bool {
+        // Link condition: the edge may only share the 2 vertices opposite to it with its
+        // neighbours, otherwise the collapse produces non-manifold geometry.
+        let shared = self.neighbours(c.from);
+        let common = self.neighbours(c.to).intersection(&shared).count();
+        let edge_triangles = self.adjacency[c.from as usize]
+            .iter()
+            .filter(|t| !self.triangle_removed[**t as usize])
+            .filter(|t| self.triangles[**t as usize].contains(&c.to))
+            .count();
+        if common > edge_triangles {
+            return false;
+        }
+
+        // Reject collapses that flip the orientation of any remaining triangle
+        self.adjacency[c.from as usize]
+            .iter()
+            .filter(|t| !self.triangle_removed[**t as usize])
+            .map(|t| self.triangles[*t as usize])
+            .filter(|t| !t.contains(&c.to))
+            .all(|t| {
+                let p = |i: u32| self.vertices[i as usize].position;
+                let before = (p(t[1]) - p(t[0])).cross(p(t[2]) - p(t[0]));
+                let q = |i: u32| if i == c.from { position } else { p(i) };
+                let after = (q(t[1]) - q(t[0])).cross(q(t[2]) - q(t[0]));
+                before.dot(after) > 0.0
+            })
+    }
+
+    fn collapse(&mut self, c: &Collapse) -> usize {
+        let from = c.from as usize;
+        let to = c.to as usize;
+
+        self.vertices[to] = self.vertices[from].lerp(&self.vertices[to], c.t);
+        self.quadrics[to] = self.quadrics[from] + self.quadrics[to];
+        self.removed[from] = true;
+        self.versions[to] += 1;
+
+        let mut removed = 0;
+        for t in std::mem::take(&mut self.adjacency[from]) {
+            if self.triangle_removed[t as usize] {
+                continue;
+            }
+
+            let triangle = &mut self.triangles[t as usize];
+            if triangle.contains(&c.to) {
+                self.triangle_removed[t as usize] = true;
+                removed += 1;
+            } else {
+                triangle
+                    .iter_mut()
+                    .filter(|i| **i == c.from)
+                    .for_each(|i| *i = c.to);
+                self.adjacency[to].push(t);
+            }
+        }
+
+        let triangle_removed = &self.triangle_removed;
+        self.adjacency[to].retain(|t| !triangle_removed[*t as usize]);
+        removed
+    }
+}
+
+impl Mesh3D {
+    /// Reduces the triangle count of this mesh to roughly `target_ratio` times its current
+    /// triangle count using quadric error metric edge collapses.
+    /// Vertices on open boundaries, uv/normal seams and material borders are never moved,
+    /// the attributes of all other vertices are interpolated along collapsed edges.
+    pub fn simplify(&self, target_ratio: f32) -> Mesh3D {
+        let triangle_count = self.vertices.len() / 3;
+        let target = ((triangle_count as f32 * target_ratio.max(0.0).min(1.0)) as usize).max(1);
+        if self.is_empty() || target >= triangle_count {
+            return self.clone();
+        }
+
+        let has_skin = self.skin_data.len() == self.vertices.len();
+        let key = |v: Vec3| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+
+        // Weld identical vertices to recover the connectivity of this mesh
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut lookup: HashMap<[u32; 10], u32> = HashMap::new();
+        let mut triangles: Vec<[u32; 3]> = Vec::with_capacity(triangle_count);
+        let mut materials: Vec<u32> = Vec::with_capacity(triangle_count);
+        for (i, triangle) in self.vertices.chunks_exact(3).enumerate() {
+            let mut indices = [0; 3];
+            for (j, v) in triangle.iter().enumerate() {
+                let p = key(v.vertex.truncate());
+                let n = key(v.normal);
+                let vertex_key = [
+                    p[0],
+                    p[1],
+                    p[2],
+                    n[0],
+                    n[1],
+                    n[2],
+                    v.uv.x.to_bits(),
+                    v.uv.y.to_bits(),
+                    v.uv1.x.to_bits(),
+                    v.uv1.y.to_bits(),
+                ];
+
+                let index = i * 3 + j;
+                indices[j] = *lookup.entry(vertex_key).or_insert_with(|| {
+                    let (joints, weights) = if has_skin {
+                        let skin = &self.skin_data[index];
+                        (
+                            [
+                                skin.joint[0] as u16,
+                                skin.joint[1] as u16,
+                                skin.joint[2] as u16,
+                                skin.joint[3] as u16,
+                            ],
+                            skin.weight.into(),
+                        )
+                    } else {
+                        ([0; 4], [0.25; 4])
+                    };
+
+                    vertices.push(Vertex {
+                        position: v.vertex.truncate(),
+                        normal: v.normal,
+                        uv: v.uv,
+                        uv1: v.uv1,
+                        joints,
+                        weights,
+                    });
+                    (vertices.len() - 1) as u32
+                });
+            }
+
+            // Degenerate triangles do not contribute to the result
+            if indices[0] == indices[1] || indices[1] == indices[2] || indices[0] == indices[2] {
+                continue;
+            }
+
+            triangles.push(indices);
+            materials.push(self.materials[i]);
+        }
+
+        let mut locked = vec![false; vertices.len()];
+
+        // Positions that are shared by multiple vertices lie on an attribute seam
+        let mut positions: HashMap<[u32; 3], u32> = HashMap::new();
+        vertices
+            .iter()
+            .for_each(|v| *positions.entry(key(v.position)).or_insert(0) += 1);
+        vertices
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| positions[&key(v.position)] > 1)
+            .for_each(|(i, _)| locked[i] = true);
+
+        // Edges used by a single triangle are boundary edges
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for t in triangles.iter() {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        edges
+            .iter()
+            .filter(|(_, count)| **count == 1)
+            .for_each(|((a, b), _)| {
+                locked[*a as usize] = true;
+                locked[*b as usize] = true;
+            });
+
+        let mut quadrics = vec![Quadric::default(); vertices.len()];
+        let mut adjacency: Vec<Vec<u32>> = vec![Vec::new(); vertices.len()];
+        let mut vertex_materials: Vec<Option<u32>> = vec![None; vertices.len()];
+        for (i, t) in triangles.iter().enumerate() {
+            let p0 = vertices[t[0] as usize].position;
+            let p1 = vertices[t[1] as usize].position;
+            let p2 = vertices[t[2] as usize].position;
+
+            let n = (p1 - p0).cross(p2 - p0);
+            let area = n.length();
+            let q = if area > 0.0 {
+                let n = n / area;
+                Quadric::from_plane(n, -n.dot(p0), area)
+            } else {
+                Quadric::default()
+            };
+
+            for v in t.iter().map(|v| *v as usize) {
+                quadrics[v] = quadrics[v] + q;
+                adjacency[v].push(i as u32);
+
+                // Vertices shared by triangles of different materials lie on a material border
+                match vertex_materials[v] {
+                    Some(m) if m != materials[i] => locked[v] = true,
+                    _ => vertex_materials[v] = Some(materials[i]),
+                }
+            }
+        }
+
+        let mut simplifier = Simplifier {
+            removed: vec![false; vertices.len()],
+            versions: vec![0; vertices.len()],
+            triangle_removed: vec![false; triangles.len()],
+            vertices,
+            quadrics,
+            locked,
+            adjacency,
+            triangles,
+        };
+
+        let mut heap: BinaryHeap<Collapse> = edges
+            .keys()
+            .filter_map(|(a, b)| simplifier.evaluate(*a, *b))
+            .collect();
+
+        let mut remaining = simplifier.triangles.len();
+        while remaining > target {
+            let c = match heap.pop() {
+                Some(c) => c,
+                None => break,
+            };
+
+            let (from, to) = (c.from as usize, c.to as usize);
+            if simplifier.removed[from]
+                || simplifier.removed[to]
+                || c.versions != (simplifier.versions[from], simplifier.versions[to])
+            {
+                continue;
+            }
+
+            let position = simplifier.vertices[from]
+                .position
+                .lerp(simplifier.vertices[to].position, c.t);
+            if !simplifier.is_valid(&c, position) {
+                continue;
+            }
+
+            remaining -= simplifier.collapse(&c);
+            for n in simplifier.neighbours(c.to) {
+                if let Some(c) = simplifier.evaluate(n, c.to) {
+                    heap.push(c);
+                }
+            }
+        }
+
+        let mut indices = Vec::with_capacity(remaining);
+        let mut material_ids = Vec::with_capacity(remaining);
+        let mut uv1 = Vec::with_capacity(remaining * 3);
+        for (i, t) in simplifier.triangles.iter().enumerate() {
+            if simplifier.triangle_removed[i] {
+                continue;
+            }
+
+            indices.push(*t);
+            material_ids.push(materials[i]);
+            uv1.extend(t.iter().map(|v| simplifier.vertices[*v as usize].uv1));
+        }
+
+        let vertices = simplifier.vertices;
+        let (joints, weights) = if has_skin {
+            (
+                vec![vertices.iter().map(|v| v.joints).collect()],
+                vec![vertices.iter().map(|v| v.weights).collect()],
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        Mesh3D::new_indexed(
+            indices,
+            vertices.iter().map(|v| v.position).collect(),
+            vertices.iter().map(|v| v.normal).collect(),
+            joints,
+            weights,
+            vertices.iter().map(|v| v.uv).collect(),
+            material_ids,
+            self.flags,
+            Some(self.name.clone()),
+        )
+        .with_uv1(&uv1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::objects_3d::*;
+    use rfw_math::*;
+
+    #[test]
+    fn simplify_sphere() {
+        let sphere = Sphere::new(Vec3::ZERO, 1.0, 0)
+            .with_quality(Quality::High)
+            .into_mesh_3d();
+        let simplified = sphere.simplify(0.5);
+
+        let original_count = sphere.triangles.len();
+        let count = simplified.triangles.len();
+        assert!(count < original_count);
+        assert!(count <= original_count / 2 + 1);
+        assert_eq!(simplified.vertices.len(), count * 3);
+
+        let min = simplified.bounds.min - sphere.bounds.min;
+        let max = simplified.bounds.max - sphere.bounds.max;
+        assert!(min.abs().max_element() < 0.1);
+        assert!(max.abs().max_element() < 0.1);
+    }
+}
@@ -4,6 +4,7 @@ pub mod input;
 pub mod resources;
 pub mod system;
 pub mod window;
+use std::error::Error;
 use std::time::Duration;
 
 use event::Events;
@@ -113,7 +114,14 @@ impl Instance {
             .build(&event_loop)
             .expect("Could not create window.");
 
-        let renderer = T::init(&window, width, height, 1.0).expect("Could not initialize renderer");
+        let renderer = system::create_backend::<T>(&window, width, height, 1.0)
+            .expect("Could not initialize renderer");
+
+        // Backends that build mip levels on upload do not need them to be generated on the CPU
+        let mut scene = Scene::new();
+        scene
+            .get_materials_mut()
+            .set_generate_mipmaps(!renderer.generates_mipmaps());
 
         // rfw_utils::log::SimpleLogger::new().init().unwrap();
         // rfw_utils::log::info!("initialized renderer: {}", std::any::type_name::<T>());
@@ -128,17 +136,20 @@ impl Instance {
             window,
         };
 
+        let (command_queue, commands) = rfw_backend::command_queue();
         this.add_plugin(RenderSystem {
             width,
             height,
             scale_factor: 1.0,
             renderer,
+            command_queue,
+            commands,
             mode: RenderMode::Default,
         })
         .add_resource(bevy_tasks::ComputeTaskPool(
             bevy_tasks::TaskPoolBuilder::new().build(),
         ))
-        .add_resource(Scene::new())
+        .add_resource(scene)
         .add_resource(Camera3D::new().with_aspect_ratio(width as f32 / height as f32))
         .add_resource(Camera2D::from_width_height(width, height, None))
         .add_resource(Input::<winit::event::VirtualKeyCode>::new())
@@ -276,6 +287,35 @@ impl Instance {
         self
     }
 
+    /// Replaces the renderer by a backend created through `factory` on the same window, the
+    /// scene gets uploaded to it again during the next frame and the settings of the current
+    /// renderer are applied to it where its settings have the same keys. The new backend is
+    /// created while the current renderer still exists, which keeps the current renderer in
+    /// place when creating it fails. Graphics APIs that only let a single surface use a window
+    /// fail at that point. State that was set on the renderer directly, such as its skybox, and
+    /// commands that were already applied are not carried over.
+    pub fn set_backend(&mut self, factory: system::BackendFactory) -> Result<(), Box<dyn Error>> {
+        let generates_mipmaps = {
+            let mut system = self.world.get_resource_mut::<RenderSystem>().unwrap();
+            let mut renderer = factory(
+                &self.window,
+                system.width,
+                system.height,
+                system.scale_factor,
+            )?;
+            renderer.apply_settings(&system.renderer.export_settings());
+            system.renderer = renderer;
+            system.renderer.generates_mipmaps()
+        };
+
+        let mut scene = self.get_scene_mut();
+        scene
+            .get_materials_mut()
+            .set_generate_mipmaps(!generates_mipmaps);
+        scene.set_changed();
+        Ok(())
+    }
+
     pub fn resize(&mut self, window_size: (u32, u32), scale_factor: Option<f64>) {
         let mut system = self.world.get_resource_mut::<RenderSystem>().unwrap();
         let scale_factor = scale_factor.unwrap_or(system.scale_factor);
@@ -426,5 +466,7 @@ fn render_system(
     let view_2d = camera_2d.get_view();
     let view_3d = camera_3d.get_view(system.width, system.height);
     let mode = system.mode;
-    system.renderer.render(view_2d, view_3d, mode);
+    if let Err(e) = system.renderer.render(view_2d, view_3d, mode) {
+        rfw_utils::log::warn!("could not render frame: {}", e);
+    }
 }
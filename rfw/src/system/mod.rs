@@ -1,15 +1,38 @@
 use crate::backend::RenderMode;
 use crate::ecs::*;
 use crate::prelude::InstancesData3D;
-use rfw_backend::{Backend, DataFormat, MeshData2D, MeshData3D, SkinData, TextureData};
+use rfw_backend::{
+    Backend, CommandQueue, CommandReceiver, DataFormat, FromWindowHandle, MeshData2D, MeshData3D,
+    SkinData, TextureData,
+};
 use rfw_scene::Scene;
 use rfw_utils::BytesConversion;
+use std::error::Error;
+
+/// Creates a backend for a window with the given size and scale factor. Lets applications
+/// pick their backend at runtime, e.g. from a list of `("wgpu", create_backend::<WgpuBackend>)`.
+pub type BackendFactory =
+    fn(&winit::window::Window, u32, u32, f64) -> Result<Box<dyn Backend>, Box<dyn Error>>;
+
+/// [BackendFactory] of backend `T`.
+pub fn create_backend<T: 'static + Backend + FromWindowHandle>(
+    window: &winit::window::Window,
+    width: u32,
+    height: u32,
+    scale_factor: f64,
+) -> Result<Box<dyn Backend>, Box<dyn Error>> {
+    let backend: Box<dyn Backend> = T::init(window, width, height, scale_factor)?;
+    backend.warm_pipelines();
+    Ok(backend)
+}
 
 pub struct RenderSystem {
     pub(crate) width: u32,
     pub(crate) height: u32,
     pub(crate) scale_factor: f64,
     pub(crate) renderer: Box<dyn Backend>,
+    pub(crate) command_queue: CommandQueue,
+    pub(crate) commands: CommandReceiver,
     pub mode: RenderMode,
 }
 
@@ -55,7 +78,9 @@ fn synchronize_system(mut system: ResMut<RenderSystem>, mut scene: ResMut<Scene>
             continue;
         }
 
-        system.renderer.set_2d_instances(id, instances.into());
+        if let Err(e) = system.renderer.set_2d_instances(id, instances.into()) {
+            rfw_utils::log::warn!("could not set 2D instances: {}", e);
+        }
     }
 
     let meshes_3d = scene.get_meshes_3d();
@@ -79,6 +104,7 @@ fn synchronize_system(mut system: ResMut<RenderSystem>, mut scene: ResMut<Scene>
 
     let instances_3d = scene.get_instances_3d();
     let light_flags = scene.get_materials().light_flags();
+    let mut changed_instances = Vec::new();
     for (i, mesh) in meshes_3d.iter() {
         let instances = &instances_3d[i];
         if !instances.any_changed() {
@@ -99,16 +125,29 @@ fn synchronize_system(mut system: ResMut<RenderSystem>, mut scene: ResMut<Scene>
             }
         }
 
-        changed = true;
-        system.renderer.set_3d_instances(
+        changed_instances.push((
             i,
             InstancesData3D {
                 matrices: instances.matrices(),
                 skin_ids: instances.skin_ids(),
                 flags: instances.flags(),
+                user_data: instances.user_data(),
+                tints: instances.tints(),
+                flipbook_times: instances.flipbook_times(),
+                wind_weights: instances.wind_weights(),
                 local_aabb: meshes_3d[i].bounds,
             },
-        );
+        ));
+    }
+
+    if !changed_instances.is_empty() {
+        changed = true;
+        if let Err(e) = system
+            .renderer
+            .set_all_3d_instances(changed_instances.as_slice())
+        {
+            rfw_utils::log::warn!("could not set 3D instances: {}", e);
+        }
     }
 
     update_lights |= found_light;
@@ -119,18 +158,24 @@ fn synchronize_system(mut system: ResMut<RenderSystem>, mut scene: ResMut<Scene>
         let textures = materials.get_textures();
         let tex_data: Vec<TextureData> = textures
             .iter()
-            .map(|t| TextureData {
+            .enumerate()
+            .map(|(i, t)| TextureData {
                 width: t.width,
                 height: t.height,
                 mip_levels: t.mip_levels,
                 bytes: t.data.as_bytes(),
                 format: DataFormat::BGRA8,
+                filter: materials.get_texture_filter(i),
+                premultiplied: materials.is_texture_premultiplied(i),
             })
             .collect();
 
-        system
+        if let Err(e) = system
             .renderer
-            .set_textures(tex_data.as_slice(), materials.get_textures_changed());
+            .set_textures(tex_data.as_slice(), materials.get_textures_changed())
+        {
+            rfw_utils::log::warn!("could not set textures: {}", e);
+        }
         changed = true;
         mat_changed = true;
     }
@@ -196,6 +241,17 @@ fn synchronize_system(mut system: ResMut<RenderSystem>, mut scene: ResMut<Scene>
         system.renderer.unload_3d_meshes(deleted_meshes);
     }
 
+    // Commands from other threads are applied after the scene, so they override its data
+    let RenderSystem {
+        renderer, commands, ..
+    } = &mut *system;
+    let applied = commands.apply(&mut **renderer, |e| {
+        rfw_utils::log::warn!("could not apply command: {}", e)
+    });
+    if applied != 0 {
+        changed = true;
+    }
+
     // Reset changes in scene
     scene.reset_changed();
 
@@ -226,6 +282,40 @@ impl RenderSystem {
         self.scale_factor
     }
 
+    pub fn renderer(&self) -> &dyn Backend {
+        &*self.renderer
+    }
+
+    pub fn renderer_mut(&mut self) -> &mut dyn Backend {
+        &mut *self.renderer
+    }
+
+    /// Returns a queue that submits scene updates to the renderer from any thread. Queued
+    /// commands are applied right before the renderer synchronizes and bypass the [Scene], so
+    /// they should not target meshes or instances that the scene also manages.
+    pub fn command_queue(&self) -> CommandQueue {
+        self.command_queue.clone()
+    }
+
+    /// Writes the most recently rendered frame to `path` as a PNG.
+    #[cfg(feature = "png")]
+    pub fn screenshot<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        self.renderer.screenshot(path.as_ref())
+    }
+
+    /// Writes the linear radiance of the most recently rendered frame to `path` as an OpenEXR
+    /// file, before exposure and tonemapping are applied.
+    #[cfg(feature = "exr")]
+    pub fn save_hdr<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        match self.renderer.capture_hdr() {
+            Some(frame) => frame.save_exr(path),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "backend does not support capturing HDR frames",
+            )),
+        }
+    }
+
     pub fn resize(&mut self, width: u32, height: u32, scale_factor: Option<f64>) {
         let scale_factor = scale_factor.unwrap_or(self.scale_factor);
         self.renderer.resize((width, height), scale_factor);